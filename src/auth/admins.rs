@@ -0,0 +1,45 @@
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+
+use crate::communication::data_fetcher;
+use crate::data_store::store::DataStore;
+
+/// Returned by `require_admin` for everyone else.
+#[derive(Debug, PartialEq)]
+pub struct PermissionDenied {
+    pub user_id: i32,
+}
+
+impl fmt::Display for PermissionDenied {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "user({}) is not an admin", self.user_id)
+    }
+}
+
+/// Admins are configured by user id with
+/// ADMIN_USER_IDS, a comma separated list.
+pub fn is_admin(user_id: &i32) -> bool {
+    admin_ids_from(&env::var("ADMIN_USER_IDS").unwrap_or_default()).contains(user_id)
+}
+
+pub fn admin_ids_from(value: &str) -> HashSet<i32> {
+    value
+        .split(',')
+        .filter_map(|user_id| user_id.trim().parse().ok())
+        .collect()
+}
+
+/// Guards privileged handlers. Users flagged with isAdmin are
+/// admins, as is anyone in ADMIN_USER_IDS so the first admin
+/// can be configured before the flag is set on anyone.
+pub async fn require_admin(
+    user_id: &i32,
+    execution_handler: &mut dyn DataStore,
+) -> Result<(), PermissionDenied> {
+    if is_admin(user_id) || data_fetcher::get_user_is_admin(user_id, execution_handler).await {
+        Ok(())
+    } else {
+        Err(PermissionDenied { user_id: *user_id })
+    }
+}
@@ -4,9 +4,9 @@ by using access tokens.
 */
 use crate::communication::data_capturer;
 use crate::data_store::db_models::DBUser;
+use crate::data_store::pool::ExecutionHandlerPool;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
 use chrono::Utc;
-use futures::lock::Mutex;
 use std::sync::Arc;
 use tokio_postgres::row::Row;
 use uuid::Uuid;
@@ -16,7 +16,7 @@ use uuid::Uuid;
 //updates tokens if it does
 pub async fn parse_and_capture_discord_user_data(
     data: serde_json::Value,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
     access_token: String,
 ) -> bool {
     let mut action_was_successful: bool = true;
@@ -62,7 +62,7 @@ pub async fn parse_and_capture_discord_user_data(
 
 pub async fn parse_and_capture_github_user_data(
     data: serde_json::Value,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
     access_token: String,
 ) -> bool {
     let mut action_was_successful = true;
@@ -159,6 +159,7 @@ async fn generate_and_capture_new_user(
         bio: "This user is a myth!".to_string(),
         contributions: 0,
         banner_url: "".to_string(),
+        is_admin: false,
     };
     let user_id = data_capturer::capture_new_user(execution_handler, &user).await;
     return user_id;
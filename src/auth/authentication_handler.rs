@@ -6,8 +6,7 @@ use reqwest::Error;
 use serde::{Deserialize, Serialize};
 use std::env;
 
-use crate::data_store::sql_execution_handler::ExecutionHandler;
-use futures::lock::Mutex;
+use crate::data_store::pool::ExecutionHandlerPool;
 use std::sync::Arc;
 
 /*
@@ -23,7 +22,7 @@ pub struct CodeParams {
 
 pub async fn gather_tokens_and_construct_save_url_discord(
     code: String,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
 ) -> Result<Uri, Error> {
     let base_url = "https://discordapp.com/api/oauth2/token";
     let base_api_url = env::var("BASE_API_URL").unwrap();
@@ -92,7 +91,7 @@ pub async fn gather_tokens_and_construct_save_url_discord(
 
 pub async fn gather_tokens_and_construct_save_url_github(
     code: String,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
 ) -> Result<Uri, Error> {
     let base_url = "https://github.com/login/oauth/access_token";
     let client_id = env::var("GH_CLIENT_ID").unwrap();
@@ -187,6 +186,32 @@ pub async fn exchange_discord_refresh_token_for_access(
     return Ok(result);
 }
 
+//only works for github apps that have
+//expiring user tokens turned on.
+pub async fn exchange_github_refresh_token_for_access(
+    refresh_token: String,
+) -> Result<serde_json::Value, Error> {
+    let base_url = "https://github.com/login/oauth/access_token";
+    let client_id = env::var("GH_CLIENT_ID").unwrap();
+    let client_secret = env::var("GH_CLIENT_SECRET").unwrap();
+    let params = [
+        ("client_id", client_id),
+        ("client_secret", client_secret),
+        ("grant_type", "refresh_token".to_owned()),
+        ("refresh_token", refresh_token),
+    ];
+    let client = reqwest::Client::new();
+    let result = client
+        .post(base_url)
+        .form(&params)
+        .header("Accept", "application/json")
+        .send()
+        .await?
+        .json()
+        .await?;
+    return Ok(result);
+}
+
 pub async fn gather_user_basic_data_discord(
     access_token: String,
 ) -> Result<serde_json::Value, Error> {
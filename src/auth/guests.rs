@@ -0,0 +1,56 @@
+/*
+Guests listen in public rooms without oauth.
+
+They only live in the server state, nothing about them
+reaches the database and they're gone once they disconnect.
+Guest ids count down from FIRST_GUEST_ID so they can never
+clash with a database id or the -1/-2 "not found" ids.
+*/
+use std::env;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::communication::op_codes::OpCode;
+
+pub const FIRST_GUEST_ID: i32 = -1000;
+
+static NEXT_GUEST_ID: AtomicI32 = AtomicI32::new(FIRST_GUEST_ID);
+
+/// Off unless ALLOW_GUESTS is "true".
+pub fn guests_allowed() -> bool {
+    guests_allowed_from(env::var("ALLOW_GUESTS").ok().as_deref())
+}
+
+pub fn guests_allowed_from(value: Option<&str>) -> bool {
+    value.is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+}
+
+/// Never handed out twice while the server runs.
+pub fn next_guest_id() -> i32 {
+    NEXT_GUEST_ID.fetch_sub(1, Ordering::Relaxed)
+}
+
+/// Guest ids are handed out from FIRST_GUEST_ID down.
+pub fn is_guest_id(user_id: i32) -> bool {
+    user_id <= FIRST_GUEST_ID
+}
+
+/// What a listener needs, browsing public rooms, joining one as
+/// a peer, receiving audio and leaving. Speaking, following,
+/// profiles and anything privileged stay with signed in users.
+pub fn op_allowed_for_guests(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::GetTopRooms
+            | OpCode::SearchRooms
+            | OpCode::GetRoomsByTag
+            | OpCode::JoinAsNewPeer
+            | OpCode::ConnectTransport
+            | OpCode::GetRecvTracks
+            | OpCode::InitialRoomData
+            | OpCode::GatherAllUsersInRoom
+            | OpCode::AllRoomPermissions
+            | OpCode::UserPreviews
+            | OpCode::UpdateDeafAndMute
+            | OpCode::LeaveRoom
+    )
+}
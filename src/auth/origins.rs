@@ -0,0 +1,35 @@
+use std::collections::HashSet;
+use std::env;
+
+/// Web pages allowed to open a websocket, set with
+/// ALLOWED_ORIGINS(comma separated, e.g. https://app.example.com).
+/// None when unset, which allows every origin.
+pub fn allowed_origins() -> Option<HashSet<String>> {
+    origins_from(&env::var("ALLOWED_ORIGINS").unwrap_or_default())
+}
+
+pub fn origins_from(value: &str) -> Option<HashSet<String>> {
+    let origins: HashSet<String> = value
+        .split(',')
+        .map(normalize_origin)
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    if origins.is_empty() {
+        None
+    } else {
+        Some(origins)
+    }
+}
+
+/// Browsers always send an Origin, so a missing one is a
+/// non-browser client that can't be hijacked cross-site.
+pub fn origin_is_allowed(allowed: &Option<HashSet<String>>, origin: Option<&str>) -> bool {
+    match (allowed, origin) {
+        (Some(allowed), Some(origin)) => allowed.contains(&normalize_origin(origin)),
+        _ => true,
+    }
+}
+
+fn normalize_origin(origin: &str) -> String {
+    origin.trim().trim_end_matches('/').to_lowercase()
+}
@@ -0,0 +1,86 @@
+use super::admins::{self, PermissionDenied};
+use super::guests;
+use super::origins;
+use crate::communication::op_codes::OpCode;
+use crate::data_store::store::MemoryDataStore;
+
+pub fn test_origin_allowlist() {
+    assert_eq!(origins::origins_from(""), None);
+    assert_eq!(origins::origins_from(" , "), None);
+    let allowed = origins::origins_from("https://app.example.com, http://localhost:3000/");
+    assert_eq!(allowed.as_ref().map(|origins| origins.len()), Some(2));
+
+    assert!(origins::origin_is_allowed(
+        &allowed,
+        Some("https://app.example.com")
+    ));
+    assert!(origins::origin_is_allowed(
+        &allowed,
+        Some("HTTPS://App.Example.com/")
+    ));
+    assert!(origins::origin_is_allowed(
+        &allowed,
+        Some("http://localhost:3000")
+    ));
+    assert!(!origins::origin_is_allowed(
+        &allowed,
+        Some("https://evil.example.com")
+    ));
+    // the port is part of the origin
+    assert!(!origins::origin_is_allowed(
+        &allowed,
+        Some("http://localhost:4000")
+    ));
+    assert!(origins::origin_is_allowed(&allowed, None));
+    // nothing configured allows everyone
+    assert!(origins::origin_is_allowed(
+        &None,
+        Some("https://evil.example.com")
+    ));
+}
+
+/// Only users flagged as admins(or listed in
+/// ADMIN_USER_IDS) get past require_admin.
+pub async fn test_require_admin() {
+    let mut store = MemoryDataStore::new();
+    store.admins.insert(7);
+    assert_eq!(admins::require_admin(&7, &mut store).await, Ok(()));
+    assert_eq!(
+        admins::require_admin(&33, &mut store).await,
+        Err(PermissionDenied { user_id: 33 })
+    );
+}
+
+/// Guests are opt in, get ids no database user can have
+/// and can only use what a listener needs.
+pub fn test_guests() {
+    assert!(!guests::guests_allowed_from(None));
+    assert!(!guests::guests_allowed_from(Some("yes")));
+    assert!(guests::guests_allowed_from(Some(" TRUE ")));
+
+    let first = guests::next_guest_id();
+    let second = guests::next_guest_id();
+    assert!(first <= guests::FIRST_GUEST_ID);
+    assert!(second < first);
+
+    for op_code in [
+        OpCode::GetTopRooms,
+        OpCode::JoinAsNewPeer,
+        OpCode::GetRecvTracks,
+        OpCode::LeaveRoom,
+    ] {
+        assert!(guests::op_allowed_for_guests(op_code));
+    }
+    for op_code in [
+        OpCode::JoinAsSpeaker,
+        OpCode::SendTrack,
+        OpCode::RaiseHand,
+        OpCode::SendChatMsg,
+        OpCode::FollowUser,
+        OpCode::EditProfile,
+        OpCode::CreateRoom,
+        OpCode::KickUserFromRoom,
+    ] {
+        assert!(!guests::op_allowed_for_guests(op_code));
+    }
+}
@@ -3,9 +3,11 @@ after they authenticate using the
  auth endpoints, we need to authenticate them
 with their access/refresh tokens that they have
 */
-use crate::auth::authentication_handler;
+use crate::auth::{api_data_handler, authentication_handler, guests};
+use crate::communication::data_fetcher;
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::data_store::sql_execution_handler::DatabaseError;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
-use futures::lock::Mutex;
 use std::sync::Arc;
 use tokio_postgres::Row;
 
@@ -15,72 +17,225 @@ pub struct UserIdAndNewAuthCredentials {
     pub user_id: i32,
     pub refresh: Option<String>,
     pub access: Option<String>,
+    /// no oauth, see auth::guests
+    pub guest: bool,
+}
+
+/// Why a websocket connection couldn't be authenticated.
+#[derive(Debug, PartialEq)]
+pub enum WsAuthFailure {
+    /// The credentials were bad or we couldn't find/create the user.
+    Invalid,
+    /// Both the access and refresh token were rejected.
+    Expired,
+    /// The user is globally banned, holds the reason.
+    Banned(String),
+}
+
+impl WsAuthFailure {
+    pub fn op_code(&self) -> String {
+        match self {
+            WsAuthFailure::Invalid => "auth-not-good".to_owned(),
+            WsAuthFailure::Expired => "auth_expired".to_owned(),
+            WsAuthFailure::Banned(_) => "banned".to_owned(),
+        }
+    }
+}
+
+/// Guests get a fresh id every connection and
+/// never touch the database, see auth::guests.
+pub fn gather_guest_id() -> Result<UserIdAndNewAuthCredentials, WsAuthFailure> {
+    if !guests::guests_allowed() {
+        return Err(WsAuthFailure::Invalid);
+    }
+    Ok(UserIdAndNewAuthCredentials {
+        user_id: guests::next_guest_id(),
+        access: None,
+        refresh: None,
+        guest: true,
+    })
 }
 
 pub async fn gather_user_id_using_discord_id(
     refresh: String,
     access: String,
-    handler: &Arc<Mutex<ExecutionHandler>>,
-) -> Option<UserIdAndNewAuthCredentials> {
-    let gather_result = authentication_handler::gather_user_basic_data_discord(access).await;
-    if let Ok(response_data) = gather_result {
+    handler: &Arc<ExecutionHandlerPool>,
+) -> Result<UserIdAndNewAuthCredentials, WsAuthFailure> {
+    gather_user_id_using_tokens("dc", refresh, access, handler).await
+}
+
+pub async fn gather_user_id_using_github_id(
+    refresh: String,
+    access: String,
+    handler: &Arc<ExecutionHandlerPool>,
+) -> Result<UserIdAndNewAuthCredentials, WsAuthFailure> {
+    gather_user_id_using_tokens("gh", refresh, access, handler).await
+}
+
+//Tries the access token first, if the oauth provider
+//rejects it we exchange the refresh token for a new
+//set and hand those back to the user.
+async fn gather_user_id_using_tokens(
+    type_of_select: &str,
+    refresh: String,
+    access: String,
+    handler: &Arc<ExecutionHandlerPool>,
+) -> Result<UserIdAndNewAuthCredentials, WsAuthFailure> {
+    if let Some(response_data) = gather_basic_data(type_of_select, access.clone()).await {
         //if we found an id in the response go and
         //get the user from the db
-        let user_id = get_id_from_response(response_data, handler, "dc").await?;
-        return Some(UserIdAndNewAuthCredentials {
+        let user_id = resolve_or_create_user_id(response_data, handler, type_of_select, access)
+            .await
+            .ok_or(WsAuthFailure::Invalid)?;
+        ensure_not_banned(handler, &user_id).await?;
+        return Ok(UserIdAndNewAuthCredentials {
             user_id,
-            refresh: None,
             access: None,
+            refresh: None,
+            guest: false,
         });
     }
-    let result = authentication_handler::exchange_discord_refresh_token_for_access(refresh).await;
-    if let Ok(response_data) = result {
-        let user_id = get_id_from_response(response_data.clone(), handler, "dc").await?;
-        // if we can't find user
-        // try to exchange old refresh for new set
-        if user_id != -1 {
-            let new_access = response_data["access_token"].to_string();
-            let new_refresh = response_data["refresh_token"].to_string();
-            //remove extra quotes
-            let fixed_new_access = new_access[1..new_access.len() - 1].to_string();
-            let fixed_new_refresh = new_access[1..new_refresh.len() - 1].to_string();
-            return Some(UserIdAndNewAuthCredentials {
-                user_id,
-                access: Some(fixed_new_access),
-                refresh: Some(fixed_new_refresh),
-            });
+    let (new_access, new_refresh) = exchange_refresh_token(type_of_select, refresh)
+        .await
+        .ok_or(WsAuthFailure::Expired)?;
+    let response_data = gather_basic_data(type_of_select, new_access.clone())
+        .await
+        .ok_or(WsAuthFailure::Expired)?;
+    let user_id =
+        resolve_or_create_user_id(response_data, handler, type_of_select, new_access.clone())
+            .await
+            .ok_or(WsAuthFailure::Invalid)?;
+    ensure_not_banned(handler, &user_id).await?;
+    store_new_access_token(handler, type_of_select, &user_id, new_access.clone()).await;
+    Ok(UserIdAndNewAuthCredentials {
+        user_id,
+        access: Some(new_access),
+        refresh: new_refresh,
+        guest: false,
+    })
+}
+
+//Banned users are turned away before they get a session.
+async fn ensure_not_banned(
+    handler: &Arc<ExecutionHandlerPool>,
+    user_id: &i32,
+) -> Result<(), WsAuthFailure> {
+    let mut execution_handler = handler.lock().await;
+    match data_fetcher::get_user_ban_status(user_id, &mut execution_handler).await {
+        Some((true, banned_reason)) => Err(WsAuthFailure::Banned(banned_reason)),
+        _ => Ok(()),
+    }
+}
+
+//The providers answer rejected tokens with an error
+//body, so we only count responses that contain an id.
+async fn gather_basic_data(type_of_select: &str, access: String) -> Option<serde_json::Value> {
+    let gather_result = if type_of_select == "dc" {
+        authentication_handler::gather_user_basic_data_discord(access).await
+    } else {
+        authentication_handler::gather_user_basic_data_github(access).await
+    };
+    match gather_result {
+        Ok(response_data) if response_data["id"] != serde_json::Value::Null => Some(response_data),
+        _ => None,
+    }
+}
+
+//Github doesn't always give a new refresh token,
+//in that case the old one is still good.
+async fn exchange_refresh_token(
+    type_of_select: &str,
+    refresh: String,
+) -> Option<(String, Option<String>)> {
+    let response_data = if type_of_select == "dc" {
+        let result = authentication_handler::exchange_discord_refresh_token_for_access(refresh)
+            .await
+            .ok()?;
+        if !authentication_handler::discord_token_gather_is_valid(&result) {
+            return None;
         }
-        return Some(UserIdAndNewAuthCredentials {
-            user_id,
-            access: None,
-            refresh: None,
-        });
+        result
+    } else {
+        let result = authentication_handler::exchange_github_refresh_token_for_access(refresh)
+            .await
+            .ok()?;
+        if !authentication_handler::github_token_gather_is_valid(&result) {
+            return None;
+        }
+        result
+    };
+    let new_access = response_data["access_token"].as_str()?.to_owned();
+    let new_refresh = response_data["refresh_token"]
+        .as_str()
+        .map(|refresh| refresh.to_owned());
+    Some((new_access, new_refresh))
+}
+
+async fn store_new_access_token(
+    handler: &Arc<ExecutionHandlerPool>,
+    type_of_select: &str,
+    user_id: &i32,
+    new_access: String,
+) {
+    let mut execution_handler = handler.lock().await;
+    let result = if type_of_select == "dc" {
+        execution_handler
+            .update_discord_access_token(new_access, user_id)
+            .await
+    } else {
+        execution_handler
+            .update_github_access_token(new_access, user_id)
+            .await
+    };
+    if let Err(e) = result {
+        eprintln!("issue storing refreshed access token:{}", e);
     }
-    return None;
 }
 
-pub async fn gather_user_id_using_github_id(
+//A valid oauth user that we have never seen
+//gets created, the same way the auth callbacks do.
+async fn resolve_or_create_user_id(
+    response_data: serde_json::Value,
+    handler: &Arc<ExecutionHandlerPool>,
+    type_of_select: &str,
     access: String,
-    handler: &Arc<Mutex<ExecutionHandler>>,
-) -> Option<UserIdAndNewAuthCredentials> {
-    let gather_result = authentication_handler::gather_user_basic_data_github(access).await;
-    if gather_result.is_ok() {
-        let response_data = gather_result.unwrap();
-        let user_id = get_id_from_response(response_data, handler, "gh").await?;
-        return Some(UserIdAndNewAuthCredentials {
-            user_id,
-            access: None,
-            refresh: None,
-        });
+) -> Option<i32> {
+    if let Some(user_id) =
+        get_id_from_response(response_data.clone(), handler, type_of_select).await
+    {
+        return Some(user_id);
     }
-
-    return None;
+    if response_data["id"] == serde_json::Value::Null {
+        return None;
+    }
+    //the capture logic expects the token
+    //the way the oauth api gives it to us(quoted)
+    let quoted_access = format!("\"{}\"", access);
+    let created = if type_of_select == "dc" {
+        api_data_handler::parse_and_capture_discord_user_data(
+            response_data.clone(),
+            handler.clone(),
+            quoted_access,
+        )
+        .await
+    } else {
+        api_data_handler::parse_and_capture_github_user_data(
+            response_data.clone(),
+            handler.clone(),
+            quoted_access,
+        )
+        .await
+    };
+    if !created {
+        return None;
+    }
+    get_id_from_response(response_data, handler, type_of_select).await
 }
 
 //selects by discord id or github id based on the passed in type
 async fn get_id_from_response(
     response_data: serde_json::Value,
-    handler: &Arc<Mutex<ExecutionHandler>>,
+    handler: &Arc<ExecutionHandlerPool>,
     type_of_select: &str,
 ) -> Option<i32> {
     if response_data["id"] != serde_json::Value::Null {
@@ -100,7 +255,7 @@ async fn select_dc_or_gh(
     execution_handler: &mut ExecutionHandler,
     type_of_select: &str,
     response_data: serde_json::Value,
-) -> Result<Vec<Row>, tokio_postgres::Error> {
+) -> Result<Vec<Row>, DatabaseError> {
     let dc_or_gh_id = response_data["id"].to_string();
     let fixed_dc_or_gh_id = dc_or_gh_id[1..dc_or_gh_id.len() - 1].to_string();
     if type_of_select == "dc" {
@@ -0,0 +1,100 @@
+use crate::communication::types::BoardPermissions;
+use crate::state::state::ServerState;
+use crate::state::types::{Board, BoardCommandAudit};
+use chrono::Utc;
+
+/// How many commands we keep per board
+/// for collaborators to look back on.
+const MAX_RECENT_BOARD_COMMANDS: usize = 25;
+
+/*
+Handles the permission model of each Board.
+
+A Board is created when a user in a room successfully
+connects an IoT server to that room, that user becomes
+the owner of the Board. The owner is the only one who
+can grant/revoke control of the Board to other users
+in the same room.
+
+Every action that controls an IoT server must be
+checked with `user_can_control_board` before it
+is sent to the integration server.
+*/
+
+/// The owner and those granted permission
+/// by the owner are the only users that
+/// can control a board.
+pub fn user_can_control_board(board: &Board, user_id: &i32) -> bool {
+    board.owner_user_id == *user_id || board.users_with_permission.contains(user_id)
+}
+
+/// Gives/removes control of a board for a user.
+///
+/// Returns the outside name of the board if the
+/// modification was successful, so the room
+/// can be notified.
+pub fn modify_board_permission(
+    write_state: &mut ServerState,
+    room_id: &i32,
+    external_id: &String,
+    requester_id: &i32,
+    user_id: &i32,
+    now_has_permission: bool,
+) -> Option<String> {
+    // Can't give yourself permission since you must be the owner
+    // in the beginning. You already have permission.
+    if requester_id == user_id {
+        return None;
+    }
+    let room = write_state.rooms.get_mut(room_id)?;
+    // You can only give control to users in the same room
+    if now_has_permission && !room.user_ids.contains(user_id) {
+        return None;
+    }
+    let board = room.iot_server_connections.get_mut(external_id)?;
+    //only owners can give or revoke permission
+    if board.owner_user_id != *requester_id {
+        return None;
+    }
+    if now_has_permission {
+        board.users_with_permission.insert(*user_id);
+    } else {
+        board.users_with_permission.remove(user_id);
+    }
+    Some(board.outside_name.clone())
+}
+
+/// Gathers the owner and everyone who can control
+/// a specific board in a room.
+pub fn list_board_permissions(
+    read_state: &ServerState,
+    room_id: &i32,
+    external_id: &String,
+) -> Option<BoardPermissions> {
+    let room = read_state.rooms.get(room_id)?;
+    let board = room.iot_server_connections.get(external_id)?;
+    let mut users_with_permission: Vec<i32> = board.users_with_permission.iter().cloned().collect();
+    users_with_permission.sort();
+    Some(BoardPermissions {
+        external_id: board.external_server_id.clone(),
+        owner_id: board.owner_user_id,
+        outside_name: board.outside_name.clone(),
+        users_with_permission,
+    })
+}
+
+/// Keeps track of who sent what to a board,
+/// the oldest commands are dropped once we
+/// reach the max amount.
+pub fn record_board_command(board: &mut Board, user_id: i32, command: String) -> BoardCommandAudit {
+    let audit = BoardCommandAudit {
+        user_id,
+        command,
+        sent_at: Utc::now().to_string(),
+    };
+    board.recent_commands.push_back(audit.clone());
+    while board.recent_commands.len() > MAX_RECENT_BOARD_COMMANDS {
+        board.recent_commands.pop_front();
+    }
+    audit
+}
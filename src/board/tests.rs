@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
+
+use crate::rooms::chat_modes::ChatMode;
+use crate::state::state::ServerState;
+use crate::state::types::{Board, Room, User};
+
+use super::handler;
+
+pub fn test_board_permissions() {
+    let mut state = setup_state_with_board();
+    owner_can_grant_and_revoke(&mut state);
+    non_owners_cannot_grant(&mut state);
+    users_outside_room_cannot_be_granted(&mut state);
+    commands_are_audited(&mut state);
+}
+
+fn owner_can_grant_and_revoke(state: &mut ServerState) {
+    let external_id = "server_one".to_owned();
+    let result = handler::modify_board_permission(state, &3, &external_id, &33, &34, true);
+    assert_eq!(result.unwrap(), "outside");
+    let board = state.rooms[&3]
+        .iot_server_connections
+        .get(&external_id)
+        .unwrap();
+    assert!(handler::user_can_control_board(board, &34));
+    let permissions = handler::list_board_permissions(state, &3, &external_id).unwrap();
+    assert_eq!(permissions.owner_id, 33);
+    assert_eq!(permissions.users_with_permission, vec![34]);
+
+    let result = handler::modify_board_permission(state, &3, &external_id, &33, &34, false);
+    assert!(result.is_some());
+    let board = state.rooms[&3]
+        .iot_server_connections
+        .get(&external_id)
+        .unwrap();
+    assert!(!handler::user_can_control_board(board, &34));
+    // the owner can always control their own board
+    assert!(handler::user_can_control_board(board, &33));
+}
+
+fn non_owners_cannot_grant(state: &mut ServerState) {
+    let external_id = "server_one".to_owned();
+    let result = handler::modify_board_permission(state, &3, &external_id, &34, &35, true);
+    assert!(result.is_none());
+    // the owner can't grant themselves
+    let result = handler::modify_board_permission(state, &3, &external_id, &33, &33, true);
+    assert!(result.is_none());
+}
+
+fn users_outside_room_cannot_be_granted(state: &mut ServerState) {
+    let external_id = "server_one".to_owned();
+    let result = handler::modify_board_permission(state, &3, &external_id, &33, &99, true);
+    assert!(result.is_none());
+    let missing_board = "server_two".to_owned();
+    assert!(handler::list_board_permissions(state, &3, &missing_board).is_none());
+}
+
+fn commands_are_audited(state: &mut ServerState) {
+    let board = state
+        .rooms
+        .get_mut(&3)
+        .unwrap()
+        .iot_server_connections
+        .get_mut("server_one")
+        .unwrap();
+    for i in 0..30 {
+        handler::record_board_command(board, 33, format!("command_{}", i));
+    }
+    // only the most recent commands are kept
+    assert_eq!(board.recent_commands.len(), 25);
+    assert_eq!(board.recent_commands.front().unwrap().command, "command_5");
+    assert_eq!(board.recent_commands.back().unwrap().command, "command_29");
+    assert_eq!(board.recent_commands.back().unwrap().user_id, 33);
+}
+
+fn setup_state_with_board() -> ServerState {
+    let mut state = ServerState::new();
+    let mut iot_server_connections = HashMap::new();
+    iot_server_connections.insert(
+        "server_one".to_owned(),
+        Board {
+            room_id: 3,
+            owner_user_id: 33,
+            users_with_permission: HashSet::new(),
+            external_server_id: "server_one".to_owned(),
+            passive_data_snapshot: None,
+            outside_name: "outside".to_owned(),
+            recent_commands: VecDeque::new(),
+        },
+    );
+    state.rooms.insert(
+        3,
+        Room {
+            room_id: 3,
+            owner_id: 33,
+            muted: HashSet::new(),
+            name: "test".to_owned(),
+            desc: "test".to_owned(),
+            chat_throttle: 1000,
+            chat_mode: ChatMode::Everyone,
+            voice_server_id: "0".to_owned(),
+            deaf: HashSet::new(),
+            user_ids: HashSet::from([33, 34, 35]),
+            mod_ids: HashSet::new(),
+            amount_of_users: 3,
+            max_occupancy: 500,
+            public: true,
+            auto_speaker: true,
+            created_at: "now".to_owned(),
+            iot_server_connections,
+            empty_since: None,
+            tags: Vec::new(),
+            pinned_at: None,
+            recording: None,
+            last_activity: Instant::now(),
+            idle_warned_at: None,
+            event_history: VecDeque::new(),
+        },
+    );
+    for user_id in [33, 34, 35] {
+        state.active_users.insert(
+            user_id,
+            User {
+                current_room_id: 3,
+                ..Default::default()
+            },
+        );
+    }
+    state
+}
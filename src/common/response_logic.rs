@@ -1,23 +1,152 @@
-use crate::communication::types::BasicResponse;
+use crate::communication::types::{BasicResponse, ResponseEnvelope, ResponseStatus};
+use crate::logging;
 use crate::state::state::ServerState;
+use crate::state::types::PeerSendError;
+use std::future::Future;
 use warp::ws::Message;
 
+tokio::task_local! {
+    /// The request_id of the request being handled, set by
+    /// the router so replies don't need it passed around.
+    static REQUEST_ID: Option<String>;
+}
+
+/// Runs a request's handling with its request_id in scope.
+/// Tasks it spawns don't inherit it.
+pub async fn with_request_id<F: Future>(request_id: Option<String>, request: F) -> F::Output {
+    REQUEST_ID.scope(request_id, request).await
+}
+
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID
+        .try_with(|request_id| request_id.clone())
+        .ok()
+        .flatten()
+}
+
+/// The serialized reply for the requester, with its
+/// status and the request_id echoed back.
+pub fn requester_response(
+    response_data: String,
+    op_code: String,
+    status: ResponseStatus,
+    request_id: Option<String>,
+) -> String {
+    let envelope = ResponseEnvelope {
+        status,
+        response: BasicResponse {
+            response_op_code: op_code,
+            response_containing_data: response_data,
+        },
+        request_id,
+    };
+    serde_json::to_string(&envelope).unwrap()
+}
+
+/// Answers the request with an "ok" status.
 pub fn send_to_requester_channel(
     response_data: String,
     requester_id: i32,
     server_state: &mut ServerState,
     op_code: String,
 ) {
-    let response = BasicResponse {
-        response_op_code: op_code,
-        response_containing_data: response_data,
-    };
+    send_with_status(
+        response_data,
+        requester_id,
+        server_state,
+        op_code,
+        ResponseStatus::Ok,
+    );
+}
+
+/// Tells the requester their request failed,
+/// the reply goes out with an "error" status.
+pub fn send_error_to_requester(
+    response_data: String,
+    requester_id: i32,
+    server_state: &mut ServerState,
+    op_code: String,
+) {
+    send_with_status(
+        response_data,
+        requester_id,
+        server_state,
+        op_code,
+        ResponseStatus::Error,
+    );
+}
+
+fn send_with_status(
+    response_data: String,
+    requester_id: i32,
+    server_state: &mut ServerState,
+    op_code: String,
+    status: ResponseStatus,
+) {
+    let response = requester_response(response_data, op_code, status, current_request_id());
     // TODO:handle error
-    let gather_result = server_state.peer_map.get(&requester_id);
-    if gather_result.is_some() {
-        gather_result
-            .unwrap()
-            .send(Message::text(serde_json::to_string(&response).unwrap()))
-            .unwrap_or_default();
+    server_state
+        .peer_map
+        .send_to_user(&requester_id, Message::text(response))
+        .unwrap_or_default();
+}
+
+/// Sends a message to every member of a room.
+///
+/// A member whose channel is closed(or missing) is a dead
+/// connection, once everyone else got the message their
+/// closed senders are dropped from the peer map so later
+/// broadcasts don't keep trying them. Leaving the room,
+/// passing on ownership and parking the session is left
+/// to the connection's own disconnect handling, which
+/// runs once its socket task notices the drop.
+///
+/// Returns the amount of successful deliveries.
+pub fn broadcast_to_room(room_id: &i32, message: String, server_state: &mut ServerState) -> usize {
+    let mut successful_deliveries: usize = 0;
+    let mut dead_peers: Vec<i32> = Vec::new();
+    if let Some(room) = server_state.rooms.get(room_id) {
+        for user_id in room.user_ids.iter() {
+            // Users waiting to resume their session
+            // aren't dead, they just can't hear us yet.
+            if server_state.disconnected_sessions.contains_key(user_id) {
+                continue;
+            }
+            // only dead once none of their connections are open
+            let send_result = server_state
+                .peer_map
+                .send_to_user(user_id, Message::text(message.clone()));
+            match send_result {
+                Ok(()) => successful_deliveries += 1,
+                // their writer task is already disconnecting them
+                Err(PeerSendError::SlowConsumer) => {}
+                Err(PeerSendError::Closed) => dead_peers.push(*user_id),
+            }
+        }
+    }
+    for user_id in dead_peers {
+        remove_dead_senders(&user_id, room_id, server_state);
+    }
+    successful_deliveries
+}
+
+/// Sends a message to every connected peer, returns the
+/// amount of successful deliveries. Closed peers are left
+/// for their connection task to clean up.
+pub fn broadcast_to_all_peers(message: String, server_state: &ServerState) -> usize {
+    server_state
+        .peer_map
+        .values()
+        .filter(|tx| tx.send(Message::text(message.clone())).is_ok())
+        .count()
+}
+
+fn remove_dead_senders(user_id: &i32, room_id: &i32, server_state: &mut ServerState) {
+    let removed = server_state.peer_map.remove_closed_connections(user_id);
+    if removed > 0 {
+        logging::console::log_event(&format!(
+            "Dropped {} dead connection(s) of user({}) in room({})",
+            removed, user_id, room_id
+        ));
     }
 }
@@ -0,0 +1,44 @@
+use super::response_logic::{self, send_error_to_requester, send_to_requester_channel};
+use crate::communication::types::{ResponseEnvelope, ResponseStatus};
+use crate::state::state::ServerState;
+use crate::state::types::{ConnectionId, PeerSender};
+
+/// Replies to the requester carry a status, and the
+/// request_id when the client sent one.
+pub async fn test_response_envelope() {
+    let response = response_logic::requester_response(
+        "3".to_owned(),
+        "room_full".to_owned(),
+        ResponseStatus::Error,
+        None,
+    );
+    assert_eq!(
+        response,
+        r#"{"response_op_code":"room_full","response_containing_data":"3","status":"error"}"#
+    );
+
+    let mut state = ServerState::new();
+    let (tx, mut rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 1, tx);
+    response_logic::with_request_id(Some("req-7".to_owned()), async {
+        send_to_requester_channel("{}".to_owned(), 1, &mut state, "your_data".to_owned());
+    })
+    .await;
+    send_to_requester_channel("{}".to_owned(), 1, &mut state, "your_data".to_owned());
+
+    let echoed: ResponseEnvelope =
+        serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+    assert_eq!(echoed.request_id.as_deref(), Some("req-7"));
+    assert_eq!(echoed.status, ResponseStatus::Ok);
+    assert_eq!(echoed.response.response_op_code, "your_data");
+    let unscoped: ResponseEnvelope =
+        serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+    assert_eq!(unscoped.request_id, None);
+
+    // failures are labelled where they're sent, whatever the op code
+    send_error_to_requester("{}".to_owned(), 1, &mut state, "not_in_room".to_owned());
+    let failed: ResponseEnvelope =
+        serde_json::from_str(rx.recv().await.unwrap().to_str().unwrap()).unwrap();
+    assert_eq!(failed.status, ResponseStatus::Error);
+    assert_eq!(failed.response.response_op_code, "not_in_room");
+}
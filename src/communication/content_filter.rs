@@ -0,0 +1,128 @@
+/*
+Optional keyword filter for room names, descriptions and chat.
+
+Off unless CONTENT_FILTER_MODE is "mask" or "reject". The words
+come from CONTENT_FILTER_WORDS_FILE(one per line) and are read
+once at startup. Words are matched whole and case insensitively,
+so a blocked word inside a longer word is left alone.
+*/
+use std::env;
+use std::fs;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FilterMode {
+    Off,
+    /// blocked words are replaced with *s
+    Mask,
+    /// anything with a blocked word is refused
+    Reject,
+}
+
+impl FilterMode {
+    /// Anything but "mask" or "reject" leaves the filter off.
+    pub fn from_value(value: Option<&str>) -> Self {
+        match value.map(|value| value.trim().to_lowercase()).as_deref() {
+            Some("mask") => FilterMode::Mask,
+            Some("reject") => FilterMode::Reject,
+            _ => FilterMode::Off,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Filtered {
+    Clean,
+    Masked(String),
+    Rejected,
+}
+
+#[derive(Debug)]
+pub struct ContentFilter {
+    pub mode: FilterMode,
+    /// lowercase
+    words: Vec<String>,
+}
+
+impl ContentFilter {
+    pub fn new(mode: FilterMode, words: &[String]) -> Self {
+        let words = words
+            .iter()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        ContentFilter { mode, words }
+    }
+
+    /// Fails if the filter is on and its word list can't be read.
+    pub fn from_env() -> Result<Self, String> {
+        let mode = FilterMode::from_value(env::var("CONTENT_FILTER_MODE").ok().as_deref());
+        if mode == FilterMode::Off {
+            return Ok(ContentFilter::new(mode, &[]));
+        }
+        let path = env::var("CONTENT_FILTER_WORDS_FILE")
+            .map_err(|_| "CONTENT_FILTER_WORDS_FILE must be set".to_owned())?;
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| format!("couldn't read CONTENT_FILTER_WORDS_FILE({}): {}", path, e))?;
+        let words: Vec<String> = contents.lines().map(|line| line.to_owned()).collect();
+        Ok(ContentFilter::new(mode, &words))
+    }
+
+    pub fn num_words(&self) -> usize {
+        self.words.len()
+    }
+
+    pub fn apply(&self, text: &str) -> Filtered {
+        if self.mode == FilterMode::Off || self.words.is_empty() {
+            return Filtered::Clean;
+        }
+        let mut masked = String::with_capacity(text.len());
+        let mut found = false;
+        let mut word = String::new();
+        for character in text.chars().chain(std::iter::once(' ')) {
+            if character.is_alphanumeric() {
+                word.push(character);
+                continue;
+            }
+            if !word.is_empty() {
+                if self.words.contains(&word.to_lowercase()) {
+                    found = true;
+                    masked.extend(word.chars().map(|_| '*'));
+                } else {
+                    masked.push_str(&word);
+                }
+                word.clear();
+            }
+            masked.push(character);
+        }
+        //the space pushed to flush the last word
+        masked.pop();
+        match (found, self.mode) {
+            (false, _) => Filtered::Clean,
+            (true, FilterMode::Reject) => Filtered::Rejected,
+            (true, _) => Filtered::Masked(masked),
+        }
+    }
+
+    /// Masks the text in place, false if it has to be rejected.
+    pub fn filter_in_place(&self, text: &mut String) -> bool {
+        match self.apply(text) {
+            Filtered::Clean => true,
+            Filtered::Masked(masked) => {
+                *text = masked;
+                true
+            }
+            Filtered::Rejected => false,
+        }
+    }
+}
+
+/// Loaded the first time it's used, start_server
+/// calls this so a bad word list stops startup.
+pub fn content_filter() -> &'static ContentFilter {
+    static CONTENT_FILTER: OnceLock<ContentFilter> = OnceLock::new();
+    CONTENT_FILTER.get_or_init(|| {
+        ContentFilter::from_env()
+            .unwrap_or_else(|e| panic!("invalid content filter configuration: {}", e))
+    })
+}
@@ -4,23 +4,37 @@ invalid requests like a user trying to follow
 the same user twice etc.
 */
 
-use crate::communication::types::{ScheduledRoomUpdate, UserProfileEdit};
+use crate::communication::types::{
+    RoomUpdate, ScheduledRoomUpdate, UserPreferencesEdit, UserProfileEdit,
+};
+use crate::data_store::creation_queries;
 use crate::data_store::db_models::{
-    DBFollower, DBRoom, DBRoomBlock, DBRoomPermissions, DBScheduledRoom, DBScheduledRoomAttendance,
-    DBUser, DBUserBlock,
+    DBFollower, DBModerationAction, DBRoom, DBRoomBlock, DBRoomPermissions, DBRoomRecording,
+    DBRoomSessionEvent, DBScheduledRoom, DBScheduledRoomAttendance, DBUser, DBUserBlock,
+    DBUserRoomVisit,
 };
+use crate::data_store::sql_execution_handler::DatabaseError;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::rooms;
+use chrono::Utc;
 use futures_util::Future;
-use tokio_postgres::{row::Row, Error};
+use tokio_postgres::row::Row;
+use uuid::Uuid;
 
-use super::data_fetcher;
 use super::types::BaseUser;
+use super::{data_fetcher, helpers};
 
 pub struct CaptureResult {
     pub desc: String,
     pub encountered_error: bool,
 }
 
+/// How many suffixed usernames a new user
+/// is tried with before we give up.
+const NEW_USERNAME_ATTEMPTS: usize = 5;
+
+/// A new user whose username is taken(ignoring case)
+/// gets it with a short random suffix instead.
 pub async fn capture_new_user(execution_handler: &mut ExecutionHandler, user: &DBUser) -> i32 {
     // No user has both a github id and discord id
     // We use -1 for which ever doesn't exist(which is already set in the DBUser)
@@ -30,13 +44,19 @@ pub async fn capture_new_user(execution_handler: &mut ExecutionHandler, user: &D
 
     //  we haven't ran into db issues and our user doesn't exist
     if user_already_exist_result.is_ok() && user_already_exist_result.unwrap().len() == 0 {
-        let insert_result = execution_handler.insert_user(user).await;
-        if let Ok(user_id) = insert_result {
-            return user_id;
-        } else {
-            // unexpected error
-            return -2 as i32;
+        let mut new_user = user.clone();
+        for _ in 0..NEW_USERNAME_ATTEMPTS {
+            match execution_handler.insert_user(&new_user).await {
+                Ok(user_id) => return user_id,
+                Err(e) if e.violates_unique_index(creation_queries::USER_NAME_UNIQUE_INDEX) => {
+                    new_user.user_name = suffixed_username(&user.user_name);
+                }
+                // unexpected error
+                Err(_) => return -2 as i32,
+            }
         }
+        // every suffixed username was taken too
+        return -2;
     } else {
         // duplicate
         return -1 as i32;
@@ -215,17 +235,22 @@ pub async fn capture_room_removal(
     execution_handler: &mut ExecutionHandler,
     room_id: &i32,
 ) -> CaptureResult {
-    //need to combine these capture results in the future.
-    //should be good for beta though.
-    let deletion_result = execution_handler.delete_room(room_id).await;
-    execution_handler
-        .delete_all_room_permissions(room_id)
-        .await
-        .unwrap_or_default();
-    execution_handler
-        .delete_room_blocks(room_id)
-        .await
-        .unwrap_or_default();
+    //the room, its permissions, blocks and tags go together,
+    //its recordings are kept but can't still be going
+    let room_id = *room_id;
+    let ended_at = Utc::now().to_rfc3339();
+    let deletion_result = execution_handler
+        .in_transaction(|handler| {
+            Box::pin(async move {
+                let num_deleted = handler.delete_room(&room_id).await?;
+                handler.delete_all_room_permissions(&room_id).await?;
+                handler.delete_room_blocks(&room_id).await?;
+                handler.delete_room_tags(&room_id).await?;
+                handler.end_room_recordings(&room_id, &ended_at).await?;
+                Ok(num_deleted)
+            })
+        })
+        .await;
     return handle_removal_or_update_capture(
         "Room Removed".to_owned(),
         "Unexpected error removing room".to_owned(),
@@ -234,11 +259,26 @@ pub async fn capture_room_removal(
     );
 }
 
+/// The user and everything referencing them is
+/// removed, or nothing is if any part fails.
+pub async fn capture_account_deletion(
+    execution_handler: &mut ExecutionHandler,
+    user_id: &i32,
+) -> CaptureResult {
+    let deletion_result = execution_handler.delete_account(user_id).await;
+    return handle_removal_or_update_capture(
+        "Account Deleted".to_owned(),
+        "Unexpected error deleting account".to_owned(),
+        1,
+        deletion_result,
+    );
+}
+
 pub async fn capture_user_update(
     execution_handler: &mut ExecutionHandler,
     user_id: &i32,
     edit: UserProfileEdit,
-) -> CaptureResult {
+) -> Result<CaptureResult, ProfileEditProblem> {
     let current_user_or_not: Option<BaseUser> =
         data_fetcher::gather_base_user(execution_handler, user_id).await;
     if let Some(mut current_user) = current_user_or_not {
@@ -246,14 +286,185 @@ pub async fn capture_user_update(
         let update_result = execution_handler
             .update_base_user_fields(&current_user)
             .await;
-        return handle_removal_or_update_capture(
+        if let Some(problem) = username_collision(&update_result, Some(&current_user.username)) {
+            return Err(problem);
+        }
+        return Ok(handle_removal_or_update_capture(
             "Fields Successfully Updated".to_owned(),
             "Error Updating fields".to_owned(),
             1,
             update_result,
-        );
+        ));
     };
-    return generic_error_capture_result();
+    return Ok(generic_error_capture_result());
+}
+
+/// Only the fields present in the edit are written,
+/// the edit should be validated before capturing.
+pub async fn capture_profile_edit(
+    execution_handler: &mut ExecutionHandler,
+    user_id: &i32,
+    edit: &UserProfileEdit,
+) -> Result<CaptureResult, ProfileEditProblem> {
+    let update_result = execution_handler
+        .update_user_profile_fields(user_id, edit)
+        .await;
+    if let Some(problem) = username_collision(&update_result, edit.username.as_ref()) {
+        return Err(problem);
+    }
+    return Ok(handle_removal_or_update_capture(
+        "Profile Successfully Updated".to_owned(),
+        "Error Updating Profile".to_owned(),
+        1,
+        update_result,
+    ));
+}
+
+/// Checking a username before writing it is racy, the unique
+/// index has the final say, so losing to it means it's taken.
+fn username_collision(
+    update_result: &Result<u64, DatabaseError>,
+    username: Option<&String>,
+) -> Option<ProfileEditProblem> {
+    match (update_result, username) {
+        (Err(e), Some(username))
+            if e.violates_unique_index(creation_queries::USER_NAME_UNIQUE_INDEX) =>
+        {
+            Some(ProfileEditProblem::UsernameTaken(username.clone()))
+        }
+        _ => None,
+    }
+}
+
+pub async fn capture_preferences_edit(
+    execution_handler: &mut ExecutionHandler,
+    user_id: &i32,
+    edit: &UserPreferencesEdit,
+) -> CaptureResult {
+    let update_result = execution_handler
+        .update_user_preferences(serde_json::to_string(edit).unwrap(), user_id)
+        .await;
+    handle_removal_or_update_capture(
+        "Preferences Successfully Updated".to_owned(),
+        "Error Updating Preferences".to_owned(),
+        1,
+        update_result,
+    )
+}
+
+pub enum ProfileEditProblem {
+    Invalid(String),
+    UsernameTaken(String),
+}
+
+/// Gives back why the edit can't be applied, if it can't.
+pub async fn validate_profile_edit(
+    execution_handler: &mut ExecutionHandler,
+    user_id: &i32,
+    edit: &UserProfileEdit,
+) -> Option<ProfileEditProblem> {
+    if let Some(problem) = profile_edit_problem(edit) {
+        return Some(ProfileEditProblem::Invalid(problem));
+    }
+    if let Some(username) = &edit.username {
+        if username_taken_by_other_user(username, user_id, execution_handler).await {
+            return Some(ProfileEditProblem::UsernameTaken(username.clone()));
+        }
+    }
+    return None;
+}
+
+/// Everything about a profile edit we can
+/// check without going to the database.
+pub fn profile_edit_problem(edit: &UserProfileEdit) -> Option<String> {
+    if let Some(display_name) = &edit.display_name {
+        if !field_is_long_enough(display_name, 20, 3) {
+            return Some("display name must be 3-20 characters".to_owned());
+        }
+    }
+    if let Some(username) = &edit.username {
+        if !field_is_long_enough(username, 32, 3)
+            || !username
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '_' || c == '-')
+        {
+            return Some(
+                "username must be 3-32 letters, numbers, underscores or dashes".to_owned(),
+            );
+        }
+    }
+    if let Some(bio) = &edit.bio {
+        if !field_is_long_enough(bio, 40, 1) {
+            return Some("bio must be 1-40 characters".to_owned());
+        }
+    }
+    if let Some(problem) = image_url_problem(edit) {
+        return Some(problem);
+    }
+    return None;
+}
+
+/// Avatars and banners have to be https urls
+/// on one of the allowed image hosts.
+pub fn image_url_problem(edit: &UserProfileEdit) -> Option<String> {
+    let allowed_hosts = helpers::allowed_image_hosts();
+    if let Some(avatar_url) = &edit.avatar_url {
+        if !helpers::image_url_is_allowed(avatar_url, &allowed_hosts) {
+            return Some("avatar url must be https from an allowed host".to_owned());
+        }
+    }
+    if let Some(banner_url) = &edit.banner_url {
+        if !helpers::image_url_is_allowed(banner_url, &allowed_hosts) {
+            return Some("banner url must be https from an allowed host".to_owned());
+        }
+    }
+    return None;
+}
+
+/// Longest chat throttle a room can have, in milliseconds.
+pub const MAX_CHAT_THROTTLE_MS: i32 = 60_000;
+
+/// Rooms are created and updated with the same rules.
+pub fn room_details_problem(name: &String, desc: &String) -> Option<String> {
+    if !field_is_long_enough(name, 50, 1) {
+        return Some("room name must be 1-50 characters".to_owned());
+    }
+    if !field_is_long_enough(desc, 300, 0) {
+        return Some("room description must be at most 300 characters".to_owned());
+    }
+    return None;
+}
+
+pub fn room_update_problem(update: &RoomUpdate) -> Option<String> {
+    if let Some(problem) = room_details_problem(&update.name, &update.description) {
+        return Some(problem);
+    }
+    if update.chat_throttle < 0 || update.chat_throttle > MAX_CHAT_THROTTLE_MS {
+        return Some(format!(
+            "chat throttle must be 0-{} milliseconds",
+            MAX_CHAT_THROTTLE_MS
+        ));
+    }
+    if let Some(chat_mode) = update.chat_mode.as_deref() {
+        if let Err(problem) = rooms::chat_modes::chat_mode_from_request(Some(chat_mode)) {
+            return Some(problem);
+        }
+    }
+    if let Some(tags) = &update.tags {
+        if let Err(problem) = rooms::tags::tags_from_request(tags) {
+            return Some(problem);
+        }
+    }
+    if let Some(max_occupancy) = update.max_occupancy {
+        let default_max_occupancy = rooms::handler::default_max_occupancy();
+        if max_occupancy < 1 || max_occupancy > default_max_occupancy {
+            return Some(format!(
+                "max occupancy must be 1-{} users",
+                default_max_occupancy
+            ));
+        }
+    }
+    return None;
 }
 
 pub async fn capture_scheduled_room_update(
@@ -308,6 +519,53 @@ pub async fn capture_new_room_owner_update(
     );
 }
 
+pub async fn capture_ban_status_update(
+    banned: bool,
+    banned_reason: String,
+    user_id: &i32,
+    execution_handler: &mut ExecutionHandler,
+) -> CaptureResult {
+    let ban_result = execution_handler
+        .update_ban_status_of_user(banned, banned_reason, user_id)
+        .await;
+    return handle_removal_or_update_capture(
+        "Ban status updated".to_owned(),
+        "Issue updating ban status".to_owned(),
+        1,
+        ban_result,
+    );
+}
+
+pub async fn capture_contribution(
+    user_id: &i32,
+    execution_handler: &mut ExecutionHandler,
+) -> CaptureResult {
+    let increment_result = execution_handler.increment_contributions(&1, user_id).await;
+    return handle_removal_or_update_capture(
+        "Contribution counted".to_owned(),
+        "Issue counting contribution".to_owned(),
+        1,
+        increment_result,
+    );
+}
+
+pub async fn capture_last_online_batch(
+    updates: Vec<(i32, String)>,
+    execution_handler: &mut ExecutionHandler,
+) -> CaptureResult {
+    let expected_amount = updates.len() as u64;
+    let (user_ids, last_onlines): (Vec<i32>, Vec<String>) = updates.into_iter().unzip();
+    let update_result = execution_handler
+        .update_last_online_batch(&user_ids, &last_onlines)
+        .await;
+    return handle_removal_or_update_capture(
+        "Last online times updated".to_owned(),
+        "Issue updating last online times".to_owned(),
+        expected_amount,
+        update_result,
+    );
+}
+
 pub async fn capture_new_room_permissions(
     permissions: &DBRoomPermissions,
     execution_handler: &mut ExecutionHandler,
@@ -344,6 +602,17 @@ pub async fn capture_new_room_permissions_update(
     );
 }
 
+/// The users whose hands were lowered, None if the update failed.
+pub async fn capture_lower_all_hands(
+    execution_handler: &mut ExecutionHandler,
+    room_id: &i32,
+) -> Option<Vec<i32>> {
+    match execution_handler.lower_all_hands(room_id).await {
+        Ok(rows) => Some(rows.iter().map(|row| row.get(0)).collect()),
+        Err(_) => None,
+    }
+}
+
 pub async fn mod_or_unmod_user_capture(
     mod_status: bool,
     room_id: &i32,
@@ -374,7 +643,7 @@ fn handle_removal_or_update_capture(
     success_msg: String,
     error_msg: String,
     expected_amount: u64,
-    result: Result<u64, Error>,
+    result: Result<u64, DatabaseError>,
 ) -> CaptureResult {
     if result.is_ok() && result.unwrap() == expected_amount {
         return CaptureResult {
@@ -392,7 +661,7 @@ fn handle_removal_or_update_capture(
 /// Checks to see if a select future execution has rows or not
 ///     useful for checking if a user exist before trying to
 ///     follow them etc.
-async fn row_exists(select_future: impl Future<Output = Result<Vec<Row>, Error>>) -> bool {
+async fn row_exists(select_future: impl Future<Output = Result<Vec<Row>, DatabaseError>>) -> bool {
     let select_result = select_future.await;
     if select_result.is_ok() && select_result.unwrap().len() == 1 {
         return true;
@@ -404,7 +673,7 @@ async fn row_exists(select_future: impl Future<Output = Result<Vec<Row>, Error>>
 /// Used for limiting the amount of
 ///     entries a user can make to a specific table.
 async fn less_than_x_row_exists(
-    select_future: impl Future<Output = Result<Vec<Row>, Error>>,
+    select_future: impl Future<Output = Result<Vec<Row>, DatabaseError>>,
     x: usize,
 ) -> bool {
     let select_result = select_future.await;
@@ -419,7 +688,7 @@ async fn less_than_x_row_exists(
 /// won't be a duplicate.
 async fn ensure_no_duplicates_exist_and_capture(
     will_be_duplicate: bool,
-    insert_future: impl Future<Output = Result<(), Error>>,
+    insert_future: impl Future<Output = Result<(), DatabaseError>>,
     error_message: String,
 ) -> CaptureResult {
     if will_be_duplicate {
@@ -436,7 +705,7 @@ async fn ensure_no_duplicates_exist_and_capture(
 ///     in the sql_execution_handler.
 ///
 /// Could be more generic to handle other similar cases.
-async fn capture_room(future_exc: impl Future<Output = Result<i32, Error>>) -> i32 {
+async fn capture_room(future_exc: impl Future<Output = Result<i32, DatabaseError>>) -> i32 {
     let insert_result = future_exc.await;
     if let Ok(room_id) = insert_result {
         return room_id;
@@ -450,7 +719,7 @@ async fn capture_room(future_exc: impl Future<Output = Result<i32, Error>>) -> i
 ///
 /// returns whether or not issues occured
 async fn handle_basic_insert_with_no_returning(
-    future_exc: impl Future<Output = Result<(), Error>>,
+    future_exc: impl Future<Output = Result<(), DatabaseError>>,
 ) -> CaptureResult {
     let insert_result = future_exc.await;
     if insert_result.is_ok() {
@@ -470,7 +739,7 @@ async fn handle_basic_insert_with_no_returning(
 ///     a row with the same credentials already present
 ///     before insertion.
 async fn insert_will_be_duplicate(
-    future_exc: impl Future<Output = Result<Vec<Row>, Error>>,
+    future_exc: impl Future<Output = Result<Vec<Row>, DatabaseError>>,
 ) -> bool {
     let future_result = future_exc.await;
     if let Ok(selected_rows) = future_result {
@@ -518,7 +787,9 @@ async fn merge_updates_with_current_user(
         }
     };
     if let Some(target_update) = updates.username {
-        if !username_already_exist(&target_update, execution_handler).await {
+        if !username_taken_by_other_user(&target_update, &current_user.user_id, execution_handler)
+            .await
+        {
             current_user.username = target_update;
         };
     };
@@ -564,20 +835,102 @@ fn field_is_long_enough(data: &String, max_expected_len: usize, min_expected_len
     }
 }
 
-async fn username_already_exist(
+/// Leaves room for the suffix within the 32 character limit.
+pub fn suffixed_username(username: &str) -> String {
+    let base: String = username.chars().take(25).collect();
+    let suffix = Uuid::new_v4().to_string();
+    format!("{}_{}", base, &suffix[..6])
+}
+
+/// Keeping your own username(or changing its casing)
+/// isn't a collision.
+pub async fn username_taken_by_other_user(
     username: &String,
+    user_id: &i32,
     execution_handler: &mut ExecutionHandler,
 ) -> bool {
-    let search_result = execution_handler.select_user_by_username(username).await;
+    let search_result = execution_handler.select_user_id_by_username(username).await;
     if let Ok(selected_rows) = search_result {
-        if selected_rows.len() != 0 {
-            return true;
-        } else {
-            return false;
-        }
-    } else {
-        return true;
+        return selected_rows.iter().any(|row| {
+            let found_id: i32 = row.get(0);
+            found_id != *user_id
+        });
     }
+    return true;
+}
+
+/// Gives back true if the event couldn't be stored,
+/// the analytics log is best effort.
+pub async fn capture_room_session_event(
+    execution_handler: &mut ExecutionHandler,
+    event: &DBRoomSessionEvent,
+) -> bool {
+    execution_handler
+        .insert_room_session_event(event)
+        .await
+        .is_err()
+}
+
+/// Gives back true if the action couldn't be stored,
+/// the moderation log is best effort like the analytics log.
+pub async fn capture_moderation_action(
+    execution_handler: &mut ExecutionHandler,
+    action: &DBModerationAction,
+) -> bool {
+    execution_handler
+        .insert_moderation_action(action)
+        .await
+        .is_err()
+}
+
+/// How many rooms each user's history keeps.
+pub const MAX_RECENT_ROOMS: i64 = 10;
+
+/// Gives back true if the visit couldn't be stored,
+/// the history is best effort like the analytics log.
+pub async fn capture_room_visit(
+    execution_handler: &mut ExecutionHandler,
+    visit: &DBUserRoomVisit,
+) -> bool {
+    execution_handler
+        .insert_user_room_visit(visit, MAX_RECENT_ROOMS)
+        .await
+        .is_err()
+}
+
+/// Gives back true if the tags couldn't be stored,
+/// the room keeps its old tags when that happens.
+pub async fn capture_room_tags(
+    execution_handler: &mut ExecutionHandler,
+    room_id: &i32,
+    tags: &[String],
+) -> bool {
+    execution_handler
+        .replace_room_tags(room_id, tags)
+        .await
+        .is_err()
+}
+
+/// The recording's id, None if it couldn't be stored.
+pub async fn capture_room_recording_start(
+    execution_handler: &mut ExecutionHandler,
+    recording: &DBRoomRecording,
+) -> Option<i32> {
+    execution_handler
+        .insert_room_recording(recording)
+        .await
+        .ok()
+}
+
+/// Gives back true if the recording couldn't be ended.
+pub async fn capture_room_recording_end(
+    execution_handler: &mut ExecutionHandler,
+    room_id: &i32,
+) -> bool {
+    execution_handler
+        .end_room_recordings(room_id, &Utc::now().to_rfc3339())
+        .await
+        .is_err()
 }
 
 /// Atempts to insert the room creator's attendance as the owner
@@ -2,14 +2,18 @@
 abstracts usage of the sql execution handler
 by fetching and converts rows to correct response types.
 */
-use crate::communication::types::{RoomPermissions, User, UserPreview};
-use crate::data_store::db_models::DBScheduledRoom;
+use crate::communication::types::{
+    FollowStatus, ModerationLogEntry, RoomBlockedUser, RoomPermissions, User, UserPreview,
+    UserSearchResult,
+};
+use crate::data_store::db_models::{DBRoomSessionDuration, DBScheduledRoom, DBUserPreferences};
 use crate::data_store::sql_execution_handler::ExecutionHandler;
-use futures_util::Future;
+use crate::data_store::store::DataStore;
+use crate::state::session::ConnectionState;
 use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
-use tokio_postgres::{row::Row, Error};
+use tokio_postgres::row::Row;
 
+use super::data_capturer;
 use super::types::BaseUser;
 
 /*
@@ -56,27 +60,31 @@ pub async fn gather_and_construct_users_for_user(
 ) -> (bool, Vec<User>) {
     let mut error_encountered = false;
     let mut constructed_users: Vec<User> = Vec::new();
+    //one selection for every user row, instead of one per user
+    let user_gather_result = execution_handler.select_users_by_ids(&users).await;
+    if user_gather_result.is_err() {
+        return (true, constructed_users);
+    }
+    let selected_rows = user_gather_result.unwrap();
+    //keep the order the user ids were passed in
     for user in users {
-        let user_gather_result = execution_handler.select_user_by_id(&user).await;
-        //only gather user is db selection was successful
-        if user_gather_result.is_ok() {
-            let selected_rows = user_gather_result.unwrap();
-            //use user data to construct user
-            if selected_rows.len() == 1 {
-                let user_result = get_meta_data_for_user_and_construct(
-                    execution_handler,
-                    &selected_rows[0],
-                    user_ids_requesting_user_blocked.contains(&user),
-                    user_ids_requesting_user_follows.contains(&user),
-                    requesting_user_id,
-                )
-                .await;
-                check_user_result_and_handle_error(
-                    user_result,
-                    &mut constructed_users,
-                    &mut error_encountered,
-                );
-            }
+        if let Some(row) = selected_rows
+            .iter()
+            .find(|row| row.get::<_, i32>(0) == user)
+        {
+            let user_result = get_meta_data_for_user_and_construct(
+                execution_handler,
+                row,
+                user_ids_requesting_user_blocked.contains(&user),
+                user_ids_requesting_user_follows.contains(&user),
+                requesting_user_id,
+            )
+            .await;
+            check_user_result_and_handle_error(
+                user_result,
+                &mut constructed_users,
+                &mut error_encountered,
+            );
         }
     }
     return (error_encountered, constructed_users);
@@ -137,55 +145,159 @@ async fn get_meta_data_for_user_and_construct(
 
 pub async fn get_room_permissions_for_users(
     room_id: &i32,
-    execution_handler: &mut ExecutionHandler,
+    execution_handler: &mut dyn DataStore,
 ) -> (bool, HashMap<i32, RoomPermissions>) {
-    let mut permissions: HashMap<i32, RoomPermissions> = HashMap::new();
-    let gather_result = execution_handler
-        .select_all_room_permissions_for_room(room_id)
-        .await;
-    if gather_result.is_ok() {
-        let selected_rows = gather_result.unwrap();
-        for row in selected_rows {
-            let user_id: i32 = row.get(1);
-            let is_mod: bool = row.get(3);
-            let is_speaker: bool = row.get(4);
-            let asked_to_speak: bool = row.get(5);
-            let user_permission = RoomPermissions {
-                is_mod: is_mod,
-                is_speaker: is_speaker,
-                asked_to_speak: asked_to_speak,
-            };
-            permissions.insert(user_id, user_permission);
-        }
-        return (false, permissions);
-    } else {
-        return (true, permissions);
-    }
+    execution_handler.room_permissions_for_users(room_id).await
 }
 
 pub async fn get_single_user_permissions(
     room_id: &i32,
     user_id: &i32,
-    execution_handler: &mut ExecutionHandler,
+    execution_handler: &mut dyn DataStore,
 ) -> Option<RoomPermissions> {
-    let gather_result = execution_handler
-        .select_all_room_permissions_for_user(user_id, room_id)
-        .await;
-    let selected_rows = gather_result.unwrap();
-    if selected_rows.len() == 1 {
-        let row = &selected_rows[0];
-        let is_mod: bool = row.get(3);
-        let is_speaker: bool = row.get(4);
-        let asked_to_speak: bool = row.get(5);
-        return Some(RoomPermissions {
-            is_mod: is_mod,
-            is_speaker: is_speaker,
-            asked_to_speak: asked_to_speak,
-        });
+    execution_handler
+        .single_user_permissions(room_id, user_id)
+        .await
+}
+
+pub async fn get_user_id_by_username(
+    username: &String,
+    execution_handler: &mut dyn DataStore,
+) -> Option<i32> {
+    execution_handler.user_id_by_username(username).await
+}
+
+/// (banned, banned reason), None if the user
+/// doesn't exist or the lookup failed.
+pub async fn get_user_ban_status(
+    user_id: &i32,
+    execution_handler: &mut dyn DataStore,
+) -> Option<(bool, String)> {
+    execution_handler.user_ban_status(user_id).await
+}
+
+/// None if the user couldn't be found, stored
+/// json we can't read gives the defaults.
+pub async fn get_user_preferences(
+    user_id: &i32,
+    execution_handler: &mut ExecutionHandler,
+) -> Option<DBUserPreferences> {
+    let gather_result = execution_handler.select_user_preferences(user_id).await;
+    if let Ok(selected_rows) = gather_result {
+        if selected_rows.len() == 1 {
+            let preferences: String = selected_rows[0].get(0);
+            return Some(serde_json::from_str(&preferences).unwrap_or_default());
+        }
     }
     None
 }
 
+/// Time spent in each room per user, counting
+/// sessions that started at or after since(rfc3339).
+/// (encountered_error, durations)
+pub async fn get_room_session_durations(
+    since: &String,
+    execution_handler: &mut ExecutionHandler,
+) -> (bool, Vec<DBRoomSessionDuration>) {
+    match execution_handler.select_room_session_durations(since).await {
+        Ok(selected_rows) => (
+            false,
+            selected_rows
+                .iter()
+                .map(|row| DBRoomSessionDuration {
+                    user_id: row.get(0),
+                    room_id: row.get(1),
+                    sessions: row.get(2),
+                    total_seconds: row.get(3),
+                })
+                .collect(),
+        ),
+        Err(_) => (true, Vec::new()),
+    }
+}
+
+/// A failed lookup grants nothing.
+pub async fn get_user_is_admin(user_id: &i32, execution_handler: &mut dyn DataStore) -> bool {
+    execution_handler.user_is_admin(user_id).await
+}
+
+/// A failed lookup notifies nobody.
+pub async fn get_user_notifies_followers_of_rooms(
+    user_id: &i32,
+    execution_handler: &mut ExecutionHandler,
+) -> bool {
+    match get_user_preferences(user_id, execution_handler).await {
+        Some(preferences) => preferences.notify_followers_of_rooms,
+        None => false,
+    }
+}
+
+/// Most users a single search can return.
+pub const MAX_USER_SEARCH_RESULTS: i64 = 25;
+const DEFAULT_USER_SEARCH_RESULTS: i64 = 10;
+
+/// Case-insensitive prefix/substring search over display
+/// names and usernames, banned users are left out. Prefix
+/// matches come first, the (unindexed) substring search only
+/// runs when there aren't enough of them to fill the limit.
+pub async fn search_users(
+    query: &String,
+    limit: Option<i64>,
+    execution_handler: &mut ExecutionHandler,
+) -> (bool, Vec<UserSearchResult>) {
+    let limit = limit
+        .unwrap_or(DEFAULT_USER_SEARCH_RESULTS)
+        .clamp(1, MAX_USER_SEARCH_RESULTS);
+    let (prefix_pattern, substring_pattern) = user_search_patterns(query);
+    let mut results = match execution_handler
+        .search_users_by_prefix(&prefix_pattern, &limit)
+        .await
+    {
+        Ok(selected_rows) => user_search_results(selected_rows),
+        Err(_) => return (true, Vec::new()),
+    };
+    let remaining = limit - results.len() as i64;
+    if remaining == 0 {
+        return (false, results);
+    }
+    let found_ids: Vec<i32> = results.iter().map(|result| result.user_id).collect();
+    match execution_handler
+        .search_users_by_substring(&substring_pattern, &found_ids, &remaining)
+        .await
+    {
+        Ok(selected_rows) => {
+            results.extend(user_search_results(selected_rows));
+            (false, results)
+        }
+        Err(_) => (true, results),
+    }
+}
+
+fn user_search_results(selected_rows: Vec<Row>) -> Vec<UserSearchResult> {
+    selected_rows
+        .iter()
+        .map(|row| UserSearchResult {
+            user_id: row.get(0),
+            preview: UserPreview {
+                display_name: row.get(1),
+                avatar_url: row.get(2),
+            },
+        })
+        .collect()
+}
+
+/// The query is matched literally, so the LIKE
+/// wildcards users type are escaped.
+pub fn user_search_patterns(query: &String) -> (String, String) {
+    let escaped = query
+        .trim()
+        .to_lowercase()
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    (format!("{}%", escaped), format!("%{}%", escaped))
+}
+
 pub async fn get_user_previews_for_users(
     user_ids: Vec<i32>,
     execution_handler: &mut ExecutionHandler,
@@ -210,6 +322,34 @@ pub async fn get_user_previews_for_users(
     return (encountered_error, user_previews);
 }
 
+/// Oldest blocks first.
+/// (encountered_error, blocked_users)
+pub async fn get_room_blocked_users(
+    room_id: &i32,
+    execution_handler: &mut ExecutionHandler,
+) -> (bool, Vec<RoomBlockedUser>) {
+    match execution_handler
+        .select_room_blocks_with_previews(room_id)
+        .await
+    {
+        Ok(selected_rows) => (
+            false,
+            selected_rows
+                .iter()
+                .map(|row| RoomBlockedUser {
+                    block_id: row.get(0),
+                    user_id: row.get(1),
+                    preview: UserPreview {
+                        display_name: row.get(2),
+                        avatar_url: row.get(3),
+                    },
+                })
+                .collect(),
+        ),
+        Err(_) => (true, Vec::new()),
+    }
+}
+
 pub async fn get_scheduled_rooms(
     room_ids: Vec<i32>,
     execution_handler: &mut ExecutionHandler,
@@ -234,62 +374,213 @@ pub async fn get_scheduled_rooms(
 }
 
 pub async fn get_blocked_user_ids_for_user(
-    execution_handler: &mut ExecutionHandler,
+    execution_handler: &mut dyn DataStore,
     user_id: &i32,
 ) -> (bool, HashSet<i32>) {
-    let future_for_execution = execution_handler.select_all_blocked_for_user(user_id);
-    let blocked_users_result: (bool, HashSet<i32>) =
-        get_single_column_of_all_rows_by_id(2, future_for_execution).await;
-    return blocked_users_result;
+    execution_handler.blocked_user_ids_for_user(user_id).await
 }
 
 pub async fn get_following_user_ids_for_user(
-    execution_handler: &mut ExecutionHandler,
+    execution_handler: &mut dyn DataStore,
     user_id: &i32,
 ) -> (bool, HashSet<i32>) {
-    let future_for_execution = execution_handler.select_all_following_for_user(user_id);
-    let following_users_result: (bool, HashSet<i32>) =
-        get_single_column_of_all_rows_by_id(2, future_for_execution).await;
-    return following_users_result;
+    execution_handler.following_user_ids_for_user(user_id).await
 }
 
 pub async fn get_follower_user_ids_for_user(
-    execution_handler: &mut ExecutionHandler,
+    execution_handler: &mut dyn DataStore,
     user_id: &i32,
 ) -> (bool, HashSet<i32>) {
-    let future_for_execution = execution_handler.select_all_followers_for_user(user_id);
-    let followers_users_result: (bool, HashSet<i32>) =
-        get_single_column_of_all_rows_by_id(2, future_for_execution).await;
-    return followers_users_result;
+    execution_handler.follower_user_ids_for_user(user_id).await
+}
+
+/// A failed lookup counts as not following.
+pub async fn user_follows(
+    execution_handler: &mut dyn DataStore,
+    follower_id: &i32,
+    user_id: &i32,
+) -> bool {
+    execution_handler.user_follows(follower_id, user_id).await
+}
+
+/// Most users a single get_follow_statuses can ask about,
+/// a full room's worth.
+pub const MAX_FOLLOW_STATUS_USERS: usize = 500;
+
+/// Every requested user gets an entry, users with no follows
+/// either way are all false.
+/// (encountered_error, user_id -> status)
+pub async fn get_follow_statuses(
+    execution_handler: &mut ExecutionHandler,
+    requester_id: &i32,
+    user_ids: &[i32],
+) -> (bool, HashMap<i32, FollowStatus>) {
+    match execution_handler
+        .select_follows_between_user_and_users(requester_id, user_ids)
+        .await
+    {
+        Ok(selected_rows) => {
+            let follows: Vec<(i32, i32)> = selected_rows
+                .iter()
+                .map(|row| (row.get(0), row.get(1)))
+                .collect();
+            (
+                false,
+                follow_statuses_from_follows(requester_id, user_ids, &follows),
+            )
+        }
+        Err(_) => (true, HashMap::new()),
+    }
+}
+
+/// follows are (follower id, user id) pairs.
+pub fn follow_statuses_from_follows(
+    requester_id: &i32,
+    user_ids: &[i32],
+    follows: &[(i32, i32)],
+) -> HashMap<i32, FollowStatus> {
+    let mut statuses: HashMap<i32, FollowStatus> = user_ids
+        .iter()
+        .map(|user_id| (*user_id, FollowStatus::default()))
+        .collect();
+    for (follower_id, user_id) in follows {
+        if follower_id == requester_id {
+            if let Some(status) = statuses.get_mut(user_id) {
+                status.you_are_following = true;
+            }
+        }
+        if user_id == requester_id {
+            if let Some(status) = statuses.get_mut(follower_id) {
+                status.follows_you = true;
+            }
+        }
+    }
+    statuses
+}
+
+/// Most entries a single follow list page can hold.
+pub const MAX_FOLLOW_PAGE_SIZE: i64 = 200;
+const DEFAULT_FOLLOW_PAGE_SIZE: i64 = 50;
+
+/// One page of a follow list ordered by follow id, the
+/// returned cursor is `None` once the list is exhausted.
+/// (encountered_error, user_ids, next_cursor)
+pub async fn get_follow_list_page(
+    execution_handler: &mut ExecutionHandler,
+    user_id: &i32,
+    followers: bool,
+    cursor: Option<i32>,
+    limit: Option<i64>,
+) -> (bool, Vec<i32>, Option<i32>) {
+    let limit = limit
+        .unwrap_or(DEFAULT_FOLLOW_PAGE_SIZE)
+        .clamp(1, MAX_FOLLOW_PAGE_SIZE);
+    let cursor = cursor.unwrap_or(0);
+    // one extra row tells us whether another page exists
+    let fetch_limit = limit + 1;
+    let (gather_result, user_id_column) = if followers {
+        (
+            execution_handler
+                .select_followers_page_for_user(user_id, &cursor, &fetch_limit)
+                .await,
+            1,
+        )
+    } else {
+        (
+            execution_handler
+                .select_following_page_for_user(user_id, &cursor, &fetch_limit)
+                .await,
+            2,
+        )
+    };
+    match gather_result {
+        Ok(mut selected_rows) => {
+            let has_more = selected_rows.len() as i64 > limit;
+            selected_rows.truncate(limit as usize);
+            let next_cursor = if has_more {
+                selected_rows.last().map(|row| row.get(0))
+            } else {
+                None
+            };
+            let user_ids = selected_rows
+                .iter()
+                .map(|row| row.get(user_id_column))
+                .collect();
+            (false, user_ids, next_cursor)
+        }
+        Err(_) => (true, Vec::new(), None),
+    }
 }
 
 pub async fn get_blocked_user_ids_for_room(
+    execution_handler: &mut dyn DataStore,
+    room_id: &i32,
+) -> (bool, HashSet<i32>) {
+    execution_handler.blocked_user_ids_for_room(room_id).await
+}
+
+/// (room id, visited at) of the user's latest
+/// visits, most recent first.
+pub async fn get_recent_room_visits(
+    execution_handler: &mut ExecutionHandler,
+    user_id: &i32,
+) -> (bool, Vec<(i32, String)>) {
+    match execution_handler
+        .select_recent_room_visits_for_user(user_id, &data_capturer::MAX_RECENT_ROOMS)
+        .await
+    {
+        Ok(rows) => (
+            false,
+            rows.iter().map(|row| (row.get(0), row.get(1))).collect(),
+        ),
+        Err(_) => (true, Vec::new()),
+    }
+}
+
+/// How much of a room's moderation log is handed out.
+pub const MAX_MODERATION_LOG_ENTRIES: i64 = 100;
+
+/// The room's latest moderation actions, most recent first.
+pub async fn get_room_moderation_log(
     execution_handler: &mut ExecutionHandler,
     room_id: &i32,
+) -> (bool, Vec<ModerationLogEntry>) {
+    match execution_handler
+        .select_moderation_log_for_room(room_id, &MAX_MODERATION_LOG_ENTRIES)
+        .await
+    {
+        Ok(rows) => (
+            false,
+            rows.iter()
+                .map(|row| ModerationLogEntry {
+                    actor_id: row.get(0),
+                    target_id: row.get(1),
+                    action: row.get(2),
+                    reason: row.get(3),
+                    happened_at: row.get(4),
+                })
+                .collect(),
+        ),
+        Err(_) => (true, Vec::new()),
+    }
+}
+
+/// Every room tagged with the tag, active or not.
+pub async fn get_room_ids_by_tag(
+    execution_handler: &mut ExecutionHandler,
+    tag: &String,
 ) -> (bool, HashSet<i32>) {
-    let future_for_execution = execution_handler.select_all_blocked_users_for_room(room_id);
-    let blocked_users_result: (bool, HashSet<i32>) =
-        get_single_column_of_all_rows_by_id(2, future_for_execution).await;
-    return blocked_users_result;
+    match execution_handler.select_room_ids_by_tag(tag).await {
+        Ok(rows) => (false, rows.iter().map(|row| row.get(0)).collect()),
+        Err(_) => (true, HashSet::new()),
+    }
 }
 
 pub async fn get_room_owner_and_settings(
-    execution_handler: &mut ExecutionHandler,
+    execution_handler: &mut dyn DataStore,
     room_id: &i32,
 ) -> (bool, i32, String) {
-    let gather_result = execution_handler.select_room_by_id(room_id).await;
-    if gather_result.is_ok() {
-        let selected_rows = gather_result.unwrap();
-        if selected_rows.len() == 1 {
-            let owner_id: i32 = selected_rows[0].get(1);
-            let chat_mode: String = selected_rows[0].get(2);
-            return (false, owner_id, chat_mode);
-        } else {
-            return (true, -1 as i32, "".to_owned());
-        }
-    } else {
-        return (true, -2 as i32, "".to_owned());
-    }
+    execution_handler.room_owner_and_settings(room_id).await
 }
 
 fn construct_user(
@@ -326,6 +617,7 @@ fn construct_user(
         avatar_url: avatar_url,
         banner_url: banner_url,
         i_blocked_them: blocked_by_requesting_user,
+        connection_state: ConnectionState::Connected,
     };
 }
 
@@ -341,43 +633,6 @@ fn check_user_result_and_handle_error(
     constructed_users_state.push(user_result.1);
 }
 
-/*
-1.executes future to get rows(the method passed in the `select_future` parameter)
-2.selects a specifc column of the rows based on col_index
-3.collects all of the columns' data and stores in a hashset
-4.returns error status and hashset.
-
-TODO: Simplify this function, it shouldn't require any long comments
-and should self document.
-*/
-pub async fn get_single_column_of_all_rows_by_id<
-    ColumnDataType: std::fmt::Debug
-        + std::cmp::PartialEq
-        + for<'a> tokio_postgres::types::FromSql<'a>
-        + std::cmp::Eq
-        + Hash,
->(
-    col_index: usize,
-    select_future: impl Future<Output = Result<Vec<Row>, Error>>,
-) -> (bool, HashSet<ColumnDataType>) {
-    let mut data_set: HashSet<ColumnDataType> = HashSet::new();
-    let mut encountered_error: bool = false;
-    //execute select method and gather our rows
-    let gather_result = select_future.await;
-    if gather_result.is_ok() {
-        //go through the rows and get the wanted column
-        //by using the col index
-        let selected_rows = gather_result.unwrap();
-        for row in selected_rows {
-            let column_data: ColumnDataType = row.get(col_index);
-            data_set.insert(column_data);
-        }
-    } else {
-        encountered_error = true;
-    }
-    return (encountered_error, data_set);
-}
-
 fn construct_scheduled_room(row: &Row) -> DBScheduledRoom {
     let room_id: i32 = row.get(0);
     let room_name: String = row.get(1);
@@ -1,23 +1,36 @@
-use crate::common::response_logic::send_to_requester_channel;
+use crate::common::response_logic::{
+    self, requester_response, send_error_to_requester, send_to_requester_channel,
+};
 use crate::communication::data_fetcher;
 use crate::communication::helpers;
+use crate::communication::op_codes::VoiceServerOp;
 use crate::communication::types::{
-    AllUsersInRoomResponse, BasicRequest, BasicRoomCreation, BlockUserFromRoom, CommunicationRoom,
-    GenericRoomId, GenericRoomIdAndPeerId, GetFollowList, UnblockUserFromRoom, User, UserPreview,
+    AllUsersInRoomResponse, AlreadyInRoom, Announcement, BasicRequest, BasicRoomCreation,
+    BeginDrain, BlockUserFromRoom, CommunicationRoom, DenySpeakerRequest, ForceMuteUser,
+    GenericRoomId, GenericRoomIdAndPeerId, GetFollowList, GetFollowStatuses, ResponseStatus,
+    RoomBlockedUsers, RoomModerationLog, UnblockUserFromRoom, User, UserPreferencesEdit,
+    UserPreview,
 };
-use crate::data_store::db_models::{DBFollower, DBUserBlock};
+use crate::data_store::db_models::{DBFollower, DBUserBlock, DBUserPreferences, ModerationAction};
+use crate::data_store::pool::ExecutionHandlerPool;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::data_store::store::DataStore;
 use crate::integration::types::DisconnectMsg;
 use crate::integration::types::GeneralMessage;
 use crate::integration::types::HOIActionDataIncoming;
 use crate::integration::types::HOIActionDataOutgoing;
 use crate::integration::types::HouseOfIoTCredentials;
 use crate::logging;
-use crate::rabbitmq::rabbit;
-use crate::rooms::handler::EncounteredError;
+use crate::rabbitmq::rabbit::{self, PublishChannel};
+use crate::rabbitmq::signaling::VoiceSignaling;
+use crate::rooms::chat_modes::{self, ChatAccess};
+use crate::rooms::confirmations;
+use crate::rooms::handler::{EncounteredError, RoomCreation, RoomCreationRequest};
+use crate::rooms::roles::{self, RequestError, Role};
 use crate::state::state::ServerState;
-use crate::state::types::Room;
-use crate::{rooms, ws_fan};
+use crate::state::types::{PeerSender, Room};
+use crate::state::{announcements, draining, idempotency, session, stats};
+use crate::{auth, board, rooms, ws_fan};
 use futures::lock::Mutex;
 use serde_json::Result;
 use serde_json::Value;
@@ -25,25 +38,35 @@ use std::collections::{HashMap, HashSet};
 use std::mem::drop;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::time::Duration;
+use warp::ws::Message;
 
-use super::data_capturer::{self, CaptureResult};
+use super::data_capturer::{self, CaptureResult, ProfileEditProblem};
 use super::types::BlockedFromRoom;
+use super::types::BoardPermissionModification;
 use super::types::ExistingIotServer;
+use super::types::GenericExternalId;
+use super::types::GenericUsername;
 use super::types::GiveOrRevokeIot;
 use super::types::InitRoomData;
 use super::types::JoinTypeInfo;
 use super::types::LooseUserPreviewRequest;
+use super::types::MalformedPayload;
 use super::types::NewIoTController;
 use super::types::NewModStatus;
 use super::types::RelationModification;
 use super::types::RemovedIoTController;
-use super::types::RoomDetails;
 use super::types::SingleUserDataResults;
 use super::types::SingleUserPermissionResults;
 use super::types::UserProfileEdit;
+use super::types::UserProfileUpdate;
+use super::types::UserSearch;
+use super::types::{BanUser, FollowRelationship, GetUserProfile, UserProfile};
 use super::types::{
     BasicResponse, DeafAndMuteStatus, DeafAndMuteStatusUpdate, GenericUserId, RoomUpdate,
 };
+use super::types::{BoardCommandHistory, BoardCommandSent, IoTCommand, IoTCommandOutgoing};
+use super::types::{RecentRoom, RoomSearch, RoomTagSearch};
 
 /*
 Handles all functionality that has to be carried out by communication and
@@ -60,183 +83,368 @@ aren't included in the core logic of different modules.
 pub async fn create_room(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
 ) -> Result<()> {
-    let request_data: BasicRoomCreation = serde_json::from_str(&request.request_containing_data)?;
+    let mut request_data: BasicRoomCreation =
+        serde_json::from_str(&request.request_containing_data)?;
     let mut write_state = server_state.write().await;
-    //Make sure the user exist and they aren't in a room
-    if let Some(user) = write_state.active_users.get(&requester_id) {
-        if user.current_room_id == -1 {
-            rooms::handler::create_room(
+    if let Some(problem) =
+        data_capturer::room_details_problem(&request_data.name, &request_data.desc)
+    {
+        send_error_to_requester(
+            problem,
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    if !helpers::filter_room_details(&mut request_data.name, &mut request_data.desc) {
+        send_room_details_rejected(requester_id, &mut write_state);
+        return Ok(());
+    }
+    let chat_mode = match chat_modes::chat_mode_from_request(request_data.chat_mode.as_deref()) {
+        Ok(chat_mode) => chat_mode,
+        Err(problem) => {
+            send_error_to_requester(
+                problem,
+                requester_id,
                 &mut write_state,
-                publish_channel,
-                execution_handler,
+                "invalid_request".to_owned(),
+            );
+            return Ok(());
+        }
+    };
+    let tags = match rooms::tags::tags_from_request(&request_data.tags) {
+        Ok(tags) => tags,
+        Err(problem) => {
+            send_error_to_requester(
+                problem,
                 requester_id,
-                request_data.name,
-                request_data.desc,
-                request_data.public,
-            )
-            .await;
+                &mut write_state,
+                "invalid_request".to_owned(),
+            );
+            return Ok(());
+        }
+    };
+    if let Some(key) = &request_data.idempotency_key {
+        if key.is_empty() || key.len() > idempotency::MAX_IDEMPOTENCY_KEY_LENGTH {
+            send_error_to_requester(
+                format!(
+                    "idempotency key must be 1-{} characters",
+                    idempotency::MAX_IDEMPOTENCY_KEY_LENGTH
+                ),
+                requester_id,
+                &mut write_state,
+                "invalid_request".to_owned(),
+            );
+            return Ok(());
+        }
+        //a retry, hand back what the first request made
+        if let Some(room_id) = idempotency::find_created_room(&write_state, requester_id, key) {
+            //the first request answers once the voice server does
+            if confirmations::is_awaiting(room_id) {
+                return Ok(());
+            }
+            if write_state.rooms.contains_key(&room_id) {
+                let mut handler = execution_handler.lock().await;
+                rooms::handler::send_room_created(
+                    &mut write_state,
+                    &room_id,
+                    requester_id,
+                    &mut handler,
+                )
+                .await;
+                return Ok(());
+            }
+        }
+    }
+    //Make sure the user exist and they aren't in a room, they
+    //can be gone already if they disconnected mid request
+    let current_room_id = match write_state.active_users.get(&requester_id) {
+        Some(user) => user.current_room_id,
+        None => {
+            send_error_to_requester(
+                "not authenticated".to_owned(),
+                requester_id,
+                &mut write_state,
+                "not_authenticated".to_owned(),
+            );
             return Ok(());
         }
+    };
+    if current_room_id == -1 {
+        let room_request = RoomCreationRequest {
+            requester_id,
+            name: request_data.name,
+            desc: request_data.desc,
+            public: request_data.public,
+            chat_mode,
+            tags,
+        };
+        let creation = rooms::handler::create_room(
+            &mut write_state,
+            publish_channel,
+            execution_handler,
+            room_request,
+        )
+        .await;
+        match creation {
+            RoomCreation::Failed => {}
+            RoomCreation::Created(room_id) => {
+                if let Some(key) = request_data.idempotency_key {
+                    idempotency::record_room_creation(&mut write_state, requester_id, key, room_id);
+                }
+            }
+            RoomCreation::AwaitingVoiceServer(room_id, confirmation) => {
+                //recorded before the lock is let go, so a retry
+                //while we wait can't create a second room
+                if let Some(key) = &request_data.idempotency_key {
+                    idempotency::record_room_creation(
+                        &mut write_state,
+                        requester_id,
+                        key.clone(),
+                        room_id,
+                    );
+                }
+                // the voice server's answer comes in
+                // through a consumer that needs the lock
+                drop(write_state);
+                let wait = confirmations::confirm_timeout().unwrap_or_default();
+                let confirmed =
+                    confirmations::wait_for_confirmation(room_id, confirmation, wait).await;
+                write_state = server_state.write().await;
+                if !confirmed || !write_state.rooms.contains_key(&room_id) {
+                    if let Some(key) = &request_data.idempotency_key {
+                        idempotency::forget_room_creation(&mut write_state, requester_id, key);
+                    }
+                    rooms::handler::roll_back_room_creation(
+                        &mut write_state,
+                        publish_channel,
+                        execution_handler,
+                        &room_id,
+                        requester_id,
+                    )
+                    .await;
+                    return Ok(());
+                }
+                let mut handler = execution_handler.lock().await;
+                rooms::handler::finish_room_creation(
+                    &mut write_state,
+                    &room_id,
+                    requester_id,
+                    &mut handler,
+                )
+                .await;
+            }
+        }
+        return Ok(());
     }
     // If the request is invalid
     send_error_response_to_requester(requester_id, &mut write_state);
     return Ok(());
 }
 
+/// Longest reason a ban or kick can carry into the moderation log.
+pub const MAX_MODERATION_REASON_LENGTH: usize = 100;
+
+/// A blank reason is the same as none, None when it's too long.
+pub fn moderation_reason(reason: Option<String>) -> Option<Option<String>> {
+    match reason.map(|reason| reason.trim().to_owned()) {
+        Some(reason) if reason.chars().count() > MAX_MODERATION_REASON_LENGTH => None,
+        Some(reason) if reason.is_empty() => Some(None),
+        reason => Some(reason),
+    }
+}
+
+fn refuse_moderation_reason(requester_id: i32, server_state: &mut ServerState) {
+    send_error_to_requester(
+        format!(
+            "reason can be at most {} characters",
+            MAX_MODERATION_REASON_LENGTH
+        ),
+        requester_id,
+        server_state,
+        "invalid_request".to_owned(),
+    );
+}
+
 pub async fn block_user_from_room(
     request: BasicRequest,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
 ) -> Result<()> {
     let request_data: BlockUserFromRoom = serde_json::from_str(&request.request_containing_data)?;
     let mut write_state = server_state.write().await;
-
-    // Make sure this room actually exists
-    if let Some(room) = write_state.rooms.get(&request_data.room_id) {
-        // Make sure both users are in the room
-        // The owner checking happens in the room handler
-        if room.user_ids.contains(&requester_id) && room.user_ids.contains(&request_data.user_id) {
-            rooms::handler::block_user_from_room(
-                request_data.user_id,
-                request_data.room_id,
-                requester_id,
-                &mut write_state,
-                execution_handler,
-                publish_channel,
-            )
-            .await;
+    let reason = match moderation_reason(request_data.reason) {
+        Some(reason) => reason,
+        None => {
+            refuse_moderation_reason(requester_id, &mut write_state);
             return Ok(());
         }
+    };
+
+    // Make sure both users are in the room
+    // The owner checking happens in the room handler
+    if write_state.user_in_room(requester_id, request_data.room_id)
+        && write_state.user_in_room(request_data.user_id, request_data.room_id)
+    {
+        rooms::handler::block_user_from_room(
+            request_data.user_id,
+            request_data.room_id,
+            requester_id,
+            reason,
+            &mut write_state,
+            execution_handler,
+            publish_channel,
+        )
+        .await;
+        return Ok(());
     }
     send_error_response_to_requester(requester_id, &mut write_state);
     return Ok(());
 }
 
-pub async fn unblock_user_from_room(
+pub async fn kick_user_from_room(
     request: BasicRequest,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
 ) -> Result<()> {
-    let request_data: UnblockUserFromRoom = serde_json::from_str(&request.request_containing_data)?;
-    let mut handler = execution_handler.lock().await;
-    if is_mod_or_owner(&request_data.room_id, &mut handler, &requester_id).await {
-        data_capturer::capture_room_block_removal(
-            &mut handler,
-            &request_data.room_id,
-            &request_data.user_id,
+    let request_data: BlockUserFromRoom = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    let reason = match moderation_reason(request_data.reason) {
+        Some(reason) => reason,
+        None => {
+            refuse_moderation_reason(requester_id, &mut write_state);
+            return Ok(());
+        }
+    };
+
+    // Make sure both users are in the room
+    // The permission checking happens in the room handler
+    if write_state.user_in_room(requester_id, request_data.room_id)
+        && write_state.user_in_room(request_data.user_id, request_data.room_id)
+    {
+        rooms::handler::kick_user_from_room(
+            request_data.user_id,
+            request_data.room_id,
+            requester_id,
+            reason,
+            &mut write_state,
+            execution_handler,
+            publish_channel,
         )
         .await;
+        return Ok(());
     }
-
-    Ok(())
+    send_error_response_to_requester(requester_id, &mut write_state);
+    return Ok(());
 }
 
-pub async fn join_room(
+pub async fn force_mute_or_unmute_user(
     request: BasicRequest,
-    server_state: &Arc<RwLock<ServerState>>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
     requester_id: i32,
-    type_of_join: &str,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
 ) -> Result<()> {
+    let request_data: ForceMuteUser = serde_json::from_str(&request.request_containing_data)?;
+    let mute = request.request_op_code == "force_mute_user";
     let mut write_state = server_state.write().await;
-    let request_data: GenericRoomIdAndPeerId =
-        serde_json::from_str(&request.request_containing_data)?;
-
-    let room_id: i32 = request_data.roomId;
-    let peer_id: i32 = request_data.peerId;
-    //Ensure the room exist,the user isn't already in a room and this room is public
-    if room_is_joinable(&write_state, &peer_id, &requester_id, &room_id) {
-        //make sure the user isn't blocked from the room
-        let mut handler = execution_handler.lock().await;
-        let blocked_result: (bool, HashSet<i32>) =
-            data_fetcher::get_blocked_user_ids_for_room(&mut handler, &room_id).await;
-        // Nothing went wrong gathering blocked user ids
-        // and user isn't blocked
-        if blocked_result.0 == false && !blocked_result.1.contains(&peer_id) {
-            drop(handler);
-            rooms::handler::join_room(
-                request_data,
-                &mut write_state,
-                publish_channel,
+    let mut handler = execution_handler.lock().await;
+    let result = rooms::handler::force_mute_user(
+        &mut write_state,
+        &mut handler,
+        publish_channel,
+        &request_data.room_id,
+        &requester_id,
+        &request_data.user_id,
+        mute,
+    )
+    .await;
+    drop(handler);
+    match result {
+        Ok(()) => {
+            let action = if mute {
+                ModerationAction::Mute
+            } else {
+                ModerationAction::Unmute
+            };
+            rooms::handler::log_moderation_action(
                 execution_handler,
                 requester_id,
-                type_of_join,
-            )
-            .await;
-            return Ok(());
+                request_data.user_id,
+                request_data.room_id,
+                action,
+                None,
+            );
         }
+        Err(RequestError::NotInRoom) => send_error_to_requester(
+            request_data.room_id.to_string(),
+            requester_id,
+            &mut write_state,
+            "not_in_room".to_owned(),
+        ),
+        Err(_) => send_error_response_to_requester(requester_id, &mut write_state),
     }
-    send_error_response_to_requester(requester_id, &mut write_state);
-    return Ok(());
+    Ok(())
 }
 
-pub async fn add_or_remove_speaker(
+pub async fn start_or_stop_recording(
     request: BasicRequest,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    add_or_remove: &str,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
 ) -> Result<()> {
+    let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
+    let start = request.request_op_code == "start_recording";
     let mut write_state = server_state.write().await;
-    //ensure request parsing is successful
-    let request_data: GenericRoomIdAndPeerId =
-        serde_json::from_str(&request.request_containing_data)?;
-    let room_id: i32 = request_data.roomId;
-    let peer_id: i32 = request_data.peerId;
-
-    // Make sure the room being requested exists
-    if let Some(room) = write_state.rooms.get(&room_id) {
-        // Make sure the requester and requestee is in the
-        // room that is being requested
-        if room.user_ids.contains(&requester_id) && room.user_ids.contains(&peer_id) {
-            if add_or_remove == "add" {
-                rooms::handler::add_speaker(
-                    request_data,
-                    publish_channel,
-                    &requester_id,
-                    &mut write_state,
-                    execution_handler,
-                )
-                .await;
-            } else {
-                rooms::handler::remove_speaker(
-                    request_data,
-                    publish_channel,
-                    &requester_id,
-                    &mut write_state,
-                    execution_handler,
-                )
-                .await;
-            }
-            return Ok(());
-        }
+    let problem = rooms::handler::start_or_stop_recording(
+        &mut write_state,
+        execution_handler,
+        publish_channel,
+        &request_data.room_id,
+        &requester_id,
+        start,
+    )
+    .await;
+    if let Some(problem) = problem {
+        send_error_to_requester(
+            problem.to_owned(),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
     }
-    send_error_response_to_requester(requester_id, &mut write_state);
-    return Ok(());
+    Ok(())
 }
 
-pub async fn handle_web_rtc_request(
+pub async fn destroy_room(
     request: BasicRequest,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
-    let request_data: serde_json::Value = serde_json::from_str(&request.request_containing_data)?;
+    let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
     let mut write_state = server_state.write().await;
-
-    if helpers::web_rtc_request_is_valid(&write_state, &request_data, &requester_id) {
-        rooms::handler::handle_web_rtc_specific_requests(
-            request_data,
-            publish_channel,
-            &request.request_op_code,
+    // The owner check happens in the room handler
+    if write_state.rooms.contains_key(&request_data.room_id) {
+        rooms::handler::destroy_room_for_owner(
+            &mut write_state,
+            request_data.room_id,
+            requester_id,
+            voice_publish_channel,
+            integration_publish_channel,
+            execution_handler,
         )
         .await;
         return Ok(());
@@ -245,83 +453,686 @@ pub async fn handle_web_rtc_request(
     return Ok(());
 }
 
-#[allow(unused_assignments)]
-pub async fn follow_or_unfollow_user(
+/// Mods and owners can lift a room block, the requester
+/// gets the unblocked user's id back.
+pub async fn unblock_user_from_room(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
-    let user_id_data: GenericUserId = serde_json::from_str(&request.request_containing_data)?;
-    let mut write_state = server_state.write().await;
+    let request_data: UnblockUserFromRoom = serde_json::from_str(&request.request_containing_data)?;
     let mut handler = execution_handler.lock().await;
-    let mut result: Option<CaptureResult> = None;
-    let mut response_op: Option<String> = None;
-    if request.request_op_code == "follow_user" {
-        let db_follower = DBFollower {
-            id: -1,
-            follower_id: requester_id,
-            user_id: user_id_data.user_id,
-        };
-        result = Some(data_capturer::capture_new_follower(&mut handler, &db_follower).await);
-        response_op = Some("user_follow_successful".to_owned());
-        //no errors
-    } else {
-        //unfollow
-        result = Some(
-            data_capturer::capture_follower_removal(
-                &mut handler,
-                &requester_id,
-                &user_id_data.user_id,
-            )
-            .await,
-        );
-        response_op = Some("user_unfollow_successful".to_owned());
-    }
-    //if we didn't get any error from capture execution(saving to db)
-    if result.is_some() && !result.unwrap().encountered_error {
+    let unblocked = roles::require_role(
+        &mut handler,
+        &request_data.room_id,
+        &requester_id,
+        Role::Mod,
+    )
+    .await
+    .is_ok()
+        && !data_capturer::capture_room_block_removal(
+            &mut handler,
+            &request_data.room_id,
+            &request_data.user_id,
+        )
+        .await
+        .encountered_error;
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    if unblocked {
         send_to_requester_channel(
-            user_id_data.user_id.to_string(),
+            request_data.user_id.to_string(),
             requester_id,
             &mut write_state,
-            response_op.unwrap().to_owned(),
+            "user_unblocked_from_room".to_owned(),
         );
-        logging::console::log_success(&format!(
-            "User({}) successfully followed/unfollowed user({})",
-            requester_id, user_id_data.user_id
-        ));
-        return Ok(());
+    } else {
+        send_error_response_to_requester(requester_id, &mut write_state);
     }
-    logging::console::log_failure(&format!("User({}) follow/unfollow failure", requester_id));
-    drop(handler);
-    send_error_response_to_requester(requester_id, &mut write_state);
-    return Ok(());
+    Ok(())
 }
 
-pub async fn get_followers_or_following_list(
+/// Mods and owners can review every block on a room,
+/// the room doesn't have to be the one they are in.
+pub async fn get_room_blocked_users(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    type_of_request: &str,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
-    //gather all
+    let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
     let mut handler = execution_handler.lock().await;
-    let target;
-    let request_data: GetFollowList = serde_json::from_str(&request.request_containing_data)?;
-
-    if type_of_request == "followers" {
-        //(encountered_error, user_ids)
-        target =
-            data_fetcher::get_follower_user_ids_for_user(&mut handler, &request_data.user_id).await;
+    let blocked_users = if roles::require_role(
+        &mut handler,
+        &request_data.room_id,
+        &requester_id,
+        Role::Mod,
+    )
+    .await
+    .is_ok()
+    {
+        data_fetcher::get_room_blocked_users(&request_data.room_id, &mut handler).await
     } else {
-        target = data_fetcher::get_following_user_ids_for_user(&mut handler, &request_data.user_id)
-            .await;
+        (true, Vec::new())
+    };
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    if blocked_users.0 {
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return Ok(());
     }
-    helpers::send_follow_list(
-        target,
-        server_state,
-        &mut handler,
+    let response = RoomBlockedUsers {
+        room_id: request_data.room_id,
+        blocked_users: blocked_users.1,
+    };
+    send_to_requester_channel(
+        serde_json::to_string(&response)?,
+        requester_id,
+        &mut write_state,
+        "room_blocked_users".to_owned(),
+    );
+    Ok(())
+}
+
+/// Owner only, the latest bans, kicks, mutes, mod changes
+/// and ownership transfers in the room.
+pub async fn get_room_moderation_log(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let log = if roles::require_role(
+        &mut handler,
+        &request_data.room_id,
+        &requester_id,
+        Role::Owner,
+    )
+    .await
+    .is_ok()
+    {
+        data_fetcher::get_room_moderation_log(&mut handler, &request_data.room_id).await
+    } else {
+        (true, Vec::new())
+    };
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    if log.0 {
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return Ok(());
+    }
+    let response = RoomModerationLog {
+        room_id: request_data.room_id,
+        entries: log.1,
+    };
+    send_to_requester_channel(
+        serde_json::to_string(&response)?,
+        requester_id,
+        &mut write_state,
+        "room_moderation_log".to_owned(),
+    );
+    Ok(())
+}
+
+pub async fn join_room(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    type_of_join: &str,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    let request_data: GenericRoomIdAndPeerId =
+        serde_json::from_str(&request.request_containing_data)?;
+
+    let room_id: i32 = request_data.room_id;
+    let peer_id: i32 = request_data.peer_id;
+    //Ensure the room exist,the user isn't already in a room and this room is public
+    match check_room_join(&write_state, &peer_id, &requester_id, &room_id) {
+        RoomJoinCheck::Joinable => {}
+        RoomJoinCheck::AlreadyInRoom(current_room_id) => {
+            send_already_in_room(requester_id, current_room_id, &mut write_state);
+            return Ok(());
+        }
+        RoomJoinCheck::NotJoinable => {
+            send_error_response_to_requester(requester_id, &mut write_state);
+            return Ok(());
+        }
+    }
+    //make sure the user isn't blocked from the room
+    let mut handler = execution_handler.lock().await;
+    let blocked_result: (bool, HashSet<i32>) =
+        data_fetcher::get_blocked_user_ids_for_room(&mut handler, &room_id).await;
+    // Nothing went wrong gathering blocked user ids
+    // and user isn't blocked
+    if blocked_result.0 == false && !blocked_result.1.contains(&peer_id) {
+        drop(handler);
+        rooms::handler::join_room(
+            request_data,
+            &mut write_state,
+            publish_channel,
+            execution_handler,
+            requester_id,
+            type_of_join,
+        )
+        .await;
+        return Ok(());
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    return Ok(());
+}
+
+pub async fn add_or_remove_speaker(
+    request: BasicRequest,
+    publish_channel: &dyn VoiceSignaling,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    add_or_remove: &str,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    //ensure request parsing is successful
+    let request_data: GenericRoomIdAndPeerId =
+        serde_json::from_str(&request.request_containing_data)?;
+    let room_id: i32 = request_data.room_id;
+    let peer_id: i32 = request_data.peer_id;
+
+    // Make sure the requester and requestee is in the
+    // room that is being requested
+    if write_state.user_in_room(requester_id, room_id) && write_state.user_in_room(peer_id, room_id)
+    {
+        if add_or_remove == "add" {
+            rooms::handler::add_speaker(
+                request_data,
+                publish_channel,
+                &requester_id,
+                &mut write_state,
+                execution_handler,
+            )
+            .await;
+        } else {
+            rooms::handler::remove_speaker(
+                request_data,
+                publish_channel,
+                &requester_id,
+                &mut write_state,
+                execution_handler,
+            )
+            .await;
+        }
+        return Ok(());
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    return Ok(());
+}
+
+/// peer_id is the user whose raised hand is approved.
+pub async fn approve_speaker_request(
+    request: BasicRequest,
+    publish_channel: &dyn VoiceSignaling,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    let request_data: GenericRoomIdAndPeerId =
+        serde_json::from_str(&request.request_containing_data)?;
+    let room_id: i32 = request_data.room_id;
+    if write_state.user_in_room(requester_id, room_id)
+        && write_state.user_in_room(request_data.peer_id, room_id)
+    {
+        rooms::handler::approve_speaker_request(
+            request_data,
+            publish_channel,
+            &requester_id,
+            &mut write_state,
+            execution_handler,
+        )
+        .await;
+        return Ok(());
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
+/// peer_id is the user whose raised hand is denied.
+pub async fn deny_speaker_request(
+    request: BasicRequest,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    let request_data: DenySpeakerRequest = serde_json::from_str(&request.request_containing_data)?;
+    let room_id: i32 = request_data.room_id;
+    if write_state.user_in_room(requester_id, room_id)
+        && write_state.user_in_room(request_data.peer_id, room_id)
+    {
+        rooms::handler::deny_speaker_request(
+            request_data,
+            &requester_id,
+            &mut write_state,
+            execution_handler,
+        )
+        .await;
+        return Ok(());
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
+pub async fn handle_web_rtc_request(
+    request: BasicRequest,
+    publish_channel: &dyn VoiceSignaling,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: serde_json::Value = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+
+    let op = request.request_op_code.parse::<VoiceServerOp>();
+    if let (true, Ok(op)) = (
+        helpers::web_rtc_request_is_valid(&write_state, &request_data, &requester_id),
+        op,
+    ) {
+        rooms::handler::handle_web_rtc_specific_requests(request_data, publish_channel, op).await;
+        return Ok(());
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    return Ok(());
+}
+
+#[allow(unused_assignments)]
+/// Admin only, bans are global and stored on the user. A banned
+/// user who is online is pulled out of their room and disconnected.
+pub async fn ban_or_unban_user(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: BanUser = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let admin_check = auth::admins::require_admin(&requester_id, &mut handler).await;
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    let ban = request.request_op_code == "ban_user";
+    let problem = if admin_check.is_err() {
+        Some("only admins can ban users")
+    } else if ban
+        && (request_data.banned_reason.is_empty() || request_data.banned_reason.len() > 50)
+    {
+        Some("ban reason must be 1-50 characters")
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        send_error_to_requester(
+            problem.to_owned(),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let banned_reason = if ban {
+        request_data.banned_reason
+    } else {
+        "not banned".to_owned()
+    };
+    let mut handler = execution_handler.lock().await;
+    let result = data_capturer::capture_ban_status_update(
+        ban,
+        banned_reason.clone(),
+        &request_data.user_id,
+        &mut handler,
+    )
+    .await;
+    drop(handler);
+    if result.encountered_error {
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return Ok(());
+    }
+    if ban {
+        rooms::handler::remove_banned_user(
+            &mut write_state,
+            &request_data.user_id,
+            banned_reason,
+            voice_publish_channel,
+            integration_publish_channel,
+            execution_handler,
+        )
+        .await;
+    }
+    let response_op = if ban { "user_banned" } else { "user_unbanned" };
+    send_to_requester_channel(
+        request_data.user_id.to_string(),
+        requester_id,
+        &mut write_state,
+        response_op.to_owned(),
+    );
+    logging::console::log_success(&format!(
+        "admin({}) {} user({})",
+        requester_id,
+        if ban { "banned" } else { "unbanned" },
+        request_data.user_id
+    ));
+    Ok(())
+}
+
+/// Deletes the requester's account for good, the request has
+/// to repeat their username as confirmation. Nothing is removed
+/// from the server until the database deletion went through.
+pub async fn delete_account(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: GenericUsername = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let confirmed = data_fetcher::get_user_id_by_username(&request_data.username, &mut handler)
+        .await
+        == Some(requester_id);
+    let result = if confirmed {
+        Some(data_capturer::capture_account_deletion(&mut handler, &requester_id).await)
+    } else {
+        None
+    };
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    match result {
+        None => send_error_to_requester(
+            "username doesn't match the account".to_owned(),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        ),
+        Some(result) if result.encountered_error => {
+            logging::console::log_failure(&format!(
+                "user({}) account deletion failed:{}",
+                requester_id, result.desc
+            ));
+            send_error_response_to_requester(requester_id, &mut write_state);
+        }
+        Some(_) => {
+            rooms::handler::remove_deleted_user(
+                &mut write_state,
+                &requester_id,
+                voice_publish_channel,
+                integration_publish_channel,
+                execution_handler,
+            )
+            .await;
+        }
+    }
+    Ok(())
+}
+
+/// Admin only, sends an `announcement` to every connected
+/// user. Each admin can announce once per cooldown.
+pub async fn broadcast_announcement(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: Announcement = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let admin_check = auth::admins::require_admin(&requester_id, &mut handler).await;
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    let problem = if admin_check.is_err() {
+        Some("only admins can send announcements".to_owned())
+    } else if request_data.message.trim().is_empty()
+        || request_data.message.chars().count() > announcements::MAX_ANNOUNCEMENT_LENGTH
+    {
+        Some(format!(
+            "announcements must be 1-{} characters",
+            announcements::MAX_ANNOUNCEMENT_LENGTH
+        ))
+    } else if !announcements::try_start_announcement(&mut write_state, requester_id) {
+        Some(format!(
+            "only one announcement every {} seconds",
+            announcements::ANNOUNCEMENT_COOLDOWN_SECONDS
+        ))
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        send_error_to_requester(
+            problem,
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let response = BasicResponse {
+        response_op_code: "announcement".to_owned(),
+        response_containing_data: serde_json::to_string(&request_data)?,
+    };
+    let delivered =
+        response_logic::broadcast_to_all_peers(serde_json::to_string(&response)?, &write_state);
+    logging::console::log_event(&format!(
+        "admin({}) sent a {:?} announcement to {} users: {}",
+        requester_id, request_data.severity, delivered, request_data.message
+    ));
+    Ok(())
+}
+
+/// Admins feature rooms at the top of get_top_rooms.
+pub async fn pin_or_unpin_room(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let admin_check = auth::admins::require_admin(&requester_id, &mut handler).await;
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    let pin = request.request_op_code == "pin_room";
+    let problem = if admin_check.is_err() {
+        Some("only admins can pin rooms")
+    } else if !rooms::handler::set_room_pinned(&mut write_state, &request_data.room_id, pin) {
+        Some("room doesn't exist")
+    } else {
+        None
+    };
+    if let Some(problem) = problem {
+        send_error_to_requester(
+            problem.to_owned(),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let (op_code, action) = if pin {
+        ("room_pinned", "pinned")
+    } else {
+        ("room_unpinned", "unpinned")
+    };
+    logging::console::log_event(&format!(
+        "admin({}) {} room({})",
+        requester_id, action, request_data.room_id
+    ));
+    send_to_requester_channel(
+        request_data.room_id.to_string(),
+        requester_id,
+        &mut write_state,
+        op_code.to_owned(),
+    );
+    Ok(())
+}
+
+/// Admins start a drain ahead of a deploy, new connections are
+/// refused and everyone still connected has until the deadline.
+pub async fn begin_drain(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: BeginDrain = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let admin_check = auth::admins::require_admin(&requester_id, &mut handler).await;
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    if admin_check.is_err() {
+        send_error_to_requester(
+            "only admins can drain the server".to_owned(),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let deadline = match request_data.deadline_seconds {
+        Some(seconds) => Duration::from_secs(seconds),
+        None => draining::drain_deadline(),
+    };
+    if draining::begin_drain(&mut write_state, deadline) {
+        logging::console::log_event(&format!(
+            "admin({}) started draining, {} connections get {:?}",
+            requester_id,
+            write_state.peer_map.len(),
+            deadline
+        ));
+    }
+    let status = draining::drain_status(&write_state);
+    send_to_requester_channel(
+        serde_json::to_string(&status)?,
+        requester_id,
+        &mut write_state,
+        "drain_started".to_owned(),
+    );
+    Ok(())
+}
+
+/// Following is idempotent, following someone twice or
+/// unfollowing someone you don't follow just hands back the
+/// current relationship. The target hears about new
+/// followers right away if they are online.
+pub async fn follow_or_unfollow_user(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let user_id_data: GenericUserId = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    if user_id_data.user_id == requester_id {
+        send_error_to_requester(
+            "you can't follow yourself".to_owned(),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let mut handler = execution_handler.lock().await;
+    let following =
+        data_fetcher::get_following_user_ids_for_user(&mut handler, &requester_id).await;
+    let already_following = following.1.contains(&user_id_data.user_id);
+    let follow = request.request_op_code == "follow_user";
+    let mut result: Option<CaptureResult> = None;
+    let response_op;
+    if follow {
+        if !already_following {
+            let db_follower = DBFollower {
+                id: -1,
+                follower_id: requester_id,
+                user_id: user_id_data.user_id,
+            };
+            result = Some(data_capturer::capture_new_follower(&mut handler, &db_follower).await);
+        }
+        response_op = "user_follow_successful".to_owned();
+    } else {
+        //unfollow
+        if already_following {
+            result = Some(
+                data_capturer::capture_follower_removal(
+                    &mut handler,
+                    &requester_id,
+                    &user_id_data.user_id,
+                )
+                .await,
+            );
+        }
+        response_op = "user_unfollow_successful".to_owned();
+    }
+    let followers = data_fetcher::get_follower_user_ids_for_user(&mut handler, &requester_id).await;
+    drop(handler);
+    //if we didn't get any error from capture execution(saving to db)
+    let encountered_error = match &result {
+        Some(result) => result.encountered_error,
+        None => false,
+    };
+    if !following.0 && !followers.0 && !encountered_error {
+        let relationship = FollowRelationship {
+            user_id: user_id_data.user_id,
+            you_are_following: follow,
+            follows_you: followers.1.contains(&user_id_data.user_id),
+        };
+        send_to_requester_channel(
+            serde_json::to_string(&relationship).unwrap(),
+            requester_id,
+            &mut write_state,
+            response_op,
+        );
+        if follow && result.is_some() {
+            send_to_requester_channel(
+                requester_id.to_string(),
+                user_id_data.user_id,
+                &mut write_state,
+                "new_follower".to_owned(),
+            );
+        }
+        logging::console::log_success(&format!(
+            "User({}) successfully followed/unfollowed user({})",
+            requester_id, user_id_data.user_id
+        ));
+        return Ok(());
+    }
+    logging::console::log_failure(&format!("User({}) follow/unfollow failure", requester_id));
+    send_error_response_to_requester(requester_id, &mut write_state);
+    return Ok(());
+}
+
+pub async fn get_followers_or_following_list(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    type_of_request: &str,
+) -> Result<()> {
+    //gather all
+    let mut handler = execution_handler.lock().await;
+    let request_data: GetFollowList = serde_json::from_str(&request.request_containing_data)?;
+    //(encountered_error, user_ids, next_cursor)
+    let target = data_fetcher::get_follow_list_page(
+        &mut handler,
+        &request_data.user_id,
+        type_of_request == "followers",
+        request_data.cursor,
+        request_data.limit,
+    )
+    .await;
+    helpers::send_follow_list(
+        target,
+        server_state,
+        &mut handler,
         requester_id,
         request_data.user_id,
     )
@@ -329,17 +1140,60 @@ pub async fn get_followers_or_following_list(
     return Ok(());
 }
 
+/// Whether the requester follows, and is followed by, each
+/// of the given users. Answered with a single selection so
+/// room UIs don't fetch a follow list per user.
+pub async fn get_follow_statuses(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: GetFollowStatuses = serde_json::from_str(&request.request_containing_data)?;
+    let mut user_ids = request_data.user_ids;
+    user_ids.sort_unstable();
+    user_ids.dedup();
+    if user_ids.len() > data_fetcher::MAX_FOLLOW_STATUS_USERS {
+        let mut write_state = server_state.write().await;
+        send_error_to_requester(
+            format!(
+                "at most {} users per request",
+                data_fetcher::MAX_FOLLOW_STATUS_USERS
+            ),
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let mut handler = execution_handler.lock().await;
+    let (encountered_error, statuses) =
+        data_fetcher::get_follow_statuses(&mut handler, &requester_id, &user_ids).await;
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    if encountered_error {
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return Ok(());
+    }
+    send_to_requester_channel(
+        serde_json::to_string(&statuses).unwrap(),
+        requester_id,
+        &mut write_state,
+        "follow_statuses".to_owned(),
+    );
+    Ok(())
+}
+
 // Currently top rooms are rooms with the most people.
 // In the future, top rooms will be user driven and
 // will need to be limited with pagination techniques.
 pub async fn get_top_rooms(
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     let mut write_state = server_state.write().await;
-    let mut all_rooms: Vec<&Room> = write_state.rooms.values().into_iter().collect();
-    all_rooms.sort_by_key(|room| room.amount_of_users);
+    let all_rooms: Vec<&Room> = rooms::handler::gather_top_public_rooms(&write_state);
     let mut handler = execution_handler.lock().await;
     let mut communication_rooms: Vec<CommunicationRoom> = Vec::new();
     for room in all_rooms {
@@ -365,27 +1219,188 @@ pub async fn get_top_rooms(
                 room,
                 &mut communication_rooms,
                 owner_data_and_chat_mode.1,
-                owner_data_and_chat_mode.2,
             );
         }
     }
-    //clean up old mutexes and send the response
+    //clean up old mutexes and send the response
+    drop(handler);
+    let response_containing_data = serde_json::to_string(&communication_rooms).unwrap();
+
+    send_to_requester_channel(
+        response_containing_data,
+        requester_id,
+        &mut write_state,
+        "top_rooms".to_owned(),
+    );
+}
+
+/// Most rooms a single search can return.
+const MAX_ROOM_SEARCH_RESULTS: usize = 25;
+
+/// Searches the active public rooms by name, scheduled
+/// rooms aren't searched since they have no visibility yet.
+/// Counts are gathered under a read lock, the write lock
+/// is only taken to hand the response to the requester.
+pub async fn get_server_stats(server_state: &Arc<RwLock<ServerState>>, requester_id: i32) {
+    let stats = stats::gather_server_stats(&*server_state.read().await);
+    let mut write_state = server_state.write().await;
+    send_to_requester_channel(
+        serde_json::to_string(&stats).unwrap(),
+        requester_id,
+        &mut write_state,
+        "server_stats".to_owned(),
+    );
+}
+
+pub async fn search_rooms(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let request_data: RoomSearch = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    let limit = request_data
+        .limit
+        .unwrap_or(MAX_ROOM_SEARCH_RESULTS)
+        .clamp(1, MAX_ROOM_SEARCH_RESULTS);
+    let room_ids = rooms::handler::search_public_rooms(&write_state, &request_data.query, limit);
+    let mut handler = execution_handler.lock().await;
+    let communication_rooms =
+        gather_listed_rooms(&write_state, room_ids, requester_id, &mut handler).await;
+    drop(handler);
+    send_to_requester_channel(
+        serde_json::to_string(&communication_rooms).unwrap(),
+        requester_id,
+        &mut write_state,
+        "search_rooms".to_owned(),
+    );
+    Ok(())
+}
+
+/// The active public rooms with the tag, busiest first.
+pub async fn get_rooms_by_tag(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let request_data: RoomTagSearch = serde_json::from_str(&request.request_containing_data)?;
+    let tag = rooms::tags::normalize_tag(&request_data.tag);
+    if let Some(problem) = rooms::tags::tag_problem(&tag) {
+        let mut write_state = server_state.write().await;
+        send_error_to_requester(
+            problem,
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    let limit = request_data
+        .limit
+        .unwrap_or(MAX_ROOM_SEARCH_RESULTS)
+        .clamp(1, MAX_ROOM_SEARCH_RESULTS);
+    let mut write_state = server_state.write().await;
+    let mut handler = execution_handler.lock().await;
+    let (encountered_error, tagged_room_ids) =
+        data_fetcher::get_room_ids_by_tag(&mut handler, &tag).await;
+    if encountered_error {
+        drop(handler);
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return Ok(());
+    }
+    let room_ids = rooms::handler::tagged_public_rooms(&write_state, &tagged_room_ids, limit);
+    let communication_rooms =
+        gather_listed_rooms(&write_state, room_ids, requester_id, &mut handler).await;
+    drop(handler);
+    send_to_requester_channel(
+        serde_json::to_string(&communication_rooms).unwrap(),
+        requester_id,
+        &mut write_state,
+        "rooms_by_tag".to_owned(),
+    );
+    Ok(())
+}
+
+/// The rooms the requester joined lately that are still active,
+/// each listed once with when they last joined it.
+pub async fn get_recent_rooms(
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let mut write_state = server_state.write().await;
+    let mut handler = execution_handler.lock().await;
+    let (encountered_error, visits) =
+        data_fetcher::get_recent_room_visits(&mut handler, &requester_id).await;
+    if encountered_error {
+        drop(handler);
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return;
+    }
+    let room_ids: Vec<i32> = visits.iter().map(|(room_id, _)| *room_id).collect();
+    let communication_rooms =
+        gather_listed_rooms(&write_state, room_ids, requester_id, &mut handler).await;
     drop(handler);
-    let response_containing_data = serde_json::to_string(&communication_rooms).unwrap();
-
+    let mut visited_at: HashMap<i32, String> = visits.into_iter().collect();
+    let recent_rooms: Vec<RecentRoom> = communication_rooms
+        .into_iter()
+        .map(|room| RecentRoom {
+            visited_at: visited_at.remove(&room.room_id).unwrap_or_default(),
+            room,
+        })
+        .collect();
     send_to_requester_channel(
-        response_containing_data,
+        serde_json::to_string(&recent_rooms).unwrap(),
         requester_id,
         &mut write_state,
-        "top_rooms".to_owned(),
+        "recent_rooms".to_owned(),
     );
 }
 
+/// Rooms as they're listed to users browsing, rooms the
+/// requester is blocked from or that we couldn't gather
+/// data for are left out.
+async fn gather_listed_rooms(
+    server_state: &ServerState,
+    room_ids: Vec<i32>,
+    requester_id: i32,
+    handler: &mut ExecutionHandler,
+) -> Vec<CommunicationRoom> {
+    let mut communication_rooms: Vec<CommunicationRoom> = Vec::new();
+    for room_id in room_ids {
+        let room = match server_state.rooms.get(&room_id) {
+            Some(room) => room,
+            None => continue,
+        };
+        let blocked_users = data_fetcher::get_blocked_user_ids_for_room(handler, &room_id).await;
+        if blocked_users.0 || blocked_users.1.contains(&requester_id) {
+            continue;
+        }
+        let all_room_user_ids: Vec<i32> = room.user_ids.iter().cloned().collect();
+        let previews: (EncounteredError, HashMap<i32, UserPreview>) =
+            data_fetcher::get_user_previews_for_users(all_room_user_ids, handler).await;
+        let owner_data_and_chat_mode: (bool, i32, String) =
+            data_fetcher::get_room_owner_and_settings(handler, &room_id).await;
+        if previews.0 || owner_data_and_chat_mode.0 {
+            continue;
+        }
+        helpers::construct_communication_room(
+            previews.1,
+            room,
+            &mut communication_rooms,
+            owner_data_and_chat_mode.1,
+        );
+    }
+    communication_rooms
+}
+
 pub async fn get_initial_room_data(
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
     request_data: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
     let mut write_state = server_state.write().await;
     let mut handler = execution_handler.lock().await;
@@ -401,16 +1416,11 @@ pub async fn get_initial_room_data(
             return Ok(());
         }
         let init_data = InitRoomData {
-            details: RoomDetails {
-                name: room.name.clone(),
-                chat_throttle: room.chat_throttle.clone(),
-                is_private: !room.public,
-                description: room.desc.clone(),
-            },
+            details: helpers::construct_room_details(room),
             creator_id: owner_data_and_chat_mode.1,
             auto_speaker_setting: room.auto_speaker.clone(),
             created_at: room.created_at.clone(),
-            chat_mode: owner_data_and_chat_mode.2,
+            chat_mode: room.chat_mode.as_str().to_owned(),
         };
         //clean up old mutexes and send the response
         drop(handler);
@@ -429,10 +1439,10 @@ pub async fn get_initial_room_data(
 
 pub async fn leave_room(
     request: BasicRequest,
-    voice_server_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    voice_server_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
 ) -> Result<()> {
     let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
@@ -461,55 +1471,83 @@ pub async fn raise_hand_or_lower_hand(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     type_of_hand_action: &str,
 ) -> Result<()> {
     let mut write_state = server_state.write().await;
     let request_data: GenericRoomIdAndPeerId =
         serde_json::from_str(&request.request_containing_data)?;
-    let room_id: i32 = request_data.roomId;
-    let peer_id: i32 = request_data.peerId;
+    let room_id: i32 = request_data.room_id;
+    let peer_id: i32 = request_data.peer_id;
 
     //you can only raise your own hand
     if type_of_hand_action == "raise" && requester_id != peer_id {
         return Ok(());
     }
 
-    //room exist
-    if let Some(room) = write_state.rooms.get(&room_id) {
-        //both users are in this room
-        if room.user_ids.contains(&requester_id) && room.user_ids.contains(&peer_id) {
-            if type_of_hand_action == "lower" {
-                rooms::handler::lower_hand(
-                    &mut write_state,
-                    &room_id,
-                    &peer_id,
-                    &requester_id,
-                    execution_handler,
-                )
-                .await;
-            } else {
-                rooms::handler::raise_hand(
-                    &mut write_state,
-                    &room_id,
-                    &requester_id,
-                    execution_handler,
-                )
-                .await;
-            }
-            return Ok(());
+    //both users are in this room
+    if write_state.user_in_room(requester_id, room_id) && write_state.user_in_room(peer_id, room_id)
+    {
+        if type_of_hand_action == "lower" {
+            rooms::handler::lower_hand(
+                &mut write_state,
+                &room_id,
+                &peer_id,
+                &requester_id,
+                execution_handler,
+            )
+            .await;
+        } else {
+            rooms::handler::raise_hand(
+                &mut write_state,
+                &room_id,
+                &requester_id,
+                execution_handler,
+            )
+            .await;
         }
+        return Ok(());
     }
     send_error_response_to_requester(requester_id, &mut write_state);
     return Ok(());
 }
 
+pub async fn lower_all_hands(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let request_data: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
+    let room_id = request_data.room_id;
+    let mut write_state = server_state.write().await;
+    if write_state.user_in_room(requester_id, room_id) {
+        let mut handler = execution_handler.lock().await;
+        let allowed = roles::require_role(&mut handler, &room_id, &requester_id, Role::Mod)
+            .await
+            .is_ok();
+        drop(handler);
+        if allowed {
+            rooms::handler::lower_all_hands(
+                &mut write_state,
+                &room_id,
+                &requester_id,
+                execution_handler,
+            )
+            .await;
+            return Ok(());
+        }
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
 #[allow(unused_assignments)]
 pub async fn block_or_unblock_user_from_user(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
     let mut write_state = server_state.write().await;
     let request_data: GenericUserId = serde_json::from_str(&request.request_containing_data)?;
@@ -563,13 +1601,16 @@ pub async fn gather_all_users_in_room(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
     let room_id_obj: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
     let room_id = room_id_obj.room_id;
     let mut write_state = server_state.write().await;
-    //if the room exist
-    if let Some(room) = write_state.rooms.get(&room_id) {
+    //only users inside of the room can see who else is in it
+    if let Some(room) = write_state
+        .get_room(room_id)
+        .filter(|_| write_state.user_in_room(requester_id, room_id))
+    {
         let all_room_user_ids: Vec<i32> = room
             .user_ids
             .iter()
@@ -577,9 +1618,12 @@ pub async fn gather_all_users_in_room(
             .cloned()
             .collect();
         let mut handler = execution_handler.lock().await;
-        let users: (bool, Vec<User>) =
+        let mut users: (bool, Vec<User>) =
             data_fetcher::get_users_for_user(requester_id.clone(), all_room_user_ids, &mut handler)
                 .await;
+        for user in users.1.iter_mut() {
+            user.connection_state = session::connection_state(&write_state, &user.user_id);
+        }
         //no error was encountered
         if users.0 == false {
             //generate response with all users and send
@@ -603,7 +1647,7 @@ pub async fn gather_all_users_in_room(
 
 pub async fn gather_single_user(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
 ) -> Result<()> {
@@ -633,21 +1677,105 @@ pub async fn gather_single_user(
     Ok(())
 }
 
+/// Resolves @mentions and profile links
+/// that only know the username.
+pub async fn get_user_by_username(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    let mut handler = execution_handler.lock().await;
+    let data_obj: GenericUsername = serde_json::from_str(&request.request_containing_data)?;
+    if let Some(user_id) =
+        data_fetcher::get_user_id_by_username(&data_obj.username, &mut handler).await
+    {
+        let mut users: (bool, Vec<User>) =
+            data_fetcher::get_users_for_user(requester_id.clone(), vec![user_id], &mut handler)
+                .await;
+        if users.0 == false && users.1.len() == 1 {
+            let response = SingleUserDataResults {
+                user_id: user_id,
+                data: users.1.remove(0),
+            };
+            send_to_requester_channel(
+                serde_json::to_string(&response).unwrap(),
+                requester_id,
+                &mut write_state,
+                "user_by_username".to_owned(),
+            );
+            return Ok(());
+        }
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
+/// Backs the profile page, the relationship fields
+/// are filled out from the requester's point of view.
+pub async fn get_user_profile(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    let mut handler = execution_handler.lock().await;
+    let data_obj: GetUserProfile = serde_json::from_str(&request.request_containing_data)?;
+    let user_id = data_obj.user_id;
+    let profile = match data_fetcher::get_user_ban_status(&user_id, &mut handler).await {
+        Some((true, _)) => Some(UserProfile {
+            user_id,
+            banned: true,
+            data: None,
+        }),
+        Some((false, _)) => {
+            let mut users: (bool, Vec<User>) =
+                data_fetcher::get_users_for_user(requester_id, vec![user_id], &mut handler).await;
+            if users.0 == false && users.1.len() == 1 {
+                Some(UserProfile {
+                    user_id,
+                    banned: false,
+                    data: Some(users.1.remove(0)),
+                })
+            } else {
+                None
+            }
+        }
+        None => None,
+    };
+    if let Some(profile) = profile {
+        send_to_requester_channel(
+            serde_json::to_string(&profile).unwrap(),
+            requester_id,
+            &mut write_state,
+            "user_profile".to_owned(),
+        );
+        return Ok(());
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
 pub async fn gather_single_user_permission(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
 ) -> Result<()> {
     let mut write_state = server_state.write().await;
     let mut handler = execution_handler.lock().await;
     let data_obj: GenericRoomIdAndPeerId = serde_json::from_str(&request.request_containing_data)?;
-    let permissions_res =
-        data_fetcher::get_single_user_permissions(&data_obj.roomId, &data_obj.peerId, &mut handler)
-            .await;
+    let permissions_res = data_fetcher::get_single_user_permissions(
+        &data_obj.room_id,
+        &data_obj.peer_id,
+        &mut handler,
+    )
+    .await;
     if let Some(permissions) = permissions_res {
         let response = SingleUserPermissionResults {
-            user_id: data_obj.peerId,
+            user_id: data_obj.peer_id,
             data: permissions,
         };
         let response_str = serde_json::to_string(&response).unwrap();
@@ -663,16 +1791,82 @@ pub async fn gather_single_user_permission(
     Ok(())
 }
 
+/// What UIs poll after role changes, cheaper
+/// than gathering everyone in the room.
+pub async fn get_my_room_permissions(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let data_obj: GenericRoomId = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    let mut handler = execution_handler.lock().await;
+    let permissions = rooms::handler::my_room_permissions(
+        &write_state,
+        &mut handler,
+        &data_obj.room_id,
+        &requester_id,
+    )
+    .await;
+    drop(handler);
+    match permissions {
+        Ok(permissions) => {
+            let response = SingleUserPermissionResults {
+                user_id: requester_id,
+                data: permissions,
+            };
+            send_to_requester_channel(
+                serde_json::to_string(&response).unwrap(),
+                requester_id,
+                &mut write_state,
+                "my_room_permissions".to_owned(),
+            );
+        }
+        Err(RequestError::NotInRoom) => send_error_to_requester(
+            data_obj.room_id.to_string(),
+            requester_id,
+            &mut write_state,
+            "not_in_room".to_owned(),
+        ),
+        Err(_) => send_error_response_to_requester(requester_id, &mut write_state),
+    }
+    Ok(())
+}
+
 pub async fn update_entire_user(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
 ) -> Result<()> {
     let mut write_state = server_state.write().await;
     let mut handler = execution_handler.lock().await;
     let data_obj: UserProfileEdit = serde_json::from_str(&request.request_containing_data)?;
-    let result = data_capturer::capture_user_update(&mut handler, &requester_id, data_obj).await;
+    if let Some(problem) = data_capturer::image_url_problem(&data_obj) {
+        send_error_to_requester(
+            problem,
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    if let Some(username) = &data_obj.username {
+        if data_capturer::username_taken_by_other_user(username, &requester_id, &mut handler).await
+        {
+            send_username_taken(username.clone(), requester_id, &mut write_state);
+            return Ok(());
+        }
+    }
+    let result =
+        match data_capturer::capture_user_update(&mut handler, &requester_id, data_obj).await {
+            Ok(result) => result,
+            Err(problem) => {
+                send_profile_edit_problem(problem, requester_id, &mut write_state);
+                return Ok(());
+            }
+        };
     if !result.encountered_error {
         // If this user exists and is in a room
         // we should notify the room that he is in
@@ -707,57 +1901,145 @@ pub async fn update_entire_user(
     Ok(())
 }
 
+/// Applies only the fields the user sent,
+/// rejecting the whole edit if any of them are invalid.
+pub async fn edit_profile(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let data_obj: UserProfileEdit = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    if let Some(problem) =
+        data_capturer::validate_profile_edit(&mut handler, &requester_id, &data_obj).await
+    {
+        drop(handler);
+        send_profile_edit_problem(problem, requester_id, &mut *server_state.write().await);
+        return Ok(());
+    }
+    let result =
+        match data_capturer::capture_profile_edit(&mut handler, &requester_id, &data_obj).await {
+            Ok(result) => result,
+            Err(problem) => {
+                drop(handler);
+                send_profile_edit_problem(problem, requester_id, &mut *server_state.write().await);
+                return Ok(());
+            }
+        };
+    let updated_user = if !result.encountered_error {
+        data_fetcher::gather_base_user(&mut handler, &requester_id).await
+    } else {
+        None
+    };
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    let updated_user = match updated_user {
+        Some(updated_user) => updated_user,
+        None => {
+            send_error_response_to_requester(requester_id, &mut write_state);
+            return Ok(());
+        }
+    };
+    // Only what shows up in previews matters to the room
+    if data_obj.display_name.is_some() || data_obj.avatar_url.is_some() {
+        let room_id = write_state.current_room_id(requester_id);
+        if room_id != -1 {
+            let response = BasicResponse {
+                response_op_code: "user_profile_updated".to_owned(),
+                response_containing_data: serde_json::to_string(&UserProfileUpdate {
+                    user_id: requester_id,
+                    display_name: updated_user.display_name.clone(),
+                    avatar_url: updated_user.avatar_url.clone(),
+                })
+                .unwrap(),
+            };
+            ws_fan::fan::broadcast_message_to_room_excluding_user(
+                serde_json::to_string(&response).unwrap(),
+                &mut write_state,
+                room_id,
+                requester_id,
+            )
+            .await;
+        }
+    }
+    send_to_requester_channel(
+        serde_json::to_string(&updated_user).unwrap(),
+        requester_id,
+        &mut write_state,
+        "profile_edited".to_owned(),
+    );
+    Ok(())
+}
+
 /// Gives mod to someone in the room
 pub async fn change_user_mod_status(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
 ) -> Result<()> {
-    let mut write_state = server_state.write().await;
-    let mut handler = execution_handler.lock().await;
     let data_obj: NewModStatus = serde_json::from_str(&request.request_containing_data)?;
-    if let Some(user) = write_state.active_users.get(&requester_id) {
-        let user_current_room = user.current_room_id.clone();
-        // We will need the server state again
-        // have to drop to comply with borrow rules
-        drop(user);
-        // Is this user even in a room?
-        if user_current_room != -1 {
-            let owner_and_settings =
-                data_fetcher::get_room_owner_and_settings(&mut handler, &user_current_room).await;
-            // 1. We haven't encountered an error gathering from db
-            // 2. Are we the owner of the room?
-            // Only the owner of the room can
-            // appoint mods
-            if owner_and_settings.1 == requester_id && !owner_and_settings.0 {
-                let result = data_capturer::mod_or_unmod_user_capture(
-                    data_obj.new_status,
-                    &user_current_room,
-                    &data_obj.user_id,
-                    &mut handler,
-                )
-                .await;
-                if !result.encountered_error {
-                    let basic_response = BasicResponse {
-                        response_op_code: type_of_mod_op(data_obj.new_status.clone()),
-                        response_containing_data: data_obj.user_id.to_string(),
-                    };
-                    println!("{:?}", basic_response);
-                    ws_fan::fan::broadcast_message_to_room(
-                        serde_json::to_string(&basic_response).unwrap(),
-                        &mut write_state,
-                        user_current_room,
-                    )
-                    .await;
-                    logging::console::log_success("Mod status changed");
-                } else {
-                    send_error_response_to_requester(requester_id, &mut write_state);
-                    logging::console::log_failure("Issue with changing mod status");
-                }
-            }
+    let mut write_state = server_state.write().await;
+    let user_current_room = write_state.current_room_id(requester_id);
+    // Is this user even in a room?
+    if user_current_room != -1 {
+        let result = rooms::handler::change_mod_status(
+            &mut write_state,
+            execution_handler,
+            &user_current_room,
+            requester_id,
+            data_obj.user_id,
+            data_obj.new_status,
+        )
+        .await;
+        if result.is_some() {
+            let basic_response = BasicResponse {
+                response_op_code: type_of_mod_op(data_obj.new_status.clone()),
+                response_containing_data: data_obj.user_id.to_string(),
+            };
+            ws_fan::fan::broadcast_message_to_room(
+                serde_json::to_string(&basic_response).unwrap(),
+                &mut write_state,
+                user_current_room,
+            )
+            .await;
+            logging::console::log_success("Mod status changed");
+        } else {
+            send_error_response_to_requester(requester_id, &mut write_state);
+            logging::console::log_failure("Issue with changing mod status");
+        }
+    }
+    Ok(())
+}
+
+/// Owner only, promotes or demotes a
+/// user in the owner's current room.
+pub async fn add_or_remove_mod(
+    request: BasicRequest,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    new_status: bool,
+) -> Result<()> {
+    let data_obj: GenericUserId = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    let user_current_room = write_state.current_room_id(requester_id);
+    if user_current_room != -1 {
+        let result = rooms::handler::change_mod_status(
+            &mut write_state,
+            execution_handler,
+            &user_current_room,
+            requester_id,
+            data_obj.user_id,
+            new_status,
+        )
+        .await;
+        if result.is_some() {
+            return Ok(());
         }
     }
+    send_error_response_to_requester(requester_id, &mut write_state);
     Ok(())
 }
 
@@ -765,16 +2047,28 @@ pub async fn change_room_metadata(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
-    let room_update: RoomUpdate = serde_json::from_str(&request.request_containing_data)?;
+    let mut room_update: RoomUpdate = serde_json::from_str(&request.request_containing_data)?;
     let mut write_state = server_state.write().await;
-    let user = write_state.active_users.get(&requester_id).unwrap();
-    let user_room_id = user.current_room_id.clone();
+    if let Some(problem) = data_capturer::room_update_problem(&room_update) {
+        send_error_to_requester(
+            problem,
+            requester_id,
+            &mut write_state,
+            "invalid_request".to_owned(),
+        );
+        return Ok(());
+    }
+    if !helpers::filter_room_details(&mut room_update.name, &mut room_update.description) {
+        send_room_details_rejected(requester_id, &mut write_state);
+        return Ok(());
+    }
+    let user_room_id = write_state.current_room_id(requester_id);
     //never go through with requests when the user isn't
     //in a room
     if user_room_id != -1 {
-        rooms::handler::update_room_meta_data(
+        rooms::handler::update_room(
             &mut write_state,
             &user_room_id,
             requester_id,
@@ -796,9 +2090,24 @@ pub async fn update_mute_and_deaf_status(
 ) -> Result<()> {
     let mute_and_deaf: DeafAndMuteStatus = serde_json::from_str(&request.request_containing_data)?;
     let mut write_state = server_state.write().await;
+    //only a mod can undo a force mute
+    let force_muted = write_state
+        .active_users
+        .get(&requester_id)
+        .and_then(|user| write_state.rooms.get(&user.current_room_id))
+        .map_or(false, |room| room.muted.contains(&requester_id));
     if let Some(user) = write_state.active_users.get_mut(&requester_id) {
         //you can only update your muted/deaf status if you aren't in a room
         if user.current_room_id != -1 {
+            if force_muted && !mute_and_deaf.muted {
+                send_to_requester_channel(
+                    requester_id.to_string(),
+                    requester_id,
+                    &mut write_state,
+                    "force_muted".to_owned(),
+                );
+                return Ok(());
+            }
             user.deaf = mute_and_deaf.deaf.clone();
             user.muted = mute_and_deaf.muted.clone();
             let user_room_id = user.current_room_id.clone();
@@ -836,19 +2145,48 @@ pub async fn gather_previews(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<()> {
+    let mut write_state = server_state.write().await;
+    let mut handler = execution_handler.lock().await;
+    let request_data: LooseUserPreviewRequest =
+        serde_json::from_str(&request.request_containing_data)?;
+    let result =
+        data_fetcher::get_user_previews_for_users(request_data.user_ids, &mut handler).await;
+    send_to_requester_channel(
+        serde_json::to_string(&result.1).unwrap(),
+        requester_id,
+        &mut write_state,
+        "user_previews".to_owned(),
+    );
+    Ok(())
+}
+
+/// Powers the "find people" screen.
+pub async fn search_users(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
-    let mut write_state = server_state.write().await;
+    let request_data: UserSearch = serde_json::from_str(&request.request_containing_data)?;
     let mut handler = execution_handler.lock().await;
-    let request_data: LooseUserPreviewRequest =
-        serde_json::from_str(&request.request_containing_data)?;
-    let result =
-        data_fetcher::get_user_previews_for_users(request_data.user_ids, &mut handler).await;
+    let result = if request_data.query.trim().is_empty() {
+        (true, Vec::new())
+    } else {
+        data_fetcher::search_users(&request_data.query, request_data.limit, &mut handler).await
+    };
+    drop(handler);
+    let mut write_state = server_state.write().await;
+    if result.0 {
+        send_error_response_to_requester(requester_id, &mut write_state);
+        return Ok(());
+    }
     send_to_requester_channel(
         serde_json::to_string(&result.1).unwrap(),
         requester_id,
         &mut write_state,
-        "user_previews".to_owned(),
+        "search_users".to_owned(),
     );
     Ok(())
 }
@@ -858,7 +2196,7 @@ pub async fn gather_previews(
 // own information.
 pub async fn gather_base_user(
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     server_state: &Arc<RwLock<ServerState>>,
 ) {
     let mut write_state = server_state.write().await;
@@ -872,13 +2210,64 @@ pub async fn gather_base_user(
     );
 }
 
+pub async fn get_preferences(
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let mut handler = execution_handler.lock().await;
+    let preferences = data_fetcher::get_user_preferences(&requester_id, &mut handler).await;
+    drop(handler);
+    send_preferences(preferences, requester_id, server_state).await;
+    Ok(())
+}
+
+/// Gives back every preference once the
+/// edit is applied, not only the changed ones.
+pub async fn set_preferences(
+    request: BasicRequest,
+    requester_id: i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    server_state: &Arc<RwLock<ServerState>>,
+) -> Result<()> {
+    let data_obj: UserPreferencesEdit = serde_json::from_str(&request.request_containing_data)?;
+    let mut handler = execution_handler.lock().await;
+    let result =
+        data_capturer::capture_preferences_edit(&mut handler, &requester_id, &data_obj).await;
+    let preferences = if !result.encountered_error {
+        data_fetcher::get_user_preferences(&requester_id, &mut handler).await
+    } else {
+        None
+    };
+    drop(handler);
+    send_preferences(preferences, requester_id, server_state).await;
+    Ok(())
+}
+
+async fn send_preferences(
+    preferences: Option<DBUserPreferences>,
+    requester_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+) {
+    let mut write_state = server_state.write().await;
+    match preferences {
+        Some(preferences) => send_to_requester_channel(
+            serde_json::to_string(&preferences).unwrap(),
+            requester_id,
+            &mut write_state,
+            "your_preferences".to_owned(),
+        ),
+        None => send_error_response_to_requester(requester_id, &mut write_state),
+    }
+}
+
 /// Needed to let the frontend know
 /// what type of successful join
 /// request options there are.
 pub async fn gather_type_of_room_join(
     request: BasicRequest,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     server_state: &Arc<RwLock<ServerState>>,
 ) -> Result<()> {
     let mut write_state = server_state.write().await;
@@ -910,22 +2299,76 @@ pub async fn gather_type_of_room_join(
     Ok(())
 }
 
+/// Why the requester can't chat, as (reason, response op),
+/// None when they can.
+pub async fn chat_refusal(
+    access: ChatAccess,
+    requester_id: &i32,
+    execution_handler: &mut dyn DataStore,
+) -> Option<(&'static str, &'static str)> {
+    match access {
+        ChatAccess::Open => None,
+        ChatAccess::Closed => Some(("chat is turned off in this room", "chat_disabled")),
+        ChatAccess::NeedsFollow(owner_id) => {
+            if data_fetcher::user_follows(execution_handler, requester_id, &owner_id).await {
+                None
+            } else {
+                Some((
+                    "only followers of the room owner can chat",
+                    "chat_followers_only",
+                ))
+            }
+        }
+    }
+}
+
 pub async fn send_chat_message(
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
     message: String,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
     //we need to inject the message with the
     //requester id so the frontend can know
     //who sent the message
     let mut message: Value = serde_json::from_str(&message)?;
     message["userId"] = Value::String(requester_id.to_string());
-    let new_message = serde_json::to_string(&message)?;
 
     //broadcast to the room
     let mut write_state = server_state.write().await;
     if let Some(user) = write_state.active_users.get_mut(&requester_id) {
         let user_room_id = user.current_room_id.clone();
+        let access = match write_state.get_room(user_room_id) {
+            Some(room) => chat_modes::chat_access(room, &requester_id),
+            None => ChatAccess::Open,
+        };
+        let refusal = if access == ChatAccess::Open {
+            None
+        } else {
+            let mut handler = execution_handler.lock().await;
+            chat_refusal(access, &requester_id, &mut handler).await
+        };
+        if let Some((reason, op_code)) = refusal {
+            send_error_to_requester(
+                reason.to_owned(),
+                requester_id,
+                &mut write_state,
+                op_code.to_owned(),
+            );
+            return Ok(());
+        }
+        let new_message = match helpers::filter_chat_message(&mut message) {
+            Some(()) => serde_json::to_string(&message)?,
+            None => {
+                send_error_to_requester(
+                    "message contains blocked words".to_owned(),
+                    requester_id,
+                    &mut write_state,
+                    "content_rejected".to_owned(),
+                );
+                return Ok(());
+            }
+        };
         if user_room_id != -1 {
             let basic_response = BasicResponse {
                 response_op_code: "new_chat_message".to_owned(),
@@ -945,22 +2388,118 @@ pub async fn send_chat_message(
     Ok(())
 }
 
-pub async fn normal_invalid_request(server_state: &Arc<RwLock<ServerState>>, requester_id: i32) {
+/// Lets the requester know we have no
+/// handler registered for what they sent.
+pub async fn unknown_op_code(
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    op_code: &String,
+) {
+    let mut state = server_state.write().await;
+    send_error_to_requester(
+        format!("unknown op code: {}", op_code),
+        requester_id,
+        &mut state,
+        "unknown_op_code".to_owned(),
+    );
+}
+
+pub async fn guest_not_allowed(
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    op_code: &str,
+) {
+    let mut state = server_state.write().await;
+    send_error_to_requester(
+        op_code.to_owned(),
+        requester_id,
+        &mut state,
+        "guest_not_allowed".to_owned(),
+    );
+}
+
+/// The handler was cancelled and won't answer, sent
+/// straight to the requester's channel so it doesn't
+/// wait on the state lock.
+pub fn request_timeout(
+    requester_txs: Vec<PeerSender>,
+    requester_id: i32,
+    op_code: &str,
+    request_id: Option<String>,
+    timeout_after: Duration,
+) {
+    logging::console::log_failure(&format!(
+        "{} request from user({}) took longer than {:?}",
+        op_code, requester_id, timeout_after
+    ));
+    let response = requester_response(
+        op_code.to_owned(),
+        "request_timeout".to_owned(),
+        ResponseStatus::Error,
+        request_id,
+    );
+    for tx in requester_txs {
+        tx.send(Message::text(response.clone())).unwrap_or_default();
+    }
+}
+
+/// The request was dropped without being parsed.
+pub async fn payload_too_large(
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    size: usize,
+    max_bytes: usize,
+) {
+    logging::console::log_failure(&format!(
+        "user({}) sent a {} byte request",
+        requester_id, size
+    ));
+    let mut state = server_state.write().await;
+    send_error_to_requester(
+        format!("requests can be at most {} bytes", max_bytes),
+        requester_id,
+        &mut state,
+        "payload_too_large".to_owned(),
+    );
+}
+
+/// Tells the requester their payload couldn't be parsed,
+/// serde's message names the field that failed.
+pub async fn malformed_payload(
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+    op_code: &str,
+    error: &serde_json::Error,
+) {
+    logging::console::log_failure(&format!(
+        "user({}) sent a malformed {} payload: {}",
+        requester_id, op_code, error
+    ));
     let mut state = server_state.write().await;
-    send_error_response_to_requester(requester_id, &mut state);
+    send_error_to_requester(
+        serde_json::to_string(&MalformedPayload {
+            op_code: op_code.to_owned(),
+            reason: error.to_string(),
+        })
+        .unwrap(),
+        requester_id,
+        &mut state,
+        "malformed_payload".to_owned(),
+    );
 }
 
 pub async fn get_room_permissions_for_users(
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     let mut write_state = server_state.write().await;
     let mut handler = execution_handler.lock().await;
     if let Some(user) = write_state.active_users.get(&requester_id) {
-        let result =
-            data_fetcher::get_room_permissions_for_users(&user.current_room_id, &mut handler).await;
+        let room_id = user.current_room_id;
+        let mut result = data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
         drop(handler);
+        rooms::handler::add_guest_listeners(&write_state, &room_id, &mut result.1);
         send_to_requester_channel(
             serde_json::to_string(&result.1).unwrap(),
             requester_id,
@@ -972,7 +2511,7 @@ pub async fn get_room_permissions_for_users(
 
 pub async fn give_owner(
     request: BasicRequest,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
 ) -> Result<()> {
@@ -981,23 +2520,35 @@ pub async fn give_owner(
     let mut write_state = server_state.write().await;
     let mut handler = execution_handler.lock().await;
     // Is this user in this room to even give them ownership?
-    if let Some(room) = write_state.rooms.get(&request_data.roomId) {
-        if room.user_ids.contains(&request_data.peerId) {
-            let is_owner = rooms::handler::user_is_owner_of_room(
-                requester_id,
+    if let Some(room) = write_state.rooms.get(&request_data.room_id) {
+        if room.user_ids.contains(&request_data.peer_id) {
+            // Is the requester the owner? only the owner can reassign ownership
+            let is_owner = roles::require_role(
                 &mut handler,
-                &request_data.roomId,
+                &request_data.room_id,
+                &requester_id,
+                Role::Owner,
             )
-            .await;
-            // Is the requester the owner? only the owner can reassign ownership
+            .await
+            .is_ok();
             if is_owner {
-                rooms::handler::update_room_owner(
+                let updated = rooms::handler::update_room_owner(
                     &mut write_state,
                     &mut handler,
-                    &request_data.roomId,
-                    &request_data.peerId,
+                    &request_data.room_id,
+                    &request_data.peer_id,
                 )
                 .await;
+                if updated {
+                    rooms::handler::log_moderation_action(
+                        execution_handler,
+                        requester_id,
+                        request_data.peer_id,
+                        request_data.room_id,
+                        ModerationAction::TransferOwnership,
+                        None,
+                    );
+                }
                 return Ok(());
             }
         }
@@ -1008,7 +2559,7 @@ pub async fn give_owner(
 
 pub async fn add_or_remove_relation_for_hoi(
     request: BasicRequest,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
 ) -> Result<()> {
@@ -1040,9 +2591,9 @@ pub async fn add_or_remove_relation_for_hoi(
 
 pub async fn create_hoi_connection(
     request: BasicRequest,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
 ) -> Result<()> {
     let request_data: HouseOfIoTCredentials =
@@ -1053,7 +2604,15 @@ pub async fn create_hoi_connection(
         //ensure our user is actually in a room
         if user.current_room_id != -1 {
             // Only mods can actually make the request to connect to a server
-            if is_mod_or_owner(&user.current_room_id, &mut handler, &requester_id).await {
+            if roles::require_role(
+                &mut handler,
+                &user.current_room_id,
+                &requester_id,
+                Role::Mod,
+            )
+            .await
+            .is_ok()
+            {
                 // Users can only make this request on behalf of themselves.
                 if request_data.user_id == requester_id {
                     send_request_to_integration_server(
@@ -1073,7 +2632,7 @@ pub async fn create_hoi_connection(
 }
 
 pub async fn send_request_to_integration_server(
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     data: String,
     category: String,
     server_id: String,
@@ -1091,7 +2650,7 @@ pub async fn send_request_to_integration_server(
 
 pub async fn remove_hoi_connection(
     request: BasicRequest,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
 ) -> Result<()> {
@@ -1118,7 +2677,7 @@ pub async fn remove_hoi_connection(
 
 pub async fn remove_hoi_connection_directly(
     server_id: String,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     write_state: &mut ServerState,
     requester_id: i32,
 ) -> Result<bool> {
@@ -1160,7 +2719,7 @@ pub async fn remove_hoi_connection_directly(
 
 pub async fn request_hoi_action(
     request: BasicRequest,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
 ) -> Result<()> {
@@ -1172,9 +2731,7 @@ pub async fn request_hoi_action(
         if let Some(room) = write_state.rooms.get(&user.current_room_id) {
             // Only people with permission can make requests
             if let Some(board) = room.iot_server_connections.get(&request_data.server_id) {
-                if board.users_with_permission.contains(&requester_id)
-                    || board.owner_user_id == requester_id
-                {
+                if board::handler::user_can_control_board(board, &requester_id) {
                     logging::console::log_event(&format!(
                         "Executing HOI Action:{:?}",
                         request_data
@@ -1199,75 +2756,221 @@ pub async fn request_hoi_action(
     Ok(())
 }
 
+/// Sends a control command to an IoT server connected
+/// to a room, only the owner of the board and those
+/// granted permission can send commands.
+pub async fn send_iot_command(
+    request: BasicRequest,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: IoTCommand = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    let current_room_id = write_state.current_room_id(requester_id);
+    // You can only control boards in your current room
+    if current_room_id != -1 && current_room_id == request_data.board_room_id {
+        if let Some(room) = write_state.rooms.get_mut(&current_room_id) {
+            if let Some(board) = room.iot_server_connections.get_mut(&request_data.server_id) {
+                if board::handler::user_can_control_board(board, &requester_id) {
+                    let audit = board::handler::record_board_command(
+                        board,
+                        requester_id,
+                        request_data.command.clone(),
+                    );
+                    let audit_response = serde_json::to_string(&BasicResponse {
+                        response_op_code: "iot_command_sent".to_owned(),
+                        response_containing_data: serde_json::to_string(&BoardCommandSent {
+                            external_id: request_data.server_id.clone(),
+                            audit: &audit,
+                        })
+                        .unwrap(),
+                    })
+                    .unwrap();
+                    logging::console::log_event(&format!(
+                        "User({}) sent command({}) to IoT server({})",
+                        requester_id, request_data.command, request_data.server_id
+                    ));
+                    send_request_to_integration_server(
+                        integration_publish_channel,
+                        serde_json::to_string(&IoTCommandOutgoing {
+                            command: request_data.command,
+                            payload: request_data.payload,
+                        })
+                        .unwrap(),
+                        "command_hoi".to_owned(),
+                        request_data.server_id,
+                    )
+                    .await;
+                    // Let the collaborators know who did what
+                    ws_fan::fan::broadcast_message_to_room(
+                        audit_response,
+                        &mut write_state,
+                        current_room_id,
+                    )
+                    .await;
+                    return Ok(());
+                }
+            }
+        }
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
+pub async fn get_board_command_history(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: GenericExternalId = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    if let Some(user) = write_state.active_users.get(&requester_id) {
+        if let Some(room) = write_state.rooms.get(&user.current_room_id) {
+            if let Some(board) = room.iot_server_connections.get(&request_data.external_id) {
+                let response_data = serde_json::to_string(&BoardCommandHistory {
+                    external_id: request_data.external_id.clone(),
+                    commands: board.recent_commands.iter().collect(),
+                })
+                .unwrap();
+                send_to_requester_channel(
+                    response_data,
+                    requester_id,
+                    &mut write_state,
+                    "board_command_history".to_owned(),
+                );
+                return Ok(());
+            }
+        }
+    }
+    send_error_response_to_requester(requester_id, &mut write_state);
+    Ok(())
+}
+
 /// Give a users permission to control an iot server
 /// if that user is in your current room.
-/// TODO:Break this method up
 pub async fn give_or_revoke_iot_permission(
     request: BasicRequest,
     server_state: &Arc<RwLock<ServerState>>,
     requester_id: i32,
 ) -> Result<()> {
-    let mut write_state = server_state.write().await;
     let request_data: GiveOrRevokeIot = serde_json::from_str(&request.request_containing_data)?;
-    // Can't give yourself permission since you must be the owner
-    // in the beginning. You already have permission.
-    if request_data.user_id != requester_id {
-        if let Some(user) = write_state.active_users.get(&requester_id) {
-            let current_room_id = user.current_room_id.clone();
-            drop(user);
-            if let Some(room) = write_state.rooms.get_mut(&current_room_id) {
-                if let Some(board) = room
-                    .iot_server_connections
-                    .get_mut(&request_data.external_id)
-                {
-                    //only owners can give permission
-                    if board.owner_user_id == requester_id {
-                        //default -> revoke permissions
-                        let mut outgoing_op_code = "removed_hoi_controller";
-                        let mut outgoing_response_data =
-                            serde_json::to_string(&RemovedIoTController {
-                                external_id: request_data.external_id.clone(),
-                                user_id: request_data.user_id.clone(),
-                                outside_name: board.outside_name.clone(),
-                            })
-                            .unwrap();
-
-                        //if we are giving permission
-                        if request_data.now_has_permission {
-                            board.users_with_permission.insert(request_data.user_id);
-                            outgoing_op_code = "new_hoi_controller";
-                            outgoing_response_data = serde_json::to_string(&NewIoTController {
-                                external_id: request_data.external_id,
-                                user_id: request_data.user_id,
-                                outside_name: board.outside_name.clone(),
-                            })
-                            .unwrap();
-                        } else {
-                            board.users_with_permission.remove(&request_data.user_id);
-                        }
-
-                        //Let the room know this user has been removed
-                        //from controlling this board
-                        ws_fan::fan::broadcast_message_to_room(
-                            serde_json::to_string(&BasicResponse {
-                                response_op_code: outgoing_op_code.to_owned(),
-                                response_containing_data: outgoing_response_data,
-                            })
-                            .unwrap(),
-                            &mut write_state,
-                            current_room_id,
-                        )
-                        .await;
-                        return Ok(());
-                    }
-                }
-            }
+    let mut write_state = server_state.write().await;
+    modify_board_permission_and_notify_room(
+        &mut write_state,
+        requester_id,
+        request_data.external_id,
+        request_data.user_id,
+        request_data.now_has_permission,
+    )
+    .await;
+    Ok(())
+}
+
+/// Handles both "grant_board_permission" and
+/// "revoke_board_permission", only the owner
+/// of the board can grant/revoke.
+pub async fn grant_or_revoke_board_permission(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: BoardPermissionModification =
+        serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    modify_board_permission_and_notify_room(
+        &mut write_state,
+        requester_id,
+        request_data.external_id,
+        request_data.user_id,
+        request.request_op_code == "grant_board_permission",
+    )
+    .await;
+    Ok(())
+}
+
+/// Lets anyone in the room of the board see
+/// who currently has control of it.
+pub async fn list_board_permissions(
+    request: BasicRequest,
+    server_state: &Arc<RwLock<ServerState>>,
+    requester_id: i32,
+) -> Result<()> {
+    let request_data: GenericExternalId = serde_json::from_str(&request.request_containing_data)?;
+    let mut write_state = server_state.write().await;
+    if let Some(user) = write_state.active_users.get(&requester_id) {
+        let current_room_id = user.current_room_id;
+        if let Some(permissions) = board::handler::list_board_permissions(
+            &write_state,
+            &current_room_id,
+            &request_data.external_id,
+        ) {
+            send_to_requester_channel(
+                serde_json::to_string(&permissions).unwrap(),
+                requester_id,
+                &mut write_state,
+                "board_permissions".to_owned(),
+            );
+            return Ok(());
         }
     }
     send_error_response_to_requester(requester_id, &mut write_state);
     Ok(())
 }
 
+async fn modify_board_permission_and_notify_room(
+    write_state: &mut ServerState,
+    requester_id: i32,
+    external_id: String,
+    user_id: i32,
+    now_has_permission: bool,
+) {
+    if let Some(user) = write_state.active_users.get(&requester_id) {
+        let current_room_id = user.current_room_id;
+        let modification_result = board::handler::modify_board_permission(
+            write_state,
+            &current_room_id,
+            &external_id,
+            &requester_id,
+            &user_id,
+            now_has_permission,
+        );
+        if let Some(outside_name) = modification_result {
+            //default -> revoke permissions
+            let mut outgoing_op_code = "removed_hoi_controller";
+            let mut outgoing_response_data = serde_json::to_string(&RemovedIoTController {
+                external_id: external_id.clone(),
+                user_id,
+                outside_name: outside_name.clone(),
+            })
+            .unwrap();
+            if now_has_permission {
+                outgoing_op_code = "new_hoi_controller";
+                outgoing_response_data = serde_json::to_string(&NewIoTController {
+                    external_id,
+                    user_id,
+                    outside_name,
+                })
+                .unwrap();
+            }
+            //Let the room know this user has been added/removed
+            //from controlling this board
+            ws_fan::fan::broadcast_message_to_room(
+                serde_json::to_string(&BasicResponse {
+                    response_op_code: outgoing_op_code.to_owned(),
+                    response_containing_data: outgoing_response_data,
+                })
+                .unwrap(),
+                write_state,
+                current_room_id,
+            )
+            .await;
+            return;
+        }
+    }
+    send_error_response_to_requester(requester_id, write_state);
+}
+
 pub async fn get_passive_data_snapshot(server_state: &Arc<RwLock<ServerState>>, requester_id: i32) {
     let mut write_state = server_state.write().await;
     if let Some(user) = write_state.active_users.get(&requester_id) {
@@ -1299,7 +3002,7 @@ pub async fn get_passive_data_snapshot(server_state: &Arc<RwLock<ServerState>>,
 
 pub async fn get_blocked_users_for_room(
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
 ) {
     let mut write_state = server_state.write().await;
@@ -1307,7 +3010,15 @@ pub async fn get_blocked_users_for_room(
         if user.current_room_id != -1 {
             let mut handler = execution_handler.lock().await;
 
-            if is_mod_or_owner(&user.current_room_id, &mut handler, &requester_id).await {
+            if roles::require_role(
+                &mut handler,
+                &user.current_room_id,
+                &requester_id,
+                Role::Mod,
+            )
+            .await
+            .is_ok()
+            {
                 let blocked_user_ids: Vec<i32> = data_fetcher::get_blocked_user_ids_for_room(
                     &mut handler,
                     &user.current_room_id,
@@ -1333,39 +3044,51 @@ pub async fn get_blocked_users_for_room(
     }
 }
 
-pub fn room_is_joinable(
+#[derive(Debug, PartialEq)]
+pub enum RoomJoinCheck {
+    Joinable,
+    /// holds the room the user is currently in
+    AlreadyInRoom(i32),
+    NotJoinable,
+}
+
+/// Users join for themselves, only while they aren't
+/// in another room and only public rooms that exist.
+pub fn check_room_join(
     read_state: &ServerState,
     peer_id: &i32,
     requester_id: &i32,
     room_id: &i32,
-) -> bool {
-    if let Some(user) = read_state.active_users.get(peer_id) {
-        if let Some(room) = read_state.rooms.get(room_id) {
-            if user.current_room_id == -1 && room.public && peer_id == requester_id {
-                return true;
-            }
-        }
+) -> RoomJoinCheck {
+    if !read_state.active_users.contains_key(peer_id) || peer_id != requester_id {
+        return RoomJoinCheck::NotJoinable;
+    }
+    let current_room_id = read_state.current_room_id(*peer_id);
+    if current_room_id != -1 {
+        RoomJoinCheck::AlreadyInRoom(current_room_id)
+    } else if read_state.room_is_public(*room_id) {
+        RoomJoinCheck::Joinable
+    } else {
+        RoomJoinCheck::NotJoinable
     }
-    return false;
 }
 
-async fn is_mod_or_owner(
-    current_room_id: &i32,
-    handler: &mut ExecutionHandler,
-    requester_id: &i32,
-) -> bool {
-    let room_permissions =
-        data_fetcher::get_room_permissions_for_users(current_room_id, handler).await;
-    let room_owner = data_fetcher::get_room_owner_and_settings(handler, current_room_id).await;
-    if let Some(permissions) = room_permissions.1.get(requester_id) {
-        if permissions.is_mod {
-            return true;
-        }
-    }
-    if room_owner.1 == *requester_id {
-        return true;
-    }
-    false
+/// Lets the client offer to switch rooms
+/// instead of showing a generic failure.
+pub fn send_already_in_room(
+    requester_id: i32,
+    current_room_id: i32,
+    write_state: &mut ServerState,
+) {
+    send_error_to_requester(
+        serde_json::to_string(&AlreadyInRoom {
+            room_id: current_room_id,
+        })
+        .unwrap(),
+        requester_id,
+        write_state,
+        "already_in_room".to_owned(),
+    );
 }
 
 fn type_of_mod_op(mod_status: bool) -> String {
@@ -1376,8 +3099,44 @@ fn type_of_mod_op(mod_status: bool) -> String {
     }
 }
 
+fn send_profile_edit_problem(
+    problem: ProfileEditProblem,
+    requester_id: i32,
+    write_state: &mut ServerState,
+) {
+    match problem {
+        ProfileEditProblem::Invalid(reason) => send_error_to_requester(
+            reason,
+            requester_id,
+            write_state,
+            "invalid_request".to_owned(),
+        ),
+        ProfileEditProblem::UsernameTaken(username) => {
+            send_username_taken(username, requester_id, write_state)
+        }
+    }
+}
+
+fn send_username_taken(username: String, requester_id: i32, write_state: &mut ServerState) {
+    send_error_to_requester(
+        username,
+        requester_id,
+        write_state,
+        "username_taken".to_owned(),
+    );
+}
+
+fn send_room_details_rejected(requester_id: i32, write_state: &mut ServerState) {
+    send_error_to_requester(
+        "room name or description contains blocked words".to_owned(),
+        requester_id,
+        write_state,
+        "content_rejected".to_owned(),
+    );
+}
+
 fn send_error_response_to_requester(requester_id: i32, write_state: &mut ServerState) {
-    send_to_requester_channel(
+    send_error_to_requester(
         "issue with request".to_owned(),
         requester_id,
         write_state,
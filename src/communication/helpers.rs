@@ -1,15 +1,21 @@
-use crate::common::response_logic::send_to_requester_channel;
+use crate::common::response_logic::{send_error_to_requester, send_to_requester_channel};
 use crate::communication::types::GetFollowListResponse;
-use crate::communication::types::{CommunicationRoom, RoomDetails, UserPreview};
+use crate::communication::types::{
+    BasicResponse, CommunicationRoom, RoomDetails, UserBanned, UserPreview,
+};
 use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::rooms::chat_modes::ChatMode;
 use crate::state::state::ServerState;
 use crate::state::types::Room;
-use std::collections::{HashMap, HashSet};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use url::Url;
 
-use super::data_fetcher;
 use super::types::FollowInfo;
+use super::{content_filter, data_fetcher};
 
 pub fn web_rtc_request_is_valid(
     server_state: &ServerState,
@@ -60,7 +66,7 @@ pub fn parse_peer_and_room_id(
 }
 
 pub async fn send_follow_list(
-    target: (bool, HashSet<i32>),
+    target: (bool, Vec<i32>, Option<i32>),
     server_state: &Arc<RwLock<ServerState>>,
     execution_handler: &mut ExecutionHandler,
     requester_id: i32,
@@ -69,16 +75,15 @@ pub async fn send_follow_list(
     let mut write_state = server_state.write().await;
     // if we encountered error getting the follow list from the db
     if target.0 == true {
-        send_to_requester_channel(
+        send_error_to_requester(
             "issue with request".to_owned(),
             requester_id,
             &mut write_state,
             "invalid_request".to_owned(),
         );
     } else {
-        let user_ids: Vec<i32> = target.1.into_iter().collect();
         let data_result =
-            data_fetcher::get_users_for_user(requester_id.clone(), user_ids, execution_handler)
+            data_fetcher::get_users_for_user(requester_id.clone(), target.1, execution_handler)
                 .await;
         let mut follow_holder: Vec<FollowInfo> = Vec::new();
 
@@ -95,6 +100,7 @@ pub async fn send_follow_list(
         let response = GetFollowListResponse {
             user_ids: follow_holder,
             for_user: peer_id,
+            next_cursor: target.2,
         };
         let response_str = serde_json::to_string(&response).unwrap();
         send_to_requester_channel(
@@ -106,6 +112,30 @@ pub async fn send_follow_list(
     }
 }
 
+/// Both the auth path and the ban handler
+/// tell banned users why they are being closed.
+pub fn construct_banned_response(banned_reason: String) -> String {
+    let response = BasicResponse {
+        response_op_code: "banned".to_owned(),
+        response_containing_data: serde_json::to_string(&UserBanned { banned_reason }).unwrap(),
+    };
+    serde_json::to_string(&response).unwrap()
+}
+
+/// The parts of a room that users can change.
+pub fn construct_room_details(room_state: &Room) -> RoomDetails {
+    RoomDetails {
+        name: room_state.name.to_owned(),
+        description: room_state.desc.to_owned(),
+        chat_throttle: room_state.chat_throttle,
+        chat_enabled: room_state.chat_mode != ChatMode::Disabled,
+        chat_mode: room_state.chat_mode.as_str().to_owned(),
+        is_private: room_state.public == false,
+        max_occupancy: room_state.max_occupancy,
+        tags: room_state.tags.clone(),
+    }
+}
+
 /// Constructs communication rooms
 /// communication rooms are rooms that differ from state
 /// by containing user previews and other small pieces of data.
@@ -114,14 +144,8 @@ pub fn construct_communication_room(
     room_state: &Room,
     holder: &mut Vec<CommunicationRoom>,
     creator_id: i32,
-    chat_mode: String,
 ) {
-    let new_communication_room_details = RoomDetails {
-        name: room_state.name.to_owned(),
-        description: room_state.desc.to_owned(),
-        chat_throttle: room_state.chat_throttle,
-        is_private: room_state.public == false,
-    };
+    let new_communication_room_details = construct_room_details(room_state);
 
     let new_communication_room = CommunicationRoom {
         details: new_communication_room_details,
@@ -132,12 +156,31 @@ pub fn construct_communication_room(
         people_preview_data: previews,
         auto_speaker_setting: room_state.auto_speaker,
         created_at: room_state.created_at.to_owned(),
-        chat_mode: chat_mode,
+        chat_mode: room_state.chat_mode.as_str().to_owned(),
+        pinned: room_state.pinned_at.is_some(),
     };
 
     holder.push(new_communication_room);
 }
 
+/// Runs the name and description through the content
+/// filter, false if either has to be rejected.
+pub fn filter_room_details(name: &mut String, desc: &mut String) -> bool {
+    let filter = content_filter::content_filter();
+    filter.filter_in_place(name) && filter.filter_in_place(desc)
+}
+
+/// Filters the text of a chat message, None
+/// if the message has to be rejected.
+pub fn filter_chat_message(message: &mut Value) -> Option<()> {
+    if let Some(Value::String(text)) = message.get_mut("message") {
+        if !content_filter::content_filter().filter_in_place(text) {
+            return None;
+        }
+    }
+    Some(())
+}
+
 fn grab_current_room(write_state: &mut ServerState, user_id: &i32) -> Option<i32> {
     if let Some(data) = write_state.active_users.get(user_id) {
         Some(data.current_room_id)
@@ -145,3 +188,47 @@ fn grab_current_room(write_state: &mut ServerState, user_id: &i32) -> Option<i32
         None
     }
 }
+
+/// Where avatars and banners can be served from, these are
+/// echoed to every user so only hosts we trust are allowed.
+/// IMAGE_HOST_ALLOWLIST(comma separated) adds our own CDN.
+pub const DEFAULT_IMAGE_HOSTS: [&str; 2] = ["cdn.discordapp.com", "avatars.githubusercontent.com"];
+
+pub fn allowed_image_hosts() -> Vec<String> {
+    image_hosts_from(&env::var("IMAGE_HOST_ALLOWLIST").unwrap_or_default())
+}
+
+pub fn image_hosts_from(value: &str) -> Vec<String> {
+    let mut hosts: Vec<String> = DEFAULT_IMAGE_HOSTS
+        .iter()
+        .map(|host| host.to_string())
+        .collect();
+    hosts.extend(
+        value
+            .split(',')
+            .map(|host| host.trim().to_lowercase())
+            .filter(|host| !host.is_empty()),
+    );
+    hosts
+}
+
+/// Only https urls on an allowed host(exact match,
+/// subdomains have to be listed on their own).
+pub fn image_url_is_allowed(image_url: &str, allowed_hosts: &[String]) -> bool {
+    // the columns only hold 255 characters
+    if image_url.len() > 255 {
+        return false;
+    }
+    match Url::parse(image_url) {
+        Ok(parsed) => {
+            parsed.scheme() == "https"
+                && parsed.username().is_empty()
+                && parsed.password().is_none()
+                && match parsed.host_str() {
+                    Some(host) => allowed_hosts.iter().any(|allowed| allowed == host),
+                    None => false,
+                }
+        }
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,152 @@
+/*
+Every request op code the server understands.
+
+Adding a new request type means adding a variant and its
+wire name to the `op_codes!` list below and handling the
+variant in the router. The enum, `OP_CODE_TABLE` and
+`as_str` are all built from that one list, and the router
+match is exhaustive, so an op code can't be left without
+a wire name or a handler.
+*/
+use std::str::FromStr;
+
+/// Builds the enum, its wire name table and an exhaustive
+/// `as_str` from a single `Variant => "wire_name"` list.
+macro_rules! op_codes {
+    ($(#[$attr:meta])* $name:ident, $table:ident { $($variant:ident => $wire:literal,)* }) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $($variant,)*
+        }
+
+        pub const $table: &[(&str, $name)] = &[$(($wire, $name::$variant),)*];
+
+        impl $name {
+            pub fn as_str(&self) -> &'static str {
+                match self {
+                    $($name::$variant => $wire,)*
+                }
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ();
+
+            fn from_str(wire: &str) -> Result<Self, Self::Err> {
+                $table
+                    .iter()
+                    .find(|(name, _)| *name == wire)
+                    .map(|(_, op)| *op)
+                    .ok_or(())
+            }
+        }
+    };
+}
+
+op_codes!(
+    OpCode,
+    OP_CODE_TABLE {
+        CreateRoom => "create_room",
+        ConnectTransport => "@connect-transport",
+        SendTrack => "@send-track",
+        GetRecvTracks => "@get-recv-tracks",
+        AddSpeaker => "add_speaker",
+        ApproveSpeakerRequest => "approve_speaker_request",
+        DenySpeakerRequest => "deny_speaker_request",
+        RemoveSpeaker => "remove_speaker",
+        BlockUserFromRoom => "block_user_from_room",
+        KickUserFromRoom => "kick_user_from_room",
+        ForceMuteUser => "force_mute_user",
+        ForceUnmuteUser => "force_unmute_user",
+        StartRecording => "start_recording",
+        StopRecording => "stop_recording",
+        DestroyRoom => "destroy_room",
+        GetFollowers => "get_followers",
+        GetFollowing => "get_following",
+        GetFollowStatuses => "get_follow_statuses",
+        JoinAsSpeaker => "join-as-speaker",
+        JoinAsNewPeer => "join-as-new-peer",
+        GetTopRooms => "get_top_rooms",
+        SearchRooms => "search_rooms",
+        GetRoomsByTag => "get_rooms_by_tag",
+        GetRecentRooms => "get_recent_rooms",
+        GetServerStats => "get_server_stats",
+        RaiseHand => "raise_hand",
+        LowerHand => "lower_hand",
+        LowerAllHands => "lower_all_hands",
+        GatherAllUsersInRoom => "gather_all_users_in_room",
+        BanUser => "ban_user",
+        UnbanUser => "unban_user",
+        BroadcastAnnouncement => "broadcast_announcement",
+        BeginDrain => "begin_drain",
+        PinRoom => "pin_room",
+        UnpinRoom => "unpin_room",
+        FollowUser => "follow_user",
+        UnfollowUser => "unfollow_user",
+        BlockUser => "block_user",
+        UnblockUser => "unblock_user",
+        LeaveRoom => "leave_room",
+        InitialRoomData => "initial_room_data",
+        UpdateRoomMeta => "update_room_meta",
+        UpdateDeafAndMute => "update_deaf_and_mute",
+        AllRoomPermissions => "all_room_permissions",
+        UserPreviews => "user_previews",
+        SearchUsers => "search_users",
+        SendChatMsg => "send_chat_msg",
+        JoinType => "join_type",
+        MyData => "my_data",
+        SingleUserData => "single_user_data",
+        GetUserByUsername => "get_user_by_username",
+        GetUserProfile => "get_user_profile",
+        ChangeUserModStatus => "change_user_mod_status",
+        AddMod => "add_mod",
+        RemoveMod => "remove_mod",
+        GiveOwner => "give_owner",
+        UpdateUserData => "update_user_data",
+        EditProfile => "edit_profile",
+        SingleUserPermissions => "single_user_permissions",
+        GetMyRoomPermissions => "get_my_room_permissions",
+        ConnectHoi => "connect_hoi",
+        DisconnectHoi => "disconnect_hoi",
+        GiveOrRevokeControllerIot => "give_or_revoke_controller_iot",
+        GrantBoardPermission => "grant_board_permission",
+        RevokeBoardPermission => "revoke_board_permission",
+        ListBoardPermissions => "list_board_permissions",
+        RelationModification => "relation_modification",
+        GetRoomBlocked => "get_room_blocked",
+        GetRoomBlockedUsers => "get_room_blocked_users",
+        GetRoomModerationLog => "get_room_moderation_log",
+        UnblockUserFromRoom => "unblock_user_from_room",
+        RequestHoiAction => "request_hoi_action",
+        SendIotCommand => "send_iot_command",
+        BoardCommandHistory => "board_command_history",
+        GetIotPassive => "get_iot_passive",
+        GetPreferences => "get_preferences",
+        SetPreferences => "set_preferences",
+        DeleteAccount => "delete_account",
+    }
+);
+
+op_codes!(
+    /// Every op code the voice server understands, requests
+    /// to it are built from these so a typo can't quietly
+    /// turn into a message the voice server ignores.
+    VoiceServerOp,
+    VOICE_SERVER_OP_TABLE {
+        CreateRoom => "create-room",
+        DestroyRoom => "destroy-room",
+        ClosePeer => "close-peer",
+        JoinAsSpeaker => "join-as-speaker",
+        JoinAsNewPeer => "join-as-new-peer",
+        AddSpeaker => "add-speaker",
+        RemoveSpeaker => "remove-speaker",
+        MutePeer => "mute-peer",
+        UnmutePeer => "unmute-peer",
+        StartRecording => "start-recording",
+        StopRecording => "stop-recording",
+        ConnectTransport => "@connect-transport",
+        SendTrack => "@send-track",
+        GetRecvTracks => "@get-recv-tracks",
+    }
+);
@@ -2,31 +2,250 @@
 Handles all of the communication op_code_routing
 to the intended functionality
 */
+use crate::auth::guests;
+use crate::common::response_logic::with_request_id;
 use crate::communication::handler;
+use crate::communication::op_codes::OpCode;
 use crate::communication::types::BasicRequest;
-use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::logging;
+use crate::rabbitmq::rabbit::PublishChannel;
+use crate::rabbitmq::signaling::VoiceSignaling;
 use crate::state::state::ServerState;
+use crate::state::types::PeerSender;
 use futures::lock::Mutex;
 use serde_json::Result;
+use std::env;
+use std::str::FromStr;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::{timeout, Duration};
+
+/// Largest request we'll parse unless
+/// MAX_REQUEST_BYTES says otherwise.
+pub const DEFAULT_MAX_REQUEST_BYTES: usize = 64 * 1024;
+
+pub fn max_request_bytes() -> usize {
+    match env::var("MAX_REQUEST_BYTES") {
+        Ok(value) => value.parse().unwrap_or(DEFAULT_MAX_REQUEST_BYTES),
+        Err(_) => DEFAULT_MAX_REQUEST_BYTES,
+    }
+}
+
+/// How long a read only request can run before it's cancelled and
+/// the requester is told request_timeout, unless REQUEST_TIMEOUT_MS
+/// says otherwise.
+pub const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 5000;
+
+/// REQUEST_TIMEOUTS_MS overrides single ops,
+/// e.g. "get_top_rooms=2000,search_rooms=8000".
+pub fn request_timeout(op_code: &str) -> Duration {
+    request_timeout_from(
+        op_code,
+        env::var("REQUEST_TIMEOUT_MS").ok().as_deref(),
+        env::var("REQUEST_TIMEOUTS_MS").ok().as_deref(),
+    )
+}
+
+pub fn request_timeout_from(
+    op_code: &str,
+    default_ms: Option<&str>,
+    per_op_ms: Option<&str>,
+) -> Duration {
+    let default_ms = default_ms
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_REQUEST_TIMEOUT_MS);
+    let op_ms = per_op_ms.unwrap_or_default().split(',').find_map(|entry| {
+        let (op, ms) = entry.split_once('=')?;
+        if op.trim() == op_code {
+            ms.trim().parse().ok()
+        } else {
+            None
+        }
+    });
+    Duration::from_millis(op_ms.unwrap_or(default_ms))
+}
+
+/// Ops whose handlers only read, cancelling one part way
+/// can't leave the state or the database half changed.
+/// Anything that changes state isn't timed out, its changes
+/// are spread over several awaits and aren't undone if
+/// the handler is dropped between them.
+pub fn op_is_read_only(op_code: OpCode) -> bool {
+    matches!(
+        op_code,
+        OpCode::GetFollowers
+            | OpCode::GetFollowing
+            | OpCode::GetFollowStatuses
+            | OpCode::GetTopRooms
+            | OpCode::SearchRooms
+            | OpCode::GetRoomsByTag
+            | OpCode::GetRecentRooms
+            | OpCode::GetServerStats
+            | OpCode::GatherAllUsersInRoom
+            | OpCode::InitialRoomData
+            | OpCode::AllRoomPermissions
+            | OpCode::UserPreviews
+            | OpCode::SearchUsers
+            | OpCode::JoinType
+            | OpCode::MyData
+            | OpCode::SingleUserData
+            | OpCode::GetUserByUsername
+            | OpCode::GetUserProfile
+            | OpCode::SingleUserPermissions
+            | OpCode::GetMyRoomPermissions
+            | OpCode::ListBoardPermissions
+            | OpCode::GetRoomBlocked
+            | OpCode::GetRoomBlockedUsers
+            | OpCode::GetRoomModerationLog
+            | OpCode::BoardCommandHistory
+            | OpCode::GetIotPassive
+            | OpCode::GetPreferences
+    )
+}
 
 pub async fn route_msg(
     msg: String,
     user_id: i32,
     server_state: &Arc<RwLock<ServerState>>,
-    voice_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: Option<&Arc<Mutex<lapin::Channel>>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Option<&Arc<Mutex<PublishChannel>>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) -> Result<()> {
-    let basic_request: BasicRequest = serde_json::from_str(&msg)?;
+    // The websocket already refuses bigger frames, this
+    // keeps the parser safe no matter where msg came from.
+    let max_bytes = max_request_bytes();
+    if msg.len() > max_bytes {
+        handler::payload_too_large(server_state, user_id, msg.len(), max_bytes).await;
+        return Ok(());
+    }
+    let basic_request: BasicRequest = match serde_json::from_str(&msg) {
+        Ok(basic_request) => basic_request,
+        Err(e) => {
+            handler::malformed_payload(server_state, user_id, "unknown", &e).await;
+            return Ok(());
+        }
+    };
     //Route the request
     //We could use the basic_request op code for checking
     //different requests like add/remove user inside of the method
     //instead of using a different parameter, but this way it is
     //cleaner and opcodes are abstracted away from function implementation.
-    match basic_request.request_op_code.as_str() {
-        "create_room" => {
+    let op_code = match OpCode::from_str(&basic_request.request_op_code) {
+        Ok(op_code) => op_code,
+        Err(_) => {
+            with_request_id(
+                basic_request.request_id.clone(),
+                handler::unknown_op_code(server_state, user_id, &basic_request.request_op_code),
+            )
+            .await;
+            return Ok(());
+        }
+    };
+    if server_state.read().await.is_guest(user_id) && !guests::op_allowed_for_guests(op_code) {
+        with_request_id(
+            basic_request.request_id.clone(),
+            handler::guest_not_allowed(server_state, user_id, op_code.as_str()),
+        )
+        .await;
+        return Ok(());
+    }
+    // Grabbed up front, a stuck handler might be the
+    // one holding the state lock we'd need to reply.
+    let requester_txs: Vec<PeerSender> = server_state
+        .read()
+        .await
+        .peer_map
+        .senders_for(&user_id)
+        .into_iter()
+        .cloned()
+        .collect();
+    let timeout_after = op_is_read_only(op_code).then(|| request_timeout(op_code.as_str()));
+    let request_id = basic_request.request_id.clone();
+    // The handler runs on its own task so a timed out read can
+    // be aborted, which drops its locks and pooled connection.
+    // Handlers that change state are always run to the end.
+    let request = tokio::spawn({
+        let server_state = server_state.clone();
+        let voice_publish_channel = voice_publish_channel.clone();
+        let integration_publish_channel = integration_publish_channel.cloned();
+        let execution_handler = execution_handler.clone();
+        async move {
+            with_request_id(
+                basic_request.request_id.clone(),
+                dispatch(
+                    op_code,
+                    basic_request,
+                    user_id,
+                    &server_state,
+                    &voice_publish_channel,
+                    integration_publish_channel.as_ref(),
+                    &execution_handler,
+                ),
+            )
+            .await
+        }
+    });
+    let result = await_request(request, timeout_after, |timeout_after| {
+        handler::request_timeout(
+            requester_txs,
+            user_id,
+            op_code.as_str(),
+            request_id,
+            timeout_after,
+        )
+    })
+    .await;
+    if let Some(Err(e)) = result {
+        logging::console::log_failure(&format!(
+            "{} request from user({}) panicked: {}",
+            op_code.as_str(),
+            user_id,
+            e
+        ));
+    }
+    Ok(())
+}
+
+/// Without a timeout the request is waited on however long it
+/// takes. Past its timeout it's aborted and None is returned, but
+/// only once the task is gone, so its locks are released before
+/// the connection's next request runs.
+pub async fn await_request<T>(
+    mut request: JoinHandle<T>,
+    timeout_after: Option<Duration>,
+    on_timeout: impl FnOnce(Duration),
+) -> Option<std::result::Result<T, JoinError>> {
+    let timeout_after = match timeout_after {
+        Some(timeout_after) => timeout_after,
+        None => return Some(request.await),
+    };
+    match timeout(timeout_after, &mut request).await {
+        Ok(result) => Some(result),
+        Err(_) => {
+            request.abort();
+            let _ = request.await;
+            on_timeout(timeout_after);
+            None
+        }
+    }
+}
+
+async fn dispatch(
+    op_code: OpCode,
+    basic_request: BasicRequest,
+    user_id: i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: Option<&Arc<Mutex<PublishChannel>>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    // A payload that doesn't fit the request type is the
+    // requester's problem, so they hear about it instead of
+    // the error bubbling up to the connection task.
+    let result = match op_code {
+        OpCode::CreateRoom => {
             handler::create_room(
                 basic_request,
                 server_state,
@@ -36,7 +255,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "@connect-transport" | "@send-track" | "@get-recv-tracks" => {
+        OpCode::ConnectTransport | OpCode::SendTrack | OpCode::GetRecvTracks => {
             handler::handle_web_rtc_request(
                 basic_request,
                 voice_publish_channel,
@@ -45,7 +264,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "add_speaker" => {
+        OpCode::AddSpeaker => {
             handler::add_or_remove_speaker(
                 basic_request,
                 voice_publish_channel,
@@ -56,7 +275,21 @@ pub async fn route_msg(
             )
             .await
         }
-        "remove_speaker" => {
+        OpCode::ApproveSpeakerRequest => {
+            handler::approve_speaker_request(
+                basic_request,
+                voice_publish_channel,
+                user_id,
+                server_state,
+                execution_handler,
+            )
+            .await
+        }
+        OpCode::DenySpeakerRequest => {
+            handler::deny_speaker_request(basic_request, user_id, server_state, execution_handler)
+                .await
+        }
+        OpCode::RemoveSpeaker => {
             handler::add_or_remove_speaker(
                 basic_request,
                 voice_publish_channel,
@@ -67,7 +300,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "block_user_from_room" => {
+        OpCode::BlockUserFromRoom => {
             handler::block_user_from_room(
                 basic_request,
                 user_id,
@@ -77,7 +310,48 @@ pub async fn route_msg(
             )
             .await
         }
-        "get_followers" => {
+        OpCode::KickUserFromRoom => {
+            handler::kick_user_from_room(
+                basic_request,
+                user_id,
+                server_state,
+                execution_handler,
+                voice_publish_channel,
+            )
+            .await
+        }
+        OpCode::ForceMuteUser | OpCode::ForceUnmuteUser => {
+            handler::force_mute_or_unmute_user(
+                basic_request,
+                user_id,
+                server_state,
+                execution_handler,
+                voice_publish_channel,
+            )
+            .await
+        }
+        OpCode::StartRecording | OpCode::StopRecording => {
+            handler::start_or_stop_recording(
+                basic_request,
+                user_id,
+                server_state,
+                execution_handler,
+                voice_publish_channel,
+            )
+            .await
+        }
+        OpCode::DestroyRoom => {
+            handler::destroy_room(
+                basic_request,
+                user_id,
+                server_state,
+                voice_publish_channel,
+                integration_publish_channel.unwrap(),
+                execution_handler,
+            )
+            .await
+        }
+        OpCode::GetFollowers => {
             handler::get_followers_or_following_list(
                 basic_request,
                 execution_handler,
@@ -87,7 +361,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "get_following" => {
+        OpCode::GetFollowing => {
             handler::get_followers_or_following_list(
                 basic_request,
                 execution_handler,
@@ -97,7 +371,11 @@ pub async fn route_msg(
             )
             .await
         }
-        "join-as-speaker" => {
+        OpCode::GetFollowStatuses => {
+            handler::get_follow_statuses(basic_request, execution_handler, server_state, user_id)
+                .await
+        }
+        OpCode::JoinAsSpeaker => {
             handler::join_room(
                 basic_request,
                 server_state,
@@ -108,7 +386,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "join-as-new-peer" => {
+        OpCode::JoinAsNewPeer => {
             handler::join_room(
                 basic_request,
                 server_state,
@@ -119,10 +397,20 @@ pub async fn route_msg(
             )
             .await
         }
-        "get_top_rooms" => {
+        OpCode::GetTopRooms => {
             Ok(handler::get_top_rooms(server_state, user_id, execution_handler).await)
         }
-        "raise_hand" => {
+        OpCode::SearchRooms => {
+            handler::search_rooms(basic_request, server_state, user_id, execution_handler).await
+        }
+        OpCode::GetRoomsByTag => {
+            handler::get_rooms_by_tag(basic_request, server_state, user_id, execution_handler).await
+        }
+        OpCode::GetRecentRooms => {
+            Ok(handler::get_recent_rooms(server_state, user_id, execution_handler).await)
+        }
+        OpCode::GetServerStats => Ok(handler::get_server_stats(server_state, user_id).await),
+        OpCode::RaiseHand => {
             handler::raise_hand_or_lower_hand(
                 basic_request,
                 server_state,
@@ -132,7 +420,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "lower_hand" => {
+        OpCode::LowerHand => {
             handler::raise_hand_or_lower_hand(
                 basic_request,
                 server_state,
@@ -142,7 +430,10 @@ pub async fn route_msg(
             )
             .await
         }
-        "gather_all_users_in_room" => {
+        OpCode::LowerAllHands => {
+            handler::lower_all_hands(basic_request, server_state, user_id, execution_handler).await
+        }
+        OpCode::GatherAllUsersInRoom => {
             handler::gather_all_users_in_room(
                 basic_request,
                 server_state,
@@ -151,7 +442,29 @@ pub async fn route_msg(
             )
             .await
         }
-        "follow_user" | "unfollow_user" => {
+        OpCode::BroadcastAnnouncement => {
+            handler::broadcast_announcement(basic_request, server_state, execution_handler, user_id)
+                .await
+        }
+        OpCode::BeginDrain => {
+            handler::begin_drain(basic_request, server_state, execution_handler, user_id).await
+        }
+        OpCode::PinRoom | OpCode::UnpinRoom => {
+            handler::pin_or_unpin_room(basic_request, server_state, execution_handler, user_id)
+                .await
+        }
+        OpCode::BanUser | OpCode::UnbanUser => {
+            handler::ban_or_unban_user(
+                basic_request,
+                server_state,
+                voice_publish_channel,
+                integration_publish_channel.unwrap(),
+                execution_handler,
+                user_id,
+            )
+            .await
+        }
+        OpCode::FollowUser | OpCode::UnfollowUser => {
             handler::follow_or_unfollow_user(
                 basic_request,
                 execution_handler,
@@ -160,7 +473,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "block_user" | "unblock_user" => {
+        OpCode::BlockUser | OpCode::UnblockUser => {
             handler::block_or_unblock_user_from_user(
                 basic_request,
                 server_state,
@@ -169,7 +482,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "leave_room" => {
+        OpCode::LeaveRoom => {
             handler::leave_room(
                 basic_request,
                 voice_publish_channel,
@@ -180,32 +493,40 @@ pub async fn route_msg(
             )
             .await
         }
-        "initial_room_data" => {
+        OpCode::InitialRoomData => {
             handler::get_initial_room_data(server_state, user_id, basic_request, execution_handler)
                 .await
         }
 
-        "update_room_meta" => {
+        OpCode::UpdateRoomMeta => {
             handler::change_room_metadata(basic_request, server_state, user_id, execution_handler)
                 .await
         }
-        "update_deaf_and_mute" => {
+        OpCode::UpdateDeafAndMute => {
             handler::update_mute_and_deaf_status(basic_request, server_state, user_id).await
         }
-        "all_room_permissions" => {
+        OpCode::AllRoomPermissions => {
             Ok(
                 handler::get_room_permissions_for_users(server_state, user_id, execution_handler)
                     .await,
             )
         }
-        "user_previews" => {
+        OpCode::UserPreviews => {
             handler::gather_previews(basic_request, server_state, user_id, execution_handler).await
         }
-        "send_chat_msg" => {
-            handler::send_chat_message(server_state, user_id, basic_request.request_containing_data)
-                .await
+        OpCode::SearchUsers => {
+            handler::search_users(basic_request, server_state, user_id, execution_handler).await
+        }
+        OpCode::SendChatMsg => {
+            handler::send_chat_message(
+                server_state,
+                user_id,
+                basic_request.request_containing_data,
+                execution_handler,
+            )
+            .await
         }
-        "join_type" => {
+        OpCode::JoinType => {
             handler::gather_type_of_room_join(
                 basic_request,
                 user_id,
@@ -214,23 +535,55 @@ pub async fn route_msg(
             )
             .await
         }
-        "my_data" => Ok(handler::gather_base_user(user_id, execution_handler, server_state).await),
-        "single_user_data" => {
+        OpCode::MyData => {
+            Ok(handler::gather_base_user(user_id, execution_handler, server_state).await)
+        }
+        OpCode::SingleUserData => {
             handler::gather_single_user(basic_request, execution_handler, user_id, server_state)
                 .await
         }
-        "change_user_mod_status" => {
+        OpCode::GetUserByUsername => {
+            handler::get_user_by_username(basic_request, execution_handler, user_id, server_state)
+                .await
+        }
+        OpCode::GetUserProfile => {
+            handler::get_user_profile(basic_request, execution_handler, user_id, server_state).await
+        }
+        OpCode::ChangeUserModStatus => {
             handler::change_user_mod_status(basic_request, execution_handler, user_id, server_state)
                 .await
         }
-        "give_owner" => {
+        OpCode::AddMod => {
+            handler::add_or_remove_mod(
+                basic_request,
+                execution_handler,
+                user_id,
+                server_state,
+                true,
+            )
+            .await
+        }
+        OpCode::RemoveMod => {
+            handler::add_or_remove_mod(
+                basic_request,
+                execution_handler,
+                user_id,
+                server_state,
+                false,
+            )
+            .await
+        }
+        OpCode::GiveOwner => {
             handler::give_owner(basic_request, execution_handler, user_id, server_state).await
         }
-        "update_user_data" => {
+        OpCode::UpdateUserData => {
             handler::update_entire_user(basic_request, execution_handler, user_id, server_state)
                 .await
         }
-        "single_user_permissions" => {
+        OpCode::EditProfile => {
+            handler::edit_profile(basic_request, execution_handler, user_id, server_state).await
+        }
+        OpCode::SingleUserPermissions => {
             handler::gather_single_user_permission(
                 basic_request,
                 execution_handler,
@@ -239,7 +592,16 @@ pub async fn route_msg(
             )
             .await
         }
-        "connect_hoi" => {
+        OpCode::GetMyRoomPermissions => {
+            handler::get_my_room_permissions(
+                basic_request,
+                execution_handler,
+                user_id,
+                server_state,
+            )
+            .await
+        }
+        OpCode::ConnectHoi => {
             handler::create_hoi_connection(
                 basic_request,
                 //this is a safe unwrap seeing as though this
@@ -252,7 +614,7 @@ pub async fn route_msg(
             )
             .await
         }
-        "disconnect_hoi" => {
+        OpCode::DisconnectHoi => {
             handler::remove_hoi_connection(
                 basic_request,
                 //this is a safe unwrap seeing as though this
@@ -264,11 +626,17 @@ pub async fn route_msg(
             )
             .await
         }
-        "give_or_revoke_controller_iot" => {
+        OpCode::GiveOrRevokeControllerIot => {
             handler::give_or_revoke_iot_permission(basic_request, server_state, user_id).await
         }
+        OpCode::GrantBoardPermission | OpCode::RevokeBoardPermission => {
+            handler::grant_or_revoke_board_permission(basic_request, server_state, user_id).await
+        }
+        OpCode::ListBoardPermissions => {
+            handler::list_board_permissions(basic_request, server_state, user_id).await
+        }
 
-        "relation_modification" => {
+        OpCode::RelationModification => {
             handler::add_or_remove_relation_for_hoi(
                 basic_request,
                 integration_publish_channel.unwrap(),
@@ -278,13 +646,27 @@ pub async fn route_msg(
             .await
         }
 
-        "get_room_blocked" => {
+        OpCode::GetRoomBlocked => {
             Ok(handler::get_blocked_users_for_room(server_state, execution_handler, user_id).await)
         }
-        "unblock_user_from_room" => {
-            handler::unblock_user_from_room(basic_request, user_id, execution_handler).await
+        OpCode::UnblockUserFromRoom => {
+            handler::unblock_user_from_room(basic_request, server_state, user_id, execution_handler)
+                .await
+        }
+        OpCode::GetRoomBlockedUsers => {
+            handler::get_room_blocked_users(basic_request, server_state, user_id, execution_handler)
+                .await
+        }
+        OpCode::GetRoomModerationLog => {
+            handler::get_room_moderation_log(
+                basic_request,
+                server_state,
+                user_id,
+                execution_handler,
+            )
+            .await
         }
-        "request_hoi_action" => {
+        OpCode::RequestHoiAction => {
             handler::request_hoi_action(
                 basic_request,
                 integration_publish_channel.unwrap(),
@@ -293,8 +675,40 @@ pub async fn route_msg(
             )
             .await
         }
-        "get_iot_passive" => Ok(handler::get_passive_data_snapshot(server_state, user_id).await),
-
-        _ => Ok(handler::normal_invalid_request(server_state, user_id).await),
+        OpCode::SendIotCommand => {
+            handler::send_iot_command(
+                basic_request,
+                integration_publish_channel.unwrap(),
+                server_state,
+                user_id,
+            )
+            .await
+        }
+        OpCode::BoardCommandHistory => {
+            handler::get_board_command_history(basic_request, server_state, user_id).await
+        }
+        OpCode::GetIotPassive => {
+            Ok(handler::get_passive_data_snapshot(server_state, user_id).await)
+        }
+        OpCode::GetPreferences => {
+            handler::get_preferences(user_id, execution_handler, server_state).await
+        }
+        OpCode::SetPreferences => {
+            handler::set_preferences(basic_request, user_id, execution_handler, server_state).await
+        }
+        OpCode::DeleteAccount => {
+            handler::delete_account(
+                basic_request,
+                server_state,
+                voice_publish_channel,
+                integration_publish_channel.unwrap(),
+                execution_handler,
+                user_id,
+            )
+            .await
+        }
+    };
+    if let Err(e) = result {
+        handler::malformed_payload(server_state, user_id, op_code.as_str(), &e).await;
     }
 }
@@ -1,4 +1,5 @@
 use crate::communication::tests::capture_and_fetch;
+use crate::communication::tests::signaling_tests;
 use crate::communication::tests::tests;
 /*
 Tests aren't executed in a isolated pattern
@@ -11,13 +12,37 @@ other tests that come before them.
 
 pub async fn test() {
     test_capture_and_fetch().await;
+    test_voice_signaling().await;
     tests::tests().await;
 }
 
+async fn test_voice_signaling() {
+    signaling_tests::test_web_rtc_requests_are_published().await;
+    let execution_handler = capture_and_fetch::setup_execution_handler().await.unwrap();
+    signaling_tests::test_create_room_publishes_once(execution_handler).await;
+    let execution_handler = capture_and_fetch::setup_execution_handler().await.unwrap();
+    signaling_tests::test_create_room_for_absent_user(execution_handler).await;
+    let execution_handler = capture_and_fetch::setup_execution_handler().await.unwrap();
+    signaling_tests::test_swept_room_releases_voice_server(execution_handler).await;
+    let execution_handler = capture_and_fetch::setup_execution_handler().await.unwrap();
+    signaling_tests::test_empty_rooms_are_swept(execution_handler).await;
+    let execution_handler = capture_and_fetch::setup_execution_handler().await.unwrap();
+    signaling_tests::test_room_recording(execution_handler).await;
+    let execution_handler = capture_and_fetch::setup_execution_handler().await.unwrap();
+    signaling_tests::test_moderation_log(execution_handler).await;
+    let mut execution_handlers = Vec::new();
+    for _ in 0..4 {
+        execution_handlers.push(capture_and_fetch::setup_execution_handler().await.unwrap());
+    }
+    signaling_tests::test_concurrent_room_creation(execution_handlers).await;
+}
+
 async fn test_capture_and_fetch() {
     let execution_handler_result = capture_and_fetch::setup_execution_handler().await;
     let mut execution_handler = execution_handler_result.unwrap();
     let user_ids: (i32, i32) = capture_and_fetch::test_capture_user(&mut execution_handler).await;
+    capture_and_fetch::test_user_search(&mut execution_handler).await;
+    capture_and_fetch::test_user_ban_status_gather(&mut execution_handler, &user_ids.0).await;
     capture_and_fetch::test_follow_capture_and_gather(
         &mut execution_handler,
         (&user_ids.0, &user_ids.1),
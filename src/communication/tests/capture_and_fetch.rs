@@ -6,6 +6,7 @@ use crate::communication::types::{RoomPermissions, ScheduledRoomUpdate, User};
 use crate::data_store::db_models::{
     DBFollower, DBRoom, DBRoomBlock, DBRoomPermissions, DBScheduledRoom, DBUser, DBUserBlock,
 };
+use crate::data_store::migrations;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
 use crate::rooms::permission_configs;
 use chrono::Utc;
@@ -28,9 +29,90 @@ pub async fn test_capture_user(execution_handler: &mut ExecutionHandler) -> (i32
     let second_real_capture_user_id: i32 =
         data_capturer::capture_new_user(execution_handler, &new_second_user).await;
     assert!(second_real_capture_user_id != -1);
+    //a taken username(in any casing) gets suffixed
+    let same_name_user = dyn_generate_user_struct(
+        new_user.user_name.to_uppercase(),
+        "same_name_dc".to_owned(),
+        "same_name_gh".to_owned(),
+    );
+    let same_name_user_id =
+        data_capturer::capture_new_user(execution_handler, &same_name_user).await;
+    assert!(same_name_user_id > 0);
+    let username = select_username(execution_handler, &same_name_user_id).await;
+    assert!(username.starts_with(&format!("{}_", same_name_user.user_name)));
+    assert_eq!(
+        select_username(execution_handler, &first_capture_user_id).await,
+        new_user.user_name
+    );
     return (first_capture_user_id, second_real_capture_user_id);
 }
 
+async fn select_username(execution_handler: &mut ExecutionHandler, user_id: &i32) -> String {
+    let rows = execution_handler.select_user_by_id(user_id).await.unwrap();
+    rows[0].get(3)
+}
+
+/// Prefix matches fill the results first, substring
+/// matches only make up what's left of the limit.
+pub async fn test_user_search(execution_handler: &mut ExecutionHandler) {
+    println!("testing user search");
+    let mut user_ids = Vec::new();
+    for (index, user_name) in ["zz_Findme", "findme_first", "findme_second"]
+        .iter()
+        .enumerate()
+    {
+        let mut user = dyn_generate_user_struct(
+            user_name.to_string(),
+            format!("search_dc_{}", index),
+            format!("search_gh_{}", index),
+        );
+        user.banned = false;
+        let user_id = data_capturer::capture_new_user(execution_handler, &user).await;
+        assert!(user_id > 0);
+        user_ids.push(user_id);
+    }
+    let query = "FINDME".to_owned();
+    let (encountered_error, results) =
+        data_fetcher::search_users(&query, Some(10), execution_handler).await;
+    assert!(!encountered_error);
+    let found: Vec<i32> = results.iter().map(|result| result.user_id).collect();
+    assert_eq!(found, vec![user_ids[1], user_ids[2], user_ids[0]]);
+
+    // enough prefix matches, the substring one never shows
+    let (_, results) = data_fetcher::search_users(&query, Some(2), execution_handler).await;
+    let found: Vec<i32> = results.iter().map(|result| result.user_id).collect();
+    assert_eq!(found, vec![user_ids[1], user_ids[2]]);
+}
+
+pub async fn test_user_ban_status_gather(execution_handler: &mut ExecutionHandler, user_id: &i32) {
+    println!("testing user ban status gather");
+    let banned = data_fetcher::get_user_ban_status(user_id, execution_handler).await;
+    let user = generate_user_struct();
+    assert_eq!(banned, Some((user.banned, user.banned_reason.clone())));
+    let missing = data_fetcher::get_user_ban_status(&-9999, execution_handler).await;
+    assert_eq!(missing, None);
+
+    //lift the ban and put it back
+    let result = data_capturer::capture_ban_status_update(
+        false,
+        "not banned".to_owned(),
+        user_id,
+        execution_handler,
+    )
+    .await;
+    assert_eq!(result.encountered_error, false);
+    let banned = data_fetcher::get_user_ban_status(user_id, execution_handler).await;
+    assert_eq!(banned, Some((false, "not banned".to_owned())));
+    let result = data_capturer::capture_ban_status_update(
+        true,
+        user.banned_reason.clone(),
+        user_id,
+        execution_handler,
+    )
+    .await;
+    assert_eq!(result.encountered_error, false);
+}
+
 pub async fn test_follow_capture_and_gather(
     execution_handler: &mut ExecutionHandler,
     user_ids: (&i32, &i32),
@@ -399,6 +481,7 @@ fn generate_user_struct() -> DBUser {
         bio: "teeeest".to_string(),
         contributions: 40,
         banner_url: "test.com/dwtest_banner".to_string(),
+        is_admin: false,
     };
     return user;
 }
@@ -419,6 +502,7 @@ fn dyn_generate_user_struct(user_name: String, d_id: String, g_id: String) -> DB
         bio: "teeeest".to_string(),
         contributions: 40,
         banner_url: "test.com/dwtest_banner".to_string(),
+        is_admin: false,
     };
     return user;
 }
@@ -439,6 +523,7 @@ fn generate_different_user_struct() -> DBUser {
         bio: "teldmdst2".to_string(),
         contributions: 40,
         banner_url: "test.doijeoocom/test_banner2".to_string(),
+        is_admin: false,
     };
     return user;
 }
@@ -528,6 +613,5 @@ pub async fn setup_execution_handler() -> Result<ExecutionHandler, Error> {
 }
 
 pub async fn setup_tables(execution_handler: &mut ExecutionHandler) {
-    let result = execution_handler.create_all_tables_if_needed().await;
-    result.unwrap();
+    migrations::run_migrations(execution_handler).await.unwrap();
 }
@@ -0,0 +1,64 @@
+use crate::communication::content_filter::{ContentFilter, FilterMode, Filtered};
+use crate::communication::handler;
+use crate::data_store::store::MemoryDataStore;
+use crate::rooms::chat_modes::ChatAccess;
+
+/// Followers-only rooms check the follow before anyone
+/// else is turned away, everything else needs no lookup.
+pub async fn test_chat_refusal() {
+    let mut store = MemoryDataStore::new();
+    store.follows.insert((5, 10));
+    assert_eq!(
+        handler::chat_refusal(ChatAccess::Open, &6, &mut store).await,
+        None
+    );
+    assert_eq!(
+        handler::chat_refusal(ChatAccess::NeedsFollow(10), &5, &mut store).await,
+        None
+    );
+    let refusal = handler::chat_refusal(ChatAccess::NeedsFollow(10), &6, &mut store).await;
+    assert_eq!(
+        refusal.map(|(_, op_code)| op_code),
+        Some("chat_followers_only")
+    );
+    // following someone else doesn't count
+    store.follows.insert((6, 11));
+    assert!(
+        handler::chat_refusal(ChatAccess::NeedsFollow(10), &6, &mut store)
+            .await
+            .is_some()
+    );
+    let refusal = handler::chat_refusal(ChatAccess::Closed, &5, &mut store).await;
+    assert_eq!(refusal.map(|(_, op_code)| op_code), Some("chat_disabled"));
+}
+
+/// Only whole words are caught, whatever their case,
+/// and nothing is caught while the filter is off.
+pub fn test_content_filter() {
+    assert_eq!(FilterMode::from_value(Some(" Mask ")), FilterMode::Mask);
+    assert_eq!(FilterMode::from_value(Some("reject")), FilterMode::Reject);
+    assert_eq!(FilterMode::from_value(Some("drop")), FilterMode::Off);
+    assert_eq!(FilterMode::from_value(None), FilterMode::Off);
+
+    let words = vec!["Darn".to_owned(), " heck ".to_owned(), "".to_owned()];
+    let mask = ContentFilter::new(FilterMode::Mask, &words);
+    assert_eq!(mask.num_words(), 2);
+    assert_eq!(
+        mask.apply("well DARN, what the heck!"),
+        Filtered::Masked("well ****, what the ****!".to_owned())
+    );
+    assert_eq!(mask.apply("darned checkers"), Filtered::Clean);
+    let mut name = "heck yes".to_owned();
+    assert!(mask.filter_in_place(&mut name));
+    assert_eq!(name, "**** yes");
+
+    let reject = ContentFilter::new(FilterMode::Reject, &words);
+    assert_eq!(reject.apply("oh darn"), Filtered::Rejected);
+    assert_eq!(reject.apply("all good"), Filtered::Clean);
+    let mut desc = "oh heck".to_owned();
+    assert!(!reject.filter_in_place(&mut desc));
+    assert_eq!(desc, "oh heck");
+
+    let off = ContentFilter::new(FilterMode::Off, &words);
+    assert_eq!(off.apply("darn"), Filtered::Clean);
+}
@@ -1,19 +1,23 @@
 use crate::communication::router;
 use crate::communication::tests::helpers::helpers;
-use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::communication::types::{AllHandsLowered, GenericRoomId};
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::rabbitmq::rabbit::PublishChannel;
 use crate::state::state::ServerState;
 use futures::lock::Mutex;
+use futures::FutureExt;
+use futures_util::StreamExt;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::wrappers::ReceiverStream;
 use warp::ws::Message;
 
 pub async fn users_in_room_as_listener_can_raise(
-    listener_rx: &mut UnboundedReceiverStream<Message>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    listener_rx: &mut ReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    speaker_rx: &mut UnboundedReceiverStream<Message>,
+    speaker_rx: &mut ReceiverStream<Message>,
 ) {
     // TESTCASE - USERS IN THE ROOM AS A LISTENER CAN REQUEST TO SPEAK
     // Make sure hand raising works for users in the room as listeners.
@@ -38,11 +42,11 @@ pub async fn users_in_room_as_listener_can_raise(
 }
 
 pub async fn users_can_lower_their_own_hand(
-    listener_rx: &mut UnboundedReceiverStream<Message>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    listener_rx: &mut ReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    speaker_rx: &mut UnboundedReceiverStream<Message>,
+    speaker_rx: &mut ReceiverStream<Message>,
 ) {
     // TESTCASE - USERS CAN LOWER THEIR OWN HAND
     // Make sure user who was declined to speak can request again
@@ -81,9 +85,76 @@ pub async fn users_can_lower_their_own_hand(
     helpers::clear_message_that_was_fanned(vec![speaker_rx]).await;
 }
 
+pub async fn raising_hand_twice_changes_nothing(
+    listener_rx: &mut ReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    speaker_rx: &mut ReceiverStream<Message>,
+) {
+    // TESTCASE - RAISING A HAND THAT IS ALREADY UP IS IGNORED
+    // User 34 raises their hand twice, only the first
+    // request is broadcast. Speakers(33) are told
+    // they already are one.
+    for _ in 0..2 {
+        let raise_hand_message = helpers::basic_request(
+            "raise_hand".to_owned(),
+            helpers::basic_hand_raise_or_lower(3, 34),
+        );
+        router::route_msg(
+            raise_hand_message,
+            34,
+            state,
+            publish_channel,
+            None,
+            execution_handler,
+        )
+        .await
+        .unwrap();
+    }
+    helpers::grab_and_assert_request_response(listener_rx, "user_asking_to_speak", "34").await;
+    helpers::clear_message_that_was_fanned(vec![&mut *speaker_rx]).await;
+    assert!(listener_rx.next().now_or_never().is_none());
+    assert!(speaker_rx.next().now_or_never().is_none());
+
+    let raise_hand_message = helpers::basic_request(
+        "raise_hand".to_owned(),
+        helpers::basic_hand_raise_or_lower(3, 33),
+    );
+    router::route_msg(
+        raise_hand_message,
+        33,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(speaker_rx, "already_speaker", "3").await;
+
+    //put the hand back down for the tests after this one
+    let lower_hand_message = helpers::basic_request(
+        "lower_hand".to_owned(),
+        helpers::basic_hand_raise_or_lower(3, 34),
+    );
+    router::route_msg(
+        lower_hand_message,
+        34,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(listener_rx, "user_hand_lowered", "34").await;
+    helpers::clear_message_that_was_fanned(vec![speaker_rx]).await;
+}
+
 pub async fn users_not_in_room_cannot_make_requests(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
 ) {
     // TESTCASE - USERS NOT IN THE ROOM CAN'T MAKE LOWER/RAISE REQUESTS
@@ -137,3 +208,59 @@ pub async fn users_not_in_room_cannot_make_requests(
     )
     .await;
 }
+
+pub async fn owner_can_lower_all_hands(
+    listener_rx: &mut ReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    speaker_rx: &mut ReceiverStream<Message>,
+) {
+    // TESTCASE - ONLY MODS/OWNERS CAN LOWER EVERY HAND AT ONCE
+    // User 34 raises their hand and tries to clear every
+    // hand, which only the owner(33) is allowed to do.
+    let raise_hand_message = helpers::basic_request(
+        "raise_hand".to_owned(),
+        helpers::basic_hand_raise_or_lower(3, 34),
+    );
+    router::route_msg(
+        raise_hand_message,
+        34,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(listener_rx, "user_asking_to_speak", "34").await;
+    helpers::clear_message_that_was_fanned(vec![speaker_rx]).await;
+
+    let room_data = serde_json::to_string(&GenericRoomId { room_id: 3 }).unwrap();
+    for (requester_id, rx) in [(34, &mut *listener_rx), (33, &mut *speaker_rx)] {
+        let request = helpers::basic_request("lower_all_hands".to_owned(), room_data.clone());
+        router::route_msg(
+            request,
+            requester_id,
+            state,
+            publish_channel,
+            None,
+            execution_handler,
+        )
+        .await
+        .unwrap();
+        if requester_id == 34 {
+            helpers::grab_and_assert_request_response(rx, "invalid_request", "issue with request")
+                .await;
+        }
+    }
+    let expected = serde_json::to_string(&AllHandsLowered {
+        room_id: 3,
+        user_ids: vec![34],
+        amount: 1,
+        lowered_by: 33,
+    })
+    .unwrap();
+    helpers::grab_and_assert_request_response(speaker_rx, "all_hands_lowered", &expected).await;
+    helpers::grab_and_assert_request_response(listener_rx, "all_hands_lowered", &expected).await;
+}
@@ -8,36 +8,38 @@ pub mod helpers {
     use crate::communication::helpers;
     use crate::communication::types::{
         AllUsersInRoomResponse, BasicRequest, BasicResponse, BasicRoomCreation, CommunicationRoom,
-        GenericRoomIdAndPeerId, GenericUserId, User, UserPreview, VoiceServerClosePeer,
-        VoiceServerCreateRoom, VoiceServerRequest,
+        GenericRoomIdAndPeerId, GenericUserId, RoomCreated, SingleUserPermissionResults, User,
+        UserPreview, VoiceServerClosePeer, VoiceServerCreateRoom, VoiceServerRequest,
     };
     use crate::communication::{data_capturer, router};
     use crate::data_store::db_models::DBUser;
-    use crate::data_store::sql_execution_handler::ExecutionHandler;
-    use crate::rabbitmq::rabbit;
+    use crate::data_store::pool::ExecutionHandlerPool;
+    use crate::rabbitmq::rabbit::{self, PublishChannel};
+    use crate::state::session::ConnectionState;
     use crate::state::state::ServerState;
-    use crate::state::types;
+    use crate::state::types::{self, ConnectionId, PeerSender};
     use futures::lock::Mutex;
+    use futures::FutureExt;
     #[allow(unused_imports)]
     use futures_util::{stream::SplitSink, SinkExt, StreamExt, TryFutureExt};
     use lapin::{options::*, types::FieldTable, Channel, Connection, Consumer};
     use serde::Serialize;
     use std::collections::HashMap;
     use std::sync::Arc;
-    use tokio::sync::mpsc;
+
     use tokio::sync::RwLock;
-    use tokio_stream::wrappers::UnboundedReceiverStream;
+    use tokio_stream::wrappers::ReceiverStream;
     use warp::ws::Message;
     //All users must be present in memory before operation
     //unless they are spawned apart of a test
     pub async fn spawn_new_user_and_join_room(
-        publish_channel: &Arc<Mutex<lapin::Channel>>,
-        execution_handler: &Arc<Mutex<ExecutionHandler>>,
+        publish_channel: &Arc<Mutex<PublishChannel>>,
+        execution_handler: &Arc<ExecutionHandlerPool>,
         state: &Arc<RwLock<ServerState>>,
         user_id: i32,
         consume_channel: &mut Consumer,
-    ) -> UnboundedReceiverStream<Message> {
-        let mock_temp_user = create_and_add_new_user_channel_to_peer_map(user_id, state).await;
+    ) -> ReceiverStream<Message> {
+        let mut mock_temp_user = create_and_add_new_user_channel_to_peer_map(user_id, state).await;
         insert_user_state(state, user_id).await;
         let create_room_msg = basic_request(
             "join-as-new-peer".to_owned(),
@@ -62,6 +64,8 @@ pub mod helpers {
         //already confirmed this
         //functionality is correct.
         consume_message(consume_channel).await;
+        //the user is told how they joined
+        drain_pending_messages(vec![&mut mock_temp_user]);
         return mock_temp_user;
     }
 
@@ -77,6 +81,7 @@ pub mod helpers {
             deaf: true,
             ip: "test".to_string(),
             current_room_id: -1,
+            guest: false,
         };
         state.active_users.insert(user_id, user);
     }
@@ -105,17 +110,21 @@ pub mod helpers {
     pub async fn create_and_add_new_user_channel_to_peer_map(
         mock_id: i32,
         mock_state: &Arc<RwLock<ServerState>>,
-    ) -> UnboundedReceiverStream<Message> {
-        let (tx, rx) = mpsc::unbounded_channel();
-        let rx = UnboundedReceiverStream::new(rx);
+    ) -> ReceiverStream<Message> {
+        let (tx, rx, _) = PeerSender::new();
+        let rx = ReceiverStream::new(rx);
         //add initial peer state to state
         //we will use th
-        mock_state.write().await.peer_map.insert(mock_id, tx);
+        mock_state
+            .write()
+            .await
+            .peer_map
+            .insert(ConnectionId::next(), mock_id, tx);
         return rx;
     }
 
     pub async fn grab_and_assert_request_response(
-        rx: &mut UnboundedReceiverStream<Message>,
+        rx: &mut ReceiverStream<Message>,
         op_code: &str,
         containing_data: &str,
     ) {
@@ -144,16 +153,13 @@ pub mod helpers {
         return serde_json::to_string(&BasicRequest {
             request_op_code: op,
             request_containing_data: data,
+            request_id: None,
         })
         .unwrap();
     }
 
     pub fn basic_hand_raise_or_lower(room_id: i32, peer_id: i32) -> String {
-        return serde_json::to_string(&GenericRoomIdAndPeerId {
-            roomId: room_id,
-            peerId: peer_id,
-        })
-        .unwrap();
+        return serde_json::to_string(&GenericRoomIdAndPeerId { room_id, peer_id }).unwrap();
     }
 
     pub fn basic_room_creation() -> String {
@@ -161,29 +167,32 @@ pub mod helpers {
             name: "test".to_owned(),
             desc: "test".to_owned(),
             public: true,
+            idempotency_key: None,
+            chat_mode: None,
+            tags: vec!["music".to_owned()],
         })
         .unwrap();
     }
 
     pub fn basic_voice_server_creation() -> String {
         return serde_json::to_string(&VoiceServerCreateRoom {
-            roomId: 3.to_string(),
+            room_id: 3.to_string(),
         })
         .unwrap();
     }
 
     pub fn generic_room_and_peer_id(user_id: i32, room_id: i32) -> String {
         return serde_json::to_string(&GenericRoomIdAndPeerId {
-            roomId: room_id,
-            peerId: user_id,
+            room_id,
+            peer_id: user_id,
         })
         .unwrap();
     }
 
     pub fn generic_close_peer(user_id: i32, room_id: i32) -> String {
         return serde_json::to_string(&VoiceServerClosePeer {
-            roomId: room_id.to_string(),
-            peerId: user_id.to_string(),
+            room_id: room_id.to_string(),
+            peer_id: user_id.to_string(),
             kicked: true,
         })
         .unwrap();
@@ -192,8 +201,8 @@ pub mod helpers {
     pub async fn send_create_or_join_room_request(
         state: &Arc<RwLock<ServerState>>,
         msg: String,
-        publish_channel: &Arc<Mutex<lapin::Channel>>,
-        execution_handler: &Arc<Mutex<ExecutionHandler>>,
+        publish_channel: &Arc<Mutex<PublishChannel>>,
+        execution_handler: &Arc<ExecutionHandlerPool>,
         curr_room: i32,
         user_id: &i32,
     ) {
@@ -221,12 +230,44 @@ pub mod helpers {
     //
     //The way we do our tests, requires the user's
     //channel to be completely clear.
-    pub async fn clear_message_that_was_fanned(rxs: Vec<&mut UnboundedReceiverStream<Message>>) {
+    pub async fn clear_message_that_was_fanned(rxs: Vec<&mut ReceiverStream<Message>>) {
         for rx in rxs {
             rx.next().await.unwrap();
         }
     }
 
+    //Joining a room fans the joiner's permissions to
+    //everyone in it, this clears whatever is already
+    //sitting in the channels without waiting on more.
+    pub fn drain_pending_messages(rxs: Vec<&mut ReceiverStream<Message>>) {
+        for rx in rxs {
+            while let Some(Some(_)) = rx.next().now_or_never() {}
+        }
+    }
+
+    pub async fn grab_permissions_update(
+        rx: &mut ReceiverStream<Message>,
+    ) -> SingleUserPermissionResults {
+        let message = rx.next().await.unwrap().to_str().unwrap().to_owned();
+        let response: BasicResponse = serde_json::from_str(&message).unwrap();
+        assert_eq!(response.response_op_code, "user_permissions_updated");
+        serde_json::from_str(&response.response_containing_data).unwrap()
+    }
+
+    pub async fn grab_room_created(rx: &mut ReceiverStream<Message>) -> RoomCreated {
+        let message = rx.next().await.unwrap().to_str().unwrap().to_owned();
+        let response: BasicResponse = serde_json::from_str(&message).unwrap();
+        assert_eq!(response.response_op_code, "room_created");
+        serde_json::from_str(&response.response_containing_data).unwrap()
+    }
+
+    pub async fn grab_room_event_history(rx: &mut ReceiverStream<Message>) -> serde_json::Value {
+        let message = rx.next().await.unwrap().to_str().unwrap().to_owned();
+        let response: BasicResponse = serde_json::from_str(&message).unwrap();
+        assert_eq!(response.response_op_code, "room_event_history");
+        serde_json::from_str(&response.response_containing_data).unwrap()
+    }
+
     pub fn generate_user_struct(gh_id: String, dc_id: String) -> DBUser {
         let user: DBUser = DBUser {
             id: 0, //doesn't matter in insertion
@@ -243,6 +284,7 @@ pub mod helpers {
             bio: "teldmdst2".to_string(),
             contributions: 40,
             banner_url: "test.doijeoocom/test_banner2".to_string(),
+            is_admin: false,
         };
         return user;
     }
@@ -260,13 +302,13 @@ pub mod helpers {
     }
 
     pub async fn spawn_new_real_user_and_join_room(
-        publish_channel: &Arc<Mutex<lapin::Channel>>,
-        execution_handler: &Arc<Mutex<ExecutionHandler>>,
+        publish_channel: &Arc<Mutex<PublishChannel>>,
+        execution_handler: &Arc<ExecutionHandlerPool>,
         state: &Arc<RwLock<ServerState>>,
         consume_channel: &mut Consumer,
         gh_id: String,
         dc_id: String,
-    ) -> (i32, UnboundedReceiverStream<Message>) {
+    ) -> (i32, ReceiverStream<Message>) {
         let mut handler = execution_handler.lock().await;
         let user_id =
             data_capturer::capture_new_user(&mut handler, &generate_user_struct(gh_id, dc_id))
@@ -298,13 +340,7 @@ pub mod helpers {
         let read_state = state.read().await;
         let room_state = read_state.rooms.get(&3).unwrap();
         let mut holder: Vec<CommunicationRoom> = Vec::new();
-        helpers::construct_communication_room(
-            mock_previews,
-            room_state,
-            &mut holder,
-            33,
-            "fast".to_string(),
-        );
+        helpers::construct_communication_room(mock_previews, room_state, &mut holder, 33);
         return holder;
     }
 
@@ -324,6 +360,7 @@ pub mod helpers {
             contributions: 40,
             bio: "teldmdst2".to_string(),
             banner_url: "test.doijeoocom/test_banner2".to_string(),
+            connection_state: ConnectionState::Connected,
         };
         let response = AllUsersInRoomResponse {
             room_id: 3,
@@ -333,11 +370,11 @@ pub mod helpers {
     }
 
     pub async fn trigger_block_or_unblock(
-        publish_channel: &Arc<Mutex<lapin::Channel>>,
+        publish_channel: &Arc<Mutex<PublishChannel>>,
         state: &Arc<RwLock<ServerState>>,
-        execution_handler: &Arc<Mutex<ExecutionHandler>>,
-        new_user: &mut (i32, UnboundedReceiverStream<Message>),
-        new_second_user: &(i32, UnboundedReceiverStream<Message>),
+        execution_handler: &Arc<ExecutionHandlerPool>,
+        new_user: &mut (i32, ReceiverStream<Message>),
+        new_second_user: &(i32, ReceiverStream<Message>),
         block_op_code: String,
         response_op_code: &str,
     ) {
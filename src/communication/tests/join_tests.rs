@@ -0,0 +1,57 @@
+use crate::communication::handler::{self, RoomJoinCheck};
+use crate::communication::types::{AlreadyInRoom, BasicResponse};
+use crate::rooms::handler::construct_basic_room_for_state;
+use crate::state::state::ServerState;
+use crate::state::types::{ConnectionId, PeerSender, User};
+
+/// Joining while in another room gets its own
+/// error carrying the room the user is in.
+pub async fn test_already_in_room() {
+    let mut state = ServerState::new();
+    for room_id in [1, 2] {
+        state.rooms.insert(
+            room_id,
+            construct_basic_room_for_state(room_id, true, "join".to_owned(), "".to_owned()),
+        );
+    }
+    state.active_users.insert(
+        5,
+        User {
+            current_room_id: 1,
+            ..Default::default()
+        },
+    );
+    state.active_users.insert(
+        6,
+        User {
+            current_room_id: -1,
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        handler::check_room_join(&state, &5, &5, &2),
+        RoomJoinCheck::AlreadyInRoom(1)
+    );
+    assert_eq!(
+        handler::check_room_join(&state, &6, &6, &2),
+        RoomJoinCheck::Joinable
+    );
+    // joining for someone else or a missing room is still generic
+    assert_eq!(
+        handler::check_room_join(&state, &6, &5, &2),
+        RoomJoinCheck::NotJoinable
+    );
+    assert_eq!(
+        handler::check_room_join(&state, &6, &6, &9),
+        RoomJoinCheck::NotJoinable
+    );
+
+    let (tx, mut rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 5, tx);
+    handler::send_already_in_room(5, 1, &mut state);
+    let msg = rx.recv().await.unwrap();
+    let response: BasicResponse = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+    assert_eq!(response.response_op_code, "already_in_room");
+    let data: AlreadyInRoom = serde_json::from_str(&response.response_containing_data).unwrap();
+    assert_eq!(data.room_id, 1);
+}
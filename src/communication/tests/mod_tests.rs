@@ -1,19 +1,20 @@
 use crate::communication::router;
 use crate::communication::tests::helpers::helpers;
 use crate::communication::types::GenericRoomIdAndPeerId;
-use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::rabbitmq::rabbit::PublishChannel;
 use crate::state::state::ServerState;
 use futures::lock::Mutex;
 use lapin::Consumer;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::wrappers::ReceiverStream;
 use warp::ws::Message;
 
 pub async fn mods_can_remove_speaker(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
 ) {
     let data = helpers::generic_room_and_peer_id(34, 3);
@@ -31,8 +32,8 @@ pub async fn mods_can_remove_speaker(
 }
 
 pub async fn non_mods_can_not_bring_up_speakers(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
     consume_channel: &mut Consumer,
 ) {
@@ -56,8 +57,8 @@ pub async fn non_mods_can_not_bring_up_speakers(
 
 pub async fn mods_can_bring_up_speakers(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
 ) {
     //TESTCASE - Mods can bring up speakers
@@ -78,8 +79,8 @@ pub async fn mods_can_bring_up_speakers(
 }
 
 pub async fn non_mods_can_not_remove_speaker(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
     consume_channel: &mut Consumer,
 ) {
@@ -100,9 +101,33 @@ pub async fn non_mods_can_not_remove_speaker(
         .await;
 }
 
+/// Runs after 34 was removed as a speaker, the owner(33)
+/// is still one.
+pub async fn speaker_changes_that_change_nothing_are_refused(
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    owner_rx: &mut ReceiverStream<Message>,
+) {
+    helpers::drain_pending_messages(vec![owner_rx]);
+    let data = helpers::generic_room_and_peer_id(34, 3);
+    let request = helpers::basic_request("remove_speaker".to_string(), data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(owner_rx, "not_a_speaker", "34").await;
+
+    let data = helpers::generic_room_and_peer_id(33, 3);
+    let request = helpers::basic_request("add_speaker".to_owned(), data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(owner_rx, "already_a_speaker", "33").await;
+}
+
 pub async fn non_mods_can_not_lower_hands(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
     consume_channel: &mut Consumer,
 ) {
@@ -144,11 +169,11 @@ pub async fn non_mods_can_not_lower_hands(
 }
 
 pub async fn mods_can_lower_hands(
-    listener_rx: &mut UnboundedReceiverStream<Message>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    listener_rx: &mut ReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    speaker_rx: &mut UnboundedReceiverStream<Message>,
+    speaker_rx: &mut ReceiverStream<Message>,
 ) {
     // TESTCASE - MODS CAN LOWER HANDS
     // Make sure the room owner can lower the hand of 34 ,
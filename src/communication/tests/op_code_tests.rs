@@ -0,0 +1,157 @@
+use crate::communication::op_codes::{OpCode, VoiceServerOp, OP_CODE_TABLE, VOICE_SERVER_OP_TABLE};
+use crate::communication::router::{
+    await_request, op_is_read_only, request_timeout_from, DEFAULT_REQUEST_TIMEOUT_MS,
+};
+use crate::communication::types::{
+    GenericRoomIdAndPeerId, VoiceServerClosePeer, VoiceServerRecording, VoiceServerRequest,
+};
+use futures::future::pending;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::time::sleep;
+
+/// Every op code should be registered once,
+/// under a single wire name.
+pub fn test_op_codes_are_unique() {
+    let mut names = HashSet::new();
+    let mut op_codes = HashSet::new();
+    for (name, op_code) in OP_CODE_TABLE {
+        assert!(names.insert(*name), "duplicate op code name: {}", name);
+        assert!(
+            op_codes.insert(*op_code),
+            "op code registered twice: {}",
+            name
+        );
+        assert_eq!(OpCode::from_str(name), Ok(*op_code));
+        assert_eq!(op_code.as_str(), *name);
+    }
+    assert!(OpCode::from_str("not_a_real_op_code").is_err());
+}
+
+/// Requests to the voice server are shaped
+/// {"op":<op>,"d":<data>,"uid":<user id>}.
+pub fn test_voice_server_ops_serialize() {
+    let mut names = HashSet::new();
+    for (name, op) in VOICE_SERVER_OP_TABLE {
+        assert!(names.insert(*name), "duplicate voice server op: {}", name);
+        assert_eq!(VoiceServerOp::from_str(name), Ok(*op));
+        let request = VoiceServerRequest::new(
+            *op,
+            GenericRoomIdAndPeerId {
+                room_id: 3,
+                peer_id: 33,
+            },
+            "33".to_owned(),
+        );
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "op": name,
+                "d": {"roomId": 3, "peerId": 33},
+                "uid": "33",
+            })
+        );
+    }
+    assert!(VoiceServerOp::from_str("close_peer").is_err());
+}
+
+/// Rust fields stay snake case while
+/// the json the voice server sees is camel case.
+pub fn test_voice_server_types_are_camel_case() {
+    let close_peer = VoiceServerClosePeer {
+        room_id: "3".to_owned(),
+        peer_id: "33".to_owned(),
+        kicked: false,
+    };
+    assert_eq!(
+        serde_json::to_value(&close_peer).unwrap(),
+        serde_json::json!({"roomId": "3", "peerId": "33", "kicked": false})
+    );
+    let parsed: GenericRoomIdAndPeerId =
+        serde_json::from_str("{\"roomId\": 3, \"peerId\": 33}").unwrap();
+    assert_eq!((parsed.room_id, parsed.peer_id), (3, 33));
+    assert!(
+        serde_json::from_str::<GenericRoomIdAndPeerId>("{\"room_id\": 3, \"peer_id\": 33}")
+            .is_err()
+    );
+    let recording = VoiceServerRecording {
+        room_id: "3".to_owned(),
+        recording_id: "7".to_owned(),
+    };
+    assert_eq!(
+        serde_json::to_value(&recording).unwrap(),
+        serde_json::json!({"roomId": "3", "recordingId": "7"})
+    );
+}
+
+/// Ops without an override fall back to the default,
+/// and unparsable values are ignored.
+pub fn test_request_timeouts() {
+    let default = Duration::from_millis(DEFAULT_REQUEST_TIMEOUT_MS);
+    assert_eq!(request_timeout_from("get_top_rooms", None, None), default);
+    assert_eq!(
+        request_timeout_from("get_top_rooms", Some("1500"), None),
+        Duration::from_millis(1500)
+    );
+    assert_eq!(
+        request_timeout_from("get_top_rooms", Some("soon"), None),
+        default
+    );
+    let per_op = Some(" get_top_rooms = 2000,create_room=8000,join_room=later");
+    assert_eq!(
+        request_timeout_from("get_top_rooms", Some("1500"), per_op),
+        Duration::from_millis(2000)
+    );
+    assert_eq!(
+        request_timeout_from("create_room", None, per_op),
+        Duration::from_millis(8000)
+    );
+    assert_eq!(request_timeout_from("join_room", None, per_op), default);
+    assert_eq!(request_timeout_from("leave_room", None, per_op), default);
+}
+
+/// A read past its timeout is aborted and its locks are free
+/// by the time await_request returns, anything without a
+/// timeout is waited on to the end.
+pub async fn test_timed_out_requests_are_awaited() {
+    let lock = Arc::new(RwLock::new(0));
+    let request = tokio::spawn({
+        let lock = lock.clone();
+        async move {
+            let _guard = lock.write().await;
+            pending::<()>().await;
+        }
+    });
+    let mut timed_out = None;
+    let result = await_request(request, Some(Duration::from_millis(10)), |after| {
+        timed_out = Some(after)
+    })
+    .await;
+    assert!(result.is_none());
+    assert_eq!(timed_out, Some(Duration::from_millis(10)));
+    assert!(lock.try_write().is_ok());
+
+    let finished = Arc::new(AtomicBool::new(false));
+    let request = tokio::spawn({
+        let finished = finished.clone();
+        async move {
+            sleep(Duration::from_millis(50)).await;
+            finished.store(true, Ordering::SeqCst);
+            7
+        }
+    });
+    let mut timed_out = false;
+    let result = await_request(request, None, |_| timed_out = true).await;
+    assert!(!timed_out);
+    assert_eq!(result.unwrap().unwrap(), 7);
+    assert!(finished.load(Ordering::SeqCst));
+
+    assert!(op_is_read_only(OpCode::GetTopRooms));
+    assert!(op_is_read_only(OpCode::GetPreferences));
+    assert!(!op_is_read_only(OpCode::CreateRoom));
+    assert!(!op_is_read_only(OpCode::FollowUser));
+}
@@ -1,20 +1,27 @@
+use crate::communication::data_fetcher;
 use crate::communication::router;
 use crate::communication::tests::helpers::helpers;
-use crate::communication::types::{BlockUserFromRoom, VoiceServerClosePeer};
-use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::communication::types::{
+    BasicResponse, BlockUserFromRoom, GenericRoomId, GenericUserId, RoomBlockedUsers,
+    UserRemovedFromRoom, VoiceServerClosePeer,
+};
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::rabbitmq::rabbit::PublishChannel;
 use crate::state::state::ServerState;
 use futures::lock::Mutex;
+use futures_util::StreamExt;
 use lapin::Consumer;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::wrappers::ReceiverStream;
 use warp::ws::Message;
 
 pub async fn owner_can_block_from_room(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
+    owner_rx: &mut ReceiverStream<Message>,
 ) {
     //clear room and add new real user
     //(see begining of file for real vs mock users)
@@ -29,10 +36,13 @@ pub async fn owner_can_block_from_room(
     )
     .await
     .0;
+    let joined = helpers::grab_permissions_update(owner_rx).await;
+    assert_eq!(joined.user_id, new_real_user_id);
 
     let data = serde_json::to_string(&BlockUserFromRoom {
         user_id: new_real_user_id.clone(),
         room_id: 3,
+        reason: None,
     })
     .unwrap();
     let request = helpers::basic_request("block_user_from_room".to_string(), data.clone());
@@ -48,6 +58,14 @@ pub async fn owner_can_block_from_room(
     )
     .await;
 
+    //the room is told this was a ban
+    helpers::grab_and_assert_request_response(
+        owner_rx,
+        "user_removed_from_room",
+        &removal_data(new_real_user_id, "ban"),
+    )
+    .await;
+
     //make sure the user we just blocked is no longer in the room state
     assert!(
         state
@@ -60,18 +78,59 @@ pub async fn owner_can_block_from_room(
             .contains(&new_real_user_id)
             == false
     );
+
+    //the owner can review the block and lift it again
+    let room_data = serde_json::to_string(&GenericRoomId { room_id: 3 }).unwrap();
+    let request = helpers::basic_request("get_room_blocked_users".to_string(), room_data.clone());
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    let blocked = grab_room_blocked_users(owner_rx).await;
+    assert_eq!(blocked.room_id, 3);
+    assert!(blocked
+        .blocked_users
+        .iter()
+        .any(|blocked_user| blocked_user.user_id == new_real_user_id));
+
+    let request = helpers::basic_request("unblock_user_from_room".to_string(), data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(
+        owner_rx,
+        "user_unblocked_from_room",
+        &new_real_user_id.to_string(),
+    )
+    .await;
+    let request = helpers::basic_request("get_room_blocked_users".to_string(), room_data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    let blocked = grab_room_blocked_users(owner_rx).await;
+    assert!(!blocked
+        .blocked_users
+        .iter()
+        .any(|blocked_user| blocked_user.user_id == new_real_user_id));
+}
+
+async fn grab_room_blocked_users(rx: &mut ReceiverStream<Message>) -> RoomBlockedUsers {
+    let message = rx.next().await.unwrap().to_str().unwrap().to_owned();
+    let response: BasicResponse = serde_json::from_str(&message).unwrap();
+    assert_eq!(response.response_op_code, "room_blocked_users");
+    serde_json::from_str(&response.response_containing_data).unwrap()
 }
 
 pub async fn non_owner_can_not_block_from_room(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    listener_rx: &mut UnboundedReceiverStream<Message>,
+    listener_rx: &mut ReceiverStream<Message>,
 ) {
     //User 34 is not the owner, so this should fail
     let data = serde_json::to_string(&BlockUserFromRoom {
         user_id: 38,
         room_id: 3,
+        reason: None,
     })
     .unwrap();
     let request = helpers::basic_request("block_user_from_room".to_string(), data);
@@ -79,4 +138,197 @@ pub async fn non_owner_can_not_block_from_room(
         .await
         .unwrap();
     helpers::grab_and_assert_request_response(listener_rx, "issue_blocking_user", "38").await;
+
+    //nor can they see or lift the room's blocks
+    let room_data = serde_json::to_string(&GenericRoomId { room_id: 3 }).unwrap();
+    let request = helpers::basic_request("get_room_blocked_users".to_string(), room_data);
+    router::route_msg(request, 34, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(listener_rx, "invalid_request", "issue with request")
+        .await;
+    let data = serde_json::to_string(&BlockUserFromRoom {
+        user_id: 38,
+        room_id: 3,
+        reason: None,
+    })
+    .unwrap();
+    let request = helpers::basic_request("unblock_user_from_room".to_string(), data);
+    router::route_msg(request, 34, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(listener_rx, "invalid_request", "issue with request")
+        .await;
+}
+
+pub async fn owner_can_kick_from_room(
+    consume_channel: &mut Consumer,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    owner_rx: &mut ReceiverStream<Message>,
+) {
+    helpers::clear_all_users_except_owner(state).await;
+    let (new_real_user_id, mut kicked_rx) = helpers::spawn_new_real_user_and_join_room(
+        publish_channel,
+        execution_handler,
+        state,
+        consume_channel,
+        "kick29dkwo2kdoo2kd".to_string(),
+        "kick-29ekd92kdk".to_string(),
+    )
+    .await;
+    //listeners stay listeners when they join
+    let joined = helpers::grab_permissions_update(owner_rx).await;
+    assert_eq!(joined.user_id, new_real_user_id);
+    assert!(!joined.data.is_speaker);
+
+    let data = serde_json::to_string(&BlockUserFromRoom {
+        user_id: new_real_user_id.clone(),
+        room_id: 3,
+        reason: None,
+    })
+    .unwrap();
+    let request = helpers::basic_request("kick_user_from_room".to_string(), data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_message_to_voice_server::<VoiceServerClosePeer>(
+        consume_channel,
+        helpers::generic_close_peer(new_real_user_id, 3),
+        new_real_user_id.to_string(),
+        "close-peer".to_owned(),
+    )
+    .await;
+    let removal = removal_data(new_real_user_id, "kick");
+    helpers::grab_and_assert_request_response(owner_rx, "user_removed_from_room", &removal).await;
+    helpers::grab_and_assert_request_response(
+        &mut kicked_rx,
+        "you_were_removed_from_room",
+        &removal,
+    )
+    .await;
+
+    //kicked users leave the room, but aren't blocked
+    assert!(!state.read().await.rooms[&3]
+        .user_ids
+        .contains(&new_real_user_id));
+    let mut handler = execution_handler.lock().await;
+    let blocked = data_fetcher::get_blocked_user_ids_for_room(&mut handler, &3).await;
+    assert!(!blocked.1.contains(&new_real_user_id));
+}
+
+pub async fn non_owner_can_not_kick_from_room(
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    listener_rx: &mut ReceiverStream<Message>,
+) {
+    //User 34 is not the owner or a mod, so this should fail
+    let data = serde_json::to_string(&BlockUserFromRoom {
+        user_id: 38,
+        room_id: 3,
+        reason: None,
+    })
+    .unwrap();
+    let request = helpers::basic_request("kick_user_from_room".to_string(), data);
+    router::route_msg(request, 34, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(listener_rx, "issue_kicking_user", "38").await;
+}
+
+pub async fn non_owner_can_not_destroy_room(
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    listener_rx: &mut ReceiverStream<Message>,
+) {
+    //User 34 is not the owner, so the room should stay up
+    let data = serde_json::to_string(&GenericRoomId { room_id: 3 }).unwrap();
+    let request = helpers::basic_request("destroy_room".to_string(), data);
+    router::route_msg(
+        request,
+        34,
+        state,
+        publish_channel,
+        Some(publish_channel),
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(
+        listener_rx,
+        "invalid_request",
+        "only the owner can destroy a room",
+    )
+    .await;
+    assert!(state.read().await.rooms.contains_key(&3));
+}
+
+pub async fn owner_can_promote_and_demote_mods(
+    consume_channel: &mut Consumer,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    owner_rx: &mut ReceiverStream<Message>,
+) {
+    helpers::clear_all_users_except_owner(state).await;
+    let new_real_user_id = helpers::spawn_new_real_user_and_join_room(
+        publish_channel,
+        execution_handler,
+        state,
+        consume_channel,
+        "mod93kdo2kdoek2".to_string(),
+        "mod-2kd9ekdow2".to_string(),
+    )
+    .await
+    .0;
+    helpers::grab_permissions_update(owner_rx).await;
+    let data = serde_json::to_string(&GenericUserId {
+        user_id: new_real_user_id,
+    })
+    .unwrap();
+
+    let request = helpers::basic_request("add_mod".to_string(), data.clone());
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    let promoted = helpers::grab_permissions_update(owner_rx).await;
+    assert_eq!(promoted.user_id, new_real_user_id);
+    assert!(promoted.data.is_mod);
+    assert!(state.read().await.rooms[&3]
+        .mod_ids
+        .contains(&new_real_user_id));
+
+    let request = helpers::basic_request("remove_mod".to_string(), data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    let demoted = helpers::grab_permissions_update(owner_rx).await;
+    assert!(!demoted.data.is_mod);
+    //demoting doesn't touch speaker status
+    assert_eq!(demoted.data.is_speaker, promoted.data.is_speaker);
+    assert!(!state.read().await.rooms[&3]
+        .mod_ids
+        .contains(&new_real_user_id));
+
+    //the owner can't be demoted
+    let data = serde_json::to_string(&GenericUserId { user_id: 33 }).unwrap();
+    let request = helpers::basic_request("remove_mod".to_string(), data);
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(owner_rx, "invalid_request", "issue with request")
+        .await;
+}
+
+fn removal_data(user_id: i32, type_of_ban: &str) -> String {
+    serde_json::to_string(&UserRemovedFromRoom {
+        user_id,
+        type_of_ban: type_of_ban.to_owned(),
+        requester: 33,
+        room_id: 3,
+    })
+    .unwrap()
 }
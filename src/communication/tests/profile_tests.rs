@@ -0,0 +1,97 @@
+use crate::communication::data_capturer::profile_edit_problem;
+use crate::communication::helpers;
+use crate::communication::types::{UserPreferencesEdit, UserProfileEdit};
+use crate::data_store::db_models::DBUserPreferences;
+
+fn empty_edit() -> UserProfileEdit {
+    UserProfileEdit {
+        display_name: None,
+        username: None,
+        bio: None,
+        avatar_url: None,
+        banner_url: None,
+    }
+}
+
+/// Only the fields that are present get checked.
+pub fn test_profile_edit_validation() {
+    assert!(profile_edit_problem(&empty_edit()).is_none());
+
+    let mut edit = empty_edit();
+    edit.display_name = Some("ok name".to_owned());
+    edit.username = Some("new_user-1".to_owned());
+    edit.avatar_url = Some("https://cdn.discordapp.com/avatars/1/a.png".to_owned());
+    assert!(profile_edit_problem(&edit).is_none());
+
+    let mut edit = empty_edit();
+    edit.display_name = Some("no".to_owned());
+    assert!(profile_edit_problem(&edit).is_some());
+
+    let mut edit = empty_edit();
+    edit.username = Some("has spaces".to_owned());
+    assert!(profile_edit_problem(&edit).is_some());
+
+    let mut edit = empty_edit();
+    edit.banner_url = Some("javascript:alert(1)".to_owned());
+    assert!(profile_edit_problem(&edit).is_some());
+
+    let mut edit = empty_edit();
+    edit.bio = Some("".to_owned());
+    assert!(profile_edit_problem(&edit).is_some());
+}
+
+/// Images have to be https and come from a host we trust.
+pub fn test_image_url_allowlist() {
+    let hosts = helpers::image_hosts_from(" cdn.merlin.example , ");
+    assert_eq!(hosts.len(), 3);
+    for allowed in [
+        "https://cdn.discordapp.com/avatars/1/a.png",
+        "https://avatars.githubusercontent.com/u/1?v=4",
+        "https://cdn.merlin.example/banners/b.png",
+    ] {
+        assert!(
+            helpers::image_url_is_allowed(allowed, &hosts),
+            "{}",
+            allowed
+        );
+    }
+    for rejected in [
+        "http://cdn.discordapp.com/avatars/1/a.png",
+        "https://evil.example/a.png",
+        "https://cdn.discordapp.com.evil.example/a.png",
+        "https://user@cdn.discordapp.com/a.png",
+        "javascript:alert(1)",
+        "not a url",
+    ] {
+        assert!(
+            !helpers::image_url_is_allowed(rejected, &hosts),
+            "{}",
+            rejected
+        );
+    }
+    let too_long = format!("https://cdn.discordapp.com/{}", "a".repeat(255));
+    assert!(!helpers::image_url_is_allowed(&too_long, &hosts));
+
+    let mut edit = empty_edit();
+    edit.avatar_url = Some("https://evil.example/a.png".to_owned());
+    assert!(profile_edit_problem(&edit).is_some());
+}
+
+/// Missing keys fall back to the defaults, edits
+/// only carry what they change and reject unknown keys.
+pub fn test_user_preferences_parsing() {
+    let stored: DBUserPreferences = serde_json::from_str("{}").unwrap();
+    assert_eq!(stored, DBUserPreferences::default());
+    let stored: DBUserPreferences =
+        serde_json::from_str("{\"follower_alerts\": false, \"old_key\": 1}").unwrap();
+    assert!(!stored.follower_alerts);
+    assert!(stored.notify_followers_of_rooms);
+
+    let edit: UserPreferencesEdit =
+        serde_json::from_str("{\"whispers_from_strangers\": false}").unwrap();
+    assert_eq!(
+        serde_json::to_string(&edit).unwrap(),
+        "{\"whispers_from_strangers\":false}"
+    );
+    assert!(serde_json::from_str::<UserPreferencesEdit>("{\"theme\": \"dark\"}").is_err());
+}
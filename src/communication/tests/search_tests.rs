@@ -0,0 +1,10 @@
+use crate::communication::data_fetcher::user_search_patterns;
+
+/// Wildcards typed by users are matched literally.
+pub fn test_user_search_patterns() {
+    let (prefix, substring) = user_search_patterns(&"  Bob ".to_owned());
+    assert_eq!(prefix, "bob%");
+    assert_eq!(substring, "%bob%");
+    let (prefix, _) = user_search_patterns(&"100%_real\\".to_owned());
+    assert_eq!(prefix, "100\\%\\_real\\\\%");
+}
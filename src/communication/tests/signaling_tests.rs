@@ -0,0 +1,608 @@
+use crate::communication::op_codes::VoiceServerOp;
+use crate::communication::tests::helpers::helpers;
+use crate::communication::types::{
+    BasicRequest, BasicResponse, GenericRoomIdAndPeerId, RecordingStatus, RoomModerationLog,
+    VoiceServerCreateRoom,
+};
+use crate::communication::{data_capturer, data_fetcher, handler};
+use crate::data_store::db_models::DBRoomPermissions;
+use crate::data_store::pool::{ExecutionHandlerPool, Pool};
+use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::rabbitmq::signaling::RecordingSignaling;
+use crate::rooms;
+use crate::state::state::ServerState;
+use crate::state::types::{ConnectionId, PeerSender, User};
+use crate::state::voice_servers::{VoiceServerStrategy, VoiceServers};
+use futures::FutureExt;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{mpsc, RwLock};
+use tokio::time::{sleep, Duration};
+use warp::ws::Message;
+
+/// Media soup requests go to the voice server untouched.
+pub async fn test_web_rtc_requests_are_published() {
+    let signaling = RecordingSignaling::new();
+    let request = json!({"roomId": "3", "peerId": 34, "transportId": "t1"});
+    rooms::handler::handle_web_rtc_specific_requests(
+        request.clone(),
+        &signaling,
+        VoiceServerOp::ConnectTransport,
+    )
+    .await;
+    assert_eq!(
+        signaling.published_ops(),
+        vec![VoiceServerOp::ConnectTransport.as_str()]
+    );
+    let published: serde_json::Value = serde_json::from_str(&signaling.published()[0]).unwrap();
+    assert_eq!(published["d"], request);
+    assert_eq!(published["uid"], "34");
+}
+
+/// A room is created on the voice server exactly once, and
+/// a request that fails validation never reaches it.
+pub async fn test_create_room_publishes_once(mut execution_handler: ExecutionHandler) {
+    let user = helpers::generate_user_struct("signaling_gh".to_owned(), "signaling_dc".to_owned());
+    let user_id = data_capturer::capture_new_user(&mut execution_handler, &user).await;
+    assert_ne!(user_id, -1);
+    let execution_handler = Arc::new(Pool::new(vec![execution_handler]));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    state.write().await.active_users.insert(
+        user_id,
+        User {
+            current_room_id: -1,
+            ..Default::default()
+        },
+    );
+    let signaling = RecordingSignaling::new();
+
+    let invalid = BasicRequest {
+        request_op_code: "create_room".to_owned(),
+        request_containing_data: r#"{"name":"","desc":"","public":true}"#.to_owned(),
+        request_id: None,
+    };
+    handler::create_room(invalid, &state, &signaling, &execution_handler, user_id)
+        .await
+        .unwrap();
+    assert!(signaling.published().is_empty());
+
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "create_room".to_owned(),
+        helpers::basic_room_creation(),
+    ))
+    .unwrap();
+    handler::create_room(request, &state, &signaling, &execution_handler, user_id)
+        .await
+        .unwrap();
+    assert_eq!(
+        signaling.published_ops(),
+        vec![VoiceServerOp::CreateRoom.as_str()]
+    );
+    let published: serde_json::Value = serde_json::from_str(&signaling.published()[0]).unwrap();
+    let created: VoiceServerCreateRoom = serde_json::from_value(published["d"].clone()).unwrap();
+    assert!(state
+        .read()
+        .await
+        .rooms
+        .contains_key(&created.room_id.parse().unwrap()));
+}
+
+/// A requester that disconnected mid request is told
+/// they aren't authenticated instead of panicking.
+pub async fn test_create_room_for_absent_user(execution_handler: ExecutionHandler) {
+    let execution_handler = Arc::new(Pool::new(vec![execution_handler]));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    //still connected, but no longer an active user
+    let (tx, mut rx, _) = PeerSender::new();
+    state
+        .write()
+        .await
+        .peer_map
+        .insert(ConnectionId::next(), 404, tx);
+    let signaling = RecordingSignaling::new();
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "create_room".to_owned(),
+        helpers::basic_room_creation(),
+    ))
+    .unwrap();
+    handler::create_room(request, &state, &signaling, &execution_handler, 404)
+        .await
+        .unwrap();
+    let msg = rx.recv().await.unwrap();
+    let response: BasicResponse = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+    assert_eq!(response.response_op_code, "not_authenticated");
+    assert!(signaling.published().is_empty());
+    assert!(state.read().await.rooms.is_empty());
+}
+
+/// Rooms created at the same time each get their own id,
+/// and every one of them ends up in the state.
+pub async fn test_concurrent_room_creation(execution_handlers: Vec<ExecutionHandler>) {
+    const CREATORS: usize = 16;
+    let execution_handler = Arc::new(Pool::new(execution_handlers));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    let mut user_ids = Vec::new();
+    for index in 0..CREATORS {
+        let user = helpers::generate_user_struct(
+            format!("concurrent_gh_{}", index),
+            format!("concurrent_dc_{}", index),
+        );
+        let mut handler = execution_handler.lock().await;
+        let user_id = data_capturer::capture_new_user(&mut handler, &user).await;
+        assert_ne!(user_id, -1);
+        state.write().await.active_users.insert(
+            user_id,
+            User {
+                current_room_id: -1,
+                ..Default::default()
+            },
+        );
+        user_ids.push(user_id);
+    }
+    let signaling = Arc::new(RecordingSignaling::new());
+    let mut creations = Vec::new();
+    for user_id in user_ids.iter().copied() {
+        let (state, signaling, execution_handler) =
+            (state.clone(), signaling.clone(), execution_handler.clone());
+        creations.push(tokio::spawn(async move {
+            let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+                "create_room".to_owned(),
+                helpers::basic_room_creation(),
+            ))
+            .unwrap();
+            handler::create_room(request, &state, &*signaling, &execution_handler, user_id)
+                .await
+                .unwrap();
+        }));
+    }
+    for creation in creations {
+        creation.await.unwrap();
+    }
+    let mut room_ids: Vec<i32> = signaling
+        .published()
+        .iter()
+        .map(|data| {
+            let published: serde_json::Value = serde_json::from_str(data).unwrap();
+            let created: VoiceServerCreateRoom =
+                serde_json::from_value(published["d"].clone()).unwrap();
+            created.room_id.parse().unwrap()
+        })
+        .collect();
+    room_ids.sort();
+    room_ids.dedup();
+    assert_eq!(room_ids.len(), CREATORS);
+    let read_state = state.read().await;
+    assert_eq!(read_state.rooms.len(), CREATORS);
+    for room_id in room_ids {
+        let owner_id = read_state.rooms.get(&room_id).unwrap().owner_id;
+        assert!(user_ids.contains(&owner_id));
+    }
+}
+
+/// Sweeping a room nobody joined hands its voice
+/// server slot back.
+pub async fn test_swept_room_releases_voice_server(mut execution_handler: ExecutionHandler) {
+    let user = helpers::generate_user_struct("sweep_vs_gh".to_owned(), "sweep_vs_dc".to_owned());
+    let user_id = data_capturer::capture_new_user(&mut execution_handler, &user).await;
+    assert_ne!(user_id, -1);
+    let execution_handler = Arc::new(Pool::new(vec![execution_handler]));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    let mut write_state = state.write().await;
+    write_state.voice_servers =
+        VoiceServers::new(vec!["vs".to_owned()], VoiceServerStrategy::LeastLoaded);
+    write_state.active_users.insert(
+        user_id,
+        User {
+            current_room_id: -1,
+            ..Default::default()
+        },
+    );
+    drop(write_state);
+    let signaling = RecordingSignaling::new();
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "create_room".to_owned(),
+        helpers::basic_room_creation(),
+    ))
+    .unwrap();
+    handler::create_room(request, &state, &signaling, &execution_handler, user_id)
+        .await
+        .unwrap();
+    let mut write_state = state.write().await;
+    assert_eq!(write_state.voice_servers.room_counts["vs"], 1);
+
+    let grace = Duration::from_secs(30);
+    let swept = rooms::handler::destroy_expired_empty_rooms(
+        &mut write_state,
+        grace,
+        Instant::now() + grace,
+        &signaling,
+        &execution_handler,
+    )
+    .await;
+    assert_eq!(swept.len(), 1);
+    assert_eq!(write_state.voice_servers.room_counts["vs"], 0);
+}
+
+/// An emptied room survives its grace period, then the
+/// sweep tears down everything that belonged to it.
+pub async fn test_empty_rooms_are_swept(mut execution_handler: ExecutionHandler) {
+    let user = helpers::generate_user_struct("sweep_gh".to_owned(), "sweep_dc".to_owned());
+    let user_id = data_capturer::capture_new_user(&mut execution_handler, &user).await;
+    assert_ne!(user_id, -1);
+    let execution_handler = Arc::new(Pool::new(vec![execution_handler]));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    let mut write_state = state.write().await;
+    write_state.voice_servers =
+        VoiceServers::new(vec!["vs".to_owned()], VoiceServerStrategy::LeastLoaded);
+    write_state.active_users.insert(
+        user_id,
+        User {
+            current_room_id: -1,
+            ..Default::default()
+        },
+    );
+    drop(write_state);
+    let signaling = RecordingSignaling::new();
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "create_room".to_owned(),
+        helpers::basic_room_creation(),
+    ))
+    .unwrap();
+    handler::create_room(request, &state, &signaling, &execution_handler, user_id)
+        .await
+        .unwrap();
+    let mut write_state = state.write().await;
+    let room_id = *write_state.rooms.keys().next().unwrap();
+    write_state.add_user_to_room(user_id, room_id);
+    assert!(write_state
+        .rooms
+        .get_mut(&room_id)
+        .unwrap()
+        .remove_user(&user_id));
+    let emptied = write_state.rooms[&room_id].empty_since.unwrap();
+
+    let grace = Duration::from_secs(30);
+    let early = rooms::handler::destroy_expired_empty_rooms(
+        &mut write_state,
+        grace,
+        emptied + Duration::from_secs(29),
+        &signaling,
+        &execution_handler,
+    )
+    .await;
+    assert!(early.is_empty());
+    assert!(write_state.rooms.contains_key(&room_id));
+    assert!(write_state.owner_queues.contains_key(&room_id));
+    assert_eq!(write_state.voice_servers.room_counts["vs"], 1);
+
+    let swept = rooms::handler::destroy_expired_empty_rooms(
+        &mut write_state,
+        grace,
+        emptied + grace,
+        &signaling,
+        &execution_handler,
+    )
+    .await;
+    assert_eq!(swept, vec![room_id]);
+    assert!(!write_state.rooms.contains_key(&room_id));
+    assert!(!write_state.owner_queues.contains_key(&room_id));
+    assert_eq!(write_state.voice_servers.room_counts["vs"], 0);
+    assert_eq!(
+        signaling.published_ops(),
+        vec![
+            VoiceServerOp::CreateRoom.as_str(),
+            VoiceServerOp::DestroyRoom.as_str()
+        ]
+    );
+}
+
+/// Only the owner records, and everyone in the room(including
+/// whoever joins part way through) is told while it records.
+pub async fn test_room_recording(mut execution_handler: ExecutionHandler) {
+    let user = helpers::generate_user_struct("recording_gh".to_owned(), "recording_dc".to_owned());
+    let owner_id = data_capturer::capture_new_user(&mut execution_handler, &user).await;
+    assert_ne!(owner_id, -1);
+    let execution_handler = Arc::new(Pool::new(vec![execution_handler]));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    let signaling = RecordingSignaling::new();
+    let mut write_state = state.write().await;
+    write_state.active_users.insert(
+        owner_id,
+        User {
+            current_room_id: -1,
+            ..Default::default()
+        },
+    );
+    let (owner_tx, mut owner_rx, _) = PeerSender::new();
+    write_state
+        .peer_map
+        .insert(ConnectionId::next(), owner_id, owner_tx);
+    drop(write_state);
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "create_room".to_owned(),
+        helpers::basic_room_creation(),
+    ))
+    .unwrap();
+    handler::create_room(request, &state, &signaling, &execution_handler, owner_id)
+        .await
+        .unwrap();
+    let mut write_state = state.write().await;
+    let room_id = *write_state.rooms.keys().next().unwrap();
+    let mut early_rx = join_as_guest(&mut write_state, -2001, room_id, &signaling).await;
+    received_ops(&mut owner_rx);
+    received_ops(&mut early_rx);
+
+    let refused = rooms::handler::start_or_stop_recording(
+        &mut write_state,
+        &execution_handler,
+        &signaling,
+        &room_id,
+        &-2001,
+        true,
+    )
+    .await;
+    assert!(refused.is_some());
+    rooms::handler::start_or_stop_recording(
+        &mut write_state,
+        &execution_handler,
+        &signaling,
+        &room_id,
+        &owner_id,
+        true,
+    )
+    .await;
+    let recording = write_state.rooms[&room_id].recording.clone().unwrap();
+    assert_eq!(recording.started_by, owner_id);
+    assert_eq!(received_ops(&mut early_rx), vec!["recording_status"]);
+    // starting twice does nothing
+    assert!(rooms::handler::start_or_stop_recording(
+        &mut write_state,
+        &execution_handler,
+        &signaling,
+        &room_id,
+        &owner_id,
+        true,
+    )
+    .await
+    .is_some());
+
+    // joining part way through, they hear before anything else
+    let mut late_rx = join_as_guest(&mut write_state, -2002, room_id, &signaling).await;
+    let msg = late_rx.recv().now_or_never().flatten().unwrap();
+    let response: BasicResponse = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+    assert_eq!(response.response_op_code, "recording_status");
+    let status: RecordingStatus = serde_json::from_str(&response.response_containing_data).unwrap();
+    assert!(status.recording);
+    assert_eq!(status.started_by, Some(owner_id));
+
+    received_ops(&mut early_rx);
+    rooms::handler::start_or_stop_recording(
+        &mut write_state,
+        &execution_handler,
+        &signaling,
+        &room_id,
+        &owner_id,
+        false,
+    )
+    .await;
+    assert!(write_state.rooms[&room_id].recording.is_none());
+    assert_eq!(received_ops(&mut early_rx), vec!["recording_status"]);
+    let recording_ops: Vec<String> = signaling
+        .published_ops()
+        .into_iter()
+        .filter(|op| op.ends_with("-recording"))
+        .collect();
+    assert_eq!(recording_ops, vec!["start-recording", "stop-recording"]);
+    let mut handler = execution_handler.lock().await;
+    let rows = handler
+        .select_room_recordings_for_room(&room_id)
+        .await
+        .unwrap();
+    assert_eq!(rows[0].get::<usize, i32>(0), recording.recording_id);
+    assert!(rows[0].get::<usize, Option<String>>(3).is_some());
+}
+
+/// Bans, kicks and force mutes land in the moderation log, which
+/// only the room owner can read.
+pub async fn test_moderation_log(mut execution_handler: ExecutionHandler) {
+    let mut user_ids = Vec::new();
+    for name in ["moderation_owner", "moderation_member"] {
+        let user = helpers::generate_user_struct(format!("{}_gh", name), format!("{}_dc", name));
+        let user_id = data_capturer::capture_new_user(&mut execution_handler, &user).await;
+        assert_ne!(user_id, -1);
+        user_ids.push(user_id);
+    }
+    let (owner_id, member_id) = (user_ids[0], user_ids[1]);
+    let execution_handler = Arc::new(Pool::new(vec![execution_handler]));
+    let state = Arc::new(RwLock::new(ServerState::new()));
+    let signaling = RecordingSignaling::new();
+    let mut write_state = state.write().await;
+    let mut receivers = Vec::new();
+    for user_id in [owner_id, member_id] {
+        write_state.active_users.insert(
+            user_id,
+            User {
+                current_room_id: -1,
+                ..Default::default()
+            },
+        );
+        let (tx, rx, _) = PeerSender::new();
+        write_state
+            .peer_map
+            .insert(ConnectionId::next(), user_id, tx);
+        receivers.push(rx);
+    }
+    let mut member_rx = receivers.pop().unwrap();
+    let mut owner_rx = receivers.pop().unwrap();
+    drop(write_state);
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "create_room".to_owned(),
+        helpers::basic_room_creation(),
+    ))
+    .unwrap();
+    handler::create_room(request, &state, &signaling, &execution_handler, owner_id)
+        .await
+        .unwrap();
+    let room_id = *state.read().await.rooms.keys().next().unwrap();
+    for user_id in [owner_id, member_id] {
+        state.write().await.add_user_to_room(user_id, room_id);
+    }
+
+    // what joining would have given them
+    let listener = DBRoomPermissions {
+        id: -1,
+        user_id: member_id,
+        room_id,
+        is_mod: false,
+        is_speaker: false,
+        asked_to_speak: false,
+    };
+    execution_handler
+        .lock()
+        .await
+        .insert_room_permission(&listener)
+        .await
+        .unwrap();
+
+    // reasons are capped before anything happens
+    let kick = |reason: &str| {
+        let data = json!({"user_id": member_id, "room_id": room_id, "reason": reason});
+        serde_json::from_str::<BasicRequest>(&helpers::basic_request(
+            "kick_user_from_room".to_owned(),
+            data.to_string(),
+        ))
+        .unwrap()
+    };
+    received_ops(&mut owner_rx);
+    let too_long = "a".repeat(handler::MAX_MODERATION_REASON_LENGTH + 1);
+    handler::kick_user_from_room(
+        kick(&too_long),
+        owner_id,
+        &state,
+        &execution_handler,
+        &signaling,
+    )
+    .await
+    .unwrap();
+    assert_eq!(received_ops(&mut owner_rx), vec!["invalid_request"]);
+    assert!(state.read().await.user_in_room(member_id, room_id));
+
+    let mute = json!({"user_id": member_id, "room_id": room_id});
+    let request: BasicRequest = serde_json::from_str(&helpers::basic_request(
+        "force_mute_user".to_owned(),
+        mute.to_string(),
+    ))
+    .unwrap();
+    handler::force_mute_or_unmute_user(request, owner_id, &state, &execution_handler, &signaling)
+        .await
+        .unwrap();
+    // the log is written off the request path
+    wait_for_moderation_log(&execution_handler, room_id, 1).await;
+    handler::kick_user_from_room(
+        kick(" too loud "),
+        owner_id,
+        &state,
+        &execution_handler,
+        &signaling,
+    )
+    .await
+    .unwrap();
+    assert!(!state.read().await.user_in_room(member_id, room_id));
+    wait_for_moderation_log(&execution_handler, room_id, 2).await;
+
+    received_ops(&mut member_rx);
+    let request = || {
+        serde_json::from_str::<BasicRequest>(&helpers::basic_request(
+            "get_room_moderation_log".to_owned(),
+            json!({ "room_id": room_id }).to_string(),
+        ))
+        .unwrap()
+    };
+    handler::get_room_moderation_log(request(), &state, member_id, &execution_handler)
+        .await
+        .unwrap();
+    assert_eq!(received_ops(&mut member_rx), vec!["invalid_request"]);
+
+    received_ops(&mut owner_rx);
+    handler::get_room_moderation_log(request(), &state, owner_id, &execution_handler)
+        .await
+        .unwrap();
+    let msg = owner_rx.recv().now_or_never().flatten().unwrap();
+    let response: BasicResponse = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+    assert_eq!(response.response_op_code, "room_moderation_log");
+    let log: RoomModerationLog = serde_json::from_str(&response.response_containing_data).unwrap();
+    assert_eq!(log.room_id, room_id);
+    let entries: Vec<(i32, i32, &str, Option<&str>)> = log
+        .entries
+        .iter()
+        .map(|entry| {
+            (
+                entry.actor_id,
+                entry.target_id,
+                entry.action.as_str(),
+                entry.reason.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        entries,
+        vec![
+            (owner_id, member_id, "kick", Some("too loud")),
+            (owner_id, member_id, "mute", None),
+        ]
+    );
+}
+
+async fn wait_for_moderation_log(
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    room_id: i32,
+    entries: usize,
+) {
+    for _ in 0..100 {
+        let mut handler = execution_handler.lock().await;
+        let (encountered_error, log) =
+            data_fetcher::get_room_moderation_log(&mut handler, &room_id).await;
+        assert!(!encountered_error);
+        if log.len() >= entries {
+            return;
+        }
+        drop(handler);
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("room({}) never had {} moderation entries", room_id, entries);
+}
+
+async fn join_as_guest(
+    state: &mut ServerState,
+    guest_id: i32,
+    room_id: i32,
+    signaling: &RecordingSignaling,
+) -> mpsc::Receiver<Message> {
+    state.active_users.insert(
+        guest_id,
+        User {
+            current_room_id: -1,
+            guest: true,
+            ..Default::default()
+        },
+    );
+    let (tx, rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), guest_id, tx);
+    let join = GenericRoomIdAndPeerId {
+        room_id,
+        peer_id: guest_id,
+    };
+    rooms::handler::join_room_as_guest(join, state, signaling).await;
+    rx
+}
+
+/// Op codes of everything received so far.
+fn received_ops(rx: &mut mpsc::Receiver<Message>) -> Vec<String> {
+    let mut ops = Vec::new();
+    while let Some(Some(msg)) = rx.recv().now_or_never() {
+        let response: BasicResponse = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+        ops.push(response.response_op_code);
+    }
+    ops
+}
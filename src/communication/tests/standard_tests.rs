@@ -1,26 +1,33 @@
+use crate::communication::handler;
 use crate::communication::tests::helpers::helpers;
 use crate::communication::types::{
-    DeafAndMuteStatus, DeafAndMuteStatusUpdate, GenericRoomId, GenericRoomIdAndPeerId,
-    GenericUserId, RoomUpdate, VoiceServerClosePeer, VoiceServerCreateRoom, VoiceServerDestroyRoom,
+    Announcement, AnnouncementSeverity, BasicRequest, BasicResponse, DeafAndMuteStatus,
+    DeafAndMuteStatusUpdate, FollowRelationship, GenericRoomId, GenericRoomIdAndPeerId,
+    GenericUserId, MalformedPayload, RoomDetails, RoomMetaUpdate, RoomUpdate, VoiceServerClosePeer,
+    VoiceServerCreateRoom, VoiceServerDestroyRoom,
 };
 use crate::communication::{data_fetcher, router};
-use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::rabbitmq::rabbit::PublishChannel;
+use crate::rooms;
+use crate::rooms::chat_modes::ChatMode;
 use crate::state::state::ServerState;
 use futures::lock::Mutex;
+use futures_util::StreamExt;
 use lapin::Consumer;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::collections::HashSet;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::wrappers::ReceiverStream;
 use warp::ws::Message;
 
 pub async fn test_users_can_get_top_rooms(
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    speaker_rx: &mut UnboundedReceiverStream<Message>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    speaker_rx: &mut ReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     consume_channel: &mut Consumer,
 ) {
     //Before we make this request we need to remove
@@ -47,6 +54,7 @@ pub async fn test_users_can_get_top_rooms(
     )
     .await
     .0;
+    helpers::drain_pending_messages(vec![speaker_rx]);
     state
         .write()
         .await
@@ -78,9 +86,9 @@ pub async fn test_users_can_get_top_rooms(
 }
 
 pub async fn test_getting_all_users_in_room(
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     consume_channel: &mut Consumer,
 ) {
     //clear the only mock user
@@ -113,6 +121,7 @@ pub async fn test_getting_all_users_in_room(
         "fwieopjj291323121231231233234024nsdocikndv0".to_string(),
     )
     .await;
+    helpers::drain_pending_messages(vec![&mut new_user.1]);
     let data_for_request = GenericRoomId { room_id: 3 };
     let request = helpers::basic_request(
         "gather_all_users_in_room".to_owned(),
@@ -136,24 +145,116 @@ pub async fn test_getting_all_users_in_room(
         &mock_response,
     )
     .await;
+    //users outside of the room can't see who is in it
+    state
+        .write()
+        .await
+        .rooms
+        .get_mut(&3)
+        .unwrap()
+        .user_ids
+        .remove(&new_user.0);
+    let request = helpers::basic_request(
+        "gather_all_users_in_room".to_owned(),
+        serde_json::to_string(&data_for_request).unwrap(),
+    );
+    router::route_msg(
+        request,
+        new_user.0,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(
+        &mut new_user.1,
+        "invalid_request",
+        "issue with request",
+    )
+    .await;
     //cleanup
     let mut write_state = state.write().await;
     let room = write_state.rooms.get_mut(&3).unwrap();
-    room.user_ids.remove(&new_user.0);
     room.user_ids.remove(&new_second_user.0);
     room.user_ids.insert(33);
 }
 
+pub async fn test_joining_with_inactive_peer(
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    user_rx: &mut ReceiverStream<Message>,
+) {
+    helpers::drain_pending_messages(vec![&mut *user_rx]);
+    //9999 was never active, the same as a
+    //user that disconnected mid request
+    let request = GenericRoomIdAndPeerId {
+        room_id: 3,
+        peer_id: 9999,
+    };
+    let mut write_state = state.write().await;
+    rooms::handler::join_room(
+        request,
+        &mut write_state,
+        publish_channel,
+        execution_handler,
+        33,
+        "join-as-new-peer",
+    )
+    .await;
+    drop(write_state);
+    helpers::grab_and_assert_request_response(user_rx, "issue_joining_room", "9999").await;
+    assert!(!state
+        .read()
+        .await
+        .rooms
+        .get(&3)
+        .unwrap()
+        .user_ids
+        .contains(&9999));
+}
+
+//33 isn't in ADMIN_USER_IDS and isn't flagged
+//as an admin, so privileged handlers turn them away
+pub async fn test_non_admin_is_rejected(
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    state: &Arc<RwLock<ServerState>>,
+    user_rx: &mut ReceiverStream<Message>,
+) {
+    helpers::drain_pending_messages(vec![&mut *user_rx]);
+    let announcement = Announcement {
+        message: "maintenance in 5 minutes".to_owned(),
+        severity: AnnouncementSeverity::Warning,
+    };
+    let request = BasicRequest {
+        request_op_code: "broadcast_announcement".to_owned(),
+        request_containing_data: serde_json::to_string(&announcement).unwrap(),
+        request_id: None,
+    };
+    handler::broadcast_announcement(request, state, execution_handler, 33)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(
+        user_rx,
+        "invalid_request",
+        "only admins can send announcements",
+    )
+    .await;
+    assert!(!state.read().await.last_announcements.contains_key(&33));
+}
+
 //webrtc requests are dynamic because
 //they send browser connection information
 //to the voice server. So we will only test
 //the invalid case, a webrtc request is invalid
 //when there is no peerid and room id associated.
 pub async fn test_invalid_webrtc_request<T: Serialize + DeserializeOwned>(
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    speaker_rx: &mut UnboundedReceiverStream<Message>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    speaker_rx: &mut ReceiverStream<Message>,
     incorrect_data: T,
 ) {
     //The three invalid cases include:
@@ -181,12 +282,78 @@ pub async fn test_invalid_webrtc_request<T: Serialize + DeserializeOwned>(
         .await;
 }
 
+/// Bad request data is reported back
+/// instead of failing the route.
+pub async fn test_malformed_payload(
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_one_rx: &mut ReceiverStream<Message>,
+) {
+    println!("testing malformed payload");
+    let request = helpers::basic_request("search_rooms".to_owned(), "{}".to_owned());
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    let message = user_one_rx
+        .next()
+        .await
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let response: BasicResponse = serde_json::from_str(&message).unwrap();
+    assert_eq!(response.response_op_code, "malformed_payload");
+    let malformed: MalformedPayload =
+        serde_json::from_str(&response.response_containing_data).unwrap();
+    assert_eq!(malformed.op_code, "search_rooms");
+    assert!(malformed.reason.contains("query"));
+
+    router::route_msg(
+        "not json".to_owned(),
+        33,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    let message = user_one_rx
+        .next()
+        .await
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    let response: BasicResponse = serde_json::from_str(&message).unwrap();
+    assert_eq!(response.response_op_code, "malformed_payload");
+
+    // oversized requests are never parsed
+    let request = helpers::basic_request(
+        "search_rooms".to_owned(),
+        "a".repeat(router::DEFAULT_MAX_REQUEST_BYTES),
+    );
+    router::route_msg(request, 33, state, publish_channel, None, execution_handler)
+        .await
+        .unwrap();
+    helpers::grab_and_assert_request_response(
+        user_one_rx,
+        "payload_too_large",
+        &format!(
+            "requests can be at most {} bytes",
+            router::DEFAULT_MAX_REQUEST_BYTES
+        ),
+    )
+    .await;
+}
+
 pub async fn test_creating_room(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    user_one_rx: &mut UnboundedReceiverStream<Message>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_one_rx: &mut ReceiverStream<Message>,
 ) {
     // Make sure users cannot create a room if they
     // are currently in a room.
@@ -243,6 +410,15 @@ pub async fn test_creating_room(
     )
     .await;
 
+    // The creator gets the whole room back so they
+    // can move into it, with themselves as the only preview.
+    let created = helpers::grab_room_created(user_one_rx).await;
+    assert_eq!(created.room.room_id, 3);
+    assert_eq!(created.room.creator_id, 33);
+    assert_eq!(created.room.voice_server_id, "0");
+    assert_eq!(created.room.people_preview_data.len(), 1);
+    assert!(created.room.people_preview_data.contains_key(&33));
+
     //Check the server state after the successful creations etc.
     let server_state = state.read().await;
     assert_eq!(server_state.rooms.len(), 1);
@@ -251,9 +427,9 @@ pub async fn test_creating_room(
 
 pub async fn test_unfollowing_and_following_user(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     println!("testing following and unfollowing user");
     //create 2 new users
@@ -267,7 +443,7 @@ pub async fn test_unfollowing_and_following_user(
     )
     .await;
 
-    let new_second_user = helpers::spawn_new_real_user_and_join_room(
+    let mut new_second_user = helpers::spawn_new_real_user_and_join_room(
         publish_channel,
         execution_handler,
         state,
@@ -276,6 +452,7 @@ pub async fn test_unfollowing_and_following_user(
         "fwij!2428959478239825833234024nsdocikndv0".to_string(),
     )
     .await;
+    helpers::drain_pending_messages(vec![&mut new_user.1]);
 
     let follow_request = GenericUserId {
         user_id: new_second_user.0.to_owned(),
@@ -295,10 +472,42 @@ pub async fn test_unfollowing_and_following_user(
     )
     .await
     .unwrap();
+    let relationship = FollowRelationship {
+        user_id: new_second_user.0,
+        you_are_following: true,
+        follows_you: false,
+    };
+    helpers::grab_and_assert_request_response(
+        &mut new_user.1,
+        "user_follow_successful",
+        &serde_json::to_string(&relationship).unwrap(),
+    )
+    .await;
+    helpers::grab_and_assert_request_response(
+        &mut new_second_user.1,
+        "new_follower",
+        &new_user.0.to_string(),
+    )
+    .await;
+
+    //following twice is fine and doesn't notify again
+    router::route_msg(
+        helpers::basic_request(
+            "follow_user".to_owned(),
+            serde_json::to_string(&follow_request).unwrap(),
+        ),
+        new_user.0,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
     helpers::grab_and_assert_request_response(
         &mut new_user.1,
         "user_follow_successful",
-        &new_second_user.0.to_string(),
+        &serde_json::to_string(&relationship).unwrap(),
     )
     .await;
     //get user 1 from the perspective of user 2 after follow
@@ -329,7 +538,12 @@ pub async fn test_unfollowing_and_following_user(
     helpers::grab_and_assert_request_response(
         &mut new_user.1,
         "user_unfollow_successful",
-        &new_second_user.0.to_string(),
+        &serde_json::to_string(&FollowRelationship {
+            user_id: new_second_user.0,
+            you_are_following: false,
+            follows_you: false,
+        })
+        .unwrap(),
     )
     .await;
     //get user 1 from the perspective of user 2 after unfollow
@@ -346,9 +560,9 @@ pub async fn test_unfollowing_and_following_user(
 
 pub async fn test_blocking_and_unblocking_user(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     println!("testing blocking and unblocking user");
     //create 2 new users
@@ -371,6 +585,7 @@ pub async fn test_blocking_and_unblocking_user(
         "#@9478239825833234024nsdocikndv0".to_string(),
     )
     .await;
+    helpers::drain_pending_messages(vec![&mut new_user.1]);
 
     //use user one to block user two
     helpers::trigger_block_or_unblock(
@@ -420,9 +635,9 @@ pub async fn test_blocking_and_unblocking_user(
 
 pub async fn test_blocking_and_unblocking_user_invalid(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     gh_id: String,
     dc_id: String,
     op_code: String,
@@ -467,9 +682,9 @@ pub async fn test_blocking_and_unblocking_user_invalid(
 //after we force users to leave.
 pub async fn test_leaving_room_without_cleanup(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     println!("testing leaving room without cleanup");
     let new_user = helpers::spawn_new_real_user_and_join_room(
@@ -496,8 +711,8 @@ pub async fn test_leaving_room_without_cleanup(
     .await
     .unwrap();
     let close_peer = VoiceServerClosePeer {
-        roomId: "3".to_owned(),
-        peerId: new_user.0.to_string(),
+        room_id: "3".to_owned(),
+        peer_id: new_user.0.to_string(),
         kicked: false,
     };
     helpers::grab_and_assert_message_to_voice_server::<VoiceServerClosePeer>(
@@ -533,10 +748,10 @@ pub async fn test_leaving_room_without_cleanup(
 
 pub async fn test_leaving_room_with_cleanup(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    user_one_rx: &mut UnboundedReceiverStream<Message>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_one_rx: &mut ReceiverStream<Message>,
 ) {
     println!("testing leaving room with cleanup");
     let mut write_state = state.write().await;
@@ -566,7 +781,7 @@ pub async fn test_leaving_room_with_cleanup(
         .await
         .unwrap();
     let destroy = VoiceServerDestroyRoom {
-        roomId: "3".to_string(),
+        room_id: "3".to_string(),
     };
     //check voice server msg
     helpers::grab_and_assert_message_to_voice_server::<VoiceServerDestroyRoom>(
@@ -587,10 +802,10 @@ pub async fn test_leaving_room_with_cleanup(
 
 pub async fn test_updating_room_meta_data(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    user_one_rx: &mut UnboundedReceiverStream<Message>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_one_rx: &mut ReceiverStream<Message>,
 ) {
     //test invalid update
     //this person is not a mod so it should fail
@@ -604,6 +819,7 @@ pub async fn test_updating_room_meta_data(
         "%12312$$$$$$$$833234024nsdocikndv0".to_string(),
     )
     .await;
+    helpers::drain_pending_messages(vec![user_one_rx, &mut new_user.1]);
 
     let room_update: RoomUpdate = RoomUpdate {
         name: "test90432840".to_owned(),
@@ -611,6 +827,10 @@ pub async fn test_updating_room_meta_data(
         chat_throttle: 2000,
         description: "for the best".to_owned(),
         auto_speaker: true,
+        max_occupancy: None,
+        chat_enabled: None,
+        chat_mode: None,
+        tags: None,
     };
     let basic_request = helpers::basic_request(
         "update_room_meta".to_owned(),
@@ -635,6 +855,39 @@ pub async fn test_updating_room_meta_data(
     )
     .await;
 
+    //mods still have to send valid settings
+    let bad_update = RoomUpdate {
+        name: "".to_owned(),
+        public: true,
+        chat_throttle: 3000,
+        description: "for the bes333".to_owned(),
+        auto_speaker: true,
+        max_occupancy: None,
+        chat_enabled: None,
+        chat_mode: None,
+        tags: None,
+    };
+    let basic_request = helpers::basic_request(
+        "update_room_meta".to_owned(),
+        serde_json::to_string(&bad_update).unwrap(),
+    );
+    router::route_msg(
+        basic_request,
+        33,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(
+        user_one_rx,
+        "invalid_request",
+        "room name must be 1-50 characters",
+    )
+    .await;
+
     //test valid update
     //user 33 is the owner and is a mod so it should complete
     let room_update: RoomUpdate = RoomUpdate {
@@ -643,6 +896,14 @@ pub async fn test_updating_room_meta_data(
         chat_throttle: 3000,
         description: "for the bes333".to_owned(),
         auto_speaker: true,
+        max_occupancy: None,
+        chat_enabled: Some(false),
+        chat_mode: None,
+        tags: Some(vec![
+            "Tech".to_owned(),
+            "tech".to_owned(),
+            "home-automation".to_owned(),
+        ]),
     };
     let basic_request = helpers::basic_request(
         "update_room_meta".to_owned(),
@@ -663,7 +924,21 @@ pub async fn test_updating_room_meta_data(
     helpers::grab_and_assert_request_response(
         user_one_rx,
         "room_meta_update",
-        &serde_json::to_string(&room_update).unwrap(),
+        &serde_json::to_string(&RoomMetaUpdate {
+            room_id: 3,
+            details: RoomDetails {
+                name: room_update.name.clone(),
+                chat_throttle: room_update.chat_throttle,
+                chat_enabled: false,
+                chat_mode: ChatMode::Disabled.as_str().to_owned(),
+                is_private: !room_update.public,
+                description: room_update.description.clone(),
+                max_occupancy: rooms::handler::DEFAULT_MAX_OCCUPANCY,
+                tags: vec!["tech".to_owned(), "home-automation".to_owned()],
+            },
+            auto_speaker_setting: room_update.auto_speaker,
+        })
+        .unwrap(),
     )
     .await;
 
@@ -674,13 +949,39 @@ pub async fn test_updating_room_meta_data(
     assert_eq!(room.desc, room_update.description);
     assert_eq!(room.auto_speaker, room_update.auto_speaker);
     assert_eq!(room.name, room_update.name);
+    assert_eq!(room.chat_mode, ChatMode::Disabled);
+    drop(read_state);
+
+    //only mods can chat while chat is off
+    helpers::drain_pending_messages(vec![&mut new_user.1]);
+    let chat_request = helpers::basic_request(
+        "send_chat_msg".to_owned(),
+        "{\"message\":\"hi\"}".to_owned(),
+    );
+    router::route_msg(
+        chat_request,
+        new_user.0,
+        state,
+        publish_channel,
+        None,
+        execution_handler,
+    )
+    .await
+    .unwrap();
+    helpers::grab_and_assert_request_response(
+        &mut new_user.1,
+        "chat_disabled",
+        "chat is turned off in this room",
+    )
+    .await;
+    state.write().await.rooms.get_mut(&3).unwrap().chat_mode = ChatMode::Everyone;
 }
 
 pub async fn test_updating_muted_and_deaf(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    user_one_rx: &mut UnboundedReceiverStream<Message>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_one_rx: &mut ReceiverStream<Message>,
 ) {
     println!("testing updating muted and deaf status");
     let room_death_and_mute = DeafAndMuteStatus {
@@ -757,10 +1058,10 @@ pub async fn test_updating_muted_and_deaf(
 
 pub async fn test_joining_room(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    user_one_rx: &mut UnboundedReceiverStream<Message>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_one_rx: &mut ReceiverStream<Message>,
     type_of_join: &str,
     user_id: i32,
 ) {
@@ -809,6 +1110,23 @@ pub async fn test_joining_room(
     )
     .await;
 
+    // The joiner is in the room by now, so they
+    // are told how they joined along with everyone else
+    let joined = helpers::grab_permissions_update(user_one_rx).await;
+    assert_eq!(joined.user_id, user_id);
+    assert_eq!(joined.data.is_speaker, type_of_join == "join-as-speaker");
+
+    // The speaker joins an empty room, so only the
+    // listener has anything to catch up on
+    let history = helpers::grab_room_event_history(user_one_rx).await;
+    assert_eq!(history["room_id"], 3);
+    let events = history["events"].as_array().unwrap();
+    if type_of_join == "join-as-speaker" {
+        assert!(events.is_empty());
+    } else {
+        assert_eq!(events.last().unwrap()["type"], "user_joined");
+    }
+
     //Check:The user in the room?
     //Check:There only one user in the room?
     //Check:The user's current room state is updated?
@@ -41,21 +41,28 @@ Throughout the entire test chain, all we are doing is:
 use crate::communication::tests::helpers::helpers;
 use crate::communication::tests::{hand_tests, mod_tests, owner_tests, standard_tests};
 use crate::communication::types::{GenericRoomId, GenericRoomIdAndPeerId};
-use crate::data_store::sql_execution_handler::ExecutionHandler;
-use crate::rabbitmq::rabbit;
-use crate::server::setup_execution_handler;
+use crate::data_store::pool::ExecutionHandlerPool;
+use crate::rabbitmq::config::RabbitConfig;
+use crate::rabbitmq::rabbit::{self, PublishChannel};
+use crate::server::setup_execution_handler_pool;
 use crate::state::state::ServerState;
 use futures::lock::Mutex;
 use lapin::{options::*, types::FieldTable, Consumer};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use tokio_stream::wrappers::ReceiverStream;
 use warp::ws::Message;
 pub async fn tests() {
     //setup rabbit channels
-    let connection = rabbit::setup_rabbit_connection().await.unwrap();
-    let publish_channel: Arc<Mutex<lapin::Channel>> =
-        Arc::new(Mutex::new(helpers::setup_channel(&connection).await));
+    let rabbit_config = RabbitConfig::default();
+    let connection = rabbit::setup_rabbit_connection(&rabbit_config)
+        .await
+        .unwrap();
+    let publish_channel: Arc<Mutex<PublishChannel>> = Arc::new(Mutex::new(
+        rabbit::setup_voice_publish_channel(&connection, &rabbit_config)
+            .await
+            .unwrap(),
+    ));
     let consume_channel: lapin::Channel = helpers::setup_channel(&connection).await;
     let mut consumer = consume_channel
         .basic_consume(
@@ -68,8 +75,8 @@ pub async fn tests() {
         .unwrap();
     //setup mock state/execution handler
     let mock_state: Arc<RwLock<ServerState>> = Arc::new(RwLock::new(ServerState::new()));
-    let execution_handler: Arc<Mutex<ExecutionHandler>> =
-        Arc::new(Mutex::new(setup_execution_handler().await.unwrap()));
+    let execution_handler: Arc<ExecutionHandlerPool> =
+        Arc::new(setup_execution_handler_pool().await.unwrap());
     //Setup mock inner user channels
     //
     //We use channels to direct messages
@@ -86,6 +93,13 @@ pub async fn tests() {
     let mut rx_user_two =
         helpers::create_and_add_new_user_channel_to_peer_map(34, &mock_state).await;
     helpers::insert_starting_user_state(&mock_state).await;
+    standard_tests::test_malformed_payload(
+        &publish_channel,
+        &mock_state,
+        &execution_handler,
+        &mut rx_user_one,
+    )
+    .await;
     standard_tests::test_creating_room(
         &mut consumer,
         &publish_channel,
@@ -116,6 +130,7 @@ pub async fn tests() {
         34,
     )
     .await;
+    helpers::drain_pending_messages(vec![&mut rx_user_one]);
     test_raising_and_lowering_hand(
         &publish_channel,
         &mock_state,
@@ -153,6 +168,15 @@ pub async fn tests() {
         &mock_state,
     )
     .await;
+    mod_tests::speaker_changes_that_change_nothing_are_refused(
+        &publish_channel,
+        &execution_handler,
+        &mock_state,
+        &mut rx_user_one,
+    )
+    .await;
+    //mock users joining fanned their permissions to 33 and 34
+    helpers::drain_pending_messages(vec![&mut rx_user_one, &mut rx_user_two]);
     //after this method there are no more
     //mock users in the room, all users
     //have a db user linked to it
@@ -163,6 +187,7 @@ pub async fn tests() {
         &execution_handler,
         &mock_state,
         &mut rx_user_two,
+        &mut rx_user_one,
         &mut consumer,
     )
     .await;
@@ -182,14 +207,23 @@ pub async fn tests() {
         &mut consumer,
     )
     .await;
+    standard_tests::test_joining_with_inactive_peer(
+        &execution_handler,
+        &mock_state,
+        &publish_channel,
+        &mut rx_user_one,
+    )
+    .await;
+    standard_tests::test_non_admin_is_rejected(&execution_handler, &mock_state, &mut rx_user_one)
+        .await;
     standard_tests::test_invalid_webrtc_request(
         &execution_handler,
         &mock_state,
         &publish_channel,
         &mut rx_user_one,
         GenericRoomIdAndPeerId {
-            roomId: 3,
-            peerId: 34,
+            room_id: 3,
+            peer_id: 34,
         },
     )
     .await;
@@ -207,8 +241,8 @@ pub async fn tests() {
         &publish_channel,
         &mut rx_user_one,
         GenericRoomIdAndPeerId {
-            roomId: 2,
-            peerId: 34,
+            room_id: 2,
+            peer_id: 34,
         },
     )
     .await;
@@ -271,6 +305,7 @@ pub async fn tests() {
         &execution_handler,
     )
     .await;
+    helpers::drain_pending_messages(vec![&mut rx_user_one]);
     standard_tests::test_leaving_room_with_cleanup(
         &mut consumer,
         &publish_channel,
@@ -287,11 +322,11 @@ pub async fn tests() {
 // just check the broadcasted
 // messages.
 async fn test_raising_and_lowering_hand(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
     state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    speaker_rx: &mut UnboundedReceiverStream<Message>,
-    listener_rx: &mut UnboundedReceiverStream<Message>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    speaker_rx: &mut ReceiverStream<Message>,
+    listener_rx: &mut ReceiverStream<Message>,
     consume_channel: &mut Consumer,
 ) {
     println!("testing raising/lowering hand");
@@ -312,6 +347,7 @@ async fn test_raising_and_lowering_hand(
         consume_channel,
     )
     .await;
+    helpers::drain_pending_messages(vec![speaker_rx, listener_rx]);
     mod_tests::mods_can_lower_hands(
         listener_rx,
         publish_channel,
@@ -328,12 +364,28 @@ async fn test_raising_and_lowering_hand(
         speaker_rx,
     )
     .await;
+    hand_tests::raising_hand_twice_changes_nothing(
+        listener_rx,
+        publish_channel,
+        execution_handler,
+        state,
+        speaker_rx,
+    )
+    .await;
+    hand_tests::owner_can_lower_all_hands(
+        listener_rx,
+        publish_channel,
+        execution_handler,
+        state,
+        speaker_rx,
+    )
+    .await;
 }
 
 async fn test_adding_speaker(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
 ) {
     println!("testing adding speaker");
@@ -357,8 +409,8 @@ async fn test_adding_speaker(
 //OTHER MODS.
 async fn test_removing_speaker(
     consume_channel: &mut Consumer,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
 ) {
     println!("testing removing speaker");
@@ -374,10 +426,11 @@ async fn test_removing_speaker(
 }
 
 async fn test_blocking_from_room(
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     state: &Arc<RwLock<ServerState>>,
-    listener_rx: &mut UnboundedReceiverStream<Message>,
+    listener_rx: &mut ReceiverStream<Message>,
+    owner_rx: &mut ReceiverStream<Message>,
     consume_channel: &mut Consumer,
 ) {
     println!("testing room blocking");
@@ -388,11 +441,42 @@ async fn test_blocking_from_room(
         listener_rx,
     )
     .await;
+    owner_tests::non_owner_can_not_kick_from_room(
+        publish_channel,
+        execution_handler,
+        state,
+        listener_rx,
+    )
+    .await;
+    owner_tests::non_owner_can_not_destroy_room(
+        publish_channel,
+        execution_handler,
+        state,
+        listener_rx,
+    )
+    .await;
+    owner_tests::owner_can_kick_from_room(
+        consume_channel,
+        publish_channel,
+        execution_handler,
+        state,
+        owner_rx,
+    )
+    .await;
     owner_tests::owner_can_block_from_room(
         consume_channel,
         publish_channel,
         execution_handler,
         state,
+        owner_rx,
+    )
+    .await;
+    owner_tests::owner_can_promote_and_demote_mods(
+        consume_channel,
+        publish_channel,
+        execution_handler,
+        state,
+        owner_rx,
     )
     .await;
 }
@@ -5,8 +5,12 @@ of the request/response(if there is any) will be the 'request_containing_data/re
 in json serialization.
 
 We try to follow snake case when possible in this document,
-but our voice server requires camelcase json fields.
+but our voice server requires camelcase json fields, those
+types rename their fields with serde instead.
 */
+use crate::communication::op_codes::VoiceServerOp;
+use crate::state::session::ConnectionState;
+use crate::state::types::{BoardCommandAudit, RoomEventRecord};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -18,6 +22,10 @@ use std::option::Option;
 pub struct BasicRequest {
     pub request_op_code: String,
     pub request_containing_data: String,
+    /// set by the client, echoed back on the responses
+    /// to this request so they can be told apart
+    #[serde(default)]
+    pub request_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
@@ -26,6 +34,24 @@ pub struct BasicResponse {
     pub response_containing_data: String,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseStatus {
+    Ok,
+    Error,
+}
+
+/// What the requester gets back, broadcasts to a
+/// room stay plain BasicResponses.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ResponseEnvelope {
+    #[serde(flatten)]
+    pub response: BasicResponse,
+    pub status: ResponseStatus,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct JoinRoomAndGetInfo {
     pub room_id: i32,
@@ -34,12 +60,30 @@ pub struct JoinRoomAndGetInfo {
 #[derive(Deserialize, Serialize)]
 pub struct GetFollowList {
     pub user_id: i32,
+    // follow id to continue after, omitted for the first page
+    #[serde(default)]
+    pub cursor: Option<i32>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GetFollowStatuses {
+    pub user_ids: Vec<i32>,
+}
+
+/// Relative to whoever asked.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct FollowStatus {
+    pub you_are_following: bool,
+    pub follows_you: bool,
 }
 
 #[derive(Deserialize, Serialize)]
 pub struct GetFollowListResponse {
     pub user_ids: Vec<FollowInfo>,
     pub for_user: i32,
+    pub next_cursor: Option<i32>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -47,6 +91,25 @@ pub struct BasicRoomCreation {
     pub name: String,
     pub desc: String,
     pub public: bool,
+    /// retries with the same key get the room that was
+    /// already created, one sent while the voice server
+    /// hasn't confirmed it yet is answered by the first
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// everyone, followers_only or disabled,
+    /// the server default when left out
+    #[serde(default)]
+    pub chat_mode: Option<String>,
+    /// e.g. music or tech, see rooms/tags.rs
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Sent to the creator once their room exists, and
+/// again for retries with the same idempotency key.
+#[derive(Deserialize, Serialize)]
+pub struct RoomCreated {
+    pub room: CommunicationRoom,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -62,6 +125,29 @@ pub struct DeafAndMuteStatusUpdate {
     pub user_id: i32,
 }
 
+/// What a user missed before joining a room.
+#[derive(Serialize)]
+pub struct RoomEventHistory {
+    pub room_id: i32,
+    pub events: Vec<RoomEventRecord>,
+}
+
+/// Sent to every active user when an
+/// owner ends their room on purpose.
+#[derive(Deserialize, Serialize)]
+pub struct RoomDestroyed {
+    pub room_id: i32,
+    pub destroyed_by: i32,
+}
+
+/// Sent to a room that has been idle for the
+/// timeout, it's destroyed unless something happens.
+#[derive(Deserialize, Serialize)]
+pub struct RoomIdleWarning {
+    pub room_id: i32,
+    pub closes_in_seconds: u64,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct UserRemovedFromRoom {
     pub user_id: i32,
@@ -76,6 +162,13 @@ pub struct GetUserProfile {
 }
 
 //basic types
+/// A room from the requester's history that is still active.
+#[derive(Deserialize, Serialize)]
+pub struct RecentRoom {
+    pub visited_at: String,
+    pub room: CommunicationRoom,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct CommunicationRoom {
     pub details: RoomDetails,
@@ -87,6 +180,9 @@ pub struct CommunicationRoom {
     pub auto_speaker_setting: bool,
     pub created_at: String,
     pub chat_mode: String,
+    /// featured by an admin
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -99,11 +195,16 @@ pub struct AllUsersInRoomResponse {
 pub struct RoomDetails {
     pub name: String,
     pub chat_throttle: i32,
+    pub chat_enabled: bool,
+    pub chat_mode: String,
     pub is_private: bool,
     pub description: String,
+    pub max_occupancy: usize,
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
 
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct RoomPermissions {
     pub asked_to_speak: bool,
     pub is_speaker: bool,
@@ -117,6 +218,100 @@ pub struct UserPreview {
     pub avatar_url: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct UserSearch {
+    pub query: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RoomTagSearch {
+    pub tag: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RoomSearch {
+    pub query: String,
+    pub limit: Option<usize>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct UserSearchResult {
+    pub user_id: i32,
+    pub preview: UserPreview,
+}
+
+/// Sent to online followers when a user
+/// they follow joins a public room.
+#[derive(Deserialize, Serialize)]
+pub struct FollowedUserInRoom {
+    pub user_id: i32,
+    pub room_id: i32,
+}
+
+/// Sent to the room when a mod or the owner brings
+/// someone with a raised hand up to speak.
+#[derive(Deserialize, Serialize)]
+pub struct SpeakerRequestApproved {
+    pub room_id: i32,
+    pub user_id: i32,
+    pub approved_by: i32,
+}
+
+/// notify defaults to true, false turns the hand
+/// down without telling the user why.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct DenySpeakerRequest {
+    pub room_id: i32,
+    pub peer_id: i32,
+    #[serde(default)]
+    pub notify: Option<bool>,
+}
+
+/// Sent to the user whose raised hand was turned down.
+#[derive(Deserialize, Serialize)]
+pub struct SpeakerRequestDenied {
+    pub room_id: i32,
+    pub denied_by: i32,
+    /// Some(seconds) once they have to wait
+    /// before raising their hand again
+    pub cooldown_seconds: Option<u64>,
+}
+
+/// Relayed from the voice server's audio levels,
+/// drives the speaking ring around a user.
+#[derive(Deserialize, Serialize)]
+pub struct ActiveSpeaker {
+    pub room_id: i32,
+    pub user_id: i32,
+    pub speaking: bool,
+}
+
+/// Sent to the room when a mod clears every raised hand.
+#[derive(Deserialize, Serialize)]
+pub struct AllHandsLowered {
+    pub room_id: i32,
+    pub user_ids: Vec<i32>,
+    /// how many hands were lowered
+    pub amount: usize,
+    pub lowered_by: i32,
+}
+
+/// Only the keys that are sent get changed,
+/// keys we don't know about are rejected.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserPreferencesEdit {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notify_followers_of_rooms: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub whispers_from_strangers: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub follower_alerts: Option<bool>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct UserProfileEdit {
     pub display_name: Option<String>,
@@ -126,6 +321,14 @@ pub struct UserProfileEdit {
     pub banner_url: Option<String>,
 }
 
+/// Sent to the room so previews update live.
+#[derive(Deserialize, Serialize)]
+pub struct UserProfileUpdate {
+    pub user_id: i32,
+    pub display_name: String,
+    pub avatar_url: String,
+}
+
 pub struct RoomSettingsEditOrCreation {
     pub name: String,
     pub scheduled_for: String,
@@ -136,6 +339,53 @@ pub struct RoomSettingsEditOrCreation {
 pub struct BlockUserFromRoom {
     pub user_id: i32,
     pub room_id: i32,
+    /// kept in the moderation log, see MAX_MODERATION_REASON_LENGTH
+    #[serde(default)]
+    pub reason: Option<String>,
+}
+
+/// One row of a room's moderation log.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ModerationLogEntry {
+    pub actor_id: i32,
+    pub target_id: i32,
+    /// see ModerationAction
+    pub action: String,
+    pub reason: Option<String>,
+    pub happened_at: String,
+}
+
+/// Latest entries first.
+#[derive(Deserialize, Serialize)]
+pub struct RoomModerationLog {
+    pub room_id: i32,
+    pub entries: Vec<ModerationLogEntry>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct ForceMuteUser {
+    pub user_id: i32,
+    pub room_id: i32,
+}
+
+/// Sent to the room for force_muted/force_unmuted, unlike
+/// user_mute_and_deaf_update only a mod can undo a force mute.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ForcedMuteUpdate {
+    pub user_id: i32,
+    pub room_id: i32,
+    pub by: i32,
+}
+
+/// Sent to the room when recording starts or stops, and on its
+/// own to anyone joining(or resuming) while the room records.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct RecordingStatus {
+    pub room_id: i32,
+    pub recording: bool,
+    /// who started the recording, None once it stopped
+    pub started_by: Option<i32>,
+    pub started_at: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -159,6 +409,45 @@ pub struct RoomUpdate {
     pub chat_throttle: i32,
     pub description: String,
     pub auto_speaker: bool,
+    /// leaving this out keeps the current cap
+    #[serde(default)]
+    pub max_occupancy: Option<usize>,
+    /// leaving this out keeps chat as it is
+    #[serde(default)]
+    pub chat_enabled: Option<bool>,
+    /// everyone, followers_only or disabled
+    #[serde(default)]
+    pub chat_mode: Option<String>,
+    /// leaving this out keeps the current tags
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+}
+
+/// Sent back when a request's data doesn't
+/// match what its op code expects.
+#[derive(Deserialize, Serialize)]
+pub struct MalformedPayload {
+    pub op_code: String,
+    pub reason: String,
+}
+
+/// Runtime counts for ops dashboards.
+#[derive(Deserialize, Serialize, Debug, PartialEq)]
+pub struct ServerStats {
+    pub connections: usize,
+    pub active_users: usize,
+    pub active_rooms: usize,
+    pub users_in_rooms: usize,
+    pub uptime_seconds: u64,
+}
+
+/// What every user in a room gets after
+/// the room's settings change.
+#[derive(Deserialize, Serialize)]
+pub struct RoomMetaUpdate {
+    pub room_id: i32,
+    pub details: RoomDetails,
+    pub auto_speaker_setting: bool,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -182,6 +471,9 @@ pub struct User {
     pub avatar_url: String,
     pub banner_url: String,
     pub i_blocked_them: bool,
+    /// only filled in for users listed in a room
+    #[serde(default)]
+    pub connection_state: ConnectionState,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -198,30 +490,38 @@ pub struct BaseUser {
     pub contributions: i32,
 }
 
-#[allow(non_snake_case)]
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct VoiceServerDestroyRoom {
-    pub roomId: String,
+    pub room_id: String,
 }
 
-#[allow(non_snake_case)]
+/// Used for both start-recording and stop-recording.
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VoiceServerRecording {
+    pub room_id: String,
+    pub recording_id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct VoiceServerCreateRoom {
-    pub roomId: String,
+    pub room_id: String,
 }
-#[allow(non_snake_case)]
 #[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct VoiceServerClosePeer {
-    pub roomId: String,
-    pub peerId: String,
+    pub room_id: String,
+    pub peer_id: String,
     pub kicked: bool,
 }
 
-#[allow(non_snake_case)]
 #[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
 pub struct GenericRoomIdAndPeerId {
-    pub roomId: i32,
-    pub peerId: i32,
+    pub room_id: i32,
+    pub peer_id: i32,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -229,11 +529,64 @@ pub struct GenericRoomId {
     pub room_id: i32,
 }
 
+/// Unbanning leaves out the reason.
+#[derive(Deserialize, Serialize)]
+pub struct BanUser {
+    pub user_id: i32,
+    #[serde(default)]
+    pub banned_reason: String,
+}
+
+/// Sent to banned users right before their socket is closed.
+#[derive(Deserialize, Serialize)]
+pub struct UserBanned {
+    pub banned_reason: String,
+}
+
+/// Lets clients style an announcement.
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnouncementSeverity {
+    Info,
+    Warning,
+    Critical,
+}
+
+/// Sent by admins, every connected user
+/// gets the same fields back as an `announcement`.
+#[derive(Deserialize, Serialize)]
+pub struct Announcement {
+    pub message: String,
+    pub severity: AnnouncementSeverity,
+}
+
+/// Sent by admins, deadline_seconds falls back
+/// to DRAIN_DEADLINE_SECONDS when left out.
+#[derive(Deserialize, Serialize)]
+pub struct BeginDrain {
+    #[serde(default)]
+    pub deadline_seconds: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct DrainStatus {
+    pub draining: bool,
+    /// open websocket connections
+    pub connections: usize,
+    /// until the leftover connections are closed
+    pub seconds_left: Option<u64>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct GenericUserId {
     pub user_id: i32,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct GenericUsername {
+    pub username: String,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct VoiceServerRequest<T: Serialize> {
     pub op: String,
@@ -241,11 +594,30 @@ pub struct VoiceServerRequest<T: Serialize> {
     pub uid: String,
 }
 
+impl<T: Serialize> VoiceServerRequest<T> {
+    /// The op is typed so only ops the voice
+    /// server knows about can be sent.
+    pub fn new(op: VoiceServerOp, d: T, uid: String) -> Self {
+        VoiceServerRequest {
+            op: op.as_str().to_owned(),
+            d,
+            uid,
+        }
+    }
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct AuthCredentials {
+    /// empty for guests
+    #[serde(default)]
     pub access: String,
+    #[serde(default)]
     pub refresh: String,
+    /// "discord", "github" or "guest"
     pub oauth_type: String,
+    /// given in a previous AuthResponse, used to
+    /// get back into your room after a drop.
+    pub resume_token: Option<String>,
 }
 
 //these are optional because
@@ -255,6 +627,29 @@ pub struct AuthCredentials {
 pub struct AuthResponse {
     pub new_access: Option<String>,
     pub new_refresh: Option<String>,
+    pub resume_token: Option<String>,
+    /// the id a guest joins rooms with
+    #[serde(default)]
+    pub guest_id: Option<i32>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct SessionResumed {
+    pub room_id: i32,
+}
+
+/// Sent to the rest of the room when a user
+/// drops or comes back within the grace period.
+#[derive(Deserialize, Serialize)]
+pub struct UserConnectionState {
+    pub user_id: i32,
+    pub connection_state: ConnectionState,
+}
+
+/// Sent when joining while still in another room.
+#[derive(Deserialize, Serialize)]
+pub struct AlreadyInRoom {
+    pub room_id: i32,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -295,6 +690,24 @@ pub struct SingleUserDataResults {
     pub data: User,
 }
 
+/// Where the requester stands with a user
+/// after following or unfollowing them.
+#[derive(Deserialize, Serialize)]
+pub struct FollowRelationship {
+    pub user_id: i32,
+    pub you_are_following: bool,
+    pub follows_you: bool,
+}
+
+/// Banned users only come back with
+/// the marker, their data is left out.
+#[derive(Deserialize, Serialize)]
+pub struct UserProfile {
+    pub user_id: i32,
+    pub banned: bool,
+    pub data: Option<User>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct SingleUserPermissionResults {
     pub user_id: i32,
@@ -307,12 +720,6 @@ pub struct NewModStatus {
     pub user_id: i32,
 }
 
-#[allow(non_snake_case)]
-#[derive(Deserialize, Serialize)]
-pub struct SpecialLeaveRoomOnDestroy {
-    pub roomId: String,
-}
-
 #[derive(Deserialize, Serialize)]
 pub struct NewIoTServer {
     pub external_id: String,
@@ -325,6 +732,20 @@ pub struct BlockedFromRoom {
     pub users: Vec<User>,
 }
 
+/// A room block along with who was blocked.
+#[derive(Deserialize, Serialize)]
+pub struct RoomBlockedUser {
+    pub block_id: i32,
+    pub user_id: i32,
+    pub preview: UserPreview,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RoomBlockedUsers {
+    pub room_id: i32,
+    pub blocked_users: Vec<RoomBlockedUser>,
+}
+
 #[derive(Deserialize, Serialize)]
 pub struct GiveOrRevokeIot {
     pub external_id: String,
@@ -365,3 +786,48 @@ pub struct RelationModification {
     pub data: String,
     pub server_id: String,
 }
+
+#[derive(Deserialize, Serialize)]
+pub struct BoardPermissionModification {
+    pub external_id: String,
+    pub user_id: i32,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct GenericExternalId {
+    pub external_id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct BoardPermissions {
+    pub external_id: String,
+    pub owner_id: i32,
+    pub outside_name: String,
+    pub users_with_permission: Vec<i32>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct IoTCommand {
+    pub board_room_id: i32,
+    pub server_id: String,
+    pub command: String,
+    pub payload: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct IoTCommandOutgoing {
+    pub command: String,
+    pub payload: String,
+}
+
+#[derive(Serialize)]
+pub struct BoardCommandSent<'a> {
+    pub external_id: String,
+    pub audit: &'a BoardCommandAudit,
+}
+
+#[derive(Serialize)]
+pub struct BoardCommandHistory<'a> {
+    pub external_id: String,
+    pub commands: Vec<&'a BoardCommandAudit>,
+}
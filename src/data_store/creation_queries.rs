@@ -75,3 +75,132 @@ pub const SHEDULED_ROOM_ATTENDANCE: &str = "
         isOwner BOOLEAN NOT NULL
     );
 ";
+//lets prefix searches(LIKE 'query%') on
+//lowercased names use an index
+pub const USER_NAME_SEARCH_INDEX_CREATION: &str = "
+    CREATE INDEX IF NOT EXISTS users_lower_username_idx
+    ON users (LOWER(userName) text_pattern_ops);
+";
+pub const DISPLAY_NAME_SEARCH_INDEX_CREATION: &str = "
+    CREATE INDEX IF NOT EXISTS users_lower_display_name_idx
+    ON users (LOWER(displayName) text_pattern_ops);
+";
+//keys that aren't set take their defaults, see DBUserPreferences
+pub const USER_PREFERENCES_COLUMN_CREATION: &str = "
+    ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS preferences JSONB NOT NULL DEFAULT '{}';
+";
+//platform wide admins, only ever granted by an operator
+pub const USER_IS_ADMIN_COLUMN_CREATION: &str = "
+    ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS isAdmin BOOLEAN NOT NULL DEFAULT FALSE;
+";
+//append only, read for retention analysis
+pub const ROOM_SESSION_EVENT_TABLE_CREATION: &str = "
+    CREATE TABLE IF NOT EXISTS room_session_event(
+        Id SERIAL PRIMARY KEY,
+        userId int NOT NULL,
+        roomId int NOT NULL,
+        eventType VARCHAR(20) NOT NULL,
+        happenedAt TIMESTAMPTZ NOT NULL
+    );
+";
+pub const ROOM_SESSION_EVENT_INDEX_CREATION: &str = "
+    CREATE INDEX IF NOT EXISTS room_session_event_user_room_idx
+    ON room_session_event (userId, roomId, happenedAt);
+";
+//lowercase tags, see rooms/tags.rs
+pub const ROOM_TAGS_TABLE_CREATION: &str = "
+    CREATE TABLE IF NOT EXISTS room_tags(
+        Id SERIAL PRIMARY KEY,
+        roomId int NOT NULL,
+        tag VARCHAR(20) NOT NULL,
+        UNIQUE (roomId, tag)
+    );
+";
+pub const ROOM_TAGS_INDEX_CREATION: &str = "
+    CREATE INDEX IF NOT EXISTS room_tags_tag_idx
+    ON room_tags (tag);
+";
+//one row per room a user has joined, kept to their latest visits
+pub const USER_ROOM_HISTORY_TABLE_CREATION: &str = "
+    CREATE TABLE IF NOT EXISTS user_room_history(
+        Id SERIAL PRIMARY KEY,
+        userId int NOT NULL,
+        roomId int NOT NULL,
+        visitedAt TIMESTAMPTZ NOT NULL,
+        UNIQUE (userId, roomId)
+    );
+";
+//endedAt is NULL while the voice server is still recording
+pub const ROOM_RECORDING_TABLE_CREATION: &str = "
+    CREATE TABLE IF NOT EXISTS room_recording(
+        Id SERIAL PRIMARY KEY,
+        roomId int NOT NULL,
+        startedBy int NOT NULL,
+        startedAt TIMESTAMPTZ NOT NULL,
+        endedAt TIMESTAMPTZ
+    );
+";
+//append only, who did what to whom in which room, reason is optional
+pub const MODERATION_AUDIT_TABLE_CREATION: &str = "
+    CREATE TABLE IF NOT EXISTS moderation_audit(
+        Id SERIAL PRIMARY KEY,
+        actorId int NOT NULL,
+        targetId int NOT NULL,
+        roomId int NOT NULL,
+        action VARCHAR(20) NOT NULL,
+        reason VARCHAR(100),
+        happenedAt TIMESTAMPTZ NOT NULL
+    );
+";
+pub const MODERATION_AUDIT_INDEX_CREATION: &str = "
+    CREATE INDEX IF NOT EXISTS moderation_audit_room_idx
+    ON moderation_audit (roomId, happenedAt);
+";
+
+//usernames are unique ignoring case, users sharing one
+//before this was enforced keep it with their id appended,
+//and a counter after that if someone already has that too
+pub const USER_NAME_UNIQUE_INDEX: &str = "users_unique_lower_username_idx";
+pub const USER_NAME_DUPLICATE_RENAME: &str = "
+    DO $$
+    DECLARE
+        duplicate RECORD;
+        candidate TEXT;
+        attempt INT;
+    BEGIN
+        FOR duplicate IN
+            SELECT Id, userName FROM users AS later
+            WHERE EXISTS (
+                SELECT 1 FROM users AS original
+                WHERE LOWER(original.userName) = LOWER(later.userName)
+                AND original.Id < later.Id
+            )
+            ORDER BY Id
+        LOOP
+            candidate := duplicate.userName || '_' || duplicate.Id;
+            attempt := 1;
+            WHILE EXISTS (
+                SELECT 1 FROM users WHERE LOWER(userName) = LOWER(candidate)
+            ) LOOP
+                candidate := duplicate.userName || '_' || duplicate.Id || '_' || attempt;
+                attempt := attempt + 1;
+            END LOOP;
+            UPDATE users SET userName = candidate WHERE Id = duplicate.Id;
+        END LOOP;
+    END $$;
+";
+pub const USER_NAME_UNIQUE_INDEX_CREATION: &str = "
+    CREATE UNIQUE INDEX IF NOT EXISTS users_unique_lower_username_idx
+    ON users (LOWER(userName));
+";
+
+//every migration that has been applied, see migrations.rs
+pub const SCHEMA_MIGRATIONS_TABLE_CREATION: &str = "
+    CREATE TABLE IF NOT EXISTS schema_migrations(
+        version INT PRIMARY KEY,
+        name VARCHAR(100) NOT NULL,
+        appliedAt TIMESTAMP NOT NULL DEFAULT NOW()
+    );
+";
@@ -4,6 +4,31 @@
 //currently using this for insertion, omitting(setting as -1) the id since it is
 //uniquely generated by the db.
 
+use serde::{Deserialize, Serialize};
+
+/// Stored as json on the user, anything missing
+/// falls back to the default so older rows still load.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct DBUserPreferences {
+    /// followers hear about the public rooms this user joins
+    pub notify_followers_of_rooms: bool,
+    /// users this user doesn't follow can whisper them
+    pub whispers_from_strangers: bool,
+    /// this user hears about new followers
+    pub follower_alerts: bool,
+}
+
+impl Default for DBUserPreferences {
+    fn default() -> Self {
+        Self {
+            notify_followers_of_rooms: true,
+            whispers_from_strangers: true,
+            follower_alerts: true,
+        }
+    }
+}
+
 pub struct DBRoom {
     pub id: i32,
     pub owner_id: i32,
@@ -22,6 +47,7 @@ pub struct DBFollower {
     pub follower_id: i32,
     pub user_id: i32,
 }
+#[derive(Clone)]
 pub struct DBUser {
     pub id: i32,
     pub display_name: String,
@@ -37,6 +63,7 @@ pub struct DBUser {
     pub bio: String,
     pub contributions: i32,
     pub banner_url: String,
+    pub is_admin: bool,
 }
 pub struct DBUserBlock {
     pub id: i32,
@@ -48,6 +75,18 @@ pub struct DBRoomBlock {
     pub owner_room_id: i32,
     pub blocked_user_id: i32,
 }
+pub struct DBRoomTag {
+    pub id: i32,
+    pub room_id: i32,
+    pub tag: String,
+}
+/// visited_at is rfc3339, the latest join of the room.
+pub struct DBUserRoomVisit {
+    pub id: i32,
+    pub user_id: i32,
+    pub room_id: i32,
+    pub visited_at: String,
+}
 pub struct DBScheduledRoom {
     pub id: i32,
     pub room_name: String,
@@ -61,3 +100,89 @@ pub struct DBScheduledRoomAttendance {
     pub scheduled_room_id: i32,
     pub is_owner: bool,
 }
+/// started_at is rfc3339, the row is ended(endedAt)
+/// when the recording stops or the room goes away.
+pub struct DBRoomRecording {
+    pub id: i32,
+    pub room_id: i32,
+    pub started_by: i32,
+    pub started_at: String,
+}
+/// happened_at is rfc3339, see RoomSessionEventType
+/// for the event types.
+pub struct DBRoomSessionEvent {
+    pub id: i32,
+    pub user_id: i32,
+    pub room_id: i32,
+    pub event_type: String,
+    pub happened_at: String,
+}
+/// happened_at is rfc3339, see ModerationAction
+/// for the actions.
+pub struct DBModerationAction {
+    pub id: i32,
+    pub actor_id: i32,
+    pub target_id: i32,
+    pub room_id: i32,
+    pub action: String,
+    pub reason: Option<String>,
+    pub happened_at: String,
+}
+/// Only sessions that have ended are counted.
+#[derive(Debug, PartialEq)]
+pub struct DBRoomSessionDuration {
+    pub user_id: i32,
+    pub room_id: i32,
+    pub sessions: i64,
+    pub total_seconds: i64,
+}
+
+/// A session starts with a join and ends with
+/// whichever of leave, kick or ban comes next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoomSessionEventType {
+    Join,
+    Leave,
+    SpeakerPromote,
+    Kick,
+    Ban,
+}
+
+impl RoomSessionEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RoomSessionEventType::Join => "join",
+            RoomSessionEventType::Leave => "leave",
+            RoomSessionEventType::SpeakerPromote => "speaker_promote",
+            RoomSessionEventType::Kick => "kick",
+            RoomSessionEventType::Ban => "ban",
+        }
+    }
+}
+
+/// What a mod or owner did to another user, kept
+/// in moderation_audit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ModerationAction {
+    Ban,
+    Kick,
+    Mute,
+    Unmute,
+    Mod,
+    Unmod,
+    TransferOwnership,
+}
+
+impl ModerationAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModerationAction::Ban => "ban",
+            ModerationAction::Kick => "kick",
+            ModerationAction::Mute => "mute",
+            ModerationAction::Unmute => "unmute",
+            ModerationAction::Mod => "mod",
+            ModerationAction::Unmod => "unmod",
+            ModerationAction::TransferOwnership => "transfer_ownership",
+        }
+    }
+}
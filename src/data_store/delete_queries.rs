@@ -16,6 +16,11 @@ DELETE FROM room_block
 WHERE ownerRoomId = $1;
 ";
 
+pub const DELETE_ROOM_TAGS_QUERY: &str = "
+DELETE FROM room_tags
+WHERE roomId = $1;
+";
+
 pub const DELETE_ROOM_BLOCK_QUERY: &str = "
 DELETE FROM room_block
 WHERE ownerRoomId = $1 and blockedUserId = $2;
@@ -45,3 +50,80 @@ pub const DELETE_FOLLOWER_FOR_USER: &str = "
 DELETE FROM follower
 WHERE followerId = $1 and userId = $2;
 ";
+
+//account deletion, run together by ExecutionHandler::delete_account
+pub const DELETE_ALL_FOLLOWS_FOR_USER: &str = "
+DELETE FROM follower
+WHERE followerId = $1 or userId = $1;
+";
+
+pub const DELETE_ALL_USER_BLOCKS_FOR_USER: &str = "
+DELETE FROM user_block
+WHERE ownerUserId = $1 or blockedUserId = $1;
+";
+
+pub const DELETE_ALL_ROOM_BLOCKS_FOR_USER: &str = "
+DELETE FROM room_block
+WHERE blockedUserId = $1;
+";
+
+pub const DELETE_ALL_ROOM_PERMISSIONS_FOR_USER: &str = "
+DELETE FROM room_permission
+WHERE userId = $1;
+";
+
+//scheduled rooms the user owns are deleted with them
+pub const DELETE_OWNED_SCHEDULED_ROOMS_FOR_USER: &str = "
+DELETE FROM scheduled_room
+WHERE Id IN (
+    SELECT scheduledRoomId FROM scheduled_room_attendance
+    WHERE userId = $1 and isOwner
+);
+";
+
+//the user's own attendance and everyone's
+//attendance of the rooms the user owns
+pub const DELETE_ALL_SCHEDULED_ROOM_ATTENDANCE_FOR_USER: &str = "
+DELETE FROM scheduled_room_attendance
+WHERE userId = $1 or scheduledRoomId IN (
+    SELECT scheduledRoomId FROM scheduled_room_attendance
+    WHERE userId = $1 and isOwner
+);
+";
+
+pub const DELETE_ALL_ROOM_SESSION_EVENTS_FOR_USER: &str = "
+DELETE FROM room_session_event
+WHERE userId = $1;
+";
+
+pub const DELETE_ALL_ROOM_HISTORY_FOR_USER: &str = "
+DELETE FROM user_room_history
+WHERE userId = $1;
+";
+
+pub const DELETE_ALL_ROOM_RECORDINGS_FOR_USER: &str = "
+DELETE FROM room_recording
+WHERE startedBy = $1;
+";
+
+//whether the user acted or was acted on
+pub const DELETE_ALL_MODERATION_ACTIONS_FOR_USER: &str = "
+DELETE FROM moderation_audit
+WHERE actorId = $1 OR targetId = $1;
+";
+
+//everything but the user's latest $2 visits
+pub const DELETE_OLD_ROOM_VISITS_FOR_USER: &str = "
+DELETE FROM user_room_history
+WHERE userId = $1 AND Id NOT IN (
+    SELECT Id FROM user_room_history
+    WHERE userId = $1
+    ORDER BY visitedAt DESC, Id DESC
+    LIMIT $2
+);
+";
+
+pub const DELETE_USER_QUERY: &str = "
+DELETE FROM users
+WHERE Id = $1;
+";
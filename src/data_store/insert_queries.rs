@@ -13,9 +13,10 @@ INSERT INTO users (
             bannedReason,
             bio,
             contributions,
-            bannerUrl)
+            bannerUrl,
+            isAdmin)
             
-            VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13) 
+            VALUES($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11,$12,$13,$14) 
             RETURNING Id;
 ";
 
@@ -62,3 +63,35 @@ pub const INSERT_SCHEDULED_ATTENDANCE_QUERY: &str = "
 INSERT INTO scheduled_room_attendance(userId,scheduledRoomId,isOwner)
 VALUES($1,$2,$3);
 ";
+
+pub const INSERT_APPLIED_MIGRATION: &str = "
+INSERT INTO schema_migrations (version, name)
+VALUES ($1, $2);
+";
+
+pub const INSERT_ROOM_SESSION_EVENT_QUERY: &str = "
+INSERT INTO room_session_event(userId, roomId, eventType, happenedAt)
+VALUES($1, $2, $3, $4::text::timestamptz);
+";
+
+pub const INSERT_MODERATION_ACTION_QUERY: &str = "
+INSERT INTO moderation_audit(actorId, targetId, roomId, action, reason, happenedAt)
+VALUES($1, $2, $3, $4, $5, $6::text::timestamptz);
+";
+
+pub const INSERT_ROOM_TAG_QUERY: &str = "
+INSERT INTO room_tags(roomId, tag)
+VALUES($1, $2);
+";
+
+//rejoining a room moves it back to the top
+pub const INSERT_USER_ROOM_VISIT_QUERY: &str = "
+INSERT INTO user_room_history(userId, roomId, visitedAt)
+VALUES($1, $2, $3::text::timestamptz)
+ON CONFLICT (userId, roomId) DO UPDATE SET visitedAt = EXCLUDED.visitedAt;
+";
+
+pub const INSERT_ROOM_RECORDING_QUERY: &str = "
+INSERT INTO room_recording(roomId, startedBy, startedAt)
+VALUES($1, $2, $3::text::timestamptz) RETURNING Id;
+";
@@ -0,0 +1,149 @@
+/*
+Brings the schema up to date at startup. Each migration
+runs once, inside of a transaction, and is recorded in
+schema_migrations so it is skipped from then on.
+
+Never edit a migration that has shipped, add a new one
+with the next version instead.
+*/
+use std::fmt;
+
+use tokio_postgres::Error;
+
+use super::creation_queries;
+use super::sql_execution_handler::ExecutionHandler;
+
+pub struct Migration {
+    pub version: i32,
+    pub name: &'static str,
+    pub statements: &'static [&'static str],
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        statements: &[
+            creation_queries::ROOM_TABLE_CREATION,
+            creation_queries::ROOM_PERMISSIONS_TABLE_CREATION,
+            creation_queries::FOLLOWER_TABLE_CREATION,
+            creation_queries::USER_TABLE_CREATION,
+            creation_queries::USER_BLOCK_TABLE_CREATION,
+            creation_queries::ROOM_BLOCK_CREATION,
+            creation_queries::SCHEDULED_ROOM_CREATION,
+            creation_queries::SHEDULED_ROOM_ATTENDANCE,
+        ],
+    },
+    Migration {
+        version: 2,
+        name: "user_search_indexes",
+        statements: &[
+            creation_queries::USER_NAME_SEARCH_INDEX_CREATION,
+            creation_queries::DISPLAY_NAME_SEARCH_INDEX_CREATION,
+        ],
+    },
+    Migration {
+        version: 3,
+        name: "user_preferences",
+        statements: &[creation_queries::USER_PREFERENCES_COLUMN_CREATION],
+    },
+    Migration {
+        version: 4,
+        name: "user_is_admin",
+        statements: &[creation_queries::USER_IS_ADMIN_COLUMN_CREATION],
+    },
+    Migration {
+        version: 5,
+        name: "room_session_events",
+        statements: &[
+            creation_queries::ROOM_SESSION_EVENT_TABLE_CREATION,
+            creation_queries::ROOM_SESSION_EVENT_INDEX_CREATION,
+        ],
+    },
+    Migration {
+        version: 6,
+        name: "room_tags",
+        statements: &[
+            creation_queries::ROOM_TAGS_TABLE_CREATION,
+            creation_queries::ROOM_TAGS_INDEX_CREATION,
+        ],
+    },
+    Migration {
+        version: 7,
+        name: "user_room_history",
+        statements: &[creation_queries::USER_ROOM_HISTORY_TABLE_CREATION],
+    },
+    Migration {
+        version: 8,
+        name: "room_recording",
+        statements: &[creation_queries::ROOM_RECORDING_TABLE_CREATION],
+    },
+    Migration {
+        version: 9,
+        name: "moderation_audit",
+        statements: &[
+            creation_queries::MODERATION_AUDIT_TABLE_CREATION,
+            creation_queries::MODERATION_AUDIT_INDEX_CREATION,
+        ],
+    },
+    Migration {
+        version: 10,
+        name: "unique_usernames",
+        statements: &[
+            creation_queries::USER_NAME_DUPLICATE_RENAME,
+            creation_queries::USER_NAME_UNIQUE_INDEX_CREATION,
+        ],
+    },
+];
+
+#[derive(Debug)]
+pub struct MigrationError {
+    pub version: i32,
+    pub name: &'static str,
+    pub source: Error,
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "migration {}({}) failed: {}",
+            self.version, self.name, self.source
+        )
+    }
+}
+
+/// Gives back the versions that were applied by this run.
+pub async fn run_migrations(
+    execution_handler: &mut ExecutionHandler,
+) -> Result<Vec<i32>, MigrationError> {
+    let setup_error = |source| MigrationError {
+        version: 0,
+        name: "schema_migrations",
+        source,
+    };
+    execution_handler
+        .create_table_if_needed(creation_queries::SCHEMA_MIGRATIONS_TABLE_CREATION)
+        .await
+        .map_err(setup_error)?;
+    let applied = execution_handler
+        .select_applied_migrations()
+        .await
+        .map_err(setup_error)?;
+    let mut newly_applied = Vec::new();
+    for migration in MIGRATIONS {
+        if applied.contains(&migration.version) {
+            continue;
+        }
+        execution_handler
+            .apply_migration(migration.version, migration.name, migration.statements)
+            .await
+            .map_err(|source| MigrationError {
+                version: migration.version,
+                name: migration.name,
+                source,
+            })?;
+        newly_applied.push(migration.version);
+    }
+    Ok(newly_applied)
+}
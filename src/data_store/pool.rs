@@ -0,0 +1,52 @@
+use std::env;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use futures::lock::{Mutex, MutexGuard};
+
+use super::sql_execution_handler::ExecutionHandler;
+
+/// Connections opened when PG_POOL_SIZE isn't set.
+pub const DEFAULT_POOL_SIZE: usize = 4;
+
+pub type ExecutionHandlerPool = Pool<ExecutionHandler>;
+
+pub fn pool_size_from_env() -> usize {
+    match env::var("PG_POOL_SIZE").map(|size| size.parse::<usize>()) {
+        Ok(Ok(size)) if size > 0 => size,
+        _ => DEFAULT_POOL_SIZE,
+    }
+}
+
+/// Each member is its own connection, so handlers no
+/// longer queue up behind a single shared one.
+pub struct Pool<T> {
+    members: Vec<Mutex<T>>,
+    next: AtomicUsize,
+}
+
+impl<T> Pool<T> {
+    pub fn new(members: Vec<T>) -> Self {
+        assert!(!members.is_empty(), "a pool needs at least one member");
+        Self {
+            members: members.into_iter().map(Mutex::new).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Hands out the first free member, when every member is
+    /// busy we wait on them in turn. Guards work just like
+    /// the old shared handler's, hold one per request.
+    pub async fn lock(&self) -> MutexGuard<'_, T> {
+        for member in &self.members {
+            if let Some(guard) = member.try_lock() {
+                return guard;
+            }
+        }
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.members.len();
+        self.members[index].lock().await
+    }
+
+    pub fn size(&self) -> usize {
+        self.members.len()
+    }
+}
@@ -0,0 +1,244 @@
+/*
+Per query timings, only compiled with the metrics feature.
+
+Every query going through the execution handler is timed and
+counted against the name of its constant, so a new query should
+be added to QUERY_LABELS or it shows up as "unlabeled".
+*/
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use super::{delete_queries, insert_queries, select_queries, update_queries};
+
+/// Upper bounds of the histogram buckets in seconds,
+/// anything slower only counts towards +Inf.
+pub const BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+macro_rules! query_labels {
+    ($($module:ident::$name:ident),* $(,)?) => {
+        &[$(($module::$name, stringify!($name))),*]
+    };
+}
+
+/// sql text -> label
+pub const QUERY_LABELS: &[(&str, &str)] = query_labels![
+    select_queries::SELECT_ALL_ROOM_QUERY,
+    select_queries::SELECT_ROOM_BY_ID,
+    select_queries::SELECT_SCHEDULED_ROOM_BY_ID,
+    select_queries::SELECT_ALL_SCHEDULED_ROOMS_QUERY,
+    select_queries::SELECT_ALL_SCHEDULED_ROOM_ATTENDANCE_FOR_ROOM_QUERY,
+    select_queries::SELECT_ALL_OWNED_SCHEDULED_ROOMS_FOR_USER,
+    select_queries::SELECT_ALL_ATTENDANCE_FOR_USER_QUERY,
+    select_queries::SELECT_ALL_FOLLOWERS_FOR_USER_QUERY,
+    select_queries::SELECT_ALL_FOLLOWING_FOR_USER_QUERY,
+    select_queries::SELECT_FOLLOWERS_PAGE_FOR_USER_QUERY,
+    select_queries::SELECT_FOLLOWING_PAGE_FOR_USER_QUERY,
+    select_queries::SELECT_SINGLE_FOLLOWING_FOR_USER_QUERY,
+    select_queries::SELECT_SINGLE_ROOM_ATTENDANCE_FOR_USER_QUERY,
+    select_queries::SELECT_SINGLE_USER_BLOCK_FOR_USER_QUERY,
+    select_queries::SELECT_SINGLE_ROOM_BLOCK_FOR_USER_QUERY,
+    select_queries::SELECT_ALL_BLOCKED_FOR_USER_QUERY,
+    select_queries::SELECT_ALL_BLOCKERS_FOR_USER_QUERY,
+    select_queries::SELECT_ALL_BLOCKED_USERS_FOR_ROOM_QUERY,
+    select_queries::SELECT_ROOM_BLOCKS_WITH_PREVIEWS,
+    select_queries::SELECT_ROOM_IDS_BY_TAG,
+    select_queries::SELECT_ALL_ROOM_PERMISSIONS_FOR_USER,
+    select_queries::SELECT_USER_BY_ID,
+    select_queries::SELECT_USER_IS_ADMIN,
+    select_queries::SELECT_USER_PREFERENCES,
+    select_queries::SELECT_USERS_BY_IDS,
+    select_queries::SELECT_FOLLOWS_BETWEEN_USER_AND_USERS_QUERY,
+    select_queries::SEARCH_USERS_BY_PREFIX,
+    select_queries::SEARCH_USERS_BY_SUBSTRING,
+    select_queries::SELECT_USER_ID_BY_USERNAME,
+    select_queries::SELECT_ALL_PERMISSIONS_FOR_ROOM,
+    select_queries::SELECT_USER_PREVIEW_FOR_USER,
+    select_queries::SELECT_USER_BY_CREATION_IDENTIFIERS,
+    select_queries::SELECT_APPLIED_MIGRATIONS,
+    select_queries::HEALTH_CHECK_QUERY,
+    select_queries::SELECT_ROOM_SESSION_DURATIONS,
+    select_queries::SELECT_RECENT_ROOM_VISITS_FOR_USER,
+    select_queries::SELECT_ROOM_RECORDINGS_FOR_ROOM,
+    select_queries::SELECT_MODERATION_LOG_FOR_ROOM,
+    insert_queries::INSERT_USER_QUERY,
+    insert_queries::INSERT_ROOM_QUERY,
+    insert_queries::INSERT_ROOM_PERMISSION_QUERY,
+    insert_queries::INSERT_FOLLOWER_QUERY,
+    insert_queries::INSERT_USER_BLOCK_QUERY,
+    insert_queries::INSERT_ROOM_BLOCK_QUERY,
+    insert_queries::INSERT_SCHEDULED_ROOM_QUERY,
+    insert_queries::INSERT_SCHEDULED_ATTENDANCE_QUERY,
+    insert_queries::INSERT_APPLIED_MIGRATION,
+    insert_queries::INSERT_ROOM_SESSION_EVENT_QUERY,
+    insert_queries::INSERT_ROOM_TAG_QUERY,
+    insert_queries::INSERT_USER_ROOM_VISIT_QUERY,
+    insert_queries::INSERT_ROOM_RECORDING_QUERY,
+    insert_queries::INSERT_MODERATION_ACTION_QUERY,
+    update_queries::UPDATE_ROOM_OWNER_QUERY,
+    update_queries::UPDATE_ROOM_MOD_STATUS_QUERY,
+    update_queries::UPDATE_ENTIRE_ROOM_PERMISSIONS,
+    update_queries::LOWER_ALL_HANDS_QUERY,
+    update_queries::UPDATE_USER_AVATAR_QUERY,
+    update_queries::UPDATE_DISPLAY_NAME_QUERY,
+    update_queries::UPDATE_SCHEDULED_ROOM_QUERY,
+    update_queries::UPDATE_SCHEDULED_ROOM_ATTENDING_QUERY,
+    update_queries::UPDATE_GITHUB_ACCESS_TOKEN_QUERY,
+    update_queries::UPDATE_DISCORD_ACCESS_TOKEN_QUERY,
+    update_queries::UPDATE_CONTRIBUTIONS_QUERY,
+    update_queries::INCREMENT_CONTRIBUTIONS_QUERY,
+    update_queries::UPDATE_BANNER_URL_QUERY,
+    update_queries::UPDATE_BIO_QUERY,
+    update_queries::UPDATE_LAST_ONLINE_QUERY,
+    update_queries::UPDATE_LAST_ONLINE_BATCH_QUERY,
+    update_queries::UPDATE_USER_NAME_QUERY,
+    update_queries::BAN_USER_QUERY,
+    update_queries::UPDATE_ENTIRE_USER,
+    update_queries::UPDATE_USER_PROFILE_FIELDS,
+    update_queries::UPDATE_USER_PREFERENCES,
+    update_queries::UPDATE_BASE_USER_FIELDS,
+    update_queries::END_ROOM_RECORDINGS_QUERY,
+    delete_queries::DELETE_ROOM_QUERY,
+    delete_queries::DELETE_ROOM_PERMISSIONS_QUERY,
+    delete_queries::DELETE_ROOM_BLOCKS_QUERY,
+    delete_queries::DELETE_ROOM_TAGS_QUERY,
+    delete_queries::DELETE_OLD_ROOM_VISITS_FOR_USER,
+    delete_queries::DELETE_ROOM_BLOCK_QUERY,
+    delete_queries::DELETE_USER_BLOCK_QUERY,
+    delete_queries::DELETE_SCHEDULED_ROOM_QUERY,
+    delete_queries::DELETE_ALL_SCHEDULED_ROOM_ATTENDANCE_QUERY,
+    delete_queries::DELETE_USER_ROOM_ATTENDANCE_QUERY,
+    delete_queries::DELETE_FOLLOWER_FOR_USER,
+];
+
+pub fn query_label(query: &str) -> &'static str {
+    QUERY_LABELS
+        .iter()
+        .find(|(sql, _)| *sql == query)
+        .map(|(_, label)| *label)
+        .unwrap_or("unlabeled")
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QueryHistogram {
+    /// how many queries finished within each of BUCKETS,
+    /// not cumulative.
+    pub buckets: [u64; BUCKETS.len()],
+    pub count: u64,
+    pub sum_seconds: f64,
+}
+
+impl QueryHistogram {
+    pub fn record(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        if let Some(index) = BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[index] += 1;
+        }
+        self.count += 1;
+        self.sum_seconds += seconds;
+    }
+
+    /// Estimated as the upper bound of the bucket the
+    /// quantile lands in, infinite when it lands past
+    /// the last bucket and None when nothing was recorded.
+    pub fn quantile(&self, quantile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = ((quantile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, amount) in self.buckets.iter().enumerate() {
+            seen += amount;
+            if seen >= target {
+                return Some(BUCKETS[index]);
+            }
+        }
+        Some(f64::INFINITY)
+    }
+}
+
+#[derive(Default)]
+pub struct QueryTimings {
+    histograms: HashMap<&'static str, QueryHistogram>,
+}
+
+impl QueryTimings {
+    pub fn record(&mut self, label: &'static str, elapsed: Duration) {
+        self.histograms.entry(label).or_default().record(elapsed);
+    }
+
+    /// Sorted by label so the output is stable.
+    pub fn snapshot(&self) -> Vec<(&'static str, QueryHistogram)> {
+        let mut snapshot: Vec<(&'static str, QueryHistogram)> = self
+            .histograms
+            .iter()
+            .map(|(label, histogram)| (*label, histogram.clone()))
+            .collect();
+        snapshot.sort_by_key(|(label, _)| *label);
+        snapshot
+    }
+}
+
+/// Shared by every connection in the pool.
+pub fn query_timings() -> &'static Mutex<QueryTimings> {
+    static QUERY_TIMINGS: OnceLock<Mutex<QueryTimings>> = OnceLock::new();
+    QUERY_TIMINGS.get_or_init(|| Mutex::new(QueryTimings::default()))
+}
+
+pub fn record_query(query: &str, elapsed: Duration) {
+    if let Ok(mut timings) = query_timings().lock() {
+        timings.record(query_label(query), elapsed);
+    }
+}
+
+fn format_seconds(seconds: f64) -> String {
+    if seconds.is_infinite() {
+        "+Inf".to_owned()
+    } else {
+        seconds.to_string()
+    }
+}
+
+/// Prometheus text for GET /metrics, a histogram per query
+/// plus the estimated p50 and p95 so they can be read directly.
+pub fn to_prometheus_text(snapshot: &[(&str, QueryHistogram)]) -> String {
+    let mut text = String::from(
+        "# HELP merlin_query_duration_seconds Time spent on each database query.\n\
+         # TYPE merlin_query_duration_seconds histogram\n",
+    );
+    for (label, histogram) in snapshot {
+        let mut cumulative = 0;
+        for (index, bound) in BUCKETS.iter().enumerate() {
+            cumulative += histogram.buckets[index];
+            text.push_str(&format!(
+                "merlin_query_duration_seconds_bucket{{query=\"{}\",le=\"{}\"}} {}\n",
+                label, bound, cumulative
+            ));
+        }
+        text.push_str(&format!(
+            "merlin_query_duration_seconds_bucket{{query=\"{}\",le=\"+Inf\"}} {}\n\
+             merlin_query_duration_seconds_sum{{query=\"{}\"}} {}\n\
+             merlin_query_duration_seconds_count{{query=\"{}\"}} {}\n",
+            label, histogram.count, label, histogram.sum_seconds, label, histogram.count
+        ));
+    }
+    text.push_str(
+        "# HELP merlin_query_duration_quantile_seconds Estimated query time quantiles.\n\
+         # TYPE merlin_query_duration_quantile_seconds gauge\n",
+    );
+    for (label, histogram) in snapshot {
+        for quantile in [0.5, 0.95] {
+            if let Some(seconds) = histogram.quantile(quantile) {
+                text.push_str(&format!(
+                    "merlin_query_duration_quantile_seconds{{query=\"{}\",quantile=\"{}\"}} {}\n",
+                    label,
+                    quantile,
+                    format_seconds(seconds)
+                ));
+            }
+        }
+    }
+    text
+}
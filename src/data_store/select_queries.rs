@@ -42,11 +42,33 @@ SELECT * FROM follower
 WHERE followerId = $1;
 ";
 
+pub const SELECT_FOLLOWERS_PAGE_FOR_USER_QUERY: &str = "
+SELECT * FROM follower
+WHERE userId = $1 AND Id > $2
+ORDER BY Id
+LIMIT $3;
+";
+
+pub const SELECT_FOLLOWING_PAGE_FOR_USER_QUERY: &str = "
+SELECT * FROM follower
+WHERE followerId = $1 AND Id > $2
+ORDER BY Id
+LIMIT $3;
+";
+
 pub const SELECT_SINGLE_FOLLOWING_FOR_USER_QUERY: &str = "
 SELECT * FROM follower
 WHERE followerId = $1 AND userId = $2;
 ";
 
+//$1 is the requesting user, $2 the users to check,
+//both directions come back in one selection.
+pub const SELECT_FOLLOWS_BETWEEN_USER_AND_USERS_QUERY: &str = "
+SELECT followerId, userId FROM follower
+WHERE (followerId = $1 AND userId = ANY($2))
+OR (userId = $1 AND followerId = ANY($2));
+";
+
 pub const SELECT_SINGLE_ROOM_ATTENDANCE_FOR_USER_QUERY: &str = "
 SELECT * FROM scheduled_room_attendance 
 WHERE userId = $1 AND scheduledRoomId = $2;
@@ -76,6 +98,19 @@ SELECT * FROM room_block
 WHERE ownerRoomId = $1;
 ";
 
+pub const SELECT_ROOM_IDS_BY_TAG: &str = "
+SELECT roomId FROM room_tags
+WHERE tag = $1;
+";
+
+pub const SELECT_ROOM_BLOCKS_WITH_PREVIEWS: &str = "
+SELECT room_block.Id, room_block.blockedUserId, users.displayName, users.avatarUrl
+FROM room_block
+JOIN users ON users.Id = room_block.blockedUserId
+WHERE room_block.ownerRoomId = $1
+ORDER BY room_block.Id;
+";
+
 pub const SELECT_ALL_ROOM_PERMISSIONS_FOR_USER: &str = "
 SELECT * FROM room_permission
 WHERE userId = $1 and roomId = $2;
@@ -86,9 +121,45 @@ SELECT * FROM users
 WHERE Id = $1;
 ";
 
-pub const SELECT_USER_BY_USERNAME: &str = "
+pub const SELECT_USER_IS_ADMIN: &str = "
+SELECT isAdmin FROM users
+WHERE Id = $1;
+";
+
+pub const SELECT_USER_PREFERENCES: &str = "
+SELECT preferences::text FROM users
+WHERE Id = $1;
+";
+
+pub const SELECT_USERS_BY_IDS: &str = "
+SELECT * FROM users
+WHERE Id = ANY($1);
+";
+
+//on its own so the text_pattern_ops indexes can serve it
+pub const SEARCH_USERS_BY_PREFIX: &str = "
+SELECT Id, displayName, avatarUrl FROM users
+WHERE banned = false
+AND (LOWER(displayName) LIKE $1 OR LOWER(userName) LIKE $1)
+ORDER BY Id
+LIMIT $2;
+";
+
+//only run when the prefix search came up short,
+//$2 are the users it already found
+pub const SEARCH_USERS_BY_SUBSTRING: &str = "
+SELECT Id, displayName, avatarUrl FROM users
+WHERE banned = false
+AND (LOWER(displayName) LIKE $1 OR LOWER(userName) LIKE $1)
+AND NOT (Id = ANY($2))
+ORDER BY Id
+LIMIT $3;
+";
+
+//usernames are unique regardless of casing
+pub const SELECT_USER_ID_BY_USERNAME: &str = "
 SELECT Id FROM users
-WHERE userName = $1;
+WHERE LOWER(userName) = LOWER($1);
 ";
 
 pub const SELECT_ALL_PERMISSIONS_FOR_ROOM: &str = "
@@ -107,3 +178,55 @@ pub const SELECT_USER_BY_CREATION_IDENTIFIERS: &str = "
 SELECT * FROM users
 WHERE githubId = $1 AND discordId = $2;
 ";
+
+pub const SELECT_APPLIED_MIGRATIONS: &str = "
+SELECT version FROM schema_migrations;
+";
+
+pub const HEALTH_CHECK_QUERY: &str = "
+SELECT 1;
+";
+
+//each join is paired with the next session boundary for the
+//same user and room, joins that haven't ended are left out
+pub const SELECT_ROOM_SESSION_DURATIONS: &str = "
+SELECT userId, roomId, COUNT(*) AS sessions,
+    SUM(EXTRACT(EPOCH FROM (endedAt - happenedAt)))::BIGINT AS totalSeconds
+FROM (
+    SELECT userId, roomId, eventType, happenedAt,
+        LEAD(happenedAt) OVER (
+            PARTITION BY userId, roomId ORDER BY happenedAt, Id
+        ) AS endedAt
+    FROM room_session_event
+    WHERE eventType IN ('join', 'leave', 'kick', 'ban')
+    AND happenedAt >= $1::text::timestamptz
+) AS boundaries
+WHERE eventType = 'join' AND endedAt IS NOT NULL
+GROUP BY userId, roomId
+ORDER BY userId, roomId;
+";
+
+pub const SELECT_RECENT_ROOM_VISITS_FOR_USER: &str = "
+SELECT roomId, to_char(visitedAt AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+FROM user_room_history
+WHERE userId = $1
+ORDER BY visitedAt DESC, Id DESC
+LIMIT $2;
+";
+
+//endedAt is NULL for a recording that's still going
+pub const SELECT_ROOM_RECORDINGS_FOR_ROOM: &str = "
+SELECT Id, startedBy, startedAt::text, endedAt::text
+FROM room_recording
+WHERE roomId = $1
+ORDER BY startedAt DESC, Id DESC;
+";
+
+pub const SELECT_MODERATION_LOG_FOR_ROOM: &str = "
+SELECT actorId, targetId, action, reason,
+    to_char(happenedAt AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')
+FROM moderation_audit
+WHERE roomId = $1
+ORDER BY happenedAt DESC, Id DESC
+LIMIT $2;
+";
@@ -1,18 +1,107 @@
 use crate::data_store::db_models::{
-    DBFollower, DBRoom, DBRoomBlock, DBRoomPermissions, DBScheduledRoom, DBScheduledRoomAttendance,
-    DBUser, DBUserBlock,
+    DBFollower, DBModerationAction, DBRoom, DBRoomBlock, DBRoomPermissions, DBRoomRecording,
+    DBRoomSessionEvent, DBRoomTag, DBScheduledRoom, DBScheduledRoomAttendance, DBUser, DBUserBlock,
+    DBUserRoomVisit,
 };
 
-use crate::communication::types::BaseUser;
-use crate::data_store::creation_queries;
+use crate::communication::types::{BaseUser, UserProfileEdit};
 use crate::data_store::delete_queries;
 use crate::data_store::insert_queries;
+#[cfg(feature = "metrics")]
+use crate::data_store::query_metrics;
 use crate::data_store::select_queries;
 use crate::data_store::update_queries;
-use tokio_postgres::{row::Row, Client, Error};
+use crate::logging;
+use futures::future::BoxFuture;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+#[cfg(feature = "metrics")]
+use std::time::Instant;
+use tokio::time::Duration;
+use tokio_postgres::error::SqlState;
+use tokio_postgres::types::ToSql;
+use tokio_postgres::{row::Row, Client, Error, Statement};
+
+/// Queries that take longer than PG_QUERY_TIMEOUT_MS are stopped
+/// by postgres itself(statement_timeout), so a stuck statement
+/// doesn't keep the connection busy for whoever gets it from the
+/// pool next, even when nobody is waiting on it anymore.
+pub const DEFAULT_QUERY_TIMEOUT_MS: u64 = 5000;
+
+#[derive(Debug)]
+pub enum DatabaseError {
+    Postgres(Error),
+    /// The query didn't finish within the query timeout.
+    DatabaseTimeout,
+}
+
+impl DatabaseError {
+    /// True when the write was refused for
+    /// breaking the named unique index.
+    pub fn violates_unique_index(&self, index: &str) -> bool {
+        match self {
+            DatabaseError::Postgres(e) => {
+                e.code() == Some(&SqlState::UNIQUE_VIOLATION)
+                    && e.as_db_error().and_then(|e| e.constraint()) == Some(index)
+            }
+            DatabaseError::DatabaseTimeout => false,
+        }
+    }
+}
+
+impl From<Error> for DatabaseError {
+    fn from(e: Error) -> Self {
+        DatabaseError::Postgres(e)
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatabaseError::Postgres(e) => write!(f, "{}", e),
+            DatabaseError::DatabaseTimeout => write!(f, "database query timed out"),
+        }
+    }
+}
+
+fn query_timeout_from_env() -> Duration {
+    let timeout_ms = match env::var("PG_QUERY_TIMEOUT_MS").map(|ms| ms.parse::<u64>()) {
+        Ok(Ok(ms)) if ms > 0 => ms,
+        _ => DEFAULT_QUERY_TIMEOUT_MS,
+    };
+    Duration::from_millis(timeout_ms)
+}
 
 pub struct ExecutionHandler {
     client: Client,
+    query_timeout: Duration,
+    /// sql text -> statement prepared on this connection
+    statements: HashMap<String, Statement>,
+    /// how many times we actually asked postgres to prepare
+    preparations: u64,
+    /// set when an in_transaction future was dropped part way,
+    /// the transaction is rolled back before the next statement
+    abandoned_transaction: bool,
+    /// the statement_timeout last set on the connection,
+    /// None until it's set(or a rollback undid it)
+    applied_timeout: Option<Duration>,
+}
+
+/// Marks the transaction abandoned unless it got to
+/// commit or roll back, a dropped future can't await
+/// the rollback itself.
+struct TransactionGuard<'a> {
+    handler: &'a mut ExecutionHandler,
+    finished: bool,
+}
+
+impl Drop for TransactionGuard<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.handler.abandoned_transaction = true;
+        }
+    }
 }
 
 //Handles the main sql execution by making usage of the DB types.
@@ -20,41 +109,199 @@ pub struct ExecutionHandler {
 //execution.
 impl ExecutionHandler {
     pub fn new(client_val: Client) -> Self {
-        Self { client: client_val }
+        Self {
+            client: client_val,
+            query_timeout: query_timeout_from_env(),
+            statements: HashMap::new(),
+            preparations: 0,
+            abandoned_transaction: false,
+            applied_timeout: None,
+        }
+    }
+
+    /// Every query is prepared once per connection and reused
+    /// after that, so hot queries skip parsing and planning.
+    async fn prepared(&mut self, query: &str) -> Result<Statement, DatabaseError> {
+        self.ready_connection().await?;
+        if let Some(statement) = self.statements.get(query) {
+            return Ok(statement.clone());
+        }
+        let result = self.client.prepare(query).await;
+        let statement = self.unwrap_timed(query, result)?;
+        self.preparations += 1;
+        self.statements.insert(query.to_owned(), statement.clone());
+        return Ok(statement);
+    }
+
+    async fn cached_query(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let statement = self.prepared(query).await?;
+        let result = self.client.query(&statement, params).await;
+        #[cfg(feature = "metrics")]
+        query_metrics::record_query(query, started.elapsed());
+        self.unwrap_timed(query, result)
+    }
+
+    async fn cached_execute(
+        &mut self,
+        query: &str,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<u64, DatabaseError> {
+        #[cfg(feature = "metrics")]
+        let started = Instant::now();
+        let statement = self.prepared(query).await?;
+        let result = self.client.execute(&statement, params).await;
+        #[cfg(feature = "metrics")]
+        query_metrics::record_query(query, started.elapsed());
+        self.unwrap_timed(query, result)
+    }
+
+    /// Postgres reports a statement that ran past
+    /// statement_timeout as cancelled.
+    fn unwrap_timed<T>(&self, query: &str, result: Result<T, Error>) -> Result<T, DatabaseError> {
+        match result {
+            Err(e) if e.code() == Some(&SqlState::QUERY_CANCELED) => {
+                logging::console::log_failure(&format!(
+                    "query timed out after {:?}:{}",
+                    self.query_timeout,
+                    query.trim()
+                ));
+                Err(DatabaseError::DatabaseTimeout)
+            }
+            result => Ok(result?),
+        }
+    }
+
+    pub fn set_query_timeout(&mut self, query_timeout: Duration) {
+        self.query_timeout = query_timeout;
+    }
+
+    pub fn preparations(&self) -> u64 {
+        self.preparations
+    }
+
+    /// Runs the operations as one transaction, committed if they
+    /// return Ok and rolled back otherwise. The operations use the
+    /// handler's usual methods:
+    ///
+    /// handler.in_transaction(|handler| Box::pin(async move {
+    ///     handler.delete_room(&room_id).await?;
+    ///     handler.delete_room_blocks(&room_id).await
+    /// }))
+    ///
+    /// If this future is dropped part way the transaction is rolled
+    /// back before the connection runs anything else, so whoever
+    /// gets it from the pool next never lands inside it.
+    pub async fn in_transaction<T, F>(&mut self, operations: F) -> Result<T, DatabaseError>
+    where
+        F: for<'a> FnOnce(&'a mut ExecutionHandler) -> BoxFuture<'a, Result<T, DatabaseError>>,
+    {
+        self.ready_connection().await?;
+        let mut guard = TransactionGuard {
+            handler: self,
+            finished: false,
+        };
+        guard.handler.client.batch_execute("BEGIN").await?;
+        let result = operations(&mut *guard.handler).await;
+        let finished = match &result {
+            Ok(_) => guard.handler.client.batch_execute("COMMIT").await,
+            Err(_) => guard.handler.client.batch_execute("ROLLBACK").await,
+        };
+        guard.finished = true;
+        if finished.is_err() || result.is_err() {
+            // a timeout set inside the transaction is undone with it
+            guard.handler.applied_timeout = None;
+        }
+        match (result, finished) {
+            (Ok(result), Ok(())) => Ok(result),
+            (Ok(_), Err(commit_error)) => Err(commit_error.into()),
+            (Err(e), Err(rollback_error)) => {
+                logging::console::log_failure(&format!(
+                    "issue rolling back transaction:{}",
+                    rollback_error
+                ));
+                Err(e)
+            }
+            (Err(e), Ok(())) => Err(e),
+        }
+    }
+
+    /// Rolls back a transaction whose future was dropped part way
+    /// and makes sure the connection's statement_timeout matches
+    /// the query timeout, before this connection runs anything else.
+    async fn ready_connection(&mut self) -> Result<(), Error> {
+        if self.abandoned_transaction {
+            self.client.batch_execute("ROLLBACK").await?;
+            self.abandoned_transaction = false;
+            self.applied_timeout = None;
+        }
+        if self.applied_timeout != Some(self.query_timeout) {
+            self.client
+                .batch_execute(&format!(
+                    "SET statement_timeout = {}",
+                    self.query_timeout.as_millis()
+                ))
+                .await?;
+            self.applied_timeout = Some(self.query_timeout);
+        }
+        Ok(())
+    }
+
+    /// Used by the readiness probe, the connection
+    /// is healthy if a trivial query comes back in time.
+    pub async fn ping(&mut self) -> Result<(), DatabaseError> {
+        let query = select_queries::HEALTH_CHECK_QUERY;
+        self.cached_query(query, &[]).await?;
+        Ok(())
     }
 
     //creation
     pub async fn create_table_if_needed(&mut self, query: &str) -> Result<(), Error> {
-        self.client.query(query, &[]).await?;
+        self.ready_connection().await?;
+        self.client.batch_execute(query).await?;
         return Ok(());
     }
 
-    pub async fn create_all_tables_if_needed(&mut self) -> Result<(), Error> {
-        self.create_table_if_needed(creation_queries::ROOM_TABLE_CREATION)
-            .await?;
-        self.create_table_if_needed(creation_queries::ROOM_PERMISSIONS_TABLE_CREATION)
-            .await?;
-        self.create_table_if_needed(creation_queries::FOLLOWER_TABLE_CREATION)
-            .await?;
-        self.create_table_if_needed(creation_queries::USER_TABLE_CREATION)
-            .await?;
-        self.create_table_if_needed(creation_queries::USER_BLOCK_TABLE_CREATION)
-            .await?;
-        self.create_table_if_needed(creation_queries::ROOM_BLOCK_CREATION)
-            .await?;
-        self.create_table_if_needed(creation_queries::SCHEDULED_ROOM_CREATION)
+    pub async fn select_applied_migrations(&mut self) -> Result<Vec<i32>, Error> {
+        let query = select_queries::SELECT_APPLIED_MIGRATIONS;
+        let rows = self.client.query(query, &[]).await?;
+        return Ok(rows.iter().map(|row| row.get(0)).collect());
+    }
+
+    /// The statements and the record of the migration
+    /// commit together or not at all.
+    pub async fn apply_migration(
+        &mut self,
+        version: i32,
+        name: &str,
+        statements: &[&str],
+    ) -> Result<(), Error> {
+        let transaction = self.client.transaction().await?;
+        // migrations may rewrite whole tables, they aren't
+        // held to the query timeout
+        transaction
+            .batch_execute("SET LOCAL statement_timeout = 0")
             .await?;
-        self.create_table_if_needed(creation_queries::SHEDULED_ROOM_ATTENDANCE)
+        for statement in statements {
+            transaction.batch_execute(statement).await?;
+        }
+        transaction
+            .execute(insert_queries::INSERT_APPLIED_MIGRATION, &[&version, &name])
             .await?;
+        transaction.commit().await?;
         return Ok(());
     }
 
     // insertion
-    pub async fn insert_user(&mut self, user: &DBUser) -> Result<i32, Error> {
+    pub async fn insert_user(&mut self, user: &DBUser) -> Result<i32, DatabaseError> {
         let query = insert_queries::INSERT_USER_QUERY;
         let rows = self
-            .client
-            .query(
+            .cached_query(
                 query,
                 &[
                     &user.display_name,
@@ -70,6 +317,7 @@ impl ExecutionHandler {
                     &user.bio,
                     &user.contributions,
                     &user.banner_url,
+                    &user.is_admin,
                 ],
             )
             .await?;
@@ -77,11 +325,132 @@ impl ExecutionHandler {
         return Ok(user_id);
     }
 
-    pub async fn insert_room(&mut self, room: &DBRoom) -> Result<i32, Error> {
+    pub async fn insert_room_session_event(
+        &mut self,
+        event: &DBRoomSessionEvent,
+    ) -> Result<(), DatabaseError> {
+        let query = insert_queries::INSERT_ROOM_SESSION_EVENT_QUERY;
+        self.cached_execute(
+            query,
+            &[
+                &event.user_id,
+                &event.room_id,
+                &event.event_type,
+                &event.happened_at,
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn insert_moderation_action(
+        &mut self,
+        action: &DBModerationAction,
+    ) -> Result<(), DatabaseError> {
+        let query = insert_queries::INSERT_MODERATION_ACTION_QUERY;
+        self.cached_execute(
+            query,
+            &[
+                &action.actor_id,
+                &action.target_id,
+                &action.room_id,
+                &action.action,
+                &action.reason,
+                &action.happened_at,
+            ],
+        )
+        .await?;
+        Ok(())
+    }
+
+    pub async fn insert_room_tag(&mut self, room_tag: &DBRoomTag) -> Result<(), DatabaseError> {
+        let query = insert_queries::INSERT_ROOM_TAG_QUERY;
+        self.cached_execute(query, &[&room_tag.room_id, &room_tag.tag])
+            .await?;
+        Ok(())
+    }
+
+    /// The room ends up with exactly these tags,
+    /// its old tags are kept if any insert fails.
+    pub async fn replace_room_tags(
+        &mut self,
+        room_id: &i32,
+        tags: &[String],
+    ) -> Result<(), DatabaseError> {
+        let room_tags: Vec<DBRoomTag> = tags
+            .iter()
+            .map(|tag| DBRoomTag {
+                id: -1,
+                room_id: *room_id,
+                tag: tag.to_owned(),
+            })
+            .collect();
+        let room_id = *room_id;
+        self.in_transaction(|handler| {
+            Box::pin(async move {
+                handler.delete_room_tags(&room_id).await?;
+                for room_tag in &room_tags {
+                    handler.insert_room_tag(room_tag).await?;
+                }
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    /// Records the visit and drops the user's older
+    /// visits past the latest `keep`.
+    pub async fn insert_user_room_visit(
+        &mut self,
+        visit: &DBUserRoomVisit,
+        keep: i64,
+    ) -> Result<(), DatabaseError> {
+        let user_id = visit.user_id;
+        let room_id = visit.room_id;
+        let visited_at = visit.visited_at.to_owned();
+        self.in_transaction(|handler| {
+            Box::pin(async move {
+                handler
+                    .cached_execute(
+                        insert_queries::INSERT_USER_ROOM_VISIT_QUERY,
+                        &[&user_id, &room_id, &visited_at],
+                    )
+                    .await?;
+                handler
+                    .cached_execute(
+                        delete_queries::DELETE_OLD_ROOM_VISITS_FOR_USER,
+                        &[&user_id, &keep],
+                    )
+                    .await?;
+                Ok(())
+            })
+        })
+        .await
+    }
+
+    /// Gives back the recording's id.
+    pub async fn insert_room_recording(
+        &mut self,
+        recording: &DBRoomRecording,
+    ) -> Result<i32, DatabaseError> {
+        let query = insert_queries::INSERT_ROOM_RECORDING_QUERY;
+        let rows = self
+            .cached_query(
+                query,
+                &[
+                    &recording.room_id,
+                    &recording.started_by,
+                    &recording.started_at,
+                ],
+            )
+            .await?;
+        Ok(rows[0].get(0))
+    }
+
+    pub async fn insert_room(&mut self, room: &DBRoom) -> Result<i32, DatabaseError> {
         let query = insert_queries::INSERT_ROOM_QUERY;
         let rows = self
-            .client
-            .query(query, &[&room.owner_id, &room.chat_mode])
+            .cached_query(query, &[&room.owner_id, &room.chat_mode])
             .await?;
         let room_id: i32 = rows[0].get(0);
         return Ok(room_id);
@@ -90,61 +459,62 @@ impl ExecutionHandler {
     pub async fn insert_room_permission(
         &mut self,
         permissions: &DBRoomPermissions,
-    ) -> Result<(), Error> {
+    ) -> Result<(), DatabaseError> {
         let query = insert_queries::INSERT_ROOM_PERMISSION_QUERY;
-        self.client
-            .query(
-                query,
-                &[
-                    &permissions.user_id,
-                    &permissions.room_id,
-                    &permissions.is_mod,
-                    &permissions.is_speaker,
-                    &permissions.asked_to_speak,
-                ],
-            )
-            .await?;
+        self.cached_query(
+            query,
+            &[
+                &permissions.user_id,
+                &permissions.room_id,
+                &permissions.is_mod,
+                &permissions.is_speaker,
+                &permissions.asked_to_speak,
+            ],
+        )
+        .await?;
         return Ok(());
     }
 
-    pub async fn insert_follower(&mut self, follower: &DBFollower) -> Result<(), Error> {
+    pub async fn insert_follower(&mut self, follower: &DBFollower) -> Result<(), DatabaseError> {
         let query = insert_queries::INSERT_FOLLOWER_QUERY;
-        self.client
-            .query(query, &[&follower.follower_id, &follower.user_id])
+        self.cached_query(query, &[&follower.follower_id, &follower.user_id])
             .await?;
         return Ok(());
     }
 
-    pub async fn insert_user_block(&mut self, user_block: &DBUserBlock) -> Result<(), Error> {
+    pub async fn insert_user_block(
+        &mut self,
+        user_block: &DBUserBlock,
+    ) -> Result<(), DatabaseError> {
         let query = insert_queries::INSERT_USER_BLOCK_QUERY;
-        self.client
-            .query(
-                query,
-                &[&user_block.owner_user_id, &user_block.blocked_user_id],
-            )
-            .await?;
+        self.cached_query(
+            query,
+            &[&user_block.owner_user_id, &user_block.blocked_user_id],
+        )
+        .await?;
         return Ok(());
     }
 
-    pub async fn insert_room_block(&mut self, room_block: &DBRoomBlock) -> Result<(), Error> {
+    pub async fn insert_room_block(
+        &mut self,
+        room_block: &DBRoomBlock,
+    ) -> Result<(), DatabaseError> {
         let query = insert_queries::INSERT_ROOM_BLOCK_QUERY;
-        self.client
-            .query(
-                query,
-                &[&room_block.owner_room_id, &room_block.blocked_user_id],
-            )
-            .await?;
+        self.cached_query(
+            query,
+            &[&room_block.owner_room_id, &room_block.blocked_user_id],
+        )
+        .await?;
         return Ok(());
     }
 
     pub async fn insert_scheduled_room(
         &mut self,
         scheduled_room: &DBScheduledRoom,
-    ) -> Result<i32, Error> {
+    ) -> Result<i32, DatabaseError> {
         let query = insert_queries::INSERT_SCHEDULED_ROOM_QUERY;
         let rows = self
-            .client
-            .query(
+            .cached_query(
                 query,
                 &[
                     &scheduled_room.room_name,
@@ -161,47 +531,54 @@ impl ExecutionHandler {
     pub async fn insert_scheduled_room_attendance(
         &mut self,
         scheduled_room_attendance: &DBScheduledRoomAttendance,
-    ) -> Result<(), Error> {
+    ) -> Result<(), DatabaseError> {
         let query = insert_queries::INSERT_SCHEDULED_ATTENDANCE_QUERY;
-        self.client
-            .query(
-                query,
-                &[
-                    &scheduled_room_attendance.user_id,
-                    &scheduled_room_attendance.scheduled_room_id,
-                    &scheduled_room_attendance.is_owner,
-                ],
-            )
-            .await?;
+        self.cached_query(
+            query,
+            &[
+                &scheduled_room_attendance.user_id,
+                &scheduled_room_attendance.scheduled_room_id,
+                &scheduled_room_attendance.is_owner,
+            ],
+        )
+        .await?;
         return Ok(());
     }
 
     //deletion
-    pub async fn delete_room(&mut self, room_id: &i32) -> Result<u64, Error> {
+    pub async fn delete_room(&mut self, room_id: &i32) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_ROOM_QUERY;
-        let num_modified = self.client.execute(query, &[room_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id]).await?;
         return Ok(num_modified);
     }
 
-    pub async fn delete_all_room_permissions(&mut self, room_id: &i32) -> Result<u64, Error> {
+    pub async fn delete_all_room_permissions(
+        &mut self,
+        room_id: &i32,
+    ) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_ROOM_PERMISSIONS_QUERY;
-        let num_modified = self.client.execute(query, &[room_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id]).await?;
         return Ok(num_modified);
     }
 
-    pub async fn delete_room_blocks(&mut self, room_id: &i32) -> Result<u64, Error> {
+    pub async fn delete_room_blocks(&mut self, room_id: &i32) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_ROOM_BLOCKS_QUERY;
-        let num_modified = self.client.execute(query, &[room_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id]).await?;
         return Ok(num_modified);
     }
 
+    pub async fn delete_room_tags(&mut self, room_id: &i32) -> Result<u64, DatabaseError> {
+        let query = delete_queries::DELETE_ROOM_TAGS_QUERY;
+        self.cached_execute(query, &[room_id]).await
+    }
+
     pub async fn delete_room_block_for_user(
         &mut self,
         room_id: &i32,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_ROOM_BLOCK_QUERY;
-        let num_modified = self.client.execute(query, &[room_id, user_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id, user_id]).await?;
         return Ok(num_modified);
     }
 
@@ -209,27 +586,26 @@ impl ExecutionHandler {
         &mut self,
         user_id: &i32,
         blocked_user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_USER_BLOCK_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[user_id, blocked_user_id])
+            .cached_execute(query, &[user_id, blocked_user_id])
             .await?;
         return Ok(num_modified);
     }
 
-    pub async fn delete_scheduled_room(&mut self, room_id: &i32) -> Result<u64, Error> {
+    pub async fn delete_scheduled_room(&mut self, room_id: &i32) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_SCHEDULED_ROOM_QUERY;
-        let num_modified = self.client.execute(query, &[room_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id]).await?;
         return Ok(num_modified);
     }
 
     pub async fn delete_all_scheduled_room_attendance(
         &mut self,
         room_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_ALL_SCHEDULED_ROOM_ATTENDANCE_QUERY;
-        let num_modified = self.client.execute(query, &[room_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id]).await?;
         return Ok(num_modified);
     }
 
@@ -237,9 +613,9 @@ impl ExecutionHandler {
         &mut self,
         user_id: &i32,
         room_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_USER_ROOM_ATTENDANCE_QUERY;
-        let num_modified = self.client.execute(query, &[room_id, user_id]).await?;
+        let num_modified = self.cached_execute(query, &[room_id, user_id]).await?;
         return Ok(num_modified);
     }
 
@@ -247,18 +623,48 @@ impl ExecutionHandler {
         &mut self,
         follower_id: &i32,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = delete_queries::DELETE_FOLLOWER_FOR_USER;
-        let num_modified = self.client.execute(query, &[follower_id, user_id]).await?;
+        let num_modified = self.cached_execute(query, &[follower_id, user_id]).await?;
         return Ok(num_modified);
     }
 
+    /// Everything tied to the user goes in one transaction,
+    /// so a failure part way leaves the account as it was.
+    /// Returns the amount of users deleted.
+    pub async fn delete_account(&mut self, user_id: &i32) -> Result<u64, DatabaseError> {
+        let user_id = *user_id;
+        self.in_transaction(|handler| {
+            Box::pin(async move {
+                let statements = [
+                    delete_queries::DELETE_ALL_FOLLOWS_FOR_USER,
+                    delete_queries::DELETE_ALL_USER_BLOCKS_FOR_USER,
+                    delete_queries::DELETE_ALL_ROOM_BLOCKS_FOR_USER,
+                    delete_queries::DELETE_ALL_ROOM_PERMISSIONS_FOR_USER,
+                    update_queries::UPDATE_NUM_ATTENDING_FOR_DELETED_USER,
+                    delete_queries::DELETE_OWNED_SCHEDULED_ROOMS_FOR_USER,
+                    delete_queries::DELETE_ALL_SCHEDULED_ROOM_ATTENDANCE_FOR_USER,
+                    delete_queries::DELETE_ALL_ROOM_SESSION_EVENTS_FOR_USER,
+                    delete_queries::DELETE_ALL_ROOM_HISTORY_FOR_USER,
+                    delete_queries::DELETE_ALL_ROOM_RECORDINGS_FOR_USER,
+                    delete_queries::DELETE_ALL_MODERATION_ACTIONS_FOR_USER,
+                ];
+                for statement in statements {
+                    handler.cached_execute(statement, &[&user_id]).await?;
+                }
+                handler
+                    .cached_execute(delete_queries::DELETE_USER_QUERY, &[&user_id])
+                    .await
+            })
+        })
+        .await
+    }
+
     //update
-    pub async fn update_entire_user(&mut self, user: &DBUser) -> Result<u64, Error> {
+    pub async fn update_entire_user(&mut self, user: &DBUser) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_ENTIRE_USER;
         let num_modified = self
-            .client
-            .execute(
+            .cached_execute(
                 query,
                 &[
                     &user.display_name,
@@ -274,6 +680,7 @@ impl ExecutionHandler {
                     &user.bio,
                     &user.contributions,
                     &user.banner_url,
+                    &user.is_admin,
                     &user.id,
                 ],
             )
@@ -285,23 +692,36 @@ impl ExecutionHandler {
         &mut self,
         room_id: &i32,
         new_owner_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_ROOM_OWNER_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&new_owner_id, room_id])
+            .cached_execute(query, &[&new_owner_id, room_id])
             .await?;
         return Ok(num_modified);
     }
 
+    /// ended_at is rfc3339, gives back how many were ended.
+    pub async fn end_room_recordings(
+        &mut self,
+        room_id: &i32,
+        ended_at: &String,
+    ) -> Result<u64, DatabaseError> {
+        let query = update_queries::END_ROOM_RECORDINGS_QUERY;
+        self.cached_execute(query, &[room_id, ended_at]).await
+    }
+
+    pub async fn lower_all_hands(&mut self, room_id: &i32) -> Result<Vec<Row>, DatabaseError> {
+        let query = update_queries::LOWER_ALL_HANDS_QUERY;
+        self.cached_query(query, &[room_id]).await
+    }
+
     pub async fn update_entire_room_permissions(
         &mut self,
         room_permission: &DBRoomPermissions,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_ENTIRE_ROOM_PERMISSIONS;
         let num_modified = self
-            .client
-            .execute(
+            .cached_execute(
                 query,
                 &[
                     &room_permission.is_mod,
@@ -321,11 +741,10 @@ impl ExecutionHandler {
         room_id: &i32,
         user_id: &i32,
         is_mod: bool,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_ROOM_MOD_STATUS_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&is_mod, room_id, user_id])
+            .cached_execute(query, &[&is_mod, room_id, user_id])
             .await?;
         return Ok(num_modified);
     }
@@ -334,9 +753,9 @@ impl ExecutionHandler {
         &mut self,
         avatar_url: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_USER_AVATAR_QUERY;
-        let num_modified = self.client.execute(query, &[&avatar_url, user_id]).await?;
+        let num_modified = self.cached_execute(query, &[&avatar_url, user_id]).await?;
         return Ok(num_modified);
     }
 
@@ -344,11 +763,10 @@ impl ExecutionHandler {
         &mut self,
         display_name: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_DISPLAY_NAME_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&display_name, user_id])
+            .cached_execute(query, &[&display_name, user_id])
             .await?;
         return Ok(num_modified);
     }
@@ -359,11 +777,10 @@ impl ExecutionHandler {
         room_id: &i32,
         desc: String,
         room_name: String,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_SCHEDULED_ROOM_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&scheduled_for, &desc, &room_name, room_id])
+            .cached_execute(query, &[&scheduled_for, &desc, &room_name, room_id])
             .await?;
         return Ok(num_modified);
     }
@@ -372,11 +789,10 @@ impl ExecutionHandler {
         &mut self,
         num_attending: &i32,
         room_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_SCHEDULED_ROOM_ATTENDING_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[num_attending, room_id])
+            .cached_execute(query, &[num_attending, room_id])
             .await?;
         return Ok(num_modified);
     }
@@ -386,18 +802,31 @@ impl ExecutionHandler {
         banned: bool,
         banned_reason: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::BAN_USER_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&banned, &banned_reason, user_id])
+            .cached_execute(query, &[&banned, &banned_reason, user_id])
             .await?;
         return Ok(num_modified);
     }
 
-    pub async fn update_user_bio(&mut self, bio: String, user_id: &i32) -> Result<u64, Error> {
+    /// preferences is the json of only the keys being changed.
+    pub async fn update_user_preferences(
+        &mut self,
+        preferences: String,
+        user_id: &i32,
+    ) -> Result<u64, DatabaseError> {
+        let query = update_queries::UPDATE_USER_PREFERENCES;
+        self.cached_execute(query, &[&preferences, user_id]).await
+    }
+
+    pub async fn update_user_bio(
+        &mut self,
+        bio: String,
+        user_id: &i32,
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_BIO_QUERY;
-        let num_modified = self.client.execute(query, &[&bio, &user_id]).await?;
+        let num_modified = self.cached_execute(query, &[&bio, &user_id]).await?;
         return Ok(num_modified);
     }
 
@@ -405,9 +834,9 @@ impl ExecutionHandler {
         &mut self,
         new_token: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_GITHUB_ACCESS_TOKEN_QUERY;
-        let num_modified = self.client.execute(query, &[&new_token, user_id]).await?;
+        let num_modified = self.cached_execute(query, &[&new_token, user_id]).await?;
         return Ok(num_modified);
     }
 
@@ -415,9 +844,19 @@ impl ExecutionHandler {
         &mut self,
         new_token: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_DISCORD_ACCESS_TOKEN_QUERY;
-        let num_modified = self.client.execute(query, &[&new_token, user_id]).await?;
+        let num_modified = self.cached_execute(query, &[&new_token, user_id]).await?;
+        return Ok(num_modified);
+    }
+
+    pub async fn increment_contributions(
+        &mut self,
+        amount: &i32,
+        user_id: &i32,
+    ) -> Result<u64, DatabaseError> {
+        let query = update_queries::INCREMENT_CONTRIBUTIONS_QUERY;
+        let num_modified = self.cached_execute(query, &[amount, user_id]).await?;
         return Ok(num_modified);
     }
 
@@ -425,11 +864,10 @@ impl ExecutionHandler {
         &mut self,
         new_contributions: &i32,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_CONTRIBUTIONS_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&new_contributions, user_id])
+            .cached_execute(query, &[&new_contributions, user_id])
             .await?;
         return Ok(num_modified);
     }
@@ -438,11 +876,10 @@ impl ExecutionHandler {
         &mut self,
         new_banner_url: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_BANNER_URL_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&new_banner_url, user_id])
+            .cached_execute(query, &[&new_banner_url, user_id])
             .await?;
         return Ok(num_modified);
     }
@@ -451,11 +888,24 @@ impl ExecutionHandler {
         &mut self,
         new_last_online: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_LAST_ONLINE_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&new_last_online, user_id])
+            .cached_execute(query, &[&new_last_online, user_id])
+            .await?;
+        return Ok(num_modified);
+    }
+
+    /// One query for every user in the batch,
+    /// ids and times line up by index.
+    pub async fn update_last_online_batch(
+        &mut self,
+        user_ids: &[i32],
+        new_last_onlines: &[String],
+    ) -> Result<u64, DatabaseError> {
+        let query = update_queries::UPDATE_LAST_ONLINE_BATCH_QUERY;
+        let num_modified = self
+            .cached_execute(query, &[&user_ids, &new_last_onlines])
             .await?;
         return Ok(num_modified);
     }
@@ -464,20 +914,21 @@ impl ExecutionHandler {
         &mut self,
         new_user_name: String,
         user_id: &i32,
-    ) -> Result<u64, Error> {
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_USER_NAME_QUERY;
         let num_modified = self
-            .client
-            .execute(query, &[&new_user_name, user_id])
+            .cached_execute(query, &[&new_user_name, user_id])
             .await?;
         return Ok(num_modified);
     }
 
-    pub async fn update_base_user_fields(&mut self, base_user: &BaseUser) -> Result<u64, Error> {
+    pub async fn update_base_user_fields(
+        &mut self,
+        base_user: &BaseUser,
+    ) -> Result<u64, DatabaseError> {
         let query = update_queries::UPDATE_BASE_USER_FIELDS;
         let num_modified = self
-            .client
-            .execute(
+            .cached_execute(
                 query,
                 &[
                     &base_user.display_name,
@@ -493,126 +944,256 @@ impl ExecutionHandler {
         return Ok(num_modified);
     }
 
+    pub async fn update_user_profile_fields(
+        &mut self,
+        user_id: &i32,
+        edit: &UserProfileEdit,
+    ) -> Result<u64, DatabaseError> {
+        let query = update_queries::UPDATE_USER_PROFILE_FIELDS;
+        let num_modified = self
+            .cached_execute(
+                query,
+                &[
+                    &edit.display_name,
+                    &edit.username,
+                    &edit.bio,
+                    &edit.avatar_url,
+                    &edit.banner_url,
+                    user_id,
+                ],
+            )
+            .await?;
+        return Ok(num_modified);
+    }
+
     //select
-    pub async fn select_all_rooms(&mut self) -> Result<Vec<Row>, Error> {
+    pub async fn select_all_rooms(&mut self) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_ROOM_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[]).await?;
         return Ok(result);
     }
 
-    pub async fn select_room_by_id(&mut self, room_id: &i32) -> Result<Vec<Row>, Error> {
+    pub async fn select_room_by_id(&mut self, room_id: &i32) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ROOM_BY_ID;
-        let result: Vec<Row> = self.client.query(query, &[room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[room_id]).await?;
         return Ok(result);
     }
 
-    pub async fn select_scheduled_room_by_id(&mut self, room_id: &i32) -> Result<Vec<Row>, Error> {
+    pub async fn select_scheduled_room_by_id(
+        &mut self,
+        room_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_SCHEDULED_ROOM_BY_ID;
-        let result: Vec<Row> = self.client.query(query, &[room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[room_id]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_owned_scheduled_rooms_for_user(
         &mut self,
         user_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_OWNED_SCHEDULED_ROOMS_FOR_USER;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
-    pub async fn select_all_scheduled_rooms(&mut self) -> Result<Vec<Row>, Error> {
+    pub async fn select_all_scheduled_rooms(&mut self) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_SCHEDULED_ROOMS_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_attendance_for_scheduled_room(
         &mut self,
         room_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_SCHEDULED_ROOM_ATTENDANCE_FOR_ROOM_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[room_id]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_room_attendance_for_user(
         &mut self,
         user_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_ATTENDANCE_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_followers_for_user(
         &mut self,
         user_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_FOLLOWERS_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_following_for_user(
         &mut self,
         user_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_FOLLOWING_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
-    pub async fn select_all_blocked_for_user(&mut self, user_id: &i32) -> Result<Vec<Row>, Error> {
+    /// (id, started by, started at, ended at) latest first.
+    pub async fn select_room_recordings_for_room(
+        &mut self,
+        room_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_ROOM_RECORDINGS_FOR_ROOM;
+        self.cached_query(query, &[room_id]).await
+    }
+
+    /// (actor id, target id, action, reason, happened at) latest first.
+    pub async fn select_moderation_log_for_room(
+        &mut self,
+        room_id: &i32,
+        limit: &i64,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_MODERATION_LOG_FOR_ROOM;
+        self.cached_query(query, &[room_id, limit]).await
+    }
+
+    /// (room id, visited at) latest first.
+    pub async fn select_recent_room_visits_for_user(
+        &mut self,
+        user_id: &i32,
+        limit: &i64,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_RECENT_ROOM_VISITS_FOR_USER;
+        self.cached_query(query, &[user_id, limit]).await
+    }
+
+    pub async fn select_followers_page_for_user(
+        &mut self,
+        user_id: &i32,
+        cursor: &i32,
+        limit: &i64,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_FOLLOWERS_PAGE_FOR_USER_QUERY;
+        self.cached_query(query, &[user_id, cursor, limit]).await
+    }
+
+    pub async fn select_following_page_for_user(
+        &mut self,
+        user_id: &i32,
+        cursor: &i32,
+        limit: &i64,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_FOLLOWING_PAGE_FOR_USER_QUERY;
+        self.cached_query(query, &[user_id, cursor, limit]).await
+    }
+
+    pub async fn select_all_blocked_for_user(
+        &mut self,
+        user_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_BLOCKED_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
-    pub async fn select_all_blockers_for_user(&mut self, user_id: &i32) -> Result<Vec<Row>, Error> {
+    pub async fn select_all_blockers_for_user(
+        &mut self,
+        user_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_BLOCKERS_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_blocked_users_for_room(
         &mut self,
         room_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_BLOCKED_USERS_FOR_ROOM_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[room_id]).await?;
         return Ok(result);
     }
 
+    pub async fn select_room_ids_by_tag(
+        &mut self,
+        tag: &String,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_ROOM_IDS_BY_TAG;
+        self.cached_query(query, &[tag]).await
+    }
+
+    pub async fn select_room_blocks_with_previews(
+        &mut self,
+        room_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_ROOM_BLOCKS_WITH_PREVIEWS;
+        self.cached_query(query, &[room_id]).await
+    }
+
     //SELECTS permissions for one room for one user
     pub async fn select_all_room_permissions_for_user(
         &mut self,
         user_id: &i32,
         room_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_ROOM_PERMISSIONS_FOR_USER;
-        let result: Vec<Row> = self.client.query(query, &[user_id, room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id, room_id]).await?;
         return Ok(result);
     }
 
     pub async fn select_all_room_permissions_for_room(
         &mut self,
         room_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_ALL_PERMISSIONS_FOR_ROOM;
-        let result: Vec<Row> = self.client.query(query, &[room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[room_id]).await?;
         return Ok(result);
     }
 
-    pub async fn select_user_by_id(&mut self, user_id: &i32) -> Result<Vec<Row>, Error> {
+    pub async fn select_user_by_id(&mut self, user_id: &i32) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_USER_BY_ID;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
-    pub async fn select_user_preview_by_id(&mut self, user_id: &i32) -> Result<Vec<Row>, Error> {
+    pub async fn select_user_preferences(
+        &mut self,
+        user_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_USER_PREFERENCES;
+        self.cached_query(query, &[user_id]).await
+    }
+
+    pub async fn select_user_is_admin(&mut self, user_id: &i32) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_USER_IS_ADMIN;
+        self.cached_query(query, &[user_id]).await
+    }
+
+    /// since is rfc3339.
+    pub async fn select_room_session_durations(
+        &mut self,
+        since: &String,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_ROOM_SESSION_DURATIONS;
+        self.cached_query(query, &[since]).await
+    }
+
+    pub async fn select_users_by_ids(
+        &mut self,
+        user_ids: &[i32],
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query = select_queries::SELECT_USERS_BY_IDS;
+        let result: Vec<Row> = self.cached_query(query, &[&user_ids]).await?;
+        return Ok(result);
+    }
+
+    pub async fn select_user_preview_by_id(
+        &mut self,
+        user_id: &i32,
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query = select_queries::SELECT_USER_PREVIEW_FOR_USER;
-        let result: Vec<Row> = self.client.query(query, &[user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id]).await?;
         return Ok(result);
     }
 
@@ -620,15 +1201,41 @@ impl ExecutionHandler {
         &mut self,
         discord_id: String,
         github_id: String,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query: &str = select_queries::SELECT_USER_BY_CREATION_IDENTIFIERS;
-        let result: Vec<Row> = self.client.query(query, &[&github_id, &discord_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[&github_id, &discord_id]).await?;
+        return Ok(result);
+    }
+
+    pub async fn search_users_by_prefix(
+        &mut self,
+        prefix_pattern: &String,
+        limit: &i64,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query: &str = select_queries::SEARCH_USERS_BY_PREFIX;
+        let result: Vec<Row> = self.cached_query(query, &[prefix_pattern, limit]).await?;
+        return Ok(result);
+    }
+
+    pub async fn search_users_by_substring(
+        &mut self,
+        substring_pattern: &String,
+        excluded_user_ids: &Vec<i32>,
+        limit: &i64,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query: &str = select_queries::SEARCH_USERS_BY_SUBSTRING;
+        let result: Vec<Row> = self
+            .cached_query(query, &[substring_pattern, excluded_user_ids, limit])
+            .await?;
         return Ok(result);
     }
 
-    pub async fn select_user_by_username(&mut self, user_name: &String) -> Result<Vec<Row>, Error> {
-        let query: &str = select_queries::SELECT_USER_BY_USERNAME;
-        let result: Vec<Row> = self.client.query(query, &[user_name]).await?;
+    pub async fn select_user_id_by_username(
+        &mut self,
+        user_name: &String,
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query: &str = select_queries::SELECT_USER_ID_BY_USERNAME;
+        let result: Vec<Row> = self.cached_query(query, &[user_name]).await?;
         return Ok(result);
     }
 
@@ -636,19 +1243,29 @@ impl ExecutionHandler {
         &mut self,
         follower_id: &i32,
         user_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query: &str = select_queries::SELECT_SINGLE_FOLLOWING_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[follower_id, user_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[follower_id, user_id]).await?;
         return Ok(result);
     }
 
+    /// (follower id, user id) rows in either direction.
+    pub async fn select_follows_between_user_and_users(
+        &mut self,
+        user_id: &i32,
+        user_ids: &[i32],
+    ) -> Result<Vec<Row>, DatabaseError> {
+        let query: &str = select_queries::SELECT_FOLLOWS_BETWEEN_USER_AND_USERS_QUERY;
+        self.cached_query(query, &[user_id, &user_ids]).await
+    }
+
     pub async fn select_single_room_attendance(
         &mut self,
         user_id: &i32,
         room_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query: &str = select_queries::SELECT_SINGLE_ROOM_ATTENDANCE_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[user_id, room_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[user_id, room_id]).await?;
         return Ok(result);
     }
 
@@ -657,9 +1274,9 @@ impl ExecutionHandler {
         &mut self,
         owner_id: &i32,
         blocked_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query: &str = select_queries::SELECT_SINGLE_USER_BLOCK_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[owner_id, blocked_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[owner_id, blocked_id]).await?;
         return Ok(result);
     }
     //owner of the block = owner_id
@@ -667,9 +1284,9 @@ impl ExecutionHandler {
         &mut self,
         owner_id: &i32,
         blocked_id: &i32,
-    ) -> Result<Vec<Row>, Error> {
+    ) -> Result<Vec<Row>, DatabaseError> {
         let query: &str = select_queries::SELECT_SINGLE_ROOM_BLOCK_FOR_USER_QUERY;
-        let result: Vec<Row> = self.client.query(query, &[owner_id, blocked_id]).await?;
+        let result: Vec<Row> = self.cached_query(query, &[owner_id, blocked_id]).await?;
         return Ok(result);
     }
 }
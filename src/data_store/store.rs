@@ -0,0 +1,314 @@
+use std::collections::{HashMap, HashSet};
+
+use async_trait::async_trait;
+use futures::lock::MutexGuard;
+use tokio_postgres::Row;
+
+use super::sql_execution_handler::{DatabaseError, ExecutionHandler};
+use crate::communication::types::RoomPermissions;
+
+/// The lookups handlers run before acting on a request(who is
+/// banned, blocked, following, allowed to do what). Handlers
+/// get the real ExecutionHandler, tests can hand in a
+/// MemoryDataStore and check that logic without Postgres.
+///
+/// The (bool, ..) results lead with whether an error was
+/// encountered, like the rest of data_fetcher.
+#[async_trait]
+pub trait DataStore: Send {
+    /// (banned, banned reason), None if the user
+    /// doesn't exist or the lookup failed.
+    async fn user_ban_status(&mut self, user_id: &i32) -> Option<(bool, String)>;
+    /// A failed lookup grants nothing.
+    async fn user_is_admin(&mut self, user_id: &i32) -> bool;
+    async fn user_id_by_username(&mut self, username: &str) -> Option<i32>;
+    /// Users this user blocked.
+    async fn blocked_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>);
+    async fn blocked_user_ids_for_room(&mut self, room_id: &i32) -> (bool, HashSet<i32>);
+    /// Users this user follows.
+    async fn following_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>);
+    /// Users following this user.
+    async fn follower_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>);
+    /// A failed lookup counts as not following.
+    async fn user_follows(&mut self, follower_id: &i32, user_id: &i32) -> bool;
+    /// (encountered_error, owner id, chat mode)
+    async fn room_owner_and_settings(&mut self, room_id: &i32) -> (bool, i32, String);
+    async fn room_permissions_for_users(
+        &mut self,
+        room_id: &i32,
+    ) -> (bool, HashMap<i32, RoomPermissions>);
+    async fn single_user_permissions(
+        &mut self,
+        room_id: &i32,
+        user_id: &i32,
+    ) -> Option<RoomPermissions>;
+}
+
+fn ids_in_column(rows: Result<Vec<Row>, DatabaseError>, col_index: usize) -> (bool, HashSet<i32>) {
+    match rows {
+        Ok(rows) => (false, rows.iter().map(|row| row.get(col_index)).collect()),
+        Err(_) => (true, HashSet::new()),
+    }
+}
+
+fn permissions_from_row(row: &Row) -> RoomPermissions {
+    RoomPermissions {
+        is_mod: row.get(3),
+        is_speaker: row.get(4),
+        asked_to_speak: row.get(5),
+    }
+}
+
+#[async_trait]
+impl DataStore for ExecutionHandler {
+    async fn user_ban_status(&mut self, user_id: &i32) -> Option<(bool, String)> {
+        match self.select_user_by_id(user_id).await {
+            Ok(rows) if rows.len() == 1 => {
+                let banned: bool = rows[0].get(9);
+                let banned_reason: Option<String> = rows[0].get(10);
+                Some((banned, banned_reason.unwrap_or_default()))
+            }
+            _ => None,
+        }
+    }
+
+    async fn user_is_admin(&mut self, user_id: &i32) -> bool {
+        match self.select_user_is_admin(user_id).await {
+            Ok(rows) if rows.len() == 1 => rows[0].get(0),
+            _ => false,
+        }
+    }
+
+    async fn user_id_by_username(&mut self, username: &str) -> Option<i32> {
+        match self.select_user_id_by_username(&username.to_owned()).await {
+            Ok(rows) if rows.len() == 1 => Some(rows[0].get(0)),
+            _ => None,
+        }
+    }
+
+    async fn blocked_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        ids_in_column(self.select_all_blocked_for_user(user_id).await, 2)
+    }
+
+    async fn blocked_user_ids_for_room(&mut self, room_id: &i32) -> (bool, HashSet<i32>) {
+        ids_in_column(self.select_all_blocked_users_for_room(room_id).await, 2)
+    }
+
+    async fn following_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        ids_in_column(self.select_all_following_for_user(user_id).await, 2)
+    }
+
+    async fn follower_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        ids_in_column(self.select_all_followers_for_user(user_id).await, 2)
+    }
+
+    async fn user_follows(&mut self, follower_id: &i32, user_id: &i32) -> bool {
+        match self.select_single_follow(follower_id, user_id).await {
+            Ok(rows) => !rows.is_empty(),
+            Err(_) => false,
+        }
+    }
+
+    async fn room_owner_and_settings(&mut self, room_id: &i32) -> (bool, i32, String) {
+        match self.select_room_by_id(room_id).await {
+            Ok(rows) if rows.len() == 1 => (false, rows[0].get(1), rows[0].get(2)),
+            Ok(_) => (true, -1, "".to_owned()),
+            Err(_) => (true, -2, "".to_owned()),
+        }
+    }
+
+    async fn room_permissions_for_users(
+        &mut self,
+        room_id: &i32,
+    ) -> (bool, HashMap<i32, RoomPermissions>) {
+        match self.select_all_room_permissions_for_room(room_id).await {
+            Ok(rows) => (
+                false,
+                rows.iter()
+                    .map(|row| (row.get(1), permissions_from_row(row)))
+                    .collect(),
+            ),
+            Err(_) => (true, HashMap::new()),
+        }
+    }
+
+    async fn single_user_permissions(
+        &mut self,
+        room_id: &i32,
+        user_id: &i32,
+    ) -> Option<RoomPermissions> {
+        let rows = self
+            .select_all_room_permissions_for_user(user_id, room_id)
+            .await
+            .unwrap();
+        if rows.len() == 1 {
+            return Some(permissions_from_row(&rows[0]));
+        }
+        None
+    }
+}
+
+/// Handlers pass the pool's guard along as it is.
+#[async_trait]
+impl<S: DataStore> DataStore for MutexGuard<'_, S> {
+    async fn user_ban_status(&mut self, user_id: &i32) -> Option<(bool, String)> {
+        (**self).user_ban_status(user_id).await
+    }
+
+    async fn user_is_admin(&mut self, user_id: &i32) -> bool {
+        (**self).user_is_admin(user_id).await
+    }
+
+    async fn user_id_by_username(&mut self, username: &str) -> Option<i32> {
+        (**self).user_id_by_username(username).await
+    }
+
+    async fn blocked_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        (**self).blocked_user_ids_for_user(user_id).await
+    }
+
+    async fn blocked_user_ids_for_room(&mut self, room_id: &i32) -> (bool, HashSet<i32>) {
+        (**self).blocked_user_ids_for_room(room_id).await
+    }
+
+    async fn following_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        (**self).following_user_ids_for_user(user_id).await
+    }
+
+    async fn follower_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        (**self).follower_user_ids_for_user(user_id).await
+    }
+
+    async fn user_follows(&mut self, follower_id: &i32, user_id: &i32) -> bool {
+        (**self).user_follows(follower_id, user_id).await
+    }
+
+    async fn room_owner_and_settings(&mut self, room_id: &i32) -> (bool, i32, String) {
+        (**self).room_owner_and_settings(room_id).await
+    }
+
+    async fn room_permissions_for_users(
+        &mut self,
+        room_id: &i32,
+    ) -> (bool, HashMap<i32, RoomPermissions>) {
+        (**self).room_permissions_for_users(room_id).await
+    }
+
+    async fn single_user_permissions(
+        &mut self,
+        room_id: &i32,
+        user_id: &i32,
+    ) -> Option<RoomPermissions> {
+        (**self).single_user_permissions(room_id, user_id).await
+    }
+}
+
+/// A DataStore kept in memory for tests, fill in
+/// whatever the code under test looks up.
+#[derive(Default)]
+pub struct MemoryDataStore {
+    /// user id -> (banned, banned reason)
+    pub bans: HashMap<i32, (bool, String)>,
+    pub admins: HashSet<i32>,
+    pub usernames: HashMap<String, i32>,
+    /// (blocker, blocked)
+    pub user_blocks: HashSet<(i32, i32)>,
+    /// (room id, blocked user)
+    pub room_blocks: HashSet<(i32, i32)>,
+    /// (follower, followed)
+    pub follows: HashSet<(i32, i32)>,
+    /// room id -> (owner id, chat mode)
+    pub rooms: HashMap<i32, (i32, String)>,
+    /// room id -> user id -> permissions
+    pub permissions: HashMap<i32, HashMap<i32, RoomPermissions>>,
+}
+
+impl MemoryDataStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DataStore for MemoryDataStore {
+    async fn user_ban_status(&mut self, user_id: &i32) -> Option<(bool, String)> {
+        self.bans.get(user_id).cloned()
+    }
+
+    async fn user_is_admin(&mut self, user_id: &i32) -> bool {
+        self.admins.contains(user_id)
+    }
+
+    async fn user_id_by_username(&mut self, username: &str) -> Option<i32> {
+        self.usernames.get(username).copied()
+    }
+
+    async fn blocked_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        let blocked = self
+            .user_blocks
+            .iter()
+            .filter(|(blocker, _)| blocker == user_id)
+            .map(|(_, blocked)| *blocked)
+            .collect();
+        (false, blocked)
+    }
+
+    async fn blocked_user_ids_for_room(&mut self, room_id: &i32) -> (bool, HashSet<i32>) {
+        let blocked = self
+            .room_blocks
+            .iter()
+            .filter(|(room, _)| room == room_id)
+            .map(|(_, blocked)| *blocked)
+            .collect();
+        (false, blocked)
+    }
+
+    async fn following_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        let following = self
+            .follows
+            .iter()
+            .filter(|(follower, _)| follower == user_id)
+            .map(|(_, followed)| *followed)
+            .collect();
+        (false, following)
+    }
+
+    async fn follower_user_ids_for_user(&mut self, user_id: &i32) -> (bool, HashSet<i32>) {
+        let followers = self
+            .follows
+            .iter()
+            .filter(|(_, followed)| followed == user_id)
+            .map(|(follower, _)| *follower)
+            .collect();
+        (false, followers)
+    }
+
+    async fn user_follows(&mut self, follower_id: &i32, user_id: &i32) -> bool {
+        self.follows.contains(&(*follower_id, *user_id))
+    }
+
+    async fn room_owner_and_settings(&mut self, room_id: &i32) -> (bool, i32, String) {
+        match self.rooms.get(room_id) {
+            Some((owner_id, chat_mode)) => (false, *owner_id, chat_mode.clone()),
+            None => (true, -1, "".to_owned()),
+        }
+    }
+
+    async fn room_permissions_for_users(
+        &mut self,
+        room_id: &i32,
+    ) -> (bool, HashMap<i32, RoomPermissions>) {
+        (
+            false,
+            self.permissions.get(room_id).cloned().unwrap_or_default(),
+        )
+    }
+
+    async fn single_user_permissions(
+        &mut self,
+        room_id: &i32,
+        user_id: &i32,
+    ) -> Option<RoomPermissions> {
+        self.permissions.get(room_id)?.get(user_id).cloned()
+    }
+}
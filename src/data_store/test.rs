@@ -1,4 +1,4 @@
-use crate::data_store::{sql_execution_handler::ExecutionHandler, tests};
+use crate::data_store::{migrations, sql_execution_handler::ExecutionHandler, tests};
 use tokio_postgres::{Error, NoTls};
 
 pub async fn test() {
@@ -8,6 +8,11 @@ pub async fn test() {
     test_room(&mut execution_handler).await;
     test_follower(&mut execution_handler).await;
     test_blocks(&mut execution_handler).await;
+    tests::room_session_events::test_room_session_durations(&mut execution_handler).await;
+    tests::transactions::test_failed_transaction_rolls_back(&mut execution_handler).await;
+    tests::transactions::test_transaction_commits(&mut execution_handler).await;
+    tests::transactions::test_dropped_transaction_rolls_back(&mut execution_handler).await;
+    tests::account::test_delete_account(&mut execution_handler).await;
 }
 
 async fn test_blocks(execution_handler: &mut ExecutionHandler) {
@@ -18,6 +23,7 @@ async fn test_blocks(execution_handler: &mut ExecutionHandler) {
     tests::blocks::test_single_room_block_gather(execution_handler).await;
     tests::blocks::test_remove_user_block(execution_handler).await;
     tests::blocks::test_remove_room_block(execution_handler).await;
+    tests::blocks::test_room_blocks_with_previews(execution_handler).await;
 }
 
 async fn test_follower(execution_handler: &mut ExecutionHandler) {
@@ -25,6 +31,8 @@ async fn test_follower(execution_handler: &mut ExecutionHandler) {
     tests::follower::test_gather_following(execution_handler).await;
     tests::follower::test_gather_single_follow(execution_handler).await;
     tests::follower::test_delete_following(execution_handler).await;
+    tests::follower::test_follow_list_pages(execution_handler).await;
+    tests::follower::test_follow_statuses(execution_handler).await;
 }
 
 async fn test_room(execution_handler: &mut ExecutionHandler) {
@@ -32,7 +40,11 @@ async fn test_room(execution_handler: &mut ExecutionHandler) {
     let sch_room_id = tests::room::test_scheduled_room_insert_and_gather(execution_handler).await;
     //live-non scheduled
     tests::room::test_update_room_owner(execution_handler, room_id.clone()).await;
+    tests::room::test_replace_room_tags(execution_handler, room_id.clone()).await;
     tests::room::test_delete_room(execution_handler, room_id.clone()).await;
+    tests::room::test_room_visit_history(execution_handler).await;
+    tests::room::test_room_recordings(execution_handler).await;
+    tests::room::test_moderation_audit(execution_handler).await;
     //scheduled
     tests::room::test_update_scheduled_room_num_attending(execution_handler, sch_room_id.clone())
         .await;
@@ -58,18 +70,29 @@ async fn test_users(execution_handler: &mut ExecutionHandler) {
     tests::user::test_updating_user_display_name(execution_handler, user_id.clone()).await;
     tests::user::test_updating_user_bio(execution_handler, user_id.clone()).await;
     tests::user::test_updating_last_online(execution_handler, user_id.clone()).await;
+    tests::user::test_updating_last_online_batch(execution_handler, user_id.clone()).await;
     tests::user::test_updating_github_access_token(execution_handler, user_id.clone()).await;
     tests::user::test_updating_discord_access_token(execution_handler, user_id.clone()).await;
     tests::user::test_update_contributions(execution_handler, user_id.clone()).await;
+    tests::user::test_increment_contributions(execution_handler, user_id.clone()).await;
+    tests::user::test_prepared_statements_are_cached(execution_handler, user_id.clone()).await;
+    tests::user::test_query_timeout(execution_handler, user_id.clone()).await;
+    tests::user::test_ping(execution_handler).await;
+    tests::user::test_user_preferences(execution_handler, user_id).await;
     tests::user::test_update_banner_url(execution_handler, user_id.clone()).await;
     tests::user::test_update_user_name(execution_handler, user_id.clone()).await;
+    tests::user::test_select_user_id_by_username(execution_handler, user_id.clone()).await;
+    tests::user::test_usernames_are_unique(execution_handler, user_id).await;
+    tests::user::test_duplicate_username_rename(execution_handler).await;
     let new_user_id = tests::user::test_updating_entire_user(execution_handler).await;
     tests::user::test_updating_base_user_fields(execution_handler, new_user_id).await;
 }
 
 async fn setup_tables(execution_handler: &mut ExecutionHandler) {
-    let result = execution_handler.create_all_tables_if_needed().await;
-    result.unwrap();
+    migrations::run_migrations(execution_handler).await.unwrap();
+    //everything is applied, so a second run does nothing
+    let applied = migrations::run_migrations(execution_handler).await.unwrap();
+    assert!(applied.is_empty());
 }
 
 async fn setup_execution_handler() -> Result<ExecutionHandler, Error> {
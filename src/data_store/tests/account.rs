@@ -0,0 +1,243 @@
+use crate::data_store::db_models::{
+    DBFollower, DBModerationAction, DBRoomBlock, DBRoomPermissions, DBRoomSessionEvent,
+    DBScheduledRoom, DBScheduledRoomAttendance, DBUser, DBUserBlock, DBUserRoomVisit,
+    RoomSessionEventType,
+};
+use crate::data_store::sql_execution_handler::ExecutionHandler;
+use chrono::Utc;
+
+//ids of other users and rooms, they only
+//need to exist as rows referencing them
+const OTHER_USER: i32 = 70001;
+const THIRD_USER: i32 = 70002;
+const ROOM: i32 = 8001;
+
+pub async fn test_delete_account(execution_handler: &mut ExecutionHandler) {
+    println!("Testing deleting an account");
+    let user_id = execution_handler
+        .insert_user(&gather_deleted_user())
+        .await
+        .unwrap();
+    for (follower_id, followed_id) in [
+        (user_id, OTHER_USER),
+        (OTHER_USER, user_id),
+        (OTHER_USER, THIRD_USER),
+    ] {
+        let follower = DBFollower {
+            id: 0,
+            follower_id,
+            user_id: followed_id,
+        };
+        execution_handler.insert_follower(&follower).await.unwrap();
+    }
+    for (owner_user_id, blocked_user_id) in [(user_id, OTHER_USER), (THIRD_USER, user_id)] {
+        let block = DBUserBlock {
+            id: 0,
+            owner_user_id,
+            blocked_user_id,
+        };
+        execution_handler.insert_user_block(&block).await.unwrap();
+    }
+    let room_block = DBRoomBlock {
+        id: 0,
+        owner_room_id: ROOM,
+        blocked_user_id: user_id,
+    };
+    execution_handler
+        .insert_room_block(&room_block)
+        .await
+        .unwrap();
+    for permission_user_id in [user_id, OTHER_USER] {
+        let permissions = DBRoomPermissions {
+            id: 0,
+            user_id: permission_user_id,
+            room_id: ROOM,
+            is_mod: false,
+            is_speaker: true,
+            asked_to_speak: false,
+        };
+        execution_handler
+            .insert_room_permission(&permissions)
+            .await
+            .unwrap();
+    }
+
+    //one scheduled room the user owns and one they attend
+    let owned_room_id = insert_scheduled_room(execution_handler, 2).await;
+    let attended_room_id = insert_scheduled_room(execution_handler, 2).await;
+    for (attendee, scheduled_room_id, is_owner) in [
+        (user_id, owned_room_id, true),
+        (OTHER_USER, owned_room_id, false),
+        (OTHER_USER, attended_room_id, true),
+        (user_id, attended_room_id, false),
+    ] {
+        let attendance = DBScheduledRoomAttendance {
+            id: 0,
+            user_id: attendee,
+            scheduled_room_id,
+            is_owner,
+        };
+        execution_handler
+            .insert_scheduled_room_attendance(&attendance)
+            .await
+            .unwrap();
+    }
+    let event = DBRoomSessionEvent {
+        id: -1,
+        user_id,
+        room_id: ROOM,
+        event_type: RoomSessionEventType::Join.as_str().to_owned(),
+        happened_at: Utc::now().to_rfc3339(),
+    };
+    execution_handler
+        .insert_room_session_event(&event)
+        .await
+        .unwrap();
+
+    let visit = DBUserRoomVisit {
+        id: -1,
+        user_id,
+        room_id: ROOM,
+        visited_at: Utc::now().to_rfc3339(),
+    };
+    execution_handler
+        .insert_user_room_visit(&visit, 10)
+        .await
+        .unwrap();
+
+    //acting, acted on, and a row the user isn't in
+    for (actor_id, target_id) in [
+        (user_id, OTHER_USER),
+        (OTHER_USER, user_id),
+        (OTHER_USER, THIRD_USER),
+    ] {
+        let action = DBModerationAction {
+            id: -1,
+            actor_id,
+            target_id,
+            room_id: ROOM,
+            action: "kick".to_owned(),
+            reason: None,
+            happened_at: Utc::now().to_rfc3339(),
+        };
+        execution_handler
+            .insert_moderation_action(&action)
+            .await
+            .unwrap();
+    }
+
+    let num_deleted = execution_handler.delete_account(&user_id).await.unwrap();
+    assert_eq!(num_deleted, 1);
+    let log = execution_handler
+        .select_moderation_log_for_room(&ROOM, &10)
+        .await
+        .unwrap();
+    let log: Vec<(i32, i32)> = log.iter().map(|row| (row.get(0), row.get(1))).collect();
+    assert_eq!(log, vec![(OTHER_USER, THIRD_USER)]);
+    let visits = execution_handler
+        .select_recent_room_visits_for_user(&user_id, &10)
+        .await
+        .unwrap();
+    assert!(visits.is_empty());
+
+    let user_rows = execution_handler.select_user_by_id(&user_id).await.unwrap();
+    assert!(user_rows.is_empty());
+    //follows in both directions go, others stay
+    let following = execution_handler
+        .select_all_following_for_user(&OTHER_USER)
+        .await
+        .unwrap();
+    assert_eq!(following.len(), 1);
+    let followers = execution_handler
+        .select_all_followers_for_user(&OTHER_USER)
+        .await
+        .unwrap();
+    assert!(followers.is_empty());
+    let blocked = execution_handler
+        .select_all_blocked_for_user(&THIRD_USER)
+        .await
+        .unwrap();
+    assert!(blocked.is_empty());
+    let room_blocks = execution_handler
+        .select_all_blocked_users_for_room(&ROOM)
+        .await
+        .unwrap();
+    assert!(room_blocks.is_empty());
+    let permissions = execution_handler
+        .select_all_room_permissions_for_room(&ROOM)
+        .await
+        .unwrap();
+    assert_eq!(permissions.len(), 1);
+
+    //the owned room goes with everyone's attendance of it
+    let owned_room = execution_handler
+        .select_scheduled_room_by_id(&owned_room_id)
+        .await
+        .unwrap();
+    assert!(owned_room.is_empty());
+    let owned_attendance = execution_handler
+        .select_all_attendance_for_scheduled_room(&owned_room_id)
+        .await
+        .unwrap();
+    assert!(owned_attendance.is_empty());
+    //the attended room has one less attendee
+    let attended_room = execution_handler
+        .select_scheduled_room_by_id(&attended_room_id)
+        .await
+        .unwrap();
+    let num_attending: i32 = attended_room[0].get(2);
+    assert_eq!(num_attending, 1);
+    let attended_attendance = execution_handler
+        .select_all_attendance_for_scheduled_room(&attended_room_id)
+        .await
+        .unwrap();
+    assert_eq!(attended_attendance.len(), 1);
+    let durations = execution_handler
+        .select_room_session_durations(&"2000-01-01T00:00:00+00:00".to_owned())
+        .await
+        .unwrap();
+    assert!(durations
+        .iter()
+        .all(|row| row.get::<usize, i32>(0) != user_id));
+
+    //nothing left to delete
+    let num_deleted = execution_handler.delete_account(&user_id).await.unwrap();
+    assert_eq!(num_deleted, 0);
+}
+
+async fn insert_scheduled_room(
+    execution_handler: &mut ExecutionHandler,
+    num_attending: i32,
+) -> i32 {
+    let scheduled_room = DBScheduledRoom {
+        id: 0,
+        room_name: "deleted account".to_owned(),
+        num_attending,
+        scheduled_for: "test_val".to_owned(),
+        desc: "test".to_owned(),
+    };
+    execution_handler
+        .insert_scheduled_room(&scheduled_room)
+        .await
+        .unwrap()
+}
+
+fn gather_deleted_user() -> DBUser {
+    return DBUser {
+        id: 0, //doesn't matter in insertion
+        display_name: "deleted".to_string(),
+        avatar_url: "test.com/avatar".to_string(),
+        user_name: "soon_deleted".to_string(),
+        last_online: Utc::now().to_string(),
+        github_id: "delete_gh".to_string(),
+        discord_id: "delete_dc".to_string(),
+        github_access_token: "1".to_string(),
+        discord_access_token: "2".to_string(),
+        banned: false,
+        banned_reason: "".to_string(),
+        bio: "test".to_string(),
+        contributions: 0,
+        banner_url: "test.com/test_banner".to_string(),
+        is_admin: false,
+    };
+}
@@ -1,4 +1,4 @@
-use crate::data_store::db_models::{DBRoomBlock, DBUserBlock};
+use crate::data_store::db_models::{DBRoomBlock, DBUser, DBUserBlock};
 use crate::data_store::sql_execution_handler::ExecutionHandler;
 
 pub async fn test_insert_and_gather_user_blocks(execution_handler: &mut ExecutionHandler) {
@@ -92,6 +92,59 @@ pub async fn test_remove_room_block(execution_handler: &mut ExecutionHandler) {
     assert_eq!(rows_affected, 1);
 }
 
+pub async fn test_room_blocks_with_previews(execution_handler: &mut ExecutionHandler) {
+    println!("testing room blocks with previews");
+    let blocked_user_id = execution_handler
+        .insert_user(&gather_blocked_user())
+        .await
+        .unwrap();
+    let room_id: i32 = 23;
+    let room_block = DBRoomBlock {
+        id: 0,
+        owner_room_id: room_id,
+        blocked_user_id,
+    };
+    execution_handler
+        .insert_room_block(&room_block)
+        .await
+        .unwrap();
+    let selected_rows = execution_handler
+        .select_room_blocks_with_previews(&room_id)
+        .await
+        .unwrap();
+    assert_eq!(selected_rows.len(), 1);
+    let user_id: i32 = selected_rows[0].get(1);
+    let display_name: String = selected_rows[0].get(2);
+    let avatar_url: String = selected_rows[0].get(3);
+    assert_eq!(user_id, blocked_user_id);
+    assert_eq!(display_name, "blocked");
+    assert_eq!(avatar_url, "test.com/blocked_avatar");
+    execution_handler
+        .delete_room_block_for_user(&room_id, &blocked_user_id)
+        .await
+        .unwrap();
+}
+
+fn gather_blocked_user() -> DBUser {
+    DBUser {
+        id: 0,
+        display_name: "blocked".to_string(),
+        avatar_url: "test.com/blocked_avatar".to_string(),
+        user_name: "blocked_user".to_string(),
+        last_online: "test".to_string(),
+        github_id: "room-block-gh".to_string(),
+        discord_id: "room-block-dc".to_string(),
+        github_access_token: "".to_string(),
+        discord_access_token: "".to_string(),
+        banned: false,
+        banned_reason: "not banned".to_string(),
+        bio: "".to_string(),
+        contributions: 0,
+        banner_url: "".to_string(),
+        is_admin: false,
+    }
+}
+
 fn gather_user_block() -> DBUserBlock {
     return DBUserBlock {
         id: 0,
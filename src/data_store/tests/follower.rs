@@ -1,3 +1,5 @@
+use crate::communication::data_fetcher;
+use crate::communication::types::FollowStatus;
 use crate::data_store::db_models::DBFollower;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
 
@@ -59,6 +61,96 @@ pub async fn test_delete_following(execution_handler: &mut ExecutionHandler) {
     assert_eq!(selected_rows.len(), 0);
 }
 
+pub async fn test_follow_list_pages(execution_handler: &mut ExecutionHandler) {
+    let target_user: i32 = 35;
+    let follower_ids: [i32; 3] = [40, 41, 42];
+    for follower_id in follower_ids.iter() {
+        let follow = DBFollower {
+            id: 0,
+            follower_id: *follower_id,
+            user_id: target_user,
+        };
+        execution_handler.insert_follower(&follow).await.unwrap();
+    }
+
+    //the first page starts after id 0 and is ordered by follow id
+    let first_page = execution_handler
+        .select_followers_page_for_user(&target_user, &0, &2)
+        .await
+        .unwrap();
+    assert_eq!(first_page.len(), 2);
+    let first_ids: Vec<i32> = first_page.iter().map(|row| row.get(1)).collect();
+    assert_eq!(first_ids, vec![40, 41]);
+
+    //continuing from the last follow id only returns what is left
+    let cursor: i32 = first_page[1].get(0);
+    let second_page = execution_handler
+        .select_followers_page_for_user(&target_user, &cursor, &2)
+        .await
+        .unwrap();
+    assert_eq!(second_page.len(), 1);
+    let follower_id: i32 = second_page[0].get(1);
+    assert_eq!(follower_id, 42);
+
+    let following_page = execution_handler
+        .select_following_page_for_user(&40, &0, &2)
+        .await
+        .unwrap();
+    assert_eq!(following_page.len(), 1);
+    let user_id: i32 = following_page[0].get(2);
+    assert_eq!(user_id, target_user);
+
+    for follower_id in follower_ids.iter() {
+        execution_handler
+            .delete_follower_for_user(follower_id, &target_user)
+            .await
+            .unwrap();
+    }
+}
+
+/// Both directions for a whole list of users come back from
+/// one selection, users with no follows are still listed.
+pub async fn test_follow_statuses(execution_handler: &mut ExecutionHandler) {
+    let requester: i32 = 50;
+    // 50 follows 51, 52 follows 50 and 53 is mutual
+    let follows: [(i32, i32); 5] = [(50, 51), (52, 50), (50, 53), (53, 50), (51, 52)];
+    for (follower_id, user_id) in follows.iter() {
+        let follow = DBFollower {
+            id: 0,
+            follower_id: *follower_id,
+            user_id: *user_id,
+        };
+        execution_handler.insert_follower(&follow).await.unwrap();
+    }
+
+    let rows = execution_handler
+        .select_follows_between_user_and_users(&requester, &[51, 52, 53, 54])
+        .await
+        .unwrap();
+    // 51 following 52 has nothing to do with the requester
+    assert_eq!(rows.len(), 4);
+
+    let (encountered_error, statuses) =
+        data_fetcher::get_follow_statuses(execution_handler, &requester, &[51, 52, 53, 54]).await;
+    assert!(!encountered_error);
+    assert_eq!(statuses.len(), 4);
+    let status = |you_are_following, follows_you| FollowStatus {
+        you_are_following,
+        follows_you,
+    };
+    assert_eq!(statuses[&51], status(true, false));
+    assert_eq!(statuses[&52], status(false, true));
+    assert_eq!(statuses[&53], status(true, true));
+    assert_eq!(statuses[&54], status(false, false));
+
+    for (follower_id, user_id) in follows.iter() {
+        execution_handler
+            .delete_follower_for_user(follower_id, user_id)
+            .await
+            .unwrap();
+    }
+}
+
 fn gather_db_follower() -> DBFollower {
     return DBFollower {
         id: 0,
@@ -0,0 +1,10 @@
+use crate::data_store::migrations::MIGRATIONS;
+
+/// Versions only ever go up by one, a gap or a
+/// repeat means a migration was edited or dropped.
+pub fn test_migration_versions_are_ordered() {
+    for (index, migration) in MIGRATIONS.iter().enumerate() {
+        assert_eq!(migration.version, index as i32 + 1);
+        assert!(!migration.statements.is_empty());
+    }
+}
@@ -0,0 +1,17 @@
+use futures::FutureExt;
+
+use crate::data_store::pool::Pool;
+
+/// Busy members are skipped, and once every member
+/// is busy callers wait until one is released.
+pub async fn test_pool_hands_out_free_members() {
+    let pool = Pool::new(vec![1, 2]);
+    assert_eq!(pool.size(), 2);
+    let first = pool.lock().await;
+    let second = pool.lock().await;
+    assert_eq!(*first, 1);
+    assert_eq!(*second, 2);
+    assert!(pool.lock().now_or_never().is_none());
+    drop(first);
+    assert_eq!(*pool.lock().await, 1);
+}
@@ -0,0 +1,39 @@
+use crate::data_store::query_metrics::{self, QueryHistogram, QueryTimings};
+use crate::data_store::select_queries;
+use std::time::Duration;
+
+pub fn test_query_histograms() {
+    assert_eq!(
+        query_metrics::query_label(select_queries::SELECT_USER_PREVIEW_FOR_USER),
+        "SELECT_USER_PREVIEW_FOR_USER"
+    );
+    assert_eq!(query_metrics::query_label("SELECT 2;"), "unlabeled");
+
+    let mut histogram = QueryHistogram::default();
+    assert_eq!(histogram.quantile(0.5), None);
+    for _ in 0..19 {
+        histogram.record(Duration::from_micros(800));
+    }
+    histogram.record(Duration::from_millis(40));
+    assert_eq!(histogram.count, 20);
+    assert_eq!(histogram.quantile(0.5), Some(0.001));
+    assert_eq!(histogram.quantile(0.95), Some(0.001));
+    assert_eq!(histogram.quantile(1.0), Some(0.05));
+    // slower than the last bucket
+    histogram.record(Duration::from_secs(6));
+    assert_eq!(histogram.quantile(1.0), Some(f64::INFINITY));
+
+    let mut timings = QueryTimings::default();
+    timings.record("B", Duration::from_millis(3));
+    timings.record("A", Duration::from_millis(3));
+    let snapshot = timings.snapshot();
+    assert_eq!(
+        snapshot.iter().map(|(label, _)| *label).collect::<Vec<_>>(),
+        vec!["A", "B"]
+    );
+    let text = query_metrics::to_prometheus_text(&snapshot);
+    assert!(text.contains("merlin_query_duration_seconds_bucket{query=\"A\",le=\"0.005\"} 1\n"));
+    assert!(text.contains("merlin_query_duration_seconds_count{query=\"B\"} 1\n"));
+    assert!(text
+        .contains("merlin_query_duration_quantile_seconds{query=\"A\",quantile=\"0.95\"} 0.005\n"));
+}
@@ -1,8 +1,10 @@
 use crate::data_store::db_models::{
-    DBRoom, DBRoomPermissions, DBScheduledRoom, DBScheduledRoomAttendance,
+    DBModerationAction, DBRoom, DBRoomPermissions, DBRoomRecording, DBScheduledRoom,
+    DBScheduledRoomAttendance, DBUserRoomVisit,
 };
+use crate::data_store::sql_execution_handler::DatabaseError;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
-use tokio_postgres::{row::Row, Error};
+use tokio_postgres::row::Row;
 
 //#rooms
 
@@ -50,6 +52,214 @@ pub async fn test_delete_room(execution_handler: &mut ExecutionHandler, room_id:
     assert_eq!(selected_rows.len(), 0);
 }
 
+pub async fn test_replace_room_tags(execution_handler: &mut ExecutionHandler, room_id: i32) {
+    println!("testing replacing room tags");
+    let tags = vec!["music".to_owned(), "tech".to_owned()];
+    execution_handler
+        .replace_room_tags(&room_id, &tags)
+        .await
+        .unwrap();
+    assert_eq!(
+        select_room_ids_by_tag(execution_handler, "music").await,
+        vec![room_id]
+    );
+    //the old tags are gone once replaced
+    let tags = vec!["gaming".to_owned()];
+    execution_handler
+        .replace_room_tags(&room_id, &tags)
+        .await
+        .unwrap();
+    assert!(select_room_ids_by_tag(execution_handler, "music")
+        .await
+        .is_empty());
+    assert_eq!(
+        select_room_ids_by_tag(execution_handler, "gaming").await,
+        vec![room_id]
+    );
+    //a duplicate tag fails the whole replacement
+    let tags = vec!["tech".to_owned(), "tech".to_owned()];
+    assert!(execution_handler
+        .replace_room_tags(&room_id, &tags)
+        .await
+        .is_err());
+    assert_eq!(
+        select_room_ids_by_tag(execution_handler, "gaming").await,
+        vec![room_id]
+    );
+    let num_deleted = execution_handler.delete_room_tags(&room_id).await.unwrap();
+    assert_eq!(num_deleted, 1);
+}
+
+pub async fn test_room_visit_history(execution_handler: &mut ExecutionHandler) {
+    println!("testing room visit history");
+    let user_id = 61001;
+    let keep = 3;
+    //rooms 1-4 visited in order, then room 2 again
+    for (room_id, visited_at) in [
+        (1, "2024-01-01T00:00:00+00:00"),
+        (2, "2024-01-02T00:00:00+00:00"),
+        (3, "2024-01-03T00:00:00+00:00"),
+        (4, "2024-01-04T00:00:00+00:00"),
+        (2, "2024-01-05T00:00:00+00:00"),
+    ] {
+        let visit = DBUserRoomVisit {
+            id: -1,
+            user_id,
+            room_id,
+            visited_at: visited_at.to_owned(),
+        };
+        execution_handler
+            .insert_user_room_visit(&visit, keep)
+            .await
+            .unwrap();
+    }
+    let rows = execution_handler
+        .select_recent_room_visits_for_user(&user_id, &10)
+        .await
+        .unwrap();
+    let visits: Vec<(i32, String)> = rows.iter().map(|row| (row.get(0), row.get(1))).collect();
+    //listed once, latest first, room 1 fell off
+    assert_eq!(
+        visits,
+        vec![
+            (2, "2024-01-05T00:00:00Z".to_owned()),
+            (4, "2024-01-04T00:00:00Z".to_owned()),
+            (3, "2024-01-03T00:00:00Z".to_owned()),
+        ]
+    );
+    let rows = execution_handler
+        .select_recent_room_visits_for_user(&user_id, &1)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+}
+
+pub async fn test_room_recordings(execution_handler: &mut ExecutionHandler) {
+    println!("testing room recordings");
+    let room_id = 62001;
+    let recording = DBRoomRecording {
+        id: -1,
+        room_id,
+        started_by: 61002,
+        started_at: "2024-01-01T00:00:00+00:00".to_owned(),
+    };
+    let recording_id = execution_handler
+        .insert_room_recording(&recording)
+        .await
+        .unwrap();
+    let rows = execution_handler
+        .select_room_recordings_for_room(&room_id)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get::<usize, i32>(0), recording_id);
+    assert_eq!(rows[0].get::<usize, i32>(1), 61002);
+    //still going
+    assert_eq!(rows[0].get::<usize, Option<String>>(3), None);
+
+    let ended_at = "2024-01-01T01:00:00+00:00".to_owned();
+    let num_ended = execution_handler
+        .end_room_recordings(&room_id, &ended_at)
+        .await
+        .unwrap();
+    assert_eq!(num_ended, 1);
+    let rows = execution_handler
+        .select_room_recordings_for_room(&room_id)
+        .await
+        .unwrap();
+    assert!(rows[0].get::<usize, Option<String>>(3).is_some());
+    //an ended recording isn't ended again
+    let num_ended = execution_handler
+        .end_room_recordings(&room_id, &ended_at)
+        .await
+        .unwrap();
+    assert_eq!(num_ended, 0);
+}
+
+pub async fn test_moderation_audit(execution_handler: &mut ExecutionHandler) {
+    println!("testing moderation audit");
+    let room_id = 62002;
+    for (target_id, action, reason, happened_at) in [
+        (61004, "mute", None, "2024-01-01T00:00:00+00:00"),
+        (61004, "kick", Some("spam"), "2024-01-01T00:05:00+00:00"),
+        (61005, "mod", None, "2024-01-01T00:10:00+00:00"),
+    ] {
+        let action = DBModerationAction {
+            id: -1,
+            actor_id: 61003,
+            target_id,
+            room_id,
+            action: action.to_owned(),
+            reason: reason.map(str::to_owned),
+            happened_at: happened_at.to_owned(),
+        };
+        execution_handler
+            .insert_moderation_action(&action)
+            .await
+            .unwrap();
+    }
+    //other rooms stay out of it
+    let elsewhere = DBModerationAction {
+        id: -1,
+        actor_id: 61003,
+        target_id: 61004,
+        room_id: room_id + 1,
+        action: "ban".to_owned(),
+        reason: None,
+        happened_at: "2024-01-01T00:15:00+00:00".to_owned(),
+    };
+    execution_handler
+        .insert_moderation_action(&elsewhere)
+        .await
+        .unwrap();
+    let rows = execution_handler
+        .select_moderation_log_for_room(&room_id, &10)
+        .await
+        .unwrap();
+    let log: Vec<(i32, String, Option<String>, String)> = rows
+        .iter()
+        .map(|row| (row.get(1), row.get(2), row.get(3), row.get(4)))
+        .collect();
+    //latest first
+    assert_eq!(
+        log,
+        vec![
+            (
+                61005,
+                "mod".to_owned(),
+                None,
+                "2024-01-01T00:10:00Z".to_owned()
+            ),
+            (
+                61004,
+                "kick".to_owned(),
+                Some("spam".to_owned()),
+                "2024-01-01T00:05:00Z".to_owned()
+            ),
+            (
+                61004,
+                "mute".to_owned(),
+                None,
+                "2024-01-01T00:00:00Z".to_owned()
+            ),
+        ]
+    );
+    let rows = execution_handler
+        .select_moderation_log_for_room(&room_id, &1)
+        .await
+        .unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].get::<usize, i32>(0), 61003);
+}
+
+async fn select_room_ids_by_tag(execution_handler: &mut ExecutionHandler, tag: &str) -> Vec<i32> {
+    let rows = execution_handler
+        .select_room_ids_by_tag(&tag.to_owned())
+        .await
+        .unwrap();
+    rows.iter().map(|row| row.get(0)).collect()
+}
+
 //#scheduled rooms
 
 pub async fn test_scheduled_room_insert_and_gather(
@@ -283,7 +493,10 @@ pub async fn test_delete_room_permissions(execution_handler: &mut ExecutionHandl
     assert_eq!(selected_rows.len(), 0);
 }
 
-fn assert_permissions(new_permissions: &DBRoomPermissions, gather_result: Result<Vec<Row>, Error>) {
+fn assert_permissions(
+    new_permissions: &DBRoomPermissions,
+    gather_result: Result<Vec<Row>, DatabaseError>,
+) {
     let selected_rows = gather_result.unwrap();
     assert_eq!(selected_rows.len(), 1);
     let user_id: i32 = selected_rows[0].get(1);
@@ -0,0 +1,54 @@
+use crate::data_store::db_models::{DBRoomSessionEvent, RoomSessionEventType};
+use crate::data_store::sql_execution_handler::ExecutionHandler;
+
+pub async fn test_room_session_durations(execution_handler: &mut ExecutionHandler) {
+    let events = [
+        (RoomSessionEventType::Join, "2022-01-01T00:00:00+00:00"),
+        (
+            RoomSessionEventType::SpeakerPromote,
+            "2022-01-01T00:00:10+00:00",
+        ),
+        (RoomSessionEventType::Leave, "2022-01-01T00:01:00+00:00"),
+        (RoomSessionEventType::Join, "2022-01-01T00:02:00+00:00"),
+        (RoomSessionEventType::Kick, "2022-01-01T00:02:30+00:00"),
+        //still in the room, so it isn't counted yet
+        (RoomSessionEventType::Join, "2022-01-01T00:03:00+00:00"),
+    ];
+    for (event_type, happened_at) in events.iter() {
+        let event = DBRoomSessionEvent {
+            id: -1,
+            user_id: 501,
+            room_id: 601,
+            event_type: event_type.as_str().to_owned(),
+            happened_at: happened_at.to_string(),
+        };
+        execution_handler
+            .insert_room_session_event(&event)
+            .await
+            .unwrap();
+    }
+
+    let selected_rows = execution_handler
+        .select_room_session_durations(&"2021-12-31T00:00:00+00:00".to_owned())
+        .await
+        .unwrap();
+    let ours: Vec<(i64, i64)> = selected_rows
+        .iter()
+        .filter(|row| row.get::<_, i32>(0) == 501 && row.get::<_, i32>(1) == 601)
+        .map(|row| (row.get(2), row.get(3)))
+        .collect();
+    //one minute, then thirty seconds
+    assert_eq!(ours, vec![(2, 90)]);
+
+    //sessions before since are left out
+    let selected_rows = execution_handler
+        .select_room_session_durations(&"2022-01-01T00:01:30+00:00".to_owned())
+        .await
+        .unwrap();
+    let ours: Vec<(i64, i64)> = selected_rows
+        .iter()
+        .filter(|row| row.get::<_, i32>(0) == 501 && row.get::<_, i32>(1) == 601)
+        .map(|row| (row.get(2), row.get(3)))
+        .collect();
+    assert_eq!(ours, vec![(1, 30)]);
+}
@@ -0,0 +1,91 @@
+use crate::data_store::db_models::DBFollower;
+use crate::data_store::sql_execution_handler::ExecutionHandler;
+use std::time::Duration;
+use tokio::time::timeout;
+
+//nobody else follows between these ids
+const FOLLOWER: i32 = 90001;
+const FOLLOWED: i32 = 90002;
+
+pub async fn test_failed_transaction_rolls_back(execution_handler: &mut ExecutionHandler) {
+    println!("Testing a failed transaction rolls back");
+    let result = execution_handler
+        .in_transaction(|handler| {
+            Box::pin(async move {
+                handler.insert_follower(&gather_follower()).await?;
+                //fails, the follow above can't be kept
+                handler
+                    .create_table_if_needed("SELECT missingColumn FROM follower;")
+                    .await?;
+                Ok(())
+            })
+        })
+        .await;
+    assert!(result.is_err());
+    //the connection isn't left in the aborted transaction
+    let follows = execution_handler
+        .select_single_follow(&FOLLOWER, &FOLLOWED)
+        .await
+        .unwrap();
+    assert!(follows.is_empty());
+}
+
+pub async fn test_transaction_commits(execution_handler: &mut ExecutionHandler) {
+    println!("Testing a transaction commits");
+    let num_deleted = execution_handler
+        .in_transaction(|handler| {
+            Box::pin(async move {
+                handler.insert_follower(&gather_follower()).await?;
+                handler.insert_follower(&gather_follower()).await?;
+                handler.delete_follower_for_user(&FOLLOWER, &FOLLOWED).await
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(num_deleted, 2);
+}
+
+/// A transaction whose future is dropped part way(a request
+/// timing out, a task being aborted) doesn't stay open on the
+/// connection for whoever gets it from the pool next.
+pub async fn test_dropped_transaction_rolls_back(execution_handler: &mut ExecutionHandler) {
+    println!("Testing a dropped transaction rolls back");
+    let dropped = timeout(
+        Duration::from_millis(100),
+        execution_handler.in_transaction(|handler| {
+            Box::pin(async move {
+                handler.insert_follower(&gather_follower()).await?;
+                //never finishes, the future is dropped here
+                futures::future::pending::<()>().await;
+                Ok(())
+            })
+        }),
+    )
+    .await;
+    assert!(dropped.is_err());
+    //inside the open transaction we'd still see our own insert
+    let follows = execution_handler
+        .select_single_follow(&FOLLOWER, &FOLLOWED)
+        .await
+        .unwrap();
+    assert!(follows.is_empty());
+    //and the next transaction starts cleanly
+    let num_deleted = execution_handler
+        .in_transaction(|handler| {
+            Box::pin(async move {
+                handler.insert_follower(&gather_follower()).await?;
+                handler.delete_follower_for_user(&FOLLOWER, &FOLLOWED).await
+            })
+        })
+        .await
+        .unwrap();
+    assert_eq!(num_deleted, 1);
+}
+
+fn gather_follower() -> DBFollower {
+    return DBFollower {
+        id: 0,
+        follower_id: FOLLOWER,
+        user_id: FOLLOWED,
+    };
+}
@@ -1,8 +1,14 @@
+use crate::communication::data_fetcher;
 use crate::communication::types::BaseUser;
-use crate::data_store::db_models::DBUser;
-use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::data_store::creation_queries;
+use crate::data_store::db_models::{DBUser, DBUserPreferences};
+use crate::data_store::sql_execution_handler::{
+    DatabaseError, ExecutionHandler, DEFAULT_QUERY_TIMEOUT_MS,
+};
 use chrono::Utc;
+use tokio::time::Duration;
 use tokio_postgres::row::Row;
+use tokio_postgres::{Client, NoTls};
 
 //dry violations help reduce the confusion and makes sure tests are clear.
 //massive generic functions would make things harder to follow in this
@@ -169,6 +175,20 @@ pub async fn test_updating_last_online(
     return user_id;
 }
 
+pub async fn test_updating_last_online_batch(
+    execution_handler: &mut ExecutionHandler,
+    user_id: i32,
+) -> i32 {
+    println!("Testing updating last online in a batch");
+    let new_last_online = format!("batch {}", Utc::now());
+    let result = execution_handler
+        .update_last_online_batch(&[user_id], std::slice::from_ref(&new_last_online))
+        .await;
+    assert_eq!(result.unwrap(), 1);
+    test_user_column_after_update(4, new_last_online, &user_id, execution_handler).await;
+    return user_id;
+}
+
 pub async fn test_updating_github_access_token(
     execution_handler: &mut ExecutionHandler,
     user_id: i32,
@@ -224,6 +244,137 @@ pub async fn test_updating_discord_access_token(
     return user_id;
 }
 
+pub async fn test_prepared_statements_are_cached(
+    execution_handler: &mut ExecutionHandler,
+    user_id: i32,
+) {
+    println!("Testing prepared statement caching");
+    execution_handler.select_user_by_id(&user_id).await.unwrap();
+    let preparations = execution_handler.preparations();
+    for _ in 0..20 {
+        execution_handler.select_user_by_id(&user_id).await.unwrap();
+    }
+    assert_eq!(execution_handler.preparations(), preparations);
+}
+
+/// A plain connection for holding locks(or killing
+/// backends) while the handler under test runs.
+async fn side_client(application_name: &str) -> Client {
+    let (client, connection) = tokio_postgres::connect(
+        &format!(
+            "host=localhost user=postgres port=5432 password=password application_name={}",
+            application_name
+        ),
+        NoTls,
+    )
+    .await
+    .unwrap();
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            println!("connection error: {}", e);
+        }
+    });
+    client
+}
+
+pub async fn test_query_timeout(execution_handler: &mut ExecutionHandler, user_id: i32) {
+    println!("Testing query timeouts");
+    //the select waits on the lock for as long as it's held
+    let blocker = side_client("query_timeout_blocker").await;
+    blocker
+        .batch_execute("BEGIN; LOCK TABLE users IN ACCESS EXCLUSIVE MODE;")
+        .await
+        .unwrap();
+    execution_handler.set_query_timeout(Duration::from_millis(100));
+    let result = execution_handler.select_user_by_id(&user_id).await;
+    assert!(matches!(result, Err(DatabaseError::DatabaseTimeout)));
+    //a select whose future is dropped(an aborted request) still
+    //ends at the timeout on the server, otherwise the ping
+    //would queue behind it for as long as the lock is held
+    let dropped = tokio::time::timeout(
+        Duration::from_millis(20),
+        execution_handler.select_user_by_id(&user_id),
+    )
+    .await;
+    assert!(dropped.is_err());
+    let pinged = tokio::time::timeout(Duration::from_secs(2), execution_handler.ping()).await;
+    assert!(matches!(pinged, Ok(Ok(()))));
+    //nothing of ours is left queued behind the lock
+    let mut waiting: i64 = -1;
+    for _ in 0..20 {
+        waiting = blocker
+            .query_one(
+                "SELECT count(*) FROM pg_locks JOIN pg_class ON pg_class.oid = pg_locks.relation WHERE pg_class.relname = 'users' AND NOT pg_locks.granted;",
+                &[],
+            )
+            .await
+            .unwrap()
+            .get(0);
+        if waiting == 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(waiting, 0);
+    blocker.batch_execute("ROLLBACK;").await.unwrap();
+    execution_handler.set_query_timeout(Duration::from_millis(DEFAULT_QUERY_TIMEOUT_MS));
+    //the connection is still usable afterwards
+    assert!(execution_handler.select_user_by_id(&user_id).await.is_ok());
+}
+
+pub async fn test_ping(execution_handler: &mut ExecutionHandler) {
+    println!("Testing database ping");
+    assert!(execution_handler.ping().await.is_ok());
+    //a handler whose connection the server dropped isn't ready
+    let mut dropped_handler = ExecutionHandler::new(side_client("ping_dropped").await);
+    assert!(dropped_handler.ping().await.is_ok());
+    let killer = side_client("ping_killer").await;
+    let killed = killer
+        .query(
+            "SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE application_name = 'ping_dropped';",
+            &[],
+        )
+        .await
+        .unwrap();
+    assert_eq!(killed.len(), 1);
+    assert!(dropped_handler.ping().await.is_err());
+}
+
+pub async fn test_user_preferences(execution_handler: &mut ExecutionHandler, user_id: i32) {
+    println!("Testing user preferences");
+    let preferences = data_fetcher::get_user_preferences(&user_id, execution_handler)
+        .await
+        .unwrap();
+    assert_eq!(preferences, DBUserPreferences::default());
+    //only the sent keys change
+    execution_handler
+        .update_user_preferences("{\"follower_alerts\":false}".to_owned(), &user_id)
+        .await
+        .unwrap();
+    let preferences = data_fetcher::get_user_preferences(&user_id, execution_handler)
+        .await
+        .unwrap();
+    assert!(!preferences.follower_alerts);
+    assert!(preferences.notify_followers_of_rooms);
+    assert!(data_fetcher::get_user_preferences(&-1, execution_handler)
+        .await
+        .is_none());
+}
+
+pub async fn test_increment_contributions(
+    execution_handler: &mut ExecutionHandler,
+    user_id: i32,
+) -> i32 {
+    println!("Testing incrementing contributions");
+    //set to 30 by the update test
+    let result = execution_handler
+        .increment_contributions(&1, &user_id)
+        .await;
+    assert_eq!(result.unwrap(), 1);
+    test_user_column_after_update(12 as usize, 31, &user_id, execution_handler).await;
+    return user_id;
+}
+
 pub async fn test_update_contributions(
     execution_handler: &mut ExecutionHandler,
     user_id: i32,
@@ -298,6 +449,55 @@ pub async fn test_update_user_name(execution_handler: &mut ExecutionHandler, use
     return user_id;
 }
 
+pub async fn test_select_user_id_by_username(
+    execution_handler: &mut ExecutionHandler,
+    user_id: i32,
+) {
+    println!("Testing select user id by username");
+    //casing shouldn't matter, the stored casing should stay
+    let result = execution_handler
+        .select_user_id_by_username(&"NEW_User445".to_owned())
+        .await;
+    let selected_rows = result.unwrap();
+    assert_eq!(selected_rows.len(), 1);
+    let found_id: i32 = selected_rows[0].get(0);
+    assert_eq!(found_id, user_id);
+    test_user_column_after_update(
+        3 as usize,
+        "new_user445".to_owned(),
+        &user_id,
+        execution_handler,
+    )
+    .await;
+}
+
+pub async fn test_usernames_are_unique(execution_handler: &mut ExecutionHandler, user_id: i32) {
+    println!("Testing username uniqueness");
+    let mut user = gather_different_user_struct();
+    user.user_name = "NEW_USER445".to_owned();
+    let result = execution_handler.insert_user(&user).await;
+    assert!(result
+        .unwrap_err()
+        .violates_unique_index(creation_queries::USER_NAME_UNIQUE_INDEX));
+    user.user_name = "unique_tester".to_owned();
+    let other_user_id = execution_handler.insert_user(&user).await.unwrap();
+    let result = execution_handler
+        .update_user_name("New_User445".to_owned(), &other_user_id)
+        .await;
+    assert!(result
+        .unwrap_err()
+        .violates_unique_index(creation_queries::USER_NAME_UNIQUE_INDEX));
+    //changing the casing of your own is fine
+    let result = execution_handler
+        .update_user_name("NEW_user445".to_owned(), &user_id)
+        .await;
+    assert_eq!(result.unwrap(), 1);
+    execution_handler
+        .update_user_name("new_user445".to_owned(), &user_id)
+        .await
+        .unwrap();
+}
+
 pub async fn test_updating_entire_user(execution_handler: &mut ExecutionHandler) -> i32 {
     println!("Testing updating entire user");
     let initial_user: DBUser = gather_user_struct();
@@ -413,6 +613,7 @@ fn gather_user_struct() -> DBUser {
         bio: "test".to_string(),
         contributions: 40,
         banner_url: "test.com/test_banner".to_string(),
+        is_admin: false,
     };
     return user;
 }
@@ -433,6 +634,7 @@ fn gather_different_user_struct() -> DBUser {
         bio: "test2".to_string(),
         contributions: 40,
         banner_url: "test.com/test_banner2".to_string(),
+        is_admin: false,
     };
     return user;
 }
@@ -451,3 +653,66 @@ fn gather_base_user_struct(user_id: i32) -> BaseUser {
         contributions: 44 as i32,
     };
 }
+
+/// Migration 10 renames users who shared a username before it
+/// was unique, a rename can't land on a name someone already has.
+pub async fn test_duplicate_username_rename(execution_handler: &mut ExecutionHandler) {
+    println!("Testing the duplicate username rename");
+    let client = side_client("duplicate_username_rename").await;
+    //run against the users table as it was before the index
+    client
+        .batch_execute(&format!(
+            "BEGIN; DROP INDEX {};",
+            creation_queries::USER_NAME_UNIQUE_INDEX
+        ))
+        .await
+        .unwrap();
+    let insert =
+        "INSERT INTO users (userName, banned, contributions) VALUES ($1, false, 0) RETURNING Id;";
+    let original: i32 = client
+        .query_one(insert, &[&"rename_me"])
+        .await
+        .unwrap()
+        .get(0);
+    let duplicate: i32 = client
+        .query_one(insert, &[&"RENAME_ME"])
+        .await
+        .unwrap()
+        .get(0);
+    //someone already picked the name the duplicate would get
+    let squatter: i32 = client
+        .query_one(insert, &[&format!("RENAME_ME_{}", duplicate)])
+        .await
+        .unwrap()
+        .get(0);
+    client
+        .batch_execute(creation_queries::USER_NAME_DUPLICATE_RENAME)
+        .await
+        .unwrap();
+    client
+        .batch_execute(creation_queries::USER_NAME_UNIQUE_INDEX_CREATION)
+        .await
+        .unwrap();
+    let mut names = Vec::new();
+    for id in [original, duplicate, squatter] {
+        let name: String = client
+            .query_one("SELECT userName FROM users WHERE Id = $1;", &[&id])
+            .await
+            .unwrap()
+            .get(0);
+        names.push(name);
+    }
+    assert_eq!(
+        names,
+        vec![
+            "rename_me".to_owned(),
+            format!("RENAME_ME_{}_1", duplicate),
+            format!("RENAME_ME_{}", duplicate),
+        ]
+    );
+    client.batch_execute("ROLLBACK;").await.unwrap();
+    //the index is back once the rollback is done
+    let mut user = gather_different_user_struct();
+    user.user_name = "NEW_USER445".to_owned();
+    assert!(execution_handler.insert_user(&user).await.is_err());
+}
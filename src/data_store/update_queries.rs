@@ -19,6 +19,14 @@ SET isMod = $1,
 WHERE roomId = $4 AND userId = $5;
 ";
 
+//gives back who had their hand lowered
+pub const LOWER_ALL_HANDS_QUERY: &str = "
+UPDATE room_permission
+SET askedToSpeak = false
+WHERE roomId = $1 AND askedToSpeak AND NOT isSpeaker
+RETURNING userId;
+";
+
 pub const UPDATE_USER_AVATAR_QUERY: &str = "
 UPDATE users
 SET avatarUrl = $1
@@ -63,6 +71,14 @@ SET contributions = $1
 WHERE Id = $2;
 ";
 
+//adds to the stored value so concurrent
+//increments can't overwrite each other
+pub const INCREMENT_CONTRIBUTIONS_QUERY: &str = "
+UPDATE users
+SET contributions = contributions + $1
+WHERE Id = $2;
+";
+
 pub const UPDATE_BANNER_URL_QUERY: &str = "
 UPDATE users
 SET bannerUrl = $1
@@ -81,6 +97,13 @@ SET lastOnline = $1
 WHERE Id = $2;
 ";
 
+pub const UPDATE_LAST_ONLINE_BATCH_QUERY: &str = "
+UPDATE users
+SET lastOnline = batch.lastOnline
+FROM UNNEST($1::int[], $2::varchar[]) AS batch(Id, lastOnline)
+WHERE users.Id = batch.Id;
+";
+
 pub const UPDATE_USER_NAME_QUERY: &str = "
 UPDATE users
 SET userName = $1
@@ -108,8 +131,27 @@ SET displayName = $1,
     bannedReason = $10,
     bio = $11,
     contributions = $12,
-    bannerUrl = $13
-WHERE Id = $14;
+    bannerUrl = $13,
+    isAdmin = $14
+WHERE Id = $15;
+";
+
+//null parameters keep the current value
+pub const UPDATE_USER_PROFILE_FIELDS: &str = "
+UPDATE users
+SET displayName = COALESCE($1, displayName),
+    userName = COALESCE($2, userName),
+    bio = COALESCE($3, bio),
+    avatarUrl = COALESCE($4, avatarUrl),
+    bannerUrl = COALESCE($5, bannerUrl)
+WHERE Id = $6;
+";
+
+//merges the given keys into the stored preferences
+pub const UPDATE_USER_PREFERENCES: &str = "
+UPDATE users
+SET preferences = preferences || $1::text::jsonb
+WHERE Id = $2;
 ";
 
 pub const UPDATE_BASE_USER_FIELDS: &str = "
@@ -122,3 +164,21 @@ SET displayName = $1,
     bannerUrl = $6
 WHERE Id = $7;
 ";
+
+//rooms the user was going to attend but doesn't
+//own, run before their attendance is deleted
+pub const UPDATE_NUM_ATTENDING_FOR_DELETED_USER: &str = "
+UPDATE scheduled_room
+SET numAttending = numAttending - 1
+WHERE Id IN (
+    SELECT scheduledRoomId FROM scheduled_room_attendance
+    WHERE userId = $1 and not isOwner
+);
+";
+
+//ends whatever the room is still recording
+pub const END_ROOM_RECORDINGS_QUERY: &str = "
+UPDATE room_recording
+SET endedAt = $2::text::timestamptz
+WHERE roomId = $1 AND endedAt IS NULL;
+";
@@ -1,4 +1,4 @@
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 
 use serde_json::Value;
 
@@ -129,6 +129,7 @@ pub async fn check_auth_and_insert(msg: serde_json::Value, state: &mut ServerSta
                                 external_server_id: external_server_id.clone(),
                                 passive_data_snapshot: None,
                                 outside_name: msg["outside_name"].to_string(),
+                                recent_commands: VecDeque::new(),
                             },
                         );
                         // We need to know what room links to what external server ID, since
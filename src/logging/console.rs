@@ -1,26 +1,114 @@
 use ansi_term::Colour;
 use anyhow::Result;
+use chrono::Utc;
+use std::env;
 use std::fs::File;
 use std::io::Read;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogFormat {
+    /// colored lines for local dev
+    Text,
+    /// one json object per line, for log aggregation
+    Json,
+}
+
+impl LogFormat {
+    /// Anything but "json" keeps the text default.
+    pub fn from_value(value: Option<&str>) -> Self {
+        match value.map(|value| value.trim().to_lowercase()).as_deref() {
+            Some("json") => LogFormat::Json,
+            _ => LogFormat::Text,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum LogLevel {
+    Info,
+    Error,
+}
+
+impl LogLevel {
+    /// "error" only keeps failures, anything else logs everything.
+    pub fn from_value(value: Option<&str>) -> Self {
+        match value.map(|value| value.trim().to_lowercase()).as_deref() {
+            Some("error") => LogLevel::Error,
+            _ => LogLevel::Info,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "info",
+            LogLevel::Error => "error",
+        }
+    }
+}
+
+/// Read once from GCS_LOG_FORMAT and GCS_LOG_LEVEL.
+fn log_settings() -> (LogFormat, LogLevel) {
+    static LOG_SETTINGS: OnceLock<(LogFormat, LogLevel)> = OnceLock::new();
+    *LOG_SETTINGS.get_or_init(|| {
+        (
+            LogFormat::from_value(env::var("GCS_LOG_FORMAT").ok().as_deref()),
+            LogLevel::from_value(env::var("GCS_LOG_LEVEL").ok().as_deref()),
+        )
+    })
+}
+
+/// kind is the text label (success/failure/event),
+/// kept so json lines can still be told apart.
+pub fn json_log_line(timestamp: &str, level: LogLevel, kind: &str, message: &str) -> String {
+    serde_json::json!({
+        "timestamp": timestamp,
+        "level": level.as_str(),
+        "target": "merlin",
+        "kind": kind,
+        "message": message,
+    })
+    .to_string()
+}
+
+fn log(level: LogLevel, kind: &str, colour: Colour, data: &str) {
+    let (format, min_level) = log_settings();
+    if level < min_level {
+        return;
+    }
+    match format {
+        LogFormat::Text => println!("[{}]:{}", colour.paint(kind.to_uppercase()), data),
+        LogFormat::Json => println!(
+            "{}",
+            json_log_line(&Utc::now().to_rfc3339(), level, kind, data)
+        ),
+    }
+}
 
 pub fn clear_terminal() {
-    print!("\x1B[2J\x1B[1;1H");
+    if log_settings().0 == LogFormat::Text {
+        print!("\x1B[2J\x1B[1;1H");
+    }
 }
 
 pub fn log_success(data: &str) {
-    println!("[{}]:{}", Colour::Green.paint("SUCCESS"), data);
+    log(LogLevel::Info, "success", Colour::Green, data);
 }
 
 pub fn log_failure(data: &str) {
-    println!("[{}]:{}", Colour::Red.paint("FAILURE"), data);
+    log(LogLevel::Error, "failure", Colour::Red, data);
 }
 
 pub fn log_event(data: &str) {
-    println!("[{}]:{}", Colour::Purple.paint("EVENT"), data);
+    log(LogLevel::Info, "event", Colour::Purple, data);
 }
 
-/// Log our ascii knight to console
+/// Log our ascii knight to console, skipped for
+/// json output since it isn't a log line.
 pub fn log_start(path: String) -> Result<()> {
+    if log_settings().0 == LogFormat::Json {
+        return Ok(());
+    }
     let mut file_result = File::open(path)?;
     let mut string_data = String::new();
     file_result.read_to_string(&mut string_data)?;
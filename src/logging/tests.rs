@@ -0,0 +1,27 @@
+use super::console::{self, LogFormat, LogLevel};
+
+pub fn test_log_settings() {
+    assert_eq!(LogFormat::from_value(None), LogFormat::Text);
+    assert_eq!(LogFormat::from_value(Some("text")), LogFormat::Text);
+    assert_eq!(LogFormat::from_value(Some(" JSON ")), LogFormat::Json);
+    assert_eq!(LogFormat::from_value(Some("yaml")), LogFormat::Text);
+    assert_eq!(LogLevel::from_value(None), LogLevel::Info);
+    assert_eq!(LogLevel::from_value(Some("Error")), LogLevel::Error);
+    assert!(LogLevel::Info < LogLevel::Error);
+}
+
+pub fn test_json_log_lines() {
+    let line = console::json_log_line(
+        "2022-03-01T10:00:00+00:00",
+        LogLevel::Error,
+        "failure",
+        "user(3) said \"hi\"",
+    );
+    assert!(!line.contains('\n'));
+    let parsed: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(parsed["timestamp"], "2022-03-01T10:00:00+00:00");
+    assert_eq!(parsed["level"], "error");
+    assert_eq!(parsed["target"], "merlin");
+    assert_eq!(parsed["kind"], "failure");
+    assert_eq!(parsed["message"], "user(3) said \"hi\"");
+}
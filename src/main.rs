@@ -6,14 +6,27 @@ use std::{env, net::SocketAddr};
 mod test;
 
 pub mod state {
+    pub mod active_speakers;
+    pub mod activity;
+    pub mod announcements;
+    pub mod contributions;
+    pub mod draining;
+    pub mod idempotency;
+    pub mod idle_rooms;
     pub mod owner_queue;
+    pub mod room_limits;
+    pub mod session;
+    pub mod speaker_requests;
     pub mod state;
+    pub mod stats;
     pub mod tests;
     pub mod types;
+    pub mod voice_servers;
 }
 
 pub mod common {
     pub mod response_logic;
+    pub mod tests;
 }
 
 pub mod data_store {
@@ -21,14 +34,26 @@ pub mod data_store {
     pub mod db_models;
     pub mod delete_queries;
     pub mod insert_queries;
+    pub mod migrations;
+    pub mod pool;
+    #[cfg(feature = "metrics")]
+    pub mod query_metrics;
     pub mod select_queries;
     pub mod sql_execution_handler;
+    pub mod store;
     pub mod test;
     pub mod update_queries;
     pub mod tests {
+        pub mod account;
         pub mod blocks;
         pub mod follower;
+        pub mod migrations;
+        pub mod pool;
+        #[cfg(feature = "metrics")]
+        pub mod query_metrics;
         pub mod room;
+        pub mod room_session_events;
+        pub mod transactions;
         pub mod user;
     }
 }
@@ -39,28 +64,45 @@ pub mod integration {
 }
 
 pub mod communication {
+    pub mod content_filter;
     pub mod data_capturer;
     pub mod data_fetcher;
     pub mod handler;
     pub mod helpers;
+    pub mod op_codes;
     pub mod router;
     pub mod test;
     pub mod types;
     pub mod tests {
         pub mod capture_and_fetch;
+        pub mod chat_tests;
         pub mod hand_tests;
         pub mod helpers;
+        pub mod join_tests;
         pub mod mod_tests;
+        pub mod op_code_tests;
         pub mod owner_tests;
+        pub mod profile_tests;
+        pub mod search_tests;
+        pub mod signaling_tests;
         pub mod standard_tests;
         pub mod tests;
     }
 }
 
+pub mod board {
+    pub mod handler;
+    pub mod tests;
+}
+
 pub mod auth {
+    pub mod admins;
     pub mod api_data_handler;
     pub mod authentication_handler;
+    pub mod guests;
     pub mod oauth_locations;
+    pub mod origins;
+    pub mod tests;
     pub mod ws_auth_handler;
 }
 
@@ -69,22 +111,32 @@ pub mod ws_fan {
     pub mod fan;
 }
 pub mod rabbitmq {
+    pub mod codec;
+    pub mod config;
     pub mod rabbit;
+    pub mod signaling;
     pub mod test;
 }
 pub mod rooms {
+    pub mod chat_modes;
+    pub mod confirmations;
     pub mod handler;
     pub mod permission_configs;
+    pub mod roles;
+    pub mod tags;
+    pub mod tests;
 }
 
 pub mod vs_response {
     pub mod handler;
     pub mod router;
+    pub mod tests;
     pub mod types;
 }
 
 pub mod logging {
     pub mod console;
+    pub mod tests;
 }
 
 #[tokio::main]
@@ -102,8 +154,13 @@ async fn main() {
         logging::console::log_start(win_path).unwrap();
     }
     let addr: SocketAddr = env::var("MERLIN_LOCATION").unwrap().parse().unwrap();
-    let tls_or_not = env::var("MERLIN_TLS_OR_NOT").unwrap();
+    let tls = server::TlsPaths::from_env();
+    //probes get their own listener so they work
+    //no matter what sits in front of the websocket
+    let health_addr: Option<SocketAddr> = env::var("MERLIN_HEALTH_LOCATION")
+        .ok()
+        .map(|location| location.parse().unwrap());
 
     //start
-    server::start_server(addr, tls_or_not).await;
+    server::start_server(addr, tls, health_addr).await;
 }
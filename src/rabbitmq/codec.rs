@@ -0,0 +1,78 @@
+/*
+How messages to and from the voice server are encoded.
+
+JSON unless GCS_VOICE_CODEC says otherwise. MessagePack is
+only there in builds with the msgpack feature, and the voice
+server has to be set to the same codec or neither side
+will be able to read the other.
+*/
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VoiceCodec {
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MsgPack,
+}
+
+static VOICE_CODEC: OnceLock<VoiceCodec> = OnceLock::new();
+
+impl VoiceCodec {
+    /// Unset or empty keeps json, "msgpack" needs the msgpack feature.
+    pub fn from_value(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|value| value.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("json") => Ok(VoiceCodec::Json),
+            #[cfg(feature = "msgpack")]
+            Some("msgpack") => Ok(VoiceCodec::MsgPack),
+            #[cfg(not(feature = "msgpack"))]
+            Some("msgpack") => Err(
+                "GCS_VOICE_CODEC is msgpack but merlin was built without the msgpack feature"
+                    .to_owned(),
+            ),
+            Some(other) => Err(format!("unknown GCS_VOICE_CODEC:{}", other)),
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Vec<u8> {
+        match self {
+            VoiceCodec::Json => serde_json::to_vec(value).unwrap(),
+            // named so structs stay maps keyed by their
+            // camel case names, just like the json
+            #[cfg(feature = "msgpack")]
+            VoiceCodec::MsgPack => rmp_serde::to_vec_named(value).unwrap(),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(&self, data: &[u8]) -> Result<T, String> {
+        match self {
+            VoiceCodec::Json => serde_json::from_slice(data).map_err(|e| e.to_string()),
+            #[cfg(feature = "msgpack")]
+            VoiceCodec::MsgPack => rmp_serde::from_slice(data).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// The voice server router reads json text,
+    /// so anything else is turned into json first.
+    pub fn to_json(&self, data: &[u8]) -> Result<String, String> {
+        match self {
+            VoiceCodec::Json => Ok(String::from_utf8_lossy(data).into_owned()),
+            #[cfg(feature = "msgpack")]
+            VoiceCodec::MsgPack => self
+                .decode::<serde_json::Value>(data)
+                .map(|value| value.to_string()),
+        }
+    }
+}
+
+/// Set once at startup from the RabbitMQ config.
+pub fn set_voice_codec(codec: VoiceCodec) {
+    let _ = VOICE_CODEC.set(codec);
+}
+
+/// Json until set_voice_codec is called.
+pub fn voice_codec() -> VoiceCodec {
+    VOICE_CODEC.get().copied().unwrap_or_default()
+}
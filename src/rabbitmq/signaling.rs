@@ -0,0 +1,74 @@
+use super::codec;
+use super::rabbit::{self, PublishChannel};
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use lapin::Result;
+use std::sync::Arc;
+
+/// Where handlers send their requests for the voice server.
+/// Live it's the RabbitMQ publish channel, tests hand in a
+/// RecordingSignaling to see what would have been published.
+#[async_trait]
+pub trait VoiceSignaling: Send + Sync {
+    /// data is already encoded with the voice codec.
+    /// Ok(false) means the message was queued
+    /// until the channel is back.
+    async fn publish(&self, data: Vec<u8>) -> Result<bool>;
+}
+
+#[async_trait]
+impl VoiceSignaling for Mutex<PublishChannel> {
+    async fn publish(&self, data: Vec<u8>) -> Result<bool> {
+        let channel = self.lock().await;
+        rabbit::publish_voice_message(&channel, data).await
+    }
+}
+
+/// Lets callers holding the shared channel
+/// pass it along as it is.
+#[async_trait]
+impl<T: VoiceSignaling + ?Sized> VoiceSignaling for Arc<T> {
+    async fn publish(&self, data: Vec<u8>) -> Result<bool> {
+        self.as_ref().publish(data).await
+    }
+}
+
+/// Keeps every message in memory instead of publishing it.
+#[derive(Default)]
+pub struct RecordingSignaling {
+    published: std::sync::Mutex<Vec<Vec<u8>>>,
+}
+
+impl RecordingSignaling {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything published so far as json, oldest first.
+    pub fn published(&self) -> Vec<String> {
+        let codec = codec::voice_codec();
+        self.published
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|data| codec.to_json(data).ok())
+            .collect()
+    }
+
+    /// The op of each published voice server request.
+    pub fn published_ops(&self) -> Vec<String> {
+        self.published()
+            .iter()
+            .filter_map(|data| serde_json::from_str::<serde_json::Value>(data).ok())
+            .filter_map(|request| request["op"].as_str().map(str::to_owned))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl VoiceSignaling for RecordingSignaling {
+    async fn publish(&self, data: Vec<u8>) -> Result<bool> {
+        self.published.lock().unwrap().push(data);
+        Ok(true)
+    }
+}
@@ -0,0 +1,79 @@
+use std::env;
+
+use crate::state::types::Room;
+
+/// Who can send chat messages in a room. Mods and
+/// the owner can always chat, whatever the mode.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChatMode {
+    Everyone,
+    /// only users following the room owner
+    FollowersOnly,
+    Disabled,
+}
+
+impl ChatMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "everyone" => Some(ChatMode::Everyone),
+            "followers_only" => Some(ChatMode::FollowersOnly),
+            "disabled" => Some(ChatMode::Disabled),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChatMode::Everyone => "everyone",
+            ChatMode::FollowersOnly => "followers_only",
+            ChatMode::Disabled => "disabled",
+        }
+    }
+
+    /// The older on/off switch, turning chat back on
+    /// opens it to everyone.
+    pub fn with_chat_enabled(self, chat_enabled: bool) -> Self {
+        match (chat_enabled, self) {
+            (false, _) => ChatMode::Disabled,
+            (true, ChatMode::Disabled) => ChatMode::Everyone,
+            (true, mode) => mode,
+        }
+    }
+}
+
+/// Rooms created without a chat_mode use DEFAULT_CHAT_MODE,
+/// anything unrecognized there means everyone.
+pub fn default_chat_mode() -> ChatMode {
+    env::var("DEFAULT_CHAT_MODE")
+        .ok()
+        .and_then(|value| ChatMode::parse(value.trim()))
+        .unwrap_or(ChatMode::Everyone)
+}
+
+/// None falls back to the server default.
+pub fn chat_mode_from_request(value: Option<&str>) -> Result<ChatMode, String> {
+    match value {
+        None => Ok(default_chat_mode()),
+        Some(value) => ChatMode::parse(value)
+            .ok_or_else(|| "chat mode must be everyone, followers_only or disabled".to_owned()),
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChatAccess {
+    Open,
+    Closed,
+    /// allowed once they're known to follow this owner
+    NeedsFollow(i32),
+}
+
+pub fn chat_access(room: &Room, user_id: &i32) -> ChatAccess {
+    if room.mod_ids.contains(user_id) || room.owner_id == *user_id {
+        return ChatAccess::Open;
+    }
+    match room.chat_mode {
+        ChatMode::Everyone => ChatAccess::Open,
+        ChatMode::Disabled => ChatAccess::Closed,
+        ChatMode::FollowersOnly => ChatAccess::NeedsFollow(room.owner_id),
+    }
+}
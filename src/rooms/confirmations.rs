@@ -0,0 +1,58 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use tokio::sync::oneshot;
+use tokio::time::timeout;
+
+/// How long a new room waits for the voice server to say it
+/// created its side, from VOICE_ROOM_CONFIRM_TIMEOUT_MS. Unset
+/// means rooms are finalized right away, for voice servers
+/// that don't send room-created yet.
+pub fn confirm_timeout() -> Option<Duration> {
+    env::var("VOICE_ROOM_CONFIRM_TIMEOUT_MS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .map(Duration::from_millis)
+}
+
+/// Kept out of the server state, the request waiting
+/// on a room lets go of the state lock while it waits.
+fn pending_rooms() -> &'static Mutex<HashMap<i32, oneshot::Sender<bool>>> {
+    static PENDING_ROOMS: OnceLock<Mutex<HashMap<i32, oneshot::Sender<bool>>>> = OnceLock::new();
+    PENDING_ROOMS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Registered before the create-room request goes out,
+/// so a fast answer can't arrive before anyone listens.
+pub fn expect_confirmation(room_id: i32) -> oneshot::Receiver<bool> {
+    let (tx, rx) = oneshot::channel();
+    pending_rooms().lock().unwrap().insert(room_id, tx);
+    rx
+}
+
+/// True while a new room still waits on the voice server.
+pub fn is_awaiting(room_id: i32) -> bool {
+    pending_rooms().lock().unwrap().contains_key(&room_id)
+}
+
+/// False when nothing was waiting on the room,
+/// like an answer that came after the timeout.
+pub fn resolve_confirmation(room_id: i32, created: bool) -> bool {
+    match pending_rooms().lock().unwrap().remove(&room_id) {
+        Some(tx) => tx.send(created).is_ok(),
+        None => false,
+    }
+}
+
+/// True only when the voice server confirmed in time.
+pub async fn wait_for_confirmation(
+    room_id: i32,
+    confirmation: oneshot::Receiver<bool>,
+    wait: Duration,
+) -> bool {
+    let confirmed = matches!(timeout(wait, confirmation).await, Ok(Ok(true)));
+    pending_rooms().lock().unwrap().remove(&room_id);
+    confirmed
+}
@@ -1,26 +1,47 @@
+use super::chat_modes::ChatMode;
+use super::confirmations;
 use super::permission_configs;
-use crate::common::response_logic::send_to_requester_channel;
+use super::roles::{self, RequestError, Role};
+use super::tags;
+use crate::common::response_logic::{send_error_to_requester, send_to_requester_channel};
 use crate::communication::data_capturer::CaptureResult;
+use crate::communication::op_codes::VoiceServerOp;
 use crate::communication::types::{
-    BasicResponse, GenericRoomIdAndPeerId, RoomPermissions, RoomUpdate, SpecialLeaveRoomOnDestroy,
-    VoiceServerClosePeer, VoiceServerCreateRoom, VoiceServerDestroyRoom, VoiceServerRequest,
+    AllHandsLowered, BasicResponse, DenySpeakerRequest, FollowedUserInRoom, ForcedMuteUpdate,
+    GenericRoomIdAndPeerId, RecordingStatus, RoomCreated, RoomDestroyed, RoomEventHistory,
+    RoomIdleWarning, RoomMetaUpdate, RoomPermissions, RoomUpdate, SingleUserPermissionResults,
+    SpeakerRequestApproved, SpeakerRequestDenied, UserRemovedFromRoom, VoiceServerClosePeer,
+    VoiceServerCreateRoom, VoiceServerDestroyRoom, VoiceServerRecording, VoiceServerRequest,
 };
 use crate::communication::{self, data_capturer, data_fetcher};
-use crate::data_store::db_models::{DBRoom, DBRoomBlock, DBRoomPermissions};
+use crate::data_store::db_models::{
+    DBModerationAction, DBRoom, DBRoomBlock, DBRoomPermissions, DBRoomRecording,
+    DBRoomSessionEvent, DBUserRoomVisit, ModerationAction, RoomSessionEventType,
+};
+use crate::data_store::pool::ExecutionHandlerPool;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
+use crate::data_store::store::DataStore;
 use crate::logging;
-use crate::rabbitmq::rabbit;
+use crate::rabbitmq::codec;
+use crate::rabbitmq::rabbit::PublishChannel;
+use crate::rabbitmq::signaling::VoiceSignaling;
+use crate::state::contributions::{self, Contribution};
 use crate::state::owner_queue::OwnerQueue;
 use crate::state::state::ServerState;
-use crate::state::types::Room;
+use crate::state::types::{Room, RoomEvent, RoomEventRecord, RoomRecording};
+use crate::state::voice_servers::NoVoiceServer;
+use crate::state::{room_limits, speaker_requests};
 use crate::ws_fan::{self, fan};
 use chrono::Utc;
 use futures::lock::Mutex;
-use lapin::Channel;
 use serde::Serialize;
-use std::collections::{HashMap, HashSet, LinkedList};
+use std::collections::{HashMap, HashSet, LinkedList, VecDeque};
+use std::env;
 use std::mem::drop;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use warp::ws::Message;
 pub type EncounteredError = bool;
 pub type AllPermissionsResult = (EncounteredError, HashMap<i32, RoomPermissions>);
 pub type ListenerOrSpeaker = String;
@@ -43,9 +64,10 @@ pub async fn block_user_from_room(
     user_id: i32,
     room_id: i32,
     requester_id: i32,
+    reason: Option<String>,
     server_state: &mut ServerState,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
 ) {
     let mut handler = execution_handler.lock().await;
     let owner_gather: (bool, i32, String) =
@@ -67,18 +89,23 @@ pub async fn block_user_from_room(
         };
         let capture_result = data_capturer::capture_new_room_block(&mut handler, &new_block).await;
         drop(handler);
+        let block = UserBlockFromRoom {
+            requester_id,
+            user_id,
+            room_id,
+            reason,
+        };
         handle_user_block_capture_result(
             capture_result,
-            requester_id,
-            user_id.clone(),
+            block,
             server_state,
-            room_id,
+            execution_handler,
             publish_channel,
         )
         .await;
         return;
     }
-    send_to_requester_channel(
+    send_error_to_requester(
         user_id.to_string(),
         requester_id,
         server_state,
@@ -86,42 +113,326 @@ pub async fn block_user_from_room(
     );
 }
 
-pub async fn create_room(
+/// Removes a user from the current session only, unlike
+/// a block they are free to join the room again later.
+pub async fn kick_user_from_room(
+    user_id: i32,
+    room_id: i32,
+    requester_id: i32,
+    reason: Option<String>,
+    server_state: &mut ServerState,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
+) {
+    let mut handler = execution_handler.lock().await;
+    let owner_gather: (bool, i32, String) =
+        data_fetcher::get_room_owner_and_settings(&mut handler, &room_id).await;
+    let mut all_room_permissions =
+        data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
+    drop(handler);
+    add_guest_listeners(server_state, &room_id, &mut all_room_permissions.1);
+
+    // kicking follows the same rules as blocking
+    if !owner_gather.0
+        && !all_room_permissions.0
+        && can_block_this_user_from_room(
+            all_room_permissions.1,
+            owner_gather.1,
+            requester_id,
+            user_id,
+        )
+    {
+        notify_user_removed_from_room(server_state, user_id, room_id, requester_id, "kick").await;
+        if !server_state.is_guest(user_id) {
+            log_room_session_event(
+                execution_handler,
+                user_id,
+                room_id,
+                RoomSessionEventType::Kick,
+            );
+            log_moderation_action(
+                execution_handler,
+                requester_id,
+                user_id,
+                room_id,
+                ModerationAction::Kick,
+                reason,
+            );
+        }
+        let request = VoiceServerClosePeer {
+            room_id: room_id.to_string(),
+            peer_id: user_id.to_string(),
+            kicked: true,
+        };
+        remove_user_from_room_basic(request, server_state, publish_channel).await;
+        logging::console::log_event(&format!(
+            "user({}) kicked user({}) from room({})",
+            requester_id, user_id, room_id
+        ));
+        return;
+    }
+    send_error_to_requester(
+        user_id.to_string(),
+        requester_id,
+        server_state,
+        "issue_kicking_user".to_string(),
+    );
+}
+
+/// Lets the removed user know why they were removed
+/// and the rest of the room who was removed.
+/// type_of_ban is either "kick" or "ban".
+async fn notify_user_removed_from_room(
     server_state: &mut ServerState,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    user_id: i32,
+    room_id: i32,
     requester_id: i32,
-    name: String,
-    desc: String,
-    public: bool,
+    type_of_ban: &str,
 ) {
+    let removal = serde_json::to_string(&UserRemovedFromRoom {
+        user_id,
+        type_of_ban: type_of_ban.to_owned(),
+        requester: requester_id,
+        room_id,
+    })
+    .unwrap();
+    record_event_in_room(server_state, &room_id, RoomEvent::UserRemoved { user_id });
+    let room_response = BasicResponse {
+        response_op_code: "user_removed_from_room".to_owned(),
+        response_containing_data: removal.clone(),
+    };
+    fan::broadcast_message_to_room_excluding_user(
+        serde_json::to_string(&room_response).unwrap(),
+        server_state,
+        room_id,
+        user_id,
+    )
+    .await;
+    let user_response = BasicResponse {
+        response_op_code: "you_were_removed_from_room".to_owned(),
+        response_containing_data: removal,
+    };
+    fan::broadcast_message_to_single_user(
+        serde_json::to_string(&user_response).unwrap(),
+        server_state,
+        &user_id,
+    )
+    .await;
+}
+
+pub enum RoomCreation {
+    Failed,
+    Created(i32),
+    /// the caller should let go of the state lock, wait on the
+    /// voice server and then finish or roll back the room
+    AwaitingVoiceServer(i32, oneshot::Receiver<bool>),
+}
+
+/// A room request that has already been validated and filtered.
+pub struct RoomCreationRequest {
+    pub requester_id: i32,
+    pub name: String,
+    pub desc: String,
+    pub public: bool,
+    pub chat_mode: ChatMode,
+    pub tags: Vec<String>,
+}
+
+pub async fn create_room(
+    server_state: &mut ServerState,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    request: RoomCreationRequest,
+) -> RoomCreation {
+    let RoomCreationRequest {
+        requester_id,
+        name,
+        desc,
+        public,
+        chat_mode,
+        tags,
+    } = request;
+    if let Some(problem) = room_limits::room_creation_problem(server_state, requester_id) {
+        send_error_to_requester(
+            problem,
+            requester_id,
+            server_state,
+            "room_creation_limit".to_owned(),
+        );
+        return RoomCreation::Failed;
+    }
+    if room_limits::unique_room_names()
+        && room_limits::owns_room_named(server_state, requester_id, &name)
+    {
+        send_error_to_requester(
+            name,
+            requester_id,
+            server_state,
+            "duplicate_room_name".to_owned(),
+        );
+        return RoomCreation::Failed;
+    }
+    let voice_server_id = match server_state.voice_servers.assign() {
+        Ok(voice_server_id) => voice_server_id,
+        Err(NoVoiceServer::AtCapacity) => {
+            send_error_to_requester(
+                "every voice server is full".to_string(),
+                requester_id,
+                server_state,
+                "no_voice_capacity".to_string(),
+            );
+            logging::console::log_failure("every voice server is at its room cap");
+            return RoomCreation::Failed;
+        }
+        Err(NoVoiceServer::Unreachable) => {
+            send_error_to_requester(
+                "no voice server available".to_string(),
+                requester_id,
+                server_state,
+                "issue_creating_room".to_string(),
+            );
+            logging::console::log_failure("no reachable voice server for a new room");
+            return RoomCreation::Failed;
+        }
+    };
     let mut handler = execution_handler.lock().await;
     let db_room = DBRoom {
         id: -1,
         owner_id: requester_id,
-        chat_mode: "fast".to_owned(),
+        chat_mode: chat_mode.as_str().to_owned(),
     };
     let room_id = data_capturer::capture_new_room(&mut handler, &db_room).await;
     if room_id == -1 {
-        send_to_requester_channel(
+        server_state.voice_servers.release(&voice_server_id);
+        send_error_to_requester(
             "internal error".to_string(),
             requester_id,
             server_state,
             "issue_creating_room".to_string(),
         );
         logging::console::log_failure(&format!("user({}) create room failure", requester_id));
+        RoomCreation::Failed
     } else {
-        let channel = publish_channel.lock().await;
+        let new_room_state = construct_basic_room_for_state(room_id, public, name, desc);
+        if !server_state.insert_new_room(new_room_state) {
+            server_state.voice_servers.release(&voice_server_id);
+            send_error_to_requester(
+                "internal error".to_string(),
+                requester_id,
+                server_state,
+                "issue_creating_room".to_string(),
+            );
+            logging::console::log_failure(&format!(
+                "room({}) is already active, user({}) create room failure",
+                room_id, requester_id
+            ));
+            return RoomCreation::Failed;
+        }
+        room_limits::record_created_room(server_state, requester_id);
+        //the room is still usable without its tags
+        let tags = if tags.is_empty()
+            || !data_capturer::capture_room_tags(&mut handler, &room_id, &tags).await
+        {
+            tags
+        } else {
+            logging::console::log_failure(&format!("issue storing tags for room({})", room_id));
+            Vec::new()
+        };
+        let confirmation =
+            confirmations::confirm_timeout().map(|_| confirmations::expect_confirmation(room_id));
         continue_with_successful_room_creation(
             room_id,
-            &channel,
-            public,
+            publish_channel,
             server_state,
-            name,
-            desc,
             requester_id,
         )
         .await;
+        if let Some(room) = server_state.rooms.get_mut(&room_id) {
+            room.voice_server_id = voice_server_id;
+            room.owner_id = requester_id;
+            room.chat_mode = chat_mode;
+            room.tags = tags;
+        }
+        match confirmation {
+            Some(confirmation) => RoomCreation::AwaitingVoiceServer(room_id, confirmation),
+            None => {
+                finish_room_creation(server_state, &room_id, requester_id, &mut handler).await;
+                RoomCreation::Created(room_id)
+            }
+        }
+    }
+}
+
+/// The room is up on both sides, the creator can move in.
+pub async fn finish_room_creation(
+    server_state: &mut ServerState,
+    room_id: &i32,
+    requester_id: i32,
+    execution_handler: &mut ExecutionHandler,
+) {
+    award_contribution(
+        server_state,
+        &requester_id,
+        Contribution::CreatedRoom,
+        execution_handler,
+    )
+    .await;
+    send_room_created(server_state, room_id, requester_id, execution_handler).await;
+}
+
+/// The voice server never confirmed the room, so it is removed
+/// everywhere(including a destroy for the voice server in case
+/// it was only slow) and the creator is told.
+pub async fn roll_back_room_creation(
+    server_state: &mut ServerState,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    room_id: &i32,
+    requester_id: i32,
+) {
+    destroy_room(server_state, publish_channel, execution_handler, room_id).await;
+    send_error_to_requester(
+        room_id.to_string(),
+        requester_id,
+        server_state,
+        "voice_server_unavailable".to_owned(),
+    );
+    logging::console::log_failure(&format!(
+        "voice server never confirmed room({}), rolled it back",
+        room_id
+    ));
+}
+
+/// Gives the creator everything they need to move into
+/// their room, they are the only preview since nobody
+/// has joined yet.
+pub async fn send_room_created(
+    server_state: &mut ServerState,
+    room_id: &i32,
+    requester_id: i32,
+    execution_handler: &mut ExecutionHandler,
+) {
+    let previews = data_fetcher::get_user_previews_for_users(vec![requester_id], execution_handler)
+        .await
+        .1;
+    let owner_and_settings =
+        data_fetcher::get_room_owner_and_settings(execution_handler, room_id).await;
+    let mut holder = Vec::new();
+    if let Some(room) = server_state.rooms.get(room_id) {
+        communication::helpers::construct_communication_room(
+            previews,
+            room,
+            &mut holder,
+            owner_and_settings.1,
+        );
+    }
+    if let Some(room) = holder.pop() {
+        send_to_requester_channel(
+            serde_json::to_string(&RoomCreated { room }).unwrap(),
+            requester_id,
+            server_state,
+            "room_created".to_owned(),
+        );
     }
 }
 
@@ -131,8 +442,8 @@ pub async fn create_room(
 /// 3.VoiceServer(via rabbitmq published message)
 pub async fn destroy_room(
     server_state: &mut ServerState,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     room_id: &i32,
 ) {
     // remove from db
@@ -141,48 +452,180 @@ pub async fn destroy_room(
     drop(handler);
 
     // remove from state
-    server_state.rooms.remove(room_id);
+    if let Some(room) = server_state.rooms.remove(room_id) {
+        server_state.voice_servers.release(&room.voice_server_id);
+    }
     server_state.owner_queues.remove(room_id);
     // remove from voice server
     let request_to_voice_server = VoiceServerDestroyRoom {
-        roomId: room_id.to_string(),
+        room_id: room_id.to_string(),
     };
-    let request_str =
-        create_voice_server_request("destroy-room", &"-1".to_owned(), request_to_voice_server);
-    let channel = publish_channel.lock().await;
-    rabbit::publish_voice_message(&channel, request_str)
+    let request_str = create_voice_server_request(
+        VoiceServerOp::DestroyRoom,
+        &"-1".to_owned(),
+        request_to_voice_server,
+    );
+    publish_channel
+        .publish(request_str)
         .await
         .unwrap_or_default();
     logging::console::log_event(&format!("Destroyed room:{}", room_id));
 }
 
+/// Owner only, ends a room while people are still in it.
+pub async fn destroy_room_for_owner(
+    server_state: &mut ServerState,
+    room_id: i32,
+    requester_id: i32,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let mut handler = execution_handler.lock().await;
+    let allowed = roles::require_role(&mut handler, &room_id, &requester_id, Role::Owner).await;
+    drop(handler);
+    if allowed.is_err() {
+        send_error_to_requester(
+            "only the owner can destroy a room".to_owned(),
+            requester_id,
+            server_state,
+            "invalid_request".to_owned(),
+        );
+        return;
+    }
+    if !server_state.rooms.contains_key(&room_id) {
+        return;
+    }
+    end_room(
+        server_state,
+        room_id,
+        requester_id,
+        voice_publish_channel,
+        integration_publish_channel,
+        execution_handler,
+    )
+    .await;
+    logging::console::log_event(&format!(
+        "user({}) destroyed room({})",
+        requester_id, room_id
+    ));
+}
+
+/// Everyone is pulled out of the room and off the voice server
+/// before the room itself is destroyed, then all active users
+/// hear about it so room lists stay current. destroyed_by is -1
+/// when the server ended the room.
+async fn end_room(
+    server_state: &mut ServerState,
+    room_id: i32,
+    destroyed_by: i32,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let user_ids: Vec<i32> = match server_state.rooms.get(&room_id) {
+        Some(room) => room.user_ids.iter().cloned().collect(),
+        None => return,
+    };
+    for user_id in user_ids {
+        remove_all_owned_iot_servers(server_state, integration_publish_channel, &user_id, room_id)
+            .await;
+        let request = VoiceServerClosePeer {
+            room_id: room_id.to_string(),
+            peer_id: user_id.to_string(),
+            kicked: false,
+        };
+        remove_user_from_room_basic(request, server_state, voice_publish_channel).await;
+    }
+    // the speaker, mod, mute and deaf sets all
+    // live on the room so they go with it
+    destroy_room(
+        server_state,
+        voice_publish_channel,
+        execution_handler,
+        &room_id,
+    )
+    .await;
+    let response = BasicResponse {
+        response_op_code: "room_destroyed".to_owned(),
+        response_containing_data: serde_json::to_string(&RoomDestroyed {
+            room_id,
+            destroyed_by,
+        })
+        .unwrap(),
+    };
+    fan::broadcast_message_to_all_active_users(
+        serde_json::to_string(&response).unwrap(),
+        server_state,
+    )
+    .await;
+}
+
+/// Tells the room it's about to be destroyed
+/// unless someone joins or speaks.
+pub async fn warn_idle_room(server_state: &mut ServerState, room_id: i32, closes_in: Duration) {
+    let response = BasicResponse {
+        response_op_code: "room_idle_warning".to_owned(),
+        response_containing_data: serde_json::to_string(&RoomIdleWarning {
+            room_id,
+            closes_in_seconds: closes_in.as_secs(),
+        })
+        .unwrap(),
+    };
+    fan::broadcast_message_to_room(
+        serde_json::to_string(&response).unwrap(),
+        server_state,
+        room_id,
+    )
+    .await;
+}
+
+/// Ends a room nobody joined or spoke in since it was warned,
+/// the same way an owner would.
+pub async fn destroy_idle_room(
+    server_state: &mut ServerState,
+    room_id: i32,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    end_room(
+        server_state,
+        room_id,
+        -1,
+        voice_publish_channel,
+        integration_publish_channel,
+        execution_handler,
+    )
+    .await;
+    logging::console::log_event(&format!("Destroyed idle room:{}", room_id));
+}
+
 pub async fn remove_user_from_room_basic(
     request_to_voice_server: VoiceServerClosePeer,
     server_state: &mut ServerState,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &dyn VoiceSignaling,
 ) {
     if let Some(room) = server_state
         .rooms
-        .get_mut(&request_to_voice_server.roomId.parse().unwrap())
+        .get_mut(&request_to_voice_server.room_id.parse().unwrap())
     {
-        room.user_ids
-            .remove(&request_to_voice_server.peerId.parse().unwrap());
-        room.amount_of_users -= 1;
+        room.remove_user(&request_to_voice_server.peer_id.parse().unwrap());
     }
 
     if let Some(user) = server_state
         .active_users
-        .get_mut(&request_to_voice_server.peerId.parse().unwrap())
+        .get_mut(&request_to_voice_server.peer_id.parse().unwrap())
     {
         user.current_room_id = -1;
     }
-    let request_str: String = create_voice_server_request(
-        "close-peer",
-        &request_to_voice_server.peerId.clone(),
+    let request_str = create_voice_server_request(
+        VoiceServerOp::ClosePeer,
+        &request_to_voice_server.peer_id.clone(),
         request_to_voice_server,
     );
-    let channel = publish_channel.lock().await;
-    rabbit::publish_voice_message(&channel, request_str)
+    publish_channel
+        .publish(request_str)
         .await
         .unwrap_or_default();
 }
@@ -194,14 +637,32 @@ pub async fn remove_user_from_room_basic(
 pub async fn join_room(
     request_to_voice_server: GenericRoomIdAndPeerId,
     server_state: &mut ServerState,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     requester_id: i32,
     type_of_join: &str,
 ) {
+    let room_id: i32 = request_to_voice_server.room_id;
+    let user_id: i32 = request_to_voice_server.peer_id;
+    //the user could have disconnected before we got here
+    if !server_state.active_users.contains_key(&user_id) {
+        logging::console::log_failure(&format!(
+            "user({}) isn't active and can't join room({})",
+            user_id, room_id
+        ));
+        send_error_to_requester(
+            user_id.to_string(),
+            requester_id,
+            server_state,
+            "issue_joining_room".to_string(),
+        );
+        return;
+    }
+    if server_state.is_guest(user_id) {
+        join_room_as_guest(request_to_voice_server, server_state, publish_channel).await;
+        return;
+    }
     let mut handler = execution_handler.lock().await;
-    let room_id: i32 = request_to_voice_server.roomId;
-    let user_id: i32 = request_to_voice_server.peerId;
     let all_room_permissions: (bool, HashMap<i32, RoomPermissions>) =
         data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
     let state_room_option = server_state.rooms.get(&room_id);
@@ -210,29 +671,104 @@ pub async fn join_room(
         return;
     };
     let state_room = state_room_option.unwrap();
+    if room_is_full(state_room, &user_id) {
+        drop(handler);
+        logging::console::log_failure(&format!(
+            "user({}) can't join full room({})",
+            requester_id, room_id
+        ));
+        send_error_to_requester(
+            room_id.to_string(),
+            requester_id,
+            server_state,
+            "room_full".to_string(),
+        );
+        return;
+    }
+    let joining_as_mod = match all_room_permissions.1.get(&user_id) {
+        Some(permissions) => permissions.is_mod,
+        None => false,
+    };
+    let type_of_join = resolve_type_of_join(
+        state_room,
+        all_room_permissions.1.get(&user_id),
+        type_of_join,
+        count_speakers_in_room(state_room, &all_room_permissions.1),
+    );
 
     // ensure the user has the permissions to join
     let result: EncounteredError = check_or_insert_initial_permissions(
         state_room,
-        type_of_join,
+        type_of_join.as_str(),
         &user_id,
         all_room_permissions,
         &mut handler,
     )
     .await;
+    let new_permissions = if result == false {
+        data_fetcher::get_single_user_permissions(&room_id, &user_id, &mut handler).await
+    } else {
+        None
+    };
+    let followers = if !result && state_room.public {
+        gather_followers_to_notify(&user_id, &mut handler).await
+    } else {
+        HashSet::new()
+    };
     drop(handler);
     // if the user has this permission
     if result == false {
-        let channel = publish_channel.lock().await;
         let request_str = create_voice_server_request(
             type_of_join,
-            &request_to_voice_server.peerId.to_string(),
+            &request_to_voice_server.peer_id.to_string(),
             request_to_voice_server,
         );
         add_user_to_room_state(&room_id, user_id, server_state);
-        rabbit::publish_voice_message(&channel, request_str)
+        // told before the voice server lets them in
+        send_recording_status_if_recording(server_state, &room_id, &user_id);
+        if joining_as_mod {
+            if let Some(room) = server_state.rooms.get_mut(&room_id) {
+                room.mod_ids.insert(user_id);
+            }
+        }
+        publish_channel
+            .publish(request_str)
             .await
             .unwrap_or_default();
+        // a force mute outlives leaving and rejoining
+        if server_state
+            .rooms
+            .get(&room_id)
+            .map_or(false, |room| room.muted.contains(&user_id))
+        {
+            if let Some(user) = server_state.active_users.get_mut(&user_id) {
+                user.muted = true;
+            }
+            let mute_str = create_voice_server_request(
+                VoiceServerOp::MutePeer,
+                &user_id.to_string(),
+                GenericRoomIdAndPeerId {
+                    room_id,
+                    peer_id: user_id,
+                },
+            );
+            publish_channel.publish(mute_str).await.unwrap_or_default();
+        }
+        // everyone in the room should know if
+        // this user came in speaking or listening
+        if let Some(permissions) = new_permissions {
+            broadcast_user_permissions(server_state, &room_id, user_id, permissions).await;
+        }
+        send_room_event_history(server_state, &room_id, user_id);
+        record_event_in_room(server_state, &room_id, RoomEvent::UserJoined { user_id });
+        log_room_session_event(
+            execution_handler,
+            user_id,
+            room_id,
+            RoomSessionEventType::Join,
+        );
+        log_room_visit(execution_handler, user_id, room_id);
+        notify_followers_of_join(server_state, &room_id, user_id, &followers);
 
         //make sure this user is now reflected in our queue
         //for next-in-line ownership
@@ -245,7 +781,7 @@ pub async fn join_room(
         "user({}) issue joining room({})",
         requester_id, room_id
     ));
-    send_to_requester_channel(
+    send_error_to_requester(
         user_id.to_string(),
         requester_id,
         server_state,
@@ -253,6 +789,81 @@ pub async fn join_room(
     );
 }
 
+/// Guests only ever listen in public rooms. They have no
+/// permissions row and nothing about their visit is logged,
+/// they also never join the owner queue.
+pub async fn join_room_as_guest(
+    request_to_voice_server: GenericRoomIdAndPeerId,
+    server_state: &mut ServerState,
+    publish_channel: &dyn VoiceSignaling,
+) {
+    let room_id: i32 = request_to_voice_server.room_id;
+    let guest_id: i32 = request_to_voice_server.peer_id;
+    let problem = match server_state.rooms.get(&room_id) {
+        Some(room) if !room.public => Some("issue_joining_room"),
+        Some(room) if room_is_full(room, &guest_id) => Some("room_full"),
+        Some(_) => None,
+        None => Some("issue_joining_room"),
+    };
+    if let Some(problem) = problem {
+        logging::console::log_failure(&format!(
+            "guest({}) couldn't join room({}): {}",
+            guest_id, room_id, problem
+        ));
+        send_error_to_requester(
+            room_id.to_string(),
+            guest_id,
+            server_state,
+            problem.to_owned(),
+        );
+        return;
+    }
+    let request_str = create_voice_server_request(
+        VoiceServerOp::JoinAsNewPeer,
+        &guest_id.to_string(),
+        request_to_voice_server,
+    );
+    add_user_to_room_state(&room_id, guest_id, server_state);
+    send_recording_status_if_recording(server_state, &room_id, &guest_id);
+    publish_channel
+        .publish(request_str)
+        .await
+        .unwrap_or_default();
+    broadcast_user_permissions(server_state, &room_id, guest_id, guest_permissions()).await;
+    send_room_event_history(server_state, &room_id, guest_id);
+    record_event_in_room(
+        server_state,
+        &room_id,
+        RoomEvent::UserJoined { user_id: guest_id },
+    );
+    logging::console::log_success(&format!("guest({}) joined room({})", guest_id, room_id));
+}
+
+/// Guests have no permissions rows, this fills in the
+/// listener they always are so they show up in the room's
+/// permissions and mods can kick them like anyone else.
+pub fn add_guest_listeners(
+    server_state: &ServerState,
+    room_id: &i32,
+    permissions: &mut HashMap<i32, RoomPermissions>,
+) {
+    if let Some(room) = server_state.rooms.get(room_id) {
+        for user_id in room.user_ids.iter() {
+            if server_state.is_guest(*user_id) {
+                permissions.insert(*user_id, guest_permissions());
+            }
+        }
+    }
+}
+
+fn guest_permissions() -> RoomPermissions {
+    RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: false,
+        is_mod: false,
+    }
+}
+
 /// Removes users from a room, this method
 /// is used for both direct user triggered
 /// requests and unexpected disconnections
@@ -260,9 +871,9 @@ pub async fn leave_room(
     server_state: &mut ServerState,
     requester_id: &i32,
     room_id: &i32,
-    voice_server_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    voice_server_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     remove_all_owned_iot_servers(
         server_state,
@@ -273,38 +884,26 @@ pub async fn leave_room(
     .await;
     if let Some(user) = server_state.active_users.get_mut(&requester_id) {
         user.current_room_id = -1;
-        let mut room = server_state.rooms.get_mut(room_id).unwrap();
-        room.amount_of_users -= 1;
-        room.user_ids.remove(&requester_id);
-        //Always cleanup empty rooms
-        //this is handled during unexpected
-        //disconnects too.
-        if room.amount_of_users == 0 {
-            let response = BasicResponse {
-                response_op_code: "you_left_room".to_owned(),
-                response_containing_data: serde_json::to_string(&SpecialLeaveRoomOnDestroy {
-                    roomId: room_id.to_string(),
-                })
-                .unwrap(),
-            };
-            ws_fan::fan::broadcast_message_to_single_user(
-                serde_json::to_string(&response).unwrap(),
-                server_state,
-                requester_id,
-            )
-            .await;
-            logging::console::log_success(&format!(
-                "user({}) left room({}) which resulted in destruction",
-                requester_id, room_id
-            ));
-            destroy_room(
-                server_state,
-                voice_server_publish_channel,
+        if !user.guest {
+            log_room_session_event(
                 execution_handler,
-                room_id,
-            )
-            .await;
-        } else {
+                *requester_id,
+                *room_id,
+                RoomSessionEventType::Leave,
+            );
+        }
+        let room = server_state.rooms.get_mut(room_id).unwrap();
+        room.remove_user(requester_id);
+        record_room_event(
+            room,
+            RoomEvent::UserLeft {
+                user_id: *requester_id,
+            },
+        );
+        // An emptied room is kept for the grace period so
+        // its users can come back, the room cleanup task
+        // destroys it if nobody does.
+        if room.amount_of_users > 0 {
             select_new_owner_if_current_user_is_owner(
                 requester_id,
                 server_state,
@@ -312,9 +911,9 @@ pub async fn leave_room(
                 room_id.clone(),
             )
             .await;
-            send_close_peer_request_not_kicked(room_id, requester_id, voice_server_publish_channel)
-                .await;
         }
+        send_close_peer_request_not_kicked(room_id, requester_id, voice_server_publish_channel)
+            .await;
     }
 }
 
@@ -326,30 +925,33 @@ pub async fn leave_room(
 ///  - Mods accept other user's request to come to the stage.
 pub async fn add_speaker(
     request_to_voice_server: GenericRoomIdAndPeerId,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &dyn VoiceSignaling,
     requester_id: &i32,
     server_state: &mut ServerState,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     let mut handler = execution_handler.lock().await;
-    let room_id: i32 = request_to_voice_server.roomId;
-    let user_id: i32 = request_to_voice_server.peerId;
+    let room_id: i32 = request_to_voice_server.room_id;
+    let user_id: i32 = request_to_voice_server.peer_id;
     let all_room_permissions: AllPermissionsResult =
         data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
 
     if all_room_permissions.0 == false {
-        let requester_permissions: &RoomPermissions =
-            all_room_permissions.1.get(requester_id).unwrap();
         let requestee_permissions: &RoomPermissions = all_room_permissions.1.get(&user_id).unwrap();
+        if let Some(problem) = speaker_change_problem(true, requestee_permissions) {
+            drop(handler);
+            refuse_speaker_change(server_state, requester_id, user_id, problem);
+            return;
+        }
         let owner_and_settings =
             data_fetcher::get_room_owner_and_settings(&mut handler, &room_id).await;
         // Can the requester even add you as a speaker?
         // Did you even ask to speak?
         if requester_can_add_speaker(
-            requester_permissions,
-            requestee_permissions,
-            &owner_and_settings.1,
+            owner_and_settings.1,
+            &all_room_permissions.1,
             requester_id,
+            requestee_permissions,
         ) {
             let new_permission_config = permission_configs::create_non_preset(
                 room_id,
@@ -363,16 +965,32 @@ pub async fn add_speaker(
                 &mut handler,
             )
             .await;
+            if requester_id != &user_id {
+                award_contribution(
+                    server_state,
+                    &user_id,
+                    Contribution::BroughtUpToSpeak,
+                    &mut handler,
+                )
+                .await;
+            }
             drop(handler);
             let request_str = create_voice_server_request(
-                "add-speaker",
+                VoiceServerOp::AddSpeaker,
                 &user_id.to_string(),
                 request_to_voice_server,
             );
-            let channel = publish_channel.lock().await;
-            rabbit::publish_voice_message(&channel, request_str)
-                .await
-                .unwrap_or_default();
+            publish_channel
+                .publish(request_str)
+                .await
+                .unwrap_or_default();
+            record_event_in_room(server_state, &room_id, RoomEvent::SpeakerAdded { user_id });
+            log_room_session_event(
+                execution_handler,
+                user_id,
+                room_id,
+                RoomSessionEventType::SpeakerPromote,
+            );
             logging::console::log_success(&format!(
                 "user({}) added user({}) as to speakers",
                 requester_id, user_id
@@ -384,7 +1002,7 @@ pub async fn add_speaker(
         "user({}) failure to add speaker({})",
         requester_id, user_id
     ));
-    send_to_requester_channel(
+    send_error_to_requester(
         user_id.to_string(),
         requester_id.clone(),
         server_state,
@@ -394,28 +1012,32 @@ pub async fn add_speaker(
 
 pub async fn remove_speaker(
     request_to_voice_server: GenericRoomIdAndPeerId,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    publish_channel: &dyn VoiceSignaling,
     requester_id: &i32,
     server_state: &mut ServerState,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     println!("{:?}", request_to_voice_server);
     let mut handler = execution_handler.lock().await;
-    let room_id: i32 = request_to_voice_server.roomId;
-    let user_id: i32 = request_to_voice_server.peerId;
+    let room_id: i32 = request_to_voice_server.room_id;
+    let user_id: i32 = request_to_voice_server.peer_id;
     let all_room_permissions: AllPermissionsResult =
         data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
     let room_owner_data: RoomOwnerAndSettings =
         data_fetcher::get_room_owner_and_settings(&mut handler, &room_id).await;
     // make sure we didn't encounter errors getting essential information
     if room_owner_data.0 == false && all_room_permissions.0 == false {
-        let requester_permissions: &RoomPermissions =
-            all_room_permissions.1.get(requester_id).unwrap();
         let requestee_permissions: &RoomPermissions = all_room_permissions.1.get(&user_id).unwrap();
+        if let Some(problem) = speaker_change_problem(false, requestee_permissions) {
+            drop(handler);
+            refuse_speaker_change(server_state, requester_id, user_id, problem);
+            return;
+        }
         if requester_can_remove_speaker(
-            (requester_id, &requester_permissions),
-            (&user_id, &requestee_permissions),
-            &room_owner_data.1,
+            room_owner_data.1,
+            &all_room_permissions.1,
+            requester_id,
+            &user_id,
         ) {
             // modify the database with the new permissions(no longer a speaker)
             let new_permissions = get_new_removed_speaker_permission_config(
@@ -427,12 +1049,12 @@ pub async fn remove_speaker(
                 .await;
             drop(handler);
             let request_str = create_voice_server_request(
-                "remove-speaker",
+                VoiceServerOp::RemoveSpeaker,
                 &user_id.to_string(),
                 request_to_voice_server,
             );
-            let channel = publish_channel.lock().await;
-            rabbit::publish_voice_message(&channel, request_str)
+            publish_channel
+                .publish(request_str)
                 .await
                 .unwrap_or_default();
 
@@ -447,6 +1069,11 @@ pub async fn remove_speaker(
                 room_id,
             )
             .await;
+            record_event_in_room(
+                server_state,
+                &room_id,
+                RoomEvent::SpeakerRemoved { user_id },
+            );
             logging::console::log_success(&format!(
                 "user({}) removed user({}) from speaker",
                 requester_id, user_id
@@ -458,7 +1085,7 @@ pub async fn remove_speaker(
         "user({}) invalid remove speaker request",
         requester_id
     ));
-    send_to_requester_channel(
+    send_error_to_requester(
         user_id.to_string(),
         requester_id.clone(),
         server_state,
@@ -477,26 +1104,535 @@ pub async fn remove_speaker(
 ///  being transfered to the voice server.
 pub async fn handle_web_rtc_specific_requests(
     request_to_voice_server: serde_json::Value,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    op_code: &str,
+    publish_channel: &dyn VoiceSignaling,
+    op: VoiceServerOp,
 ) {
     let user_id = request_to_voice_server["peerId"].to_string();
-    let request_str = create_voice_server_request(op_code, &user_id, request_to_voice_server);
-    let channel = publish_channel.lock().await;
-    rabbit::publish_voice_message(&channel, request_str)
+    let request_str = create_voice_server_request(op, &user_id, request_to_voice_server);
+    publish_channel
+        .publish(request_str)
+        .await
+        .unwrap_or_default();
+}
+
+/// The requester's own permissions in a room they're in. The
+/// owner and the room's mods are mods whatever the row says.
+pub async fn my_room_permissions(
+    server_state: &ServerState,
+    store: &mut dyn DataStore,
+    room_id: &i32,
+    user_id: &i32,
+) -> Result<RoomPermissions, RequestError> {
+    let room = match server_state.rooms.get(room_id) {
+        Some(room) if room.user_ids.contains(user_id) => room,
+        _ => return Err(RequestError::NotInRoom),
+    };
+    let mut permissions = store
+        .single_user_permissions(room_id, user_id)
+        .await
+        .ok_or(RequestError::LookupFailed)?;
+    permissions.is_mod |= room.owner_id == *user_id || room.mod_ids.contains(user_id);
+    Ok(permissions)
+}
+
+/// Force mutes(or unmutes) a user for the whole room, mods can
+/// mute anyone they outrank. The voice server stops(or resumes)
+/// their publishing and the room is told with force_muted(or
+/// force_unmuted), while force muted the user can't unmute
+/// themselves through update_deaf_and_mute.
+pub async fn force_mute_user(
+    server_state: &mut ServerState,
+    store: &mut dyn DataStore,
+    publish_channel: &dyn VoiceSignaling,
+    room_id: &i32,
+    requester_id: &i32,
+    user_id: &i32,
+    mute: bool,
+) -> Result<(), RequestError> {
+    match server_state.rooms.get(room_id) {
+        Some(room) if room.user_ids.contains(requester_id) && room.user_ids.contains(user_id) => {}
+        _ => return Err(RequestError::NotInRoom),
+    }
+    let (owner_error, owner_id, _) = store.room_owner_and_settings(room_id).await;
+    let (permissions_error, permissions) = store.room_permissions_for_users(room_id).await;
+    if owner_error || permissions_error {
+        return Err(RequestError::LookupFailed);
+    }
+    roles::check_permission(owner_id, &permissions, requester_id, Role::Mod)?;
+    if !roles::outranks(owner_id, &permissions, requester_id, user_id) {
+        // only the owner is above a mod
+        return Err(RequestError::InsufficientRole {
+            required: Role::Owner,
+            actual: Role::Mod,
+        });
+    }
+
+    let room = server_state.rooms.get_mut(room_id).unwrap();
+    let (op, op_code) = if mute {
+        room.muted.insert(*user_id);
+        if let Some(user) = server_state.active_users.get_mut(user_id) {
+            user.muted = true;
+        }
+        (VoiceServerOp::MutePeer, "force_muted")
+    } else {
+        //they stay muted until they unmute themselves
+        room.muted.remove(user_id);
+        (VoiceServerOp::UnmutePeer, "force_unmuted")
+    };
+    let request_str = create_voice_server_request(
+        op,
+        &user_id.to_string(),
+        GenericRoomIdAndPeerId {
+            room_id: *room_id,
+            peer_id: *user_id,
+        },
+    );
+    publish_channel
+        .publish(request_str)
+        .await
+        .unwrap_or_default();
+    let update = ForcedMuteUpdate {
+        user_id: *user_id,
+        room_id: *room_id,
+        by: *requester_id,
+    };
+    let basic_response = BasicResponse {
+        response_op_code: op_code.to_owned(),
+        response_containing_data: serde_json::to_string(&update).unwrap(),
+    };
+    fan::broadcast_message_to_room(
+        serde_json::to_string(&basic_response).unwrap(),
+        server_state,
+        *room_id,
+    )
+    .await;
+    logging::console::log_success(&format!(
+        "user({}) {} user({}) in room({})",
+        requester_id,
+        if mute { "force muted" } else { "force unmuted" },
+        user_id,
+        room_id
+    ));
+    Ok(())
+}
+
+/// Owner only, the voice server does the recording while we
+/// keep the room's state and its room_recording row. Members
+/// hear about a recording before it starts and only after it
+/// stopped, so nobody is recorded without being told.
+pub async fn start_or_stop_recording(
+    server_state: &mut ServerState,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
+    room_id: &i32,
+    requester_id: &i32,
+    start: bool,
+) -> Option<&'static str> {
+    let mut handler = execution_handler.lock().await;
+    if roles::require_role(&mut handler, room_id, requester_id, Role::Owner)
+        .await
+        .is_err()
+    {
+        return Some("only the owner can record a room");
+    }
+    let current = match server_state.rooms.get(room_id) {
+        Some(room) => room.recording.clone(),
+        None => return Some("room doesn't exist"),
+    };
+    let recording = match (start, current) {
+        (true, Some(_)) => return Some("room is already being recorded"),
+        (false, None) => return Some("room isn't being recorded"),
+        (true, None) => {
+            let started_at = Utc::now().to_rfc3339();
+            let row = DBRoomRecording {
+                id: -1,
+                room_id: *room_id,
+                started_by: *requester_id,
+                started_at: started_at.clone(),
+            };
+            match data_capturer::capture_room_recording_start(&mut handler, &row).await {
+                Some(recording_id) => RoomRecording {
+                    recording_id,
+                    started_by: *requester_id,
+                    started_at,
+                },
+                None => return Some("issue storing the recording"),
+            }
+        }
+        (false, Some(recording)) => {
+            if data_capturer::capture_room_recording_end(&mut handler, room_id).await {
+                return Some("issue ending the recording");
+            }
+            recording
+        }
+    };
+    drop(handler);
+
+    let request = VoiceServerRecording {
+        room_id: room_id.to_string(),
+        recording_id: recording.recording_id.to_string(),
+    };
+    if start {
+        if let Some(room) = server_state.rooms.get_mut(room_id) {
+            room.recording = Some(recording.clone());
+        }
+        broadcast_recording_status(server_state, room_id).await;
+        let request_str = create_voice_server_request(
+            VoiceServerOp::StartRecording,
+            &requester_id.to_string(),
+            request,
+        );
+        publish_channel
+            .publish(request_str)
+            .await
+            .unwrap_or_default();
+    } else {
+        let request_str = create_voice_server_request(
+            VoiceServerOp::StopRecording,
+            &requester_id.to_string(),
+            request,
+        );
+        publish_channel
+            .publish(request_str)
+            .await
+            .unwrap_or_default();
+        if let Some(room) = server_state.rooms.get_mut(room_id) {
+            room.recording = None;
+        }
+        broadcast_recording_status(server_state, room_id).await;
+    }
+    logging::console::log_event(&format!(
+        "user({}) {} recording({}) of room({})",
+        requester_id,
+        if start { "started" } else { "stopped" },
+        recording.recording_id,
+        room_id
+    ));
+    None
+}
+
+fn recording_status_response(room_id: &i32, recording: Option<&RoomRecording>) -> String {
+    let status = RecordingStatus {
+        room_id: *room_id,
+        recording: recording.is_some(),
+        started_by: recording.map(|recording| recording.started_by),
+        started_at: recording.map(|recording| recording.started_at.clone()),
+    };
+    let response = BasicResponse {
+        response_op_code: "recording_status".to_owned(),
+        response_containing_data: serde_json::to_string(&status).unwrap(),
+    };
+    serde_json::to_string(&response).unwrap()
+}
+
+async fn broadcast_recording_status(server_state: &mut ServerState, room_id: &i32) {
+    let recording = server_state
+        .rooms
+        .get(room_id)
+        .and_then(|room| room.recording.as_ref());
+    let response = recording_status_response(room_id, recording);
+    fan::broadcast_message_to_room(response, server_state, *room_id).await;
+}
+
+/// Anyone who wasn't around for the recording_status broadcast,
+/// users joining and sessions resuming, hears it on their own.
+pub fn send_recording_status_if_recording(
+    server_state: &mut ServerState,
+    room_id: &i32,
+    user_id: &i32,
+) {
+    let response = match server_state
+        .rooms
+        .get(room_id)
+        .and_then(|room| room.recording.as_ref())
+    {
+        Some(recording) => recording_status_response(room_id, Some(recording)),
+        None => return,
+    };
+    if server_state.peer_map.contains_user(user_id) {
+        server_state
+            .peer_map
+            .send_to_user(user_id, Message::text(response))
+            .unwrap_or_else(|e| eprintln!("issue sending msg:{}", e));
+    }
+}
+
+/// Adding a speaker or removing a listener wouldn't change
+/// anything, so it never reaches the voice server.
+pub fn speaker_change_problem(adding: bool, requestee: &RoomPermissions) -> Option<&'static str> {
+    match (adding, requestee.is_speaker) {
+        (true, true) => Some("already_a_speaker"),
+        (false, false) => Some("not_a_speaker"),
+        _ => None,
+    }
+}
+
+fn refuse_speaker_change(
+    server_state: &mut ServerState,
+    requester_id: &i32,
+    user_id: i32,
+    problem: &str,
+) {
+    logging::console::log_failure(&format!(
+        "user({}) speaker change for user({}) refused: {}",
+        requester_id, user_id, problem
+    ));
+    send_error_to_requester(
+        user_id.to_string(),
+        *requester_id,
+        server_state,
+        problem.to_owned(),
+    );
+}
+
+#[derive(Debug, PartialEq)]
+pub enum SpeakerApprovalCheck {
+    Approve,
+    NotModOrOwner,
+    HandNotRaised,
+}
+
+/// Only mods and the owner approve requests, and only
+/// listeners whose hand is up have one to approve.
+pub fn check_speaker_approval(
+    requester_permissions: &RoomPermissions,
+    requestee_permissions: &RoomPermissions,
+    requester_is_owner: bool,
+) -> SpeakerApprovalCheck {
+    let requester_role = if requester_is_owner {
+        Role::Owner
+    } else {
+        Role::from_permissions(requester_permissions)
+    };
+    if requester_role < Role::Mod {
+        SpeakerApprovalCheck::NotModOrOwner
+    } else if requestee_permissions.is_speaker || !requestee_permissions.asked_to_speak {
+        SpeakerApprovalCheck::HandNotRaised
+    } else {
+        SpeakerApprovalCheck::Approve
+    }
+}
+
+/// Brings a raised hand up to speak. The permission write,
+/// the voice server request and the room broadcast all happen
+/// under the caller's write lock, so the room sees the hand go
+/// down and the speaker come up as one speaker_request_approved.
+pub async fn approve_speaker_request(
+    request_to_voice_server: GenericRoomIdAndPeerId,
+    publish_channel: &dyn VoiceSignaling,
+    requester_id: &i32,
+    server_state: &mut ServerState,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let mut handler = execution_handler.lock().await;
+    let room_id: i32 = request_to_voice_server.room_id;
+    let user_id: i32 = request_to_voice_server.peer_id;
+    let all_room_permissions: AllPermissionsResult =
+        data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
+    let owner_and_settings =
+        data_fetcher::get_room_owner_and_settings(&mut handler, &room_id).await;
+    let check = match (
+        all_room_permissions.1.get(requester_id),
+        all_room_permissions.1.get(&user_id),
+    ) {
+        (Some(requester_permissions), Some(requestee_permissions))
+            if !all_room_permissions.0 && !owner_and_settings.0 =>
+        {
+            check_speaker_approval(
+                requester_permissions,
+                requestee_permissions,
+                &owner_and_settings.1 == requester_id,
+            )
+        }
+        _ => SpeakerApprovalCheck::HandNotRaised,
+    };
+    let problem = match check {
+        SpeakerApprovalCheck::Approve => None,
+        SpeakerApprovalCheck::NotModOrOwner => Some("only mods and the owner can approve speakers"),
+        SpeakerApprovalCheck::HandNotRaised => Some("that user hasn't asked to speak"),
+    };
+    if let Some(problem) = problem {
+        drop(handler);
+        logging::console::log_failure(&format!(
+            "user({}) failed to approve user({}) as a speaker",
+            requester_id, user_id
+        ));
+        send_error_to_requester(
+            problem.to_owned(),
+            *requester_id,
+            server_state,
+            "invalid_request".to_owned(),
+        );
+        return;
+    }
+    let requestee_is_mod = all_room_permissions.1[&user_id].is_mod;
+    let new_permission_config =
+        permission_configs::create_non_preset(room_id, user_id, false, true, requestee_is_mod);
+    data_capturer::capture_new_room_permissions_update(&new_permission_config, &mut handler).await;
+    award_contribution(
+        server_state,
+        &user_id,
+        Contribution::BroughtUpToSpeak,
+        &mut handler,
+    )
+    .await;
+    drop(handler);
+    let request_str = create_voice_server_request(
+        VoiceServerOp::AddSpeaker,
+        &user_id.to_string(),
+        request_to_voice_server,
+    );
+    publish_channel
+        .publish(request_str)
         .await
         .unwrap_or_default();
+    let response = BasicResponse {
+        response_op_code: "speaker_request_approved".to_owned(),
+        response_containing_data: serde_json::to_string(&SpeakerRequestApproved {
+            room_id,
+            user_id,
+            approved_by: *requester_id,
+        })
+        .unwrap(),
+    };
+    fan::broadcast_message_to_room(
+        serde_json::to_string(&response).unwrap(),
+        server_state,
+        room_id,
+    )
+    .await;
+    speaker_requests::clear_denials(server_state, room_id, user_id);
+    record_event_in_room(server_state, &room_id, RoomEvent::HandLowered { user_id });
+    record_event_in_room(server_state, &room_id, RoomEvent::SpeakerAdded { user_id });
+    log_room_session_event(
+        execution_handler,
+        user_id,
+        room_id,
+        RoomSessionEventType::SpeakerPromote,
+    );
+    logging::console::log_success(&format!(
+        "user({}) approved user({})'s request to speak",
+        requester_id, user_id
+    ));
+}
+
+/// Turns a raised hand down without promoting anyone. The
+/// room sees the hand lowered, the user is told it was a mod's
+/// call (unless notify is false), and enough denials in a row
+/// keep them from raising it again for a while.
+pub async fn deny_speaker_request(
+    request: DenySpeakerRequest,
+    requester_id: &i32,
+    server_state: &mut ServerState,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let mut handler = execution_handler.lock().await;
+    let room_id: i32 = request.room_id;
+    let user_id: i32 = request.peer_id;
+    let all_room_permissions: AllPermissionsResult =
+        data_fetcher::get_room_permissions_for_users(&room_id, &mut handler).await;
+    let owner_and_settings =
+        data_fetcher::get_room_owner_and_settings(&mut handler, &room_id).await;
+    let check = match (
+        all_room_permissions.1.get(requester_id),
+        all_room_permissions.1.get(&user_id),
+    ) {
+        (Some(requester_permissions), Some(requestee_permissions))
+            if !all_room_permissions.0 && !owner_and_settings.0 =>
+        {
+            check_speaker_approval(
+                requester_permissions,
+                requestee_permissions,
+                &owner_and_settings.1 == requester_id,
+            )
+        }
+        _ => SpeakerApprovalCheck::HandNotRaised,
+    };
+    let problem = match check {
+        SpeakerApprovalCheck::Approve => None,
+        SpeakerApprovalCheck::NotModOrOwner => Some("only mods and the owner can deny speakers"),
+        SpeakerApprovalCheck::HandNotRaised => Some("that user hasn't asked to speak"),
+    };
+    if let Some(problem) = problem {
+        drop(handler);
+        logging::console::log_failure(&format!(
+            "user({}) failed to deny user({})'s request to speak",
+            requester_id, user_id
+        ));
+        send_error_to_requester(
+            problem.to_owned(),
+            *requester_id,
+            server_state,
+            "invalid_request".to_owned(),
+        );
+        return;
+    }
+    let requestee_is_mod = all_room_permissions.1[&user_id].is_mod;
+    let new_permission_config =
+        permission_configs::create_non_preset(room_id, user_id, false, false, requestee_is_mod);
+    data_capturer::capture_new_room_permissions_update(&new_permission_config, &mut handler).await;
+    drop(handler);
+    let denials = speaker_requests::record_denial(server_state, room_id, user_id);
+    let response = BasicResponse {
+        response_op_code: "user_hand_lowered".to_owned(),
+        response_containing_data: user_id.to_string(),
+    };
+    fan::broadcast_message_to_room(
+        serde_json::to_string(&response).unwrap(),
+        server_state,
+        room_id,
+    )
+    .await;
+    if request.notify.unwrap_or(true) {
+        let cooldown = speaker_requests::raise_hand_cooldown(server_state, room_id, user_id);
+        send_to_requester_channel(
+            serde_json::to_string(&SpeakerRequestDenied {
+                room_id,
+                denied_by: *requester_id,
+                cooldown_seconds: cooldown.map(|left| left.as_secs()),
+            })
+            .unwrap(),
+            user_id,
+            server_state,
+            "speaker_request_denied".to_owned(),
+        );
+    }
+    record_event_in_room(server_state, &room_id, RoomEvent::HandLowered { user_id });
+    logging::console::log_success(&format!(
+        "user({}) denied user({})'s request to speak, {} recent denials",
+        requester_id, user_id, denials
+    ));
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RaiseHandCheck {
+    Raise,
+    AlreadyRaised,
+    AlreadySpeaker,
+}
+
+/// Raising a hand that is already up changes
+/// nothing, speakers have nothing to ask for.
+pub fn check_raise_hand(permissions: &RoomPermissions) -> RaiseHandCheck {
+    if permissions.is_speaker {
+        RaiseHandCheck::AlreadySpeaker
+    } else if permissions.asked_to_speak {
+        RaiseHandCheck::AlreadyRaised
+    } else {
+        RaiseHandCheck::Raise
+    }
 }
 
-// A user can only raise a hand if:
-// They aren't a speaker. If they do, it is a
-// clear illegal request, no need to
-// respond.
+// A user can only raise a hand if they aren't a
+// speaker and their hand isn't already raised.
+// Speakers are told with already_speaker, a hand
+// that is already up is left alone without
+// writing or broadcasting again.
 pub async fn raise_hand(
     server_state: &mut ServerState,
     room_id: &i32,
     requester_id: &i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     let mut handler = execution_handler.lock().await;
     let all_room_permissions: (bool, HashMap<i32, RoomPermissions>) =
@@ -505,10 +1641,37 @@ pub async fn raise_hand(
         return;
     }
 
-    let current_user_permissions: &RoomPermissions =
-        all_room_permissions.1.get(requester_id).unwrap();
-    if current_user_permissions.is_speaker {
-        return;
+    let current_user_permissions: &RoomPermissions = match all_room_permissions.1.get(requester_id)
+    {
+        Some(permissions) => permissions,
+        None => return,
+    };
+    match check_raise_hand(current_user_permissions) {
+        RaiseHandCheck::Raise => {
+            let cooldown =
+                speaker_requests::raise_hand_cooldown(server_state, *room_id, *requester_id);
+            if let Some(left) = cooldown {
+                drop(handler);
+                send_error_to_requester(
+                    left.as_secs().to_string(),
+                    *requester_id,
+                    server_state,
+                    "hand_raise_cooldown".to_owned(),
+                );
+                return;
+            }
+        }
+        RaiseHandCheck::AlreadyRaised => return,
+        RaiseHandCheck::AlreadySpeaker => {
+            drop(handler);
+            send_error_to_requester(
+                room_id.to_string(),
+                *requester_id,
+                server_state,
+                "already_speaker".to_owned(),
+            );
+            return;
+        }
     }
     //no one should ever ask to speak if they are mods, because
     //the frontend will make the add speaker request on
@@ -528,6 +1691,13 @@ pub async fn raise_hand(
     };
     let basic_response_str = serde_json::to_string(&basic_response).unwrap();
     fan::broadcast_message_to_room(basic_response_str, server_state, room_id.clone()).await;
+    record_event_in_room(
+        server_state,
+        room_id,
+        RoomEvent::HandRaised {
+            user_id: *requester_id,
+        },
+    );
     logging::console::log_success(&format!(
         "user({}) successfully raised their hand",
         requester_id
@@ -539,7 +1709,7 @@ pub async fn lower_hand(
     room_id: &i32,
     requestee_id: &i32,
     requester_id: &i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     let mut handler = execution_handler.lock().await;
     let all_room_permissions: (bool, HashMap<i32, RoomPermissions>) =
@@ -566,7 +1736,9 @@ pub async fn lower_hand(
     //and skip the ask stage.
     //
     //- Anyone can lower their own hand.
-    if requester_user_permissions.is_mod || requester_id == requestee_id {
+    if Role::from_permissions(requester_user_permissions) >= Role::Mod
+        || requester_id == requestee_id
+    {
         let new_db_permissions = permission_configs::create_non_preset(
             room_id.clone(),
             requestee_id.clone(),
@@ -581,6 +1753,13 @@ pub async fn lower_hand(
         };
         let basic_response_str = serde_json::to_string(&basic_response).unwrap();
         fan::broadcast_message_to_room(basic_response_str, server_state, room_id.clone()).await;
+        record_event_in_room(
+            server_state,
+            room_id,
+            RoomEvent::HandLowered {
+                user_id: *requestee_id,
+            },
+        );
         logging::console::log_success(&format!(
             "User({}) hand successfully lowered by user({})",
             requestee_id, requester_id
@@ -591,7 +1770,7 @@ pub async fn lower_hand(
         "Failed lower hand request from user({})",
         requester_id
     ));
-    send_to_requester_channel(
+    send_error_to_requester(
         "issue with request".to_owned(),
         requester_id.clone(),
         server_state,
@@ -599,34 +1778,121 @@ pub async fn lower_hand(
     );
 }
 
-//changes the room name,desc and other data.
-pub async fn update_room_meta_data(
+/// Changes the room name,desc and other settings while
+/// the room is live, the update should be validated first.
+///
+/// Making a room private doesn't remove anyone already
+/// in it, it only stops new public joins.
+/// Mod/owner permissions are checked by the caller,
+/// everyone in the room hears how many hands went down.
+pub async fn lower_all_hands(
+    server_state: &mut ServerState,
+    room_id: &i32,
+    requester_id: &i32,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let mut handler = execution_handler.lock().await;
+    let lowered = data_capturer::capture_lower_all_hands(&mut handler, room_id).await;
+    drop(handler);
+    let mut user_ids = match lowered {
+        Some(user_ids) => user_ids,
+        None => {
+            logging::console::log_failure(&format!(
+                "Failed to lower all hands in room({})",
+                room_id
+            ));
+            send_error_to_requester(
+                "issue with request".to_owned(),
+                *requester_id,
+                server_state,
+                "invalid_request".to_owned(),
+            );
+            return;
+        }
+    };
+    user_ids.sort_unstable();
+    for user_id in &user_ids {
+        record_event_in_room(
+            server_state,
+            room_id,
+            RoomEvent::HandLowered { user_id: *user_id },
+        );
+    }
+    let response = BasicResponse {
+        response_op_code: "all_hands_lowered".to_owned(),
+        response_containing_data: serde_json::to_string(&AllHandsLowered {
+            room_id: *room_id,
+            amount: user_ids.len(),
+            user_ids,
+            lowered_by: *requester_id,
+        })
+        .unwrap(),
+    };
+    fan::broadcast_message_to_room(
+        serde_json::to_string(&response).unwrap(),
+        server_state,
+        *room_id,
+    )
+    .await;
+    logging::console::log_success(&format!(
+        "User({}) lowered all hands in room({})",
+        requester_id, room_id
+    ));
+}
+
+pub async fn update_room(
     server_state: &mut ServerState,
     room_id: &i32,
     requester_id: i32,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     request_data: RoomUpdate,
 ) {
     let mut handler = execution_handler.lock().await;
-    let all_room_permissions: (bool, HashMap<i32, RoomPermissions>) =
-        data_fetcher::get_room_permissions_for_users(room_id, &mut handler).await;
-    if all_room_permissions.0 {
-        return;
-    }
-
-    let requester_permissions: &RoomPermissions =
-        all_room_permissions.1.get(&requester_id).unwrap();
-    if requester_permissions.is_mod {
-        let mut room = server_state.rooms.get_mut(&room_id).unwrap();
-        room.auto_speaker = request_data.auto_speaker.clone();
-        room.chat_throttle = request_data.chat_throttle.clone();
-        room.public = request_data.public.clone();
-        room.desc = request_data.description.clone();
-        room.name = request_data.name.clone();
+    let allowed = roles::require_role(&mut handler, room_id, &requester_id, Role::Mod).await;
+    //stored first so a failed write leaves the room untouched
+    let stored_tags = match (&allowed, request_data.tags.as_deref()) {
+        (Ok(()), Some(requested)) => {
+            // already validated by room_update_problem
+            let tags = tags::tags_from_request(requested).unwrap_or_default();
+            if data_capturer::capture_room_tags(&mut handler, room_id, &tags).await {
+                Err(())
+            } else {
+                Ok(Some(tags))
+            }
+        }
+        _ => Ok(None),
+    };
+    drop(handler);
+    if let (Ok(()), Ok(stored_tags), Some(room)) =
+        (allowed, stored_tags, server_state.rooms.get_mut(room_id))
+    {
+        room.auto_speaker = request_data.auto_speaker;
+        room.chat_throttle = request_data.chat_throttle;
+        room.public = request_data.public;
+        room.desc = request_data.description;
+        room.name = request_data.name;
+        if let Some(max_occupancy) = request_data.max_occupancy {
+            room.max_occupancy = max_occupancy;
+        }
+        if let Some(chat_enabled) = request_data.chat_enabled {
+            room.chat_mode = room.chat_mode.with_chat_enabled(chat_enabled);
+        }
+        // already validated, the explicit mode wins over chat_enabled
+        if let Some(chat_mode) = request_data.chat_mode.as_deref().and_then(ChatMode::parse) {
+            room.chat_mode = chat_mode;
+        }
+        if let Some(tags) = stored_tags {
+            room.tags = tags;
+        }
         //let the users know about the update
+        let update = RoomMetaUpdate {
+            room_id: room.room_id,
+            details: communication::helpers::construct_room_details(room),
+            auto_speaker_setting: room.auto_speaker,
+        };
         let basic_response = BasicResponse {
             response_op_code: "room_meta_update".to_owned(),
-            response_containing_data: serde_json::to_string(&request_data).unwrap(),
+            response_containing_data: serde_json::to_string(&update).unwrap(),
         };
         let basic_response_str = serde_json::to_string(&basic_response).unwrap();
         ws_fan::fan::broadcast_message_to_room(basic_response_str, server_state, room_id.clone())
@@ -641,7 +1907,7 @@ pub async fn update_room_meta_data(
         "Issue updating room metadata by user({})",
         requester_id
     ));
-    send_to_requester_channel(
+    send_error_to_requester(
         "issue with request".to_owned(),
         requester_id.clone(),
         server_state,
@@ -649,24 +1915,52 @@ pub async fn update_room_meta_data(
     );
 }
 
-async fn handle_user_block_capture_result(
-    capture_result: CaptureResult,
+/// Who blocked whom from which room, and why.
+struct UserBlockFromRoom {
     requester_id: i32,
     user_id: i32,
-    server_state: &mut ServerState,
     room_id: i32,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
+    reason: Option<String>,
+}
+
+async fn handle_user_block_capture_result(
+    capture_result: CaptureResult,
+    block: UserBlockFromRoom,
+    server_state: &mut ServerState,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    publish_channel: &dyn VoiceSignaling,
 ) {
+    let UserBlockFromRoom {
+        requester_id,
+        user_id,
+        room_id,
+        reason,
+    } = block;
     if capture_result.encountered_error == false {
+        notify_user_removed_from_room(server_state, user_id, room_id, requester_id, "ban").await;
+        log_room_session_event(
+            execution_handler,
+            user_id,
+            room_id,
+            RoomSessionEventType::Ban,
+        );
+        log_moderation_action(
+            execution_handler,
+            requester_id,
+            user_id,
+            room_id,
+            ModerationAction::Ban,
+            reason,
+        );
         let request = VoiceServerClosePeer {
-            roomId: room_id.to_string(),
-            peerId: user_id.to_string(),
+            room_id: room_id.to_string(),
+            peer_id: user_id.to_string(),
             kicked: true,
         };
         remove_user_from_room_basic(request, server_state, publish_channel).await;
         return;
     }
-    send_to_requester_channel(
+    send_error_to_requester(
         user_id.to_string(),
         requester_id,
         server_state,
@@ -675,50 +1969,368 @@ async fn handle_user_block_capture_result(
 }
 
 fn add_user_to_room_state(room_id: &i32, user_id: i32, state: &mut ServerState) {
-    let room = state.rooms.get_mut(&room_id).unwrap();
-    room.user_ids.insert(user_id.clone());
-    room.amount_of_users += 1;
-    state
-        .active_users
-        .get_mut(&user_id)
-        .unwrap()
-        .current_room_id = room_id.clone();
+    for stale_room_id in state.add_user_to_room(user_id, *room_id) {
+        logging::console::log_failure(&format!(
+            "user({}) was still in room({}) while joining room({}), removed them",
+            user_id, stale_room_id, room_id
+        ));
+    }
+}
+
+/// Promotes/demotes a user in the room, only the owner
+/// can do this and the owner can't be demoted. Speaker
+/// status is left as is.
+///
+/// Returns the updated permissions of the user.
+pub async fn change_mod_status(
+    server_state: &mut ServerState,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    room_id: &i32,
+    requester_id: i32,
+    user_id: i32,
+    new_status: bool,
+) -> Option<RoomPermissions> {
+    if requester_id == user_id {
+        return None;
+    }
+    let mut handler = execution_handler.lock().await;
+    roles::require_role(&mut handler, room_id, &requester_id, Role::Owner)
+        .await
+        .ok()?;
+    let result =
+        data_capturer::mod_or_unmod_user_capture(new_status, room_id, &user_id, &mut handler).await;
+    if result.encountered_error {
+        return None;
+    }
+    let permissions =
+        data_fetcher::get_single_user_permissions(room_id, &user_id, &mut handler).await?;
+    drop(handler);
+    if let Some(room) = server_state.rooms.get_mut(room_id) {
+        if new_status {
+            room.mod_ids.insert(user_id);
+        } else {
+            room.mod_ids.remove(&user_id);
+        }
+    }
+    broadcast_user_permissions(server_state, room_id, user_id, permissions.clone()).await;
+    let action = if new_status {
+        ModerationAction::Mod
+    } else {
+        ModerationAction::Unmod
+    };
+    log_moderation_action(
+        execution_handler,
+        requester_id,
+        user_id,
+        *room_id,
+        action,
+        None,
+    );
+    logging::console::log_success(&format!(
+        "user({}) changed mod status of user({}) in room({}) to {}",
+        requester_id, user_id, room_id, new_status
+    ));
+    Some(permissions)
+}
+
+/// Public rooms whose name contains the query(ignoring case),
+/// busiest rooms first. Private rooms are never returned.
+pub fn search_public_rooms(server_state: &ServerState, query: &str, limit: usize) -> Vec<i32> {
+    let query = query.trim().to_lowercase();
+    let mut matches: Vec<&Room> = server_state
+        .rooms
+        .values()
+        .filter(|room| room.public && room.name.to_lowercase().contains(&query))
+        .collect();
+    matches.sort_by(|a, b| {
+        b.amount_of_users
+            .cmp(&a.amount_of_users)
+            .then(a.room_id.cmp(&b.room_id))
+    });
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|room| room.room_id)
+        .collect()
+}
+
+/// The active public rooms among the tagged ones,
+/// busiest first like search results.
+pub fn tagged_public_rooms(
+    server_state: &ServerState,
+    tagged_room_ids: &HashSet<i32>,
+    limit: usize,
+) -> Vec<i32> {
+    let mut matches: Vec<&Room> = tagged_room_ids
+        .iter()
+        .filter_map(|room_id| server_state.rooms.get(room_id))
+        .filter(|room| room.public)
+        .collect();
+    matches.sort_by(|a, b| {
+        b.amount_of_users
+            .cmp(&a.amount_of_users)
+            .then(a.room_id.cmp(&b.room_id))
+    });
+    matches
+        .into_iter()
+        .take(limit)
+        .map(|room| room.room_id)
+        .collect()
+}
+
+/// Rooms for the top rooms list, pinned rooms first in the
+/// order they were pinned and the rest sorted by how many
+/// users they have. Private rooms are never listed.
+pub fn gather_top_public_rooms(server_state: &ServerState) -> Vec<&Room> {
+    let (mut pinned, mut rooms): (Vec<&Room>, Vec<&Room>) = server_state
+        .rooms
+        .values()
+        .filter(|room| room.public)
+        .partition(|room| room.pinned_at.is_some());
+    pinned.sort_by_key(|room| (room.pinned_at, room.room_id));
+    rooms.sort_by_key(|room| room.amount_of_users);
+    pinned.append(&mut rooms);
+    pinned
+}
+
+/// False if the room doesn't exist. Pinning a room
+/// that is already pinned keeps its place.
+pub fn set_room_pinned(server_state: &mut ServerState, room_id: &i32, pinned: bool) -> bool {
+    match server_state.rooms.get_mut(room_id) {
+        Some(room) => {
+            if !pinned {
+                room.pinned_at = None;
+            } else if room.pinned_at.is_none() {
+                room.pinned_at = Some(Instant::now());
+            }
+            true
+        }
+        None => false,
+    }
 }
 
-fn construct_basic_room_for_state(room_id: i32, public: bool, name: String, desc: String) -> Room {
+pub fn construct_basic_room_for_state(
+    room_id: i32,
+    public: bool,
+    name: String,
+    desc: String,
+) -> Room {
     return Room {
         room_id: room_id,
+        owner_id: -1,
         muted: HashSet::new(),
         voice_server_id: 0.to_string(),
         /// not yet implemented(feature)
         deaf: HashSet::new(),
         user_ids: HashSet::new(),
+        mod_ids: HashSet::new(),
         public: public,
         auto_speaker: true,
         amount_of_users: 0,
+        max_occupancy: default_max_occupancy(),
         name: name,
         desc: desc,
         chat_throttle: 1000,
+        chat_mode: ChatMode::Everyone,
         created_at: Utc::now().to_string(),
         iot_server_connections: HashMap::new(),
+        event_history: VecDeque::new(),
+        empty_since: Some(Instant::now()),
+        tags: Vec::new(),
+        pinned_at: None,
+        recording: None,
+        last_activity: Instant::now(),
+        idle_warned_at: None,
+    };
+}
+
+/// How many events we keep per room
+/// for users who join late.
+const MAX_ROOM_EVENTS: usize = 50;
+
+/// The oldest events are dropped once
+/// we reach the max amount.
+pub fn record_room_event(room: &mut Room, event: RoomEvent) {
+    room.event_history.push_back(RoomEventRecord {
+        event,
+        happened_at: Utc::now().to_string(),
+    });
+    while room.event_history.len() > MAX_ROOM_EVENTS {
+        room.event_history.pop_front();
+    }
+}
+
+/// Catches a user who just joined up on
+/// what happened before they got there.
+fn send_room_event_history(server_state: &mut ServerState, room_id: &i32, user_id: i32) {
+    let events = match server_state.rooms.get(room_id) {
+        Some(room) => room.event_history.iter().cloned().collect(),
+        None => return,
+    };
+    let history = RoomEventHistory {
+        room_id: *room_id,
+        events,
+    };
+    send_to_requester_channel(
+        serde_json::to_string(&history).unwrap(),
+        user_id,
+        server_state,
+        "room_event_history".to_owned(),
+    );
+}
+
+/// Appends to the analytics log from a separate task
+/// so the request never waits on the insert.
+fn log_room_session_event(
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    user_id: i32,
+    room_id: i32,
+    event_type: RoomSessionEventType,
+) {
+    let event = DBRoomSessionEvent {
+        id: -1,
+        user_id,
+        room_id,
+        event_type: event_type.as_str().to_owned(),
+        happened_at: Utc::now().to_rfc3339(),
+    };
+    let execution_handler = execution_handler.clone();
+    tokio::spawn(async move {
+        let mut handler = execution_handler.lock().await;
+        if data_capturer::capture_room_session_event(&mut handler, &event).await {
+            logging::console::log_failure(&format!(
+                "couldn't log {} of user({}) in room({})",
+                event.event_type, event.user_id, event.room_id
+            ));
+        }
+    });
+}
+
+/// Adds to the moderation audit log from a separate task, like
+/// the session events a failed write is only logged.
+pub fn log_moderation_action(
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    actor_id: i32,
+    target_id: i32,
+    room_id: i32,
+    action: ModerationAction,
+    reason: Option<String>,
+) {
+    let moderation_action = DBModerationAction {
+        id: -1,
+        actor_id,
+        target_id,
+        room_id,
+        action: action.as_str().to_owned(),
+        reason,
+        happened_at: Utc::now().to_rfc3339(),
+    };
+    let execution_handler = execution_handler.clone();
+    tokio::spawn(async move {
+        let mut handler = execution_handler.lock().await;
+        if data_capturer::capture_moderation_action(&mut handler, &moderation_action).await {
+            logging::console::log_failure(&format!(
+                "couldn't log {} of user({}) by user({}) in room({})",
+                moderation_action.action,
+                moderation_action.target_id,
+                moderation_action.actor_id,
+                moderation_action.room_id
+            ));
+        }
+    });
+}
+
+/// Keeps get_recent_rooms up to date, off the join path like
+/// the session events.
+fn log_room_visit(execution_handler: &Arc<ExecutionHandlerPool>, user_id: i32, room_id: i32) {
+    let visit = DBUserRoomVisit {
+        id: -1,
+        user_id,
+        room_id,
+        visited_at: Utc::now().to_rfc3339(),
     };
+    let execution_handler = execution_handler.clone();
+    tokio::spawn(async move {
+        let mut handler = execution_handler.lock().await;
+        if data_capturer::capture_room_visit(&mut handler, &visit).await {
+            logging::console::log_failure(&format!(
+                "couldn't record the visit of user({}) to room({})",
+                visit.user_id, visit.room_id
+            ));
+        }
+    });
+}
+
+fn record_event_in_room(server_state: &mut ServerState, room_id: &i32, event: RoomEvent) {
+    if let Some(room) = server_state.rooms.get_mut(room_id) {
+        record_room_event(room, event);
+    }
 }
 
 /// executed after database insertion is proven to be successful.
+/// Empty when the user opted out of
+/// telling followers where they are.
+async fn gather_followers_to_notify(
+    user_id: &i32,
+    execution_handler: &mut ExecutionHandler,
+) -> HashSet<i32> {
+    if !data_fetcher::get_user_notifies_followers_of_rooms(user_id, execution_handler).await {
+        return HashSet::new();
+    }
+    data_fetcher::get_follower_user_ids_for_user(execution_handler, user_id)
+        .await
+        .1
+}
+
+/// Followers that are online and not
+/// already in the room being joined.
+pub fn online_followers_outside_room(
+    server_state: &ServerState,
+    followers: &HashSet<i32>,
+    room_id: &i32,
+) -> Vec<i32> {
+    let mut online: Vec<i32> = followers
+        .iter()
+        .filter(|follower| match server_state.active_users.get(follower) {
+            Some(user) => user.current_room_id != *room_id,
+            None => false,
+        })
+        .cloned()
+        .collect();
+    online.sort_unstable();
+    online
+}
+
+fn notify_followers_of_join(
+    server_state: &mut ServerState,
+    room_id: &i32,
+    user_id: i32,
+    followers: &HashSet<i32>,
+) {
+    let notification = serde_json::to_string(&FollowedUserInRoom {
+        user_id,
+        room_id: *room_id,
+    })
+    .unwrap();
+    for follower in online_followers_outside_room(server_state, followers, room_id) {
+        send_to_requester_channel(
+            notification.clone(),
+            follower,
+            server_state,
+            "followed_user_in_room".to_owned(),
+        );
+    }
+}
+
 async fn continue_with_successful_room_creation(
     room_id: i32,
-    channel: &Channel,
-    public: bool,
+    channel: &dyn VoiceSignaling,
     server_state: &mut ServerState,
-    name: String,
-    desc: String,
     user_id: i32,
 ) {
     let request_to_voice_server = VoiceServerCreateRoom {
-        roomId: room_id.clone().to_string(),
+        room_id: room_id.clone().to_string(),
     };
-    let new_room_state: Room = construct_basic_room_for_state(room_id.clone(), public, name, desc);
-    server_state.rooms.insert(room_id, new_room_state);
     server_state.owner_queues.insert(
         room_id,
         OwnerQueue {
@@ -726,17 +2338,284 @@ async fn continue_with_successful_room_creation(
             room_id: room_id,
         },
     );
-    let request_str =
-        create_voice_server_request("create-room", &user_id.to_string(), request_to_voice_server);
-    rabbit::publish_voice_message(channel, request_str)
-        .await
-        .unwrap_or_default();
+    let request_str = create_voice_server_request(
+        VoiceServerOp::CreateRoom,
+        &user_id.to_string(),
+        request_to_voice_server,
+    );
+    channel.publish(request_str).await.unwrap_or_default();
     logging::console::log_success(&format!(
         "user({}) successfully created room({})",
         user_id, room_id
     ));
 }
 
+/// Application close code sent with
+/// the "banned" reason.
+pub const BANNED_CLOSE_CODE: u16 = 4003;
+
+/// Takes a freshly banned user out of their room, including a
+/// session held for them to resume, and closes their socket.
+pub async fn remove_banned_user(
+    server_state: &mut ServerState,
+    user_id: &i32,
+    banned_reason: String,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let current_room_id = match server_state.active_users.get(user_id) {
+        Some(user) => user.current_room_id,
+        None => -1,
+    };
+    server_state.disconnected_sessions.remove(user_id);
+    let still_in_room = match server_state.rooms.get(&current_room_id) {
+        Some(room) => room.user_ids.contains(user_id),
+        None => false,
+    };
+    if still_in_room {
+        leave_room(
+            server_state,
+            user_id,
+            &current_room_id,
+            voice_publish_channel,
+            integration_publish_channel,
+            execution_handler,
+        )
+        .await;
+    }
+    let banned_response = communication::helpers::construct_banned_response(banned_reason);
+    for tx in server_state.peer_map.remove_user(user_id) {
+        tx.send(Message::text(banned_response.clone()))
+            .and_then(|_| tx.send(Message::close_with(BANNED_CLOSE_CODE, "banned")))
+            .unwrap_or_else(|e| eprintln!("issue closing banned socket:{}", e));
+    }
+    server_state.active_users.remove(user_id);
+    server_state.session_tokens.remove(user_id);
+    logging::console::log_event(&format!("Removed banned user({})", user_id));
+}
+
+/// Sockets of deleted accounts are closed with this code.
+pub const ACCOUNT_DELETED_CLOSE_CODE: u16 = 4005;
+
+/// Takes a user whose account was just deleted off the server.
+/// Unlike leave_room nothing about them is written to the
+/// database, their rooms pass on like they would on a leave.
+pub async fn remove_deleted_user(
+    server_state: &mut ServerState,
+    user_id: &i32,
+    voice_publish_channel: &dyn VoiceSignaling,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let current_room_id = server_state.current_room_id(*user_id);
+    server_state.disconnected_sessions.remove(user_id);
+    let still_in_room = match server_state.rooms.get(&current_room_id) {
+        Some(room) => room.user_ids.contains(user_id),
+        None => false,
+    };
+    if still_in_room {
+        remove_all_owned_iot_servers(
+            server_state,
+            integration_publish_channel,
+            user_id,
+            current_room_id,
+        )
+        .await;
+        let request = VoiceServerClosePeer {
+            room_id: current_room_id.to_string(),
+            peer_id: user_id.to_string(),
+            kicked: false,
+        };
+        remove_user_from_room_basic(request, server_state, voice_publish_channel).await;
+        record_event_in_room(
+            server_state,
+            &current_room_id,
+            RoomEvent::UserLeft { user_id: *user_id },
+        );
+        let room_has_users = match server_state.rooms.get(&current_room_id) {
+            Some(room) => room.amount_of_users > 0,
+            None => false,
+        };
+        if room_has_users {
+            select_new_owner_if_current_user_is_owner(
+                user_id,
+                server_state,
+                execution_handler,
+                current_room_id,
+            )
+            .await;
+        }
+    }
+    let response = serde_json::to_string(&BasicResponse {
+        response_op_code: "account_deleted".to_owned(),
+        response_containing_data: user_id.to_string(),
+    })
+    .unwrap();
+    for tx in server_state.peer_map.remove_user(user_id) {
+        tx.send(Message::text(response.clone()))
+            .and_then(|_| {
+                tx.send(Message::close_with(
+                    ACCOUNT_DELETED_CLOSE_CODE,
+                    "account_deleted",
+                ))
+            })
+            .unwrap_or_else(|e| eprintln!("issue closing deleted account socket:{}", e));
+    }
+    server_state.active_users.remove(user_id);
+    server_state.session_tokens.remove(user_id);
+    logging::console::log_event(&format!("Removed deleted account user({})", user_id));
+}
+
+/// See the contributions module for what counts, anything
+/// past the daily cap is silently dropped.
+async fn award_contribution(
+    server_state: &mut ServerState,
+    user_id: &i32,
+    contribution: Contribution,
+    execution_handler: &mut ExecutionHandler,
+) {
+    if !contributions::claim_contribution(server_state, user_id) {
+        return;
+    }
+    let result = data_capturer::capture_contribution(user_id, execution_handler).await;
+    if result.encountered_error {
+        logging::console::log_failure(&format!(
+            "user({}) {:?} contribution failure:{}",
+            user_id, contribution, result.desc
+        ));
+    }
+}
+
+/// How long a room may stay empty before it's destroyed
+/// unless EMPTY_ROOM_GRACE_SECONDS says otherwise.
+pub const DEFAULT_EMPTY_ROOM_GRACE: Duration = Duration::from_secs(30);
+
+pub fn empty_room_grace() -> Duration {
+    match env::var("EMPTY_ROOM_GRACE_SECONDS") {
+        Ok(value) => value
+            .parse()
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_EMPTY_ROOM_GRACE),
+        Err(_) => DEFAULT_EMPTY_ROOM_GRACE,
+    }
+}
+
+/// Rooms that have been empty for at least the grace
+/// period as of now, rooms with users are never listed.
+pub fn expired_empty_rooms(server_state: &ServerState, grace: Duration, now: Instant) -> Vec<i32> {
+    server_state
+        .rooms
+        .values()
+        .filter(|room| room.amount_of_users == 0)
+        .filter(|room| match room.empty_since {
+            Some(empty_since) => now.saturating_duration_since(empty_since) >= grace,
+            None => true,
+        })
+        .map(|room| room.room_id)
+        .collect()
+}
+
+/// Destroys every room whose grace period ran out as of now,
+/// releasing its voice server, and gives back their ids.
+pub async fn destroy_expired_empty_rooms(
+    server_state: &mut ServerState,
+    grace: Duration,
+    now: Instant,
+    publish_channel: &dyn VoiceSignaling,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Vec<i32> {
+    let to_delete = expired_empty_rooms(server_state, grace, now);
+    for room_id in to_delete.iter() {
+        destroy_room(server_state, publish_channel, execution_handler, room_id).await;
+        logging::console::log_event(&format!("Removed empty room:{}", room_id));
+    }
+    to_delete
+}
+
+/// Most users a room admits unless
+/// ROOM_MAX_OCCUPANCY says otherwise.
+pub const DEFAULT_MAX_OCCUPANCY: usize = 500;
+
+pub fn default_max_occupancy() -> usize {
+    match env::var("ROOM_MAX_OCCUPANCY") {
+        Ok(value) => value.parse().unwrap_or(DEFAULT_MAX_OCCUPANCY),
+        Err(_) => DEFAULT_MAX_OCCUPANCY,
+    }
+}
+
+/// Users already in the room don't count against
+/// the cap again, they might just be rejoining.
+pub fn room_is_full(room: &Room, user_id: &i32) -> bool {
+    !room.user_ids.contains(user_id) && room.user_ids.len() >= room.max_occupancy
+}
+
+/// Most speakers an auto speaker room hands out on join,
+/// anyone after that joins listening.
+pub const MAX_SPEAKERS_PER_ROOM: usize = 20;
+
+/// Decides how a user actually joins a room:
+/// - Anyone asking to only listen joins listening.
+/// - The first user in a room(the owner) always speaks.
+/// - Speakers stay speakers.
+/// - Everyone else only speaks if the room is auto speaker and
+///   the speaker cap isn't reached, otherwise they join muted
+///   as a listener and have to raise their hand.
+pub fn resolve_type_of_join(
+    room: &Room,
+    current_permissions: Option<&RoomPermissions>,
+    requested_join: &str,
+    num_of_speakers: usize,
+) -> VoiceServerOp {
+    if requested_join != "join-as-speaker" {
+        return VoiceServerOp::JoinAsNewPeer;
+    }
+    if room.user_ids.len() == 0 {
+        return VoiceServerOp::JoinAsSpeaker;
+    }
+    if let Some(permissions) = current_permissions {
+        if permissions.is_speaker {
+            return VoiceServerOp::JoinAsSpeaker;
+        }
+    }
+    if room.auto_speaker && num_of_speakers < MAX_SPEAKERS_PER_ROOM {
+        return VoiceServerOp::JoinAsSpeaker;
+    }
+    VoiceServerOp::JoinAsNewPeer
+}
+
+fn count_speakers_in_room(room: &Room, permissions: &HashMap<i32, RoomPermissions>) -> usize {
+    room.user_ids
+        .iter()
+        .filter(|user_id| match permissions.get(user_id) {
+            Some(user_permissions) => user_permissions.is_speaker,
+            None => false,
+        })
+        .count()
+}
+
+async fn broadcast_user_permissions(
+    server_state: &mut ServerState,
+    room_id: &i32,
+    user_id: i32,
+    permissions: RoomPermissions,
+) {
+    let response = BasicResponse {
+        response_op_code: "user_permissions_updated".to_owned(),
+        response_containing_data: serde_json::to_string(&SingleUserPermissionResults {
+            user_id,
+            data: permissions,
+        })
+        .unwrap(),
+    };
+    fan::broadcast_message_to_room(
+        serde_json::to_string(&response).unwrap(),
+        server_state,
+        room_id.clone(),
+    )
+    .await;
+}
+
 async fn check_or_insert_initial_permissions(
     room: &Room,
     join_as: &str,
@@ -748,7 +2627,7 @@ async fn check_or_insert_initial_permissions(
         // if the user already has permissions
         if permissions.1.contains_key(requester_id) {
             let current_user_permissions = permissions.1.get(&requester_id).unwrap();
-            // If the user is requesting to join as speaker:
+            // If the user is joining as speaker:
             // - But isn't a speaker in the database and the room is auto speaker
             //    we accept this request because the user could have been a peer
             //    previously and now they are joining as a speaker.
@@ -756,7 +2635,19 @@ async fn check_or_insert_initial_permissions(
                 && current_user_permissions.is_speaker == false
                 && room.auto_speaker == true
             {
-                return false;
+                let new_permissions = permission_configs::create_non_preset(
+                    room.room_id.clone(),
+                    requester_id.clone(),
+                    false,
+                    true,
+                    current_user_permissions.is_mod,
+                );
+                return data_capturer::capture_new_room_permissions_update(
+                    &new_permissions,
+                    handler,
+                )
+                .await
+                .encountered_error;
             }
             //if this person was in the room before and is a speaker
             if join_as == "join-as-speaker" && current_user_permissions.is_speaker == true {
@@ -814,55 +2705,46 @@ async fn create_initial_user_permissions(
     }
 }
 
-fn create_voice_server_request<T: Serialize>(op_code: &str, uid: &String, data: T) -> String {
-    let voice_server_req = VoiceServerRequest {
-        op: op_code.to_owned(),
-        uid: uid.to_owned(),
-        d: data,
-    };
-    return serde_json::to_string(&voice_server_req).unwrap();
+/// Encoded with the configured voice codec, ready to publish.
+fn create_voice_server_request<T: Serialize>(op: VoiceServerOp, uid: &String, data: T) -> Vec<u8> {
+    let voice_server_req = VoiceServerRequest::new(op, data, uid.to_owned());
+    return codec::voice_codec().encode(&voice_server_req);
 }
 
 /// When Users can only be removed from speaker:
-/// - If the owner requests(doesn't matter if the user is a mod)
-/// - If the person being removed is not a mod and the requester is a mod
-///     and the person being removed is a speaker.
-/// - If the person requesting is removing themselves and they aren't
-///   the owner
+/// - If the requester outranks them, so the owner can
+///   remove anyone and mods can remove plain speakers.
+/// - If the person requesting is removing themselves
 /// If none of these conditions are met, it is an invalid request.
 fn requester_can_remove_speaker(
-    remover_permissions: (&i32, &RoomPermissions),
-    removee_permissions: (&i32, &RoomPermissions),
-    owner_id: &i32,
+    owner_id: i32,
+    permissions: &HashMap<i32, RoomPermissions>,
+    requester_id: &i32,
+    requestee_id: &i32,
 ) -> bool {
-    if remover_permissions.0 == owner_id && removee_permissions.0 != remover_permissions.0 {
-        return true;
-    }
     // Anyone can make themselves a listener
-    if remover_permissions.0 == removee_permissions.0 && removee_permissions.1.is_speaker {
-        return true;
-    }
-    if remover_permissions.1.is_mod
-        && removee_permissions.1.is_mod == false
-        && removee_permissions.1.is_speaker
-    {
-        return true;
+    if requester_id == requestee_id {
+        return permissions
+            .get(requestee_id)
+            .map_or(false, |requestee| requestee.is_speaker);
     }
-    return false;
+    roles::check_permission(owner_id, permissions, requester_id, Role::Mod).is_ok()
+        && roles::outranks(owner_id, permissions, requester_id, requestee_id)
 }
 
+/// The owner can bring anyone up to speak,
+/// mods only users who asked to.
 fn requester_can_add_speaker(
-    requester_permissions: &RoomPermissions,
-    requestee_permissions: &RoomPermissions,
-    owner_id: &i32,
+    owner_id: i32,
+    permissions: &HashMap<i32, RoomPermissions>,
     requester_id: &i32,
+    requestee_permissions: &RoomPermissions,
 ) -> bool {
-    if requestee_permissions.asked_to_speak && requester_permissions.is_mod
-        || requester_id == owner_id
-    {
-        return true;
+    match roles::role_in_room(owner_id, permissions, requester_id) {
+        Some(Role::Owner) => true,
+        Some(Role::Mod) => requestee_permissions.asked_to_speak,
+        _ => false,
     }
-    false
 }
 
 /// We know that the speaker was removed
@@ -893,7 +2775,7 @@ fn insert_user_into_owner_queue(user_id: i32, room_id: &i32, server_state: &mut
 async fn select_new_owner_if_current_user_is_owner(
     requester_id: &i32,
     server_state: &mut ServerState,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
     room_id: i32,
 ) {
     // Update the room owner if this user that is leaving
@@ -902,7 +2784,9 @@ async fn select_new_owner_if_current_user_is_owner(
     println!("selecting new owner");
     let mut handler = execution_handler.lock().await;
     let need_to_update_room_owner =
-        user_is_owner_of_room(requester_id.clone(), &mut handler, &room_id).await;
+        roles::require_role(&mut handler, &room_id, requester_id, Role::Owner)
+            .await
+            .is_ok();
     if need_to_update_room_owner {
         update_room_owner_in_line(server_state, &mut handler, &room_id).await;
     }
@@ -920,6 +2804,9 @@ pub async fn update_room_owner_in_line(
         if let Some(new_owner_id) = new_owner {
             data_capturer::capture_new_room_owner_update(room_id, &new_owner_id, execution_handler)
                 .await;
+            if let Some(room) = server_state.rooms.get_mut(room_id) {
+                room.owner_id = new_owner_id;
+            }
             let response = BasicResponse {
                 response_op_code: "new_owner".to_owned(),
                 response_containing_data: new_owner_id.to_string(),
@@ -940,15 +2827,19 @@ pub async fn update_room_owner_in_line(
     None
 }
 
+/// Returns false if the room had no owner queue to add them to.
 pub async fn update_room_owner(
     server_state: &mut ServerState,
     execution_handler: &mut ExecutionHandler,
     room_id: &i32,
     user_id: &i32,
-) {
+) -> bool {
     if let Some(owner_queue) = server_state.owner_queues.get_mut(room_id) {
         owner_queue.insert_new_user(user_id.clone());
         data_capturer::capture_new_room_owner_update(room_id, &user_id, execution_handler).await;
+        if let Some(room) = server_state.rooms.get_mut(room_id) {
+            room.owner_id = *user_id;
+        }
         let response = BasicResponse {
             response_op_code: "new_owner".to_owned(),
             response_containing_data: user_id.to_string(),
@@ -960,8 +2851,10 @@ pub async fn update_room_owner(
         )
         .await;
         logging::console::log_success(&format!("New Owner for room:{}", room_id));
+        true
     } else {
         logging::console::log_failure(&format!("New Owner for room:{}", room_id));
+        false
     }
 }
 
@@ -971,7 +2864,7 @@ pub async fn update_room_owner(
 // it will always be removed when you leave the room.
 pub async fn remove_all_owned_iot_servers(
     write_state: &mut ServerState,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
     current_user_id: &i32,
     current_room_id: i32,
 ) {
@@ -1003,63 +2896,35 @@ pub fn gather_owned_servers(
     owned_iot_servers_to_remove
 }
 
-pub async fn user_is_owner_of_room(
-    user_id: i32,
-    execution_handler: &mut ExecutionHandler,
-    room_id: &i32,
-) -> bool {
-    let res = data_fetcher::get_room_owner_and_settings(execution_handler, room_id).await;
-    if res.0 {
-        return false;
-    }
-    return res.1 == user_id;
-}
-
 pub fn can_block_this_user_from_room(
     permissions: HashMap<i32, RoomPermissions>,
     owner_id: i32,
     requester_id: i32,
     target_to_block: i32,
 ) -> bool {
-    //no one can block themselves
-    if requester_id != target_to_block {
-        if let Some(requester_permissions) = permissions.get(&requester_id) {
-            if let Some(target_permissions) = permissions.get(&target_to_block) {
-                // The owner can block anyone
-                if requester_id == owner_id {
-                    return true;
-                }
-                // The requester can only block the
-                // target if they(requester) is a mod
-                // and the target is not a mod/the owner
-                if requester_permissions.is_mod
-                    && !target_permissions.is_mod
-                    && target_to_block != owner_id
-                {
-                    return true;
-                }
-            }
-        }
-    }
-    false
+    // The owner can block anyone, mods can
+    // only block users who aren't mods or the owner
+    requester_id != target_to_block
+        && roles::check_permission(owner_id, &permissions, &requester_id, Role::Mod).is_ok()
+        && roles::outranks(owner_id, &permissions, &requester_id, &target_to_block)
 }
 async fn send_close_peer_request_not_kicked(
     room_id: &i32,
     requester_id: &i32,
-    voice_server_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    voice_server_publish_channel: &dyn VoiceSignaling,
 ) {
     let request_to_voice_server = VoiceServerClosePeer {
-        roomId: room_id.to_string(),
-        peerId: requester_id.to_string(),
+        room_id: room_id.to_string(),
+        peer_id: requester_id.to_string(),
         kicked: false,
     };
-    let request_str: String = create_voice_server_request(
-        "close-peer",
+    let request_str = create_voice_server_request(
+        VoiceServerOp::ClosePeer,
         &requester_id.to_string(),
         request_to_voice_server,
     );
-    let channel = voice_server_publish_channel.lock().await;
-    rabbit::publish_voice_message(&channel, request_str)
+    voice_server_publish_channel
+        .publish(request_str)
         .await
         .unwrap_or_default();
     logging::console::log_success(&format!("user({}) left room({})", requester_id, room_id));
@@ -0,0 +1,100 @@
+use std::collections::HashMap;
+
+use crate::communication::types::RoomPermissions;
+use crate::data_store::store::DataStore;
+
+/// Where a user stands in a room, each role can
+/// do everything the roles below it can.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Listener,
+    Speaker,
+    Mod,
+    Owner,
+}
+
+impl Role {
+    /// The role the permissions give a user who isn't the owner.
+    pub fn from_permissions(permissions: &RoomPermissions) -> Self {
+        if permissions.is_mod {
+            Role::Mod
+        } else if permissions.is_speaker {
+            Role::Speaker
+        } else {
+            Role::Listener
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RequestError {
+    /// the room owner or permissions couldn't be looked up
+    LookupFailed,
+    /// the user has no permissions in the room
+    NotInRoom,
+    InsufficientRole {
+        required: Role,
+        actual: Role,
+    },
+}
+
+/// None if the user has no permissions in the room, the
+/// owner is always the owner whatever their permissions say.
+pub fn role_in_room(
+    owner_id: i32,
+    permissions: &HashMap<i32, RoomPermissions>,
+    user_id: &i32,
+) -> Option<Role> {
+    if owner_id == *user_id {
+        return Some(Role::Owner);
+    }
+    permissions.get(user_id).map(Role::from_permissions)
+}
+
+/// Every privileged handler checks the requester with this
+/// before acting, so the hierarchy lives in one place.
+pub fn check_permission(
+    owner_id: i32,
+    permissions: &HashMap<i32, RoomPermissions>,
+    user_id: &i32,
+    required: Role,
+) -> Result<(), RequestError> {
+    let actual = role_in_room(owner_id, permissions, user_id).ok_or(RequestError::NotInRoom)?;
+    if actual < required {
+        return Err(RequestError::InsufficientRole { required, actual });
+    }
+    Ok(())
+}
+
+/// Whether the requester's role is above the target's, used when
+/// acting on another user(kicks, blocks, taking the stage away).
+pub fn outranks(
+    owner_id: i32,
+    permissions: &HashMap<i32, RoomPermissions>,
+    requester_id: &i32,
+    target_id: &i32,
+) -> bool {
+    match (
+        role_in_room(owner_id, permissions, requester_id),
+        role_in_room(owner_id, permissions, target_id),
+    ) {
+        (Some(requester), Some(target)) => requester > target,
+        _ => false,
+    }
+}
+
+/// check_permission for handlers that don't have
+/// the room owner and permissions at hand yet.
+pub async fn require_role(
+    store: &mut dyn DataStore,
+    room_id: &i32,
+    user_id: &i32,
+    required: Role,
+) -> Result<(), RequestError> {
+    let (owner_error, owner_id, _) = store.room_owner_and_settings(room_id).await;
+    let (permissions_error, permissions) = store.room_permissions_for_users(room_id).await;
+    if owner_error || permissions_error {
+        return Err(RequestError::LookupFailed);
+    }
+    check_permission(owner_id, &permissions, user_id, required)
+}
@@ -0,0 +1,43 @@
+/// Most tags a single room can have.
+pub const MAX_ROOM_TAGS: usize = 5;
+/// Longest a tag can be, matches room_tags.tag.
+pub const MAX_TAG_LENGTH: usize = 20;
+
+/// Tags are matched case insensitively, so
+/// they're stored the way they're looked up.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Letters, digits and dashes, e.g. "music" or "home-automation".
+pub fn tag_problem(tag: &str) -> Option<String> {
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LENGTH {
+        return Some(format!("tags must be 1-{} characters", MAX_TAG_LENGTH));
+    }
+    if !tag
+        .chars()
+        .all(|character| character.is_alphanumeric() || character == '-')
+    {
+        return Some("tags can only contain letters, numbers and dashes".to_owned());
+    }
+    None
+}
+
+/// Normalizes the requested tags and drops duplicates,
+/// keeping the order they were given in.
+pub fn tags_from_request(tags: &[String]) -> Result<Vec<String>, String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = normalize_tag(tag);
+        if let Some(problem) = tag_problem(&tag) {
+            return Err(problem);
+        }
+        if !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    if normalized.len() > MAX_ROOM_TAGS {
+        return Err(format!("a room can have at most {} tags", MAX_ROOM_TAGS));
+    }
+    Ok(normalized)
+}
@@ -0,0 +1,692 @@
+use super::chat_modes::{self, ChatAccess, ChatMode};
+use super::roles::{self, RequestError, Role};
+use super::{confirmations, handler, tags};
+use crate::communication::op_codes::VoiceServerOp;
+use crate::communication::types::{
+    BasicResponse, ForcedMuteUpdate, GenericRoomIdAndPeerId, RoomPermissions,
+};
+use crate::data_store::store::MemoryDataStore;
+use crate::rabbitmq::signaling::RecordingSignaling;
+use crate::state::state::ServerState;
+use crate::state::types::{ConnectionId, PeerSender, RoomEvent, User};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+fn insert_room(state: &mut ServerState, room_id: i32, name: &str, public: bool, users: i32) {
+    let mut room =
+        handler::construct_basic_room_for_state(room_id, public, name.to_owned(), "".to_owned());
+    room.amount_of_users = users;
+    state.rooms.insert(room_id, room);
+}
+
+/// Only public rooms match, busiest first.
+pub fn test_search_public_rooms() {
+    let mut state = ServerState::new();
+    insert_room(&mut state, 1, "Garden sensors", true, 2);
+    insert_room(&mut state, 2, "garden lights", true, 9);
+    insert_room(&mut state, 3, "Secret Garden", false, 30);
+    insert_room(&mut state, 4, "kitchen", true, 50);
+
+    assert_eq!(
+        handler::search_public_rooms(&state, "GARDEN", 10),
+        vec![2, 1]
+    );
+    assert_eq!(handler::search_public_rooms(&state, "garden", 1), vec![2]);
+    assert!(handler::search_public_rooms(&state, "secret", 10).is_empty());
+}
+
+/// Private rooms never make the top rooms list.
+pub fn test_top_rooms_are_public() {
+    let mut state = ServerState::new();
+    insert_room(&mut state, 1, "open", true, 2);
+    insert_room(&mut state, 2, "hidden", false, 40);
+
+    let top: Vec<i32> = handler::gather_top_public_rooms(&state)
+        .iter()
+        .map(|room| room.room_id)
+        .collect();
+    assert_eq!(top, vec![1]);
+}
+
+/// Pinned rooms lead in pin order, whatever their occupancy.
+pub fn test_pinned_rooms_lead_top_rooms() {
+    let mut state = ServerState::new();
+    insert_room(&mut state, 1, "small", true, 1);
+    insert_room(&mut state, 2, "medium", true, 5);
+    insert_room(&mut state, 3, "large", true, 9);
+    insert_room(&mut state, 4, "hidden", false, 40);
+    let top_ids = |state: &ServerState| -> Vec<i32> {
+        handler::gather_top_public_rooms(state)
+            .iter()
+            .map(|room| room.room_id)
+            .collect()
+    };
+    assert!(handler::set_room_pinned(&mut state, &3, true));
+    std::thread::sleep(Duration::from_millis(2));
+    assert!(handler::set_room_pinned(&mut state, &1, true));
+    assert!(handler::set_room_pinned(&mut state, &4, true));
+    assert_eq!(top_ids(&state), vec![3, 1, 2]);
+    //pinning again keeps the place
+    assert!(handler::set_room_pinned(&mut state, &3, true));
+    assert_eq!(top_ids(&state), vec![3, 1, 2]);
+    assert!(handler::set_room_pinned(&mut state, &3, false));
+    assert_eq!(top_ids(&state), vec![1, 2, 3]);
+    assert!(!handler::set_room_pinned(&mut state, &5, true));
+}
+
+/// Auto speaker rooms let joiners speak until the cap,
+/// everyone else has to raise their hand.
+pub fn test_resolve_type_of_join() {
+    let mut room = handler::construct_basic_room_for_state(1, true, "a".to_owned(), "".to_owned());
+    let listener = RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let speaker = RoomPermissions {
+        is_speaker: true,
+        ..listener.clone()
+    };
+    //the owner speaks no matter what
+    room.auto_speaker = false;
+    assert_eq!(
+        handler::resolve_type_of_join(&room, None, "join-as-speaker", 0),
+        VoiceServerOp::JoinAsSpeaker
+    );
+
+    room.user_ids.insert(33);
+    assert_eq!(
+        handler::resolve_type_of_join(&room, None, "join-as-speaker", 1),
+        VoiceServerOp::JoinAsNewPeer
+    );
+    assert_eq!(
+        handler::resolve_type_of_join(&room, Some(&speaker), "join-as-speaker", 1),
+        VoiceServerOp::JoinAsSpeaker
+    );
+
+    room.auto_speaker = true;
+    assert_eq!(
+        handler::resolve_type_of_join(&room, Some(&listener), "join-as-speaker", 1),
+        VoiceServerOp::JoinAsSpeaker
+    );
+    assert_eq!(
+        handler::resolve_type_of_join(&room, None, "join-as-new-peer", 1),
+        VoiceServerOp::JoinAsNewPeer
+    );
+    assert_eq!(
+        handler::resolve_type_of_join(
+            &room,
+            None,
+            "join-as-speaker",
+            handler::MAX_SPEAKERS_PER_ROOM
+        ),
+        VoiceServerOp::JoinAsNewPeer
+    );
+}
+
+/// Full rooms turn away new users but not the ones already inside.
+/// Only the most recent events are kept,
+/// oldest are dropped first.
+pub fn test_room_event_history() {
+    let mut room =
+        handler::construct_basic_room_for_state(1, true, "events".to_owned(), "".to_owned());
+    for user_id in 0..60 {
+        handler::record_room_event(&mut room, RoomEvent::UserJoined { user_id });
+    }
+    assert_eq!(room.event_history.len(), 50);
+    assert_eq!(
+        room.event_history.front().unwrap().event,
+        RoomEvent::UserJoined { user_id: 10 }
+    );
+    handler::record_room_event(&mut room, RoomEvent::HandRaised { user_id: 7 });
+    assert_eq!(room.event_history.len(), 50);
+    let newest = serde_json::to_value(room.event_history.back().unwrap()).unwrap();
+    assert_eq!(newest["type"], "hand_raised");
+    assert_eq!(newest["user_id"], 7);
+    assert!(newest["happened_at"].is_string());
+}
+
+pub fn test_room_is_full() {
+    let mut room =
+        handler::construct_basic_room_for_state(1, true, "cap".to_owned(), "".to_owned());
+    assert_eq!(room.max_occupancy, handler::DEFAULT_MAX_OCCUPANCY);
+    room.max_occupancy = 2;
+    room.user_ids.insert(10);
+    assert!(!handler::room_is_full(&room, &11));
+    room.user_ids.insert(11);
+    assert!(handler::room_is_full(&room, &12));
+    assert!(!handler::room_is_full(&room, &10));
+}
+
+/// Empty rooms get the grace period to be rejoined,
+/// joining clears the clock and the last user leaving
+/// starts it again.
+pub fn test_expired_empty_rooms() {
+    let grace = Duration::from_secs(30);
+    let mut state = ServerState::new();
+    insert_room(&mut state, 1, "never joined", true, 0);
+    insert_room(&mut state, 2, "busy", true, 3);
+    let created = state.rooms[&1].empty_since.unwrap();
+    assert!(
+        handler::expired_empty_rooms(&state, grace, created + Duration::from_secs(29)).is_empty()
+    );
+    assert_eq!(
+        handler::expired_empty_rooms(&state, grace, created + grace),
+        vec![1]
+    );
+
+    state.active_users.insert(10, User::default());
+    state.add_user_to_room(10, 1);
+    assert_eq!(state.rooms[&1].empty_since, None);
+    assert!(handler::expired_empty_rooms(&state, grace, created + grace * 2).is_empty());
+
+    let room = state.rooms.get_mut(&1).unwrap();
+    assert!(room.remove_user(&10));
+    assert!(!room.remove_user(&10));
+    let emptied = room.empty_since.unwrap();
+    assert_eq!(room.amount_of_users, 0);
+    assert!(
+        handler::expired_empty_rooms(&state, grace, emptied + Duration::from_secs(1)).is_empty()
+    );
+    assert_eq!(
+        handler::expired_empty_rooms(&state, grace, emptied + grace),
+        vec![1]
+    );
+}
+
+/// Only online followers outside of the
+/// joined room hear about the join.
+pub fn test_online_followers_outside_room() {
+    let mut state = ServerState::new();
+    for (user_id, room_id) in [(1, 5), (2, -1), (3, 9)] {
+        state.active_users.insert(
+            user_id,
+            User {
+                current_room_id: room_id,
+                ..Default::default()
+            },
+        );
+    }
+    let followers = HashSet::from([1, 2, 3, 4]);
+    assert_eq!(
+        handler::online_followers_outside_room(&state, &followers, &5),
+        vec![2, 3]
+    );
+    assert!(handler::online_followers_outside_room(&state, &HashSet::new(), &5).is_empty());
+}
+
+/// Raising a hand twice is a no-op and
+/// speakers can't raise theirs at all.
+pub fn test_check_raise_hand() {
+    let listener = RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let raised = RoomPermissions {
+        asked_to_speak: true,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let speaker = RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: true,
+        is_mod: true,
+    };
+    assert_eq!(
+        handler::check_raise_hand(&listener),
+        handler::RaiseHandCheck::Raise
+    );
+    assert_eq!(
+        handler::check_raise_hand(&raised),
+        handler::RaiseHandCheck::AlreadyRaised
+    );
+    assert_eq!(
+        handler::check_raise_hand(&speaker),
+        handler::RaiseHandCheck::AlreadySpeaker
+    );
+}
+
+/// Approving needs a mod or the owner on one side and
+/// a raised hand on the other.
+pub fn test_check_speaker_approval() {
+    let listener = RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let raised = RoomPermissions {
+        asked_to_speak: true,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let moderator = RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: true,
+        is_mod: true,
+    };
+    assert_eq!(
+        handler::check_speaker_approval(&moderator, &raised, false),
+        handler::SpeakerApprovalCheck::Approve
+    );
+    // owners approve even without mod permissions
+    assert_eq!(
+        handler::check_speaker_approval(&listener, &raised, true),
+        handler::SpeakerApprovalCheck::Approve
+    );
+    assert_eq!(
+        handler::check_speaker_approval(&listener, &raised, false),
+        handler::SpeakerApprovalCheck::NotModOrOwner
+    );
+    assert_eq!(
+        handler::check_speaker_approval(&moderator, &listener, false),
+        handler::SpeakerApprovalCheck::HandNotRaised
+    );
+    assert_eq!(
+        handler::check_speaker_approval(&moderator, &moderator, true),
+        handler::SpeakerApprovalCheck::HandNotRaised
+    );
+}
+
+/// owner(1), mod(2), speaker(3) and listener(4), the owner
+/// holds mod permissions like rooms they create do.
+fn role_matrix_permissions() -> HashMap<i32, RoomPermissions> {
+    let permissions = |is_mod: bool, is_speaker: bool| RoomPermissions {
+        asked_to_speak: false,
+        is_speaker,
+        is_mod,
+    };
+    HashMap::from([
+        (1, permissions(true, true)),
+        (2, permissions(true, false)),
+        (3, permissions(false, true)),
+        (4, permissions(false, false)),
+    ])
+}
+
+/// Every role passes checks for its own role and the ones
+/// below it, and only outranks the roles below it.
+pub async fn test_role_permissions() {
+    let permissions = role_matrix_permissions();
+    let users = [
+        (1, Role::Owner),
+        (2, Role::Mod),
+        (3, Role::Speaker),
+        (4, Role::Listener),
+    ];
+    for (user_id, actual) in users {
+        assert_eq!(roles::role_in_room(1, &permissions, &user_id), Some(actual));
+        for (_, required) in users {
+            let expected = if actual >= required {
+                Ok(())
+            } else {
+                Err(RequestError::InsufficientRole { required, actual })
+            };
+            assert_eq!(
+                roles::check_permission(1, &permissions, &user_id, required),
+                expected
+            );
+        }
+        for (target_id, target) in users {
+            assert_eq!(
+                roles::outranks(1, &permissions, &user_id, &target_id),
+                actual > target
+            );
+        }
+    }
+
+    // users without permissions aren't in the room
+    assert_eq!(roles::role_in_room(1, &permissions, &5), None);
+    assert_eq!(
+        roles::check_permission(1, &permissions, &5, Role::Listener),
+        Err(RequestError::NotInRoom)
+    );
+    assert!(!roles::outranks(1, &permissions, &1, &5));
+    // the owner is the owner even without permissions
+    assert_eq!(
+        roles::role_in_room(1, &HashMap::new(), &1),
+        Some(Role::Owner)
+    );
+
+    // only mods and up block, and only users below them
+    assert!(handler::can_block_this_user_from_room(
+        permissions.clone(),
+        1,
+        1,
+        2
+    ));
+    assert!(handler::can_block_this_user_from_room(
+        permissions.clone(),
+        1,
+        2,
+        3
+    ));
+    assert!(!handler::can_block_this_user_from_room(
+        permissions.clone(),
+        1,
+        2,
+        1
+    ));
+    assert!(!handler::can_block_this_user_from_room(
+        permissions.clone(),
+        1,
+        3,
+        4
+    ));
+    assert!(!handler::can_block_this_user_from_room(
+        permissions.clone(),
+        1,
+        1,
+        1
+    ));
+
+    let mut store = MemoryDataStore::new();
+    store.rooms.insert(7, (1, "everyone".to_owned()));
+    store.permissions.insert(7, permissions);
+    assert_eq!(
+        roles::require_role(&mut store, &7, &1, Role::Owner).await,
+        Ok(())
+    );
+    assert_eq!(
+        roles::require_role(&mut store, &7, &2, Role::Owner).await,
+        Err(RequestError::InsufficientRole {
+            required: Role::Owner,
+            actual: Role::Mod
+        })
+    );
+    assert_eq!(
+        roles::require_role(&mut store, &8, &1, Role::Listener).await,
+        Err(RequestError::LookupFailed)
+    );
+}
+
+/// Speaker changes that wouldn't change anything
+/// are refused before reaching the voice server.
+pub fn test_speaker_change_problem() {
+    let listener = RoomPermissions {
+        asked_to_speak: true,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let speaker = RoomPermissions {
+        asked_to_speak: false,
+        is_speaker: true,
+        is_mod: true,
+    };
+    assert_eq!(handler::speaker_change_problem(true, &listener), None);
+    assert_eq!(
+        handler::speaker_change_problem(true, &speaker),
+        Some("already_a_speaker")
+    );
+    assert_eq!(handler::speaker_change_problem(false, &speaker), None);
+    assert_eq!(
+        handler::speaker_change_problem(false, &listener),
+        Some("not_a_speaker")
+    );
+}
+
+/// Only a timely created:true finalizes a room, late
+/// answers find nobody waiting.
+pub async fn test_room_confirmations() {
+    let wait = Duration::from_millis(200);
+    let confirmation = confirmations::expect_confirmation(9001);
+    assert!(confirmations::resolve_confirmation(9001, true));
+    assert!(confirmations::wait_for_confirmation(9001, confirmation, wait).await);
+
+    let confirmation = confirmations::expect_confirmation(9002);
+    assert!(confirmations::resolve_confirmation(9002, false));
+    assert!(!confirmations::wait_for_confirmation(9002, confirmation, wait).await);
+
+    let confirmation = confirmations::expect_confirmation(9003);
+    assert!(confirmations::is_awaiting(9003));
+    assert!(
+        !confirmations::wait_for_confirmation(9003, confirmation, Duration::from_millis(10)).await
+    );
+    assert!(!confirmations::is_awaiting(9003));
+    assert!(!confirmations::resolve_confirmation(9003, true));
+}
+
+/// Mods and the owner can always chat, everyone
+/// else goes by the room's chat mode.
+pub fn test_chat_modes() {
+    for mode in [
+        ChatMode::Everyone,
+        ChatMode::FollowersOnly,
+        ChatMode::Disabled,
+    ] {
+        assert_eq!(ChatMode::parse(mode.as_str()), Some(mode));
+    }
+    assert_eq!(ChatMode::parse("fast"), None);
+    assert!(chat_modes::chat_mode_from_request(Some("Everyone")).is_err());
+    assert_eq!(
+        chat_modes::chat_mode_from_request(Some("followers_only")),
+        Ok(ChatMode::FollowersOnly)
+    );
+    assert_eq!(
+        ChatMode::FollowersOnly.with_chat_enabled(true),
+        ChatMode::FollowersOnly
+    );
+    assert_eq!(
+        ChatMode::Disabled.with_chat_enabled(true),
+        ChatMode::Everyone
+    );
+    assert_eq!(
+        ChatMode::Everyone.with_chat_enabled(false),
+        ChatMode::Disabled
+    );
+
+    let mut room =
+        handler::construct_basic_room_for_state(1, true, "chat".to_owned(), "".to_owned());
+    room.owner_id = 10;
+    room.mod_ids.insert(11);
+    assert_eq!(chat_modes::chat_access(&room, &12), ChatAccess::Open);
+    room.chat_mode = ChatMode::FollowersOnly;
+    assert_eq!(
+        chat_modes::chat_access(&room, &12),
+        ChatAccess::NeedsFollow(10)
+    );
+    assert_eq!(chat_modes::chat_access(&room, &11), ChatAccess::Open);
+    room.chat_mode = ChatMode::Disabled;
+    assert_eq!(chat_modes::chat_access(&room, &12), ChatAccess::Closed);
+    assert_eq!(chat_modes::chat_access(&room, &10), ChatAccess::Open);
+}
+
+/// Tags are normalized and capped, tag lookups
+/// only list the public rooms, busiest first.
+pub fn test_room_tags() {
+    assert_eq!(
+        tags::tags_from_request(&[
+            " Music ".to_owned(),
+            "music".to_owned(),
+            "home-iot".to_owned()
+        ]),
+        Ok(vec!["music".to_owned(), "home-iot".to_owned()])
+    );
+    assert!(tags::tags_from_request(&["".to_owned()]).is_err());
+    assert!(tags::tags_from_request(&["no spaces".to_owned()]).is_err());
+    assert!(tags::tags_from_request(&["a".repeat(tags::MAX_TAG_LENGTH + 1)]).is_err());
+    let too_many: Vec<String> = (0..=tags::MAX_ROOM_TAGS)
+        .map(|index| format!("tag{}", index))
+        .collect();
+    assert!(tags::tags_from_request(&too_many).is_err());
+
+    let mut state = ServerState::new();
+    insert_room(&mut state, 1, "quiet", true, 2);
+    insert_room(&mut state, 2, "busy", true, 9);
+    insert_room(&mut state, 3, "hidden", false, 30);
+    //tagged in the database but no longer active
+    let tagged = HashSet::from([1, 2, 3, 4]);
+    assert_eq!(
+        handler::tagged_public_rooms(&state, &tagged, 10),
+        vec![2, 1]
+    );
+    assert_eq!(handler::tagged_public_rooms(&state, &tagged, 1), vec![2]);
+}
+
+/// Users only get their own permissions in a room they're in,
+/// the owner and mods in the room state count as mods.
+pub async fn test_my_room_permissions() {
+    let mut state = ServerState::new();
+    insert_room(&mut state, 7, "perms", true, 3);
+    let room = state.rooms.get_mut(&7).unwrap();
+    room.owner_id = 1;
+    room.user_ids = HashSet::from([1, 2, 3]);
+    room.mod_ids.insert(2);
+    let listener = RoomPermissions {
+        asked_to_speak: true,
+        is_speaker: false,
+        is_mod: false,
+    };
+    let mut store = MemoryDataStore::new();
+    store.permissions.insert(
+        7,
+        HashMap::from([(1, listener.clone()), (2, listener.clone())]),
+    );
+
+    let owner = handler::my_room_permissions(&state, &mut store, &7, &1)
+        .await
+        .unwrap();
+    assert!(owner.is_mod);
+    let moderator = handler::my_room_permissions(&state, &mut store, &7, &2)
+        .await
+        .unwrap();
+    assert!(moderator.is_mod && moderator.asked_to_speak && !moderator.is_speaker);
+    //in the room without a permissions row
+    assert_eq!(
+        handler::my_room_permissions(&state, &mut store, &7, &3)
+            .await
+            .unwrap_err(),
+        RequestError::LookupFailed
+    );
+    assert_eq!(
+        handler::my_room_permissions(&state, &mut store, &7, &4)
+            .await
+            .unwrap_err(),
+        RequestError::NotInRoom
+    );
+    assert_eq!(
+        handler::my_room_permissions(&state, &mut store, &8, &1)
+            .await
+            .unwrap_err(),
+        RequestError::NotInRoom
+    );
+}
+
+/// Mods force mute users below them, only a mod undoes it.
+pub async fn test_force_mute_user() {
+    let mut state = ServerState::new();
+    insert_room(&mut state, 7, "noisy", true, 4);
+    let room = state.rooms.get_mut(&7).unwrap();
+    room.owner_id = 1;
+    room.user_ids = HashSet::from([1, 2, 3, 4]);
+    //kept open so nobody is dropped as a dead peer
+    let mut receivers = Vec::new();
+    for user_id in [1, 2, 3, 4] {
+        state.active_users.insert(
+            user_id,
+            User {
+                current_room_id: 7,
+                ..Default::default()
+            },
+        );
+        let (tx, rx, _) = PeerSender::new();
+        state.peer_map.insert(ConnectionId::next(), user_id, tx);
+        receivers.push(rx);
+    }
+    let mut store = MemoryDataStore::new();
+    store.rooms.insert(7, (1, "".to_owned()));
+    store.permissions.insert(7, role_matrix_permissions());
+    let signaling = RecordingSignaling::new();
+
+    handler::force_mute_user(&mut state, &mut store, &signaling, &7, &2, &3, true)
+        .await
+        .unwrap();
+    assert!(state.rooms[&7].muted.contains(&3));
+    assert!(state.active_users[&3].muted);
+    let msg = receivers[3].recv().await.unwrap();
+    let response: BasicResponse = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+    assert_eq!(response.response_op_code, "force_muted");
+    let update: ForcedMuteUpdate =
+        serde_json::from_str(&response.response_containing_data).unwrap();
+    assert_eq!(
+        update,
+        ForcedMuteUpdate {
+            user_id: 3,
+            room_id: 7,
+            by: 2
+        }
+    );
+    // speakers can't, and mods can't mute each other
+    assert_eq!(
+        handler::force_mute_user(&mut state, &mut store, &signaling, &7, &3, &4, true).await,
+        Err(RequestError::InsufficientRole {
+            required: Role::Mod,
+            actual: Role::Speaker
+        })
+    );
+    assert!(
+        handler::force_mute_user(&mut state, &mut store, &signaling, &7, &2, &1, true)
+            .await
+            .is_err()
+    );
+    assert_eq!(
+        handler::force_mute_user(&mut state, &mut store, &signaling, &7, &2, &5, true).await,
+        Err(RequestError::NotInRoom)
+    );
+
+    handler::force_mute_user(&mut state, &mut store, &signaling, &7, &1, &3, false)
+        .await
+        .unwrap();
+    assert!(state.rooms[&7].muted.is_empty());
+    assert_eq!(signaling.published_ops(), vec!["mute-peer", "unmute-peer"]);
+}
+
+/// Guests join public rooms listening and show
+/// up as listeners, private rooms turn them away.
+pub async fn test_join_room_as_guest() {
+    let mut state = ServerState::new();
+    insert_room(&mut state, 1, "open", true, 0);
+    insert_room(&mut state, 2, "hidden", false, 0);
+    state.rooms.get_mut(&1).unwrap().user_ids = HashSet::from([5]);
+    state.active_users.insert(
+        -1000,
+        User {
+            current_room_id: -1,
+            guest: true,
+            ..Default::default()
+        },
+    );
+    state.active_users.insert(
+        5,
+        User {
+            current_room_id: 1,
+            ..Default::default()
+        },
+    );
+    let (guest_tx, _guest_rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), -1000, guest_tx);
+    let (tx, _rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 5, tx);
+    let signaling = RecordingSignaling::new();
+
+    let join = |room_id: i32| GenericRoomIdAndPeerId {
+        room_id,
+        peer_id: -1000,
+    };
+    handler::join_room_as_guest(join(2), &mut state, &signaling).await;
+    assert!(!state.rooms[&2].user_ids.contains(&-1000));
+    assert!(signaling.published_ops().is_empty());
+
+    handler::join_room_as_guest(join(1), &mut state, &signaling).await;
+    assert!(state.rooms[&1].user_ids.contains(&-1000));
+    assert_eq!(state.active_users[&-1000].current_room_id, 1);
+    assert_eq!(signaling.published_ops(), vec!["join-as-new-peer"]);
+
+    let mut permissions = HashMap::new();
+    handler::add_guest_listeners(&state, &1, &mut permissions);
+    // user 5 isn't a guest, their row comes from the database
+    assert!(state.rooms[&1].user_ids.contains(&5));
+    assert_eq!(permissions.len(), 1);
+    let guest = &permissions[&-1000];
+    assert!(!guest.is_speaker && !guest.is_mod && !guest.asked_to_speak);
+}
@@ -1,63 +1,156 @@
 // #![deny(warnings)]
 use crate::auth::authentication_handler::CodeParams;
 use crate::auth::oauth_locations;
-use crate::auth::ws_auth_handler::UserIdAndNewAuthCredentials;
-use crate::auth::{authentication_handler, ws_auth_handler};
-use crate::communication::router;
-use crate::communication::types::{AuthCredentials, AuthResponse, BasicResponse};
+use crate::auth::ws_auth_handler::{UserIdAndNewAuthCredentials, WsAuthFailure};
+use crate::auth::{authentication_handler, origins, ws_auth_handler};
+use crate::communication::content_filter::{self, FilterMode};
+use crate::communication::types::{
+    AuthCredentials, AuthResponse, BasicResponse, SessionResumed, UserConnectionState,
+};
+use crate::communication::{self, data_capturer, router};
+use crate::data_store::migrations;
+use crate::data_store::pool::{self, ExecutionHandlerPool};
+#[cfg(feature = "metrics")]
+use crate::data_store::query_metrics;
 use crate::data_store::sql_execution_handler::ExecutionHandler;
-use crate::rabbitmq::rabbit;
+use crate::rabbitmq::codec;
+use crate::rabbitmq::config::RabbitConfig;
+use crate::rabbitmq::rabbit::{self, PublishChannel};
 use crate::state::state::ServerState;
-use crate::state::types::User;
-use crate::warp::http::Uri;
-use crate::{logging, rooms};
+#[cfg(feature = "metrics")]
+use crate::state::stats;
+use crate::state::types::{ConnectionId, PeerSender, User};
+use crate::state::{
+    active_speakers, activity, draining, idempotency, idle_rooms, room_limits, session,
+    speaker_requests,
+};
+use crate::warp::http::{StatusCode, Uri};
+use crate::ws_fan::fan;
+use crate::{logging, rooms, vs_response, ws_fan};
 use futures::lock::Mutex;
 use futures_util::stream::SplitStream;
-use futures_util::{stream::SplitSink, SinkExt, StreamExt, TryFutureExt};
-use lapin::Connection;
+use futures_util::{stream::SplitSink, SinkExt, StreamExt};
 use std::env;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::{mpsc, RwLock};
-use tokio::time::{sleep, Duration};
+use std::time::Instant;
+use tokio::sync::{mpsc, Notify, RwLock};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Duration};
 use tokio_postgres::{Error, NoTls};
-use tokio_stream::wrappers::UnboundedReceiverStream;
 use warp::ws::{Message, WebSocket};
-use warp::Filter;
+use warp::{Filter, Reply};
 
-pub async fn start_server<T: Into<SocketAddr>>(addr: T, tls_or_not: String) {
-    println!("tls OR NOT -> {}", tls_or_not);
+/// How long a new connection has to
+/// send its credentials.
+const AUTH_TIMEOUT_SECONDS: u64 = 10;
+
+/// Application close code sent with the "session_replaced"
+/// reason when the same user logs in somewhere else.
+const SESSION_REPLACED_CLOSE_CODE: u16 = 4000;
+
+/// Where the certificate chain and private key (both pem)
+/// live when the server terminates tls itself.
+pub struct TlsPaths {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
+impl TlsPaths {
+    /// None unless both MERLIN_TLS_CERT_PATH and
+    /// MERLIN_TLS_KEY_PATH are set, plain tcp is used then.
+    pub fn from_env() -> Option<Self> {
+        match (
+            env::var("MERLIN_TLS_CERT_PATH"),
+            env::var("MERLIN_TLS_KEY_PATH"),
+        ) {
+            (Ok(cert_path), Ok(key_path)) if !cert_path.is_empty() && !key_path.is_empty() => {
+                Some(Self {
+                    cert_path,
+                    key_path,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+pub async fn start_server<T: Into<SocketAddr>>(
+    addr: T,
+    tls: Option<TlsPaths>,
+    health_addr: Option<SocketAddr>,
+) {
+    // serving plain tcp when tls was asked for would be worse than not starting
+    #[cfg(not(feature = "tls"))]
+    if tls.is_some() {
+        panic!("tls paths are set but merlin was built without the tls feature");
+    }
+    // a bad setting is caught here instead of as
+    // messages that silently never arrive
+    let rabbit_config = RabbitConfig::from_env()
+        .unwrap_or_else(|e| panic!("invalid RabbitMQ configuration: {}", e));
+    codec::set_voice_codec(rabbit_config.voice_codec);
+    let content_filter = content_filter::content_filter();
+    if content_filter.mode != FilterMode::Off {
+        logging::console::log_success(&format!(
+            "content filter on({:?}) with {} words",
+            content_filter.mode,
+            content_filter.num_words()
+        ));
+    }
     //these should never panic, if they do then the server is
     //100% in fault and can't run anyway.
     let server_state: Arc<RwLock<ServerState>> = Arc::new(RwLock::new(ServerState::new()));
-    let execution_handler: Arc<Mutex<ExecutionHandler>> =
-        Arc::new(Mutex::new(setup_execution_handler().await.unwrap()));
-    let rabbit_connection: Connection = rabbit::setup_rabbit_connection().await.unwrap();
-    let voice_publish_channel: Arc<Mutex<lapin::Channel>> = Arc::new(Mutex::new(
-        rabbit::setup_voice_publish_channel(&rabbit_connection)
-            .await
-            .unwrap(),
-    ));
-    let integration_publish_channel: Arc<Mutex<lapin::Channel>> = Arc::new(Mutex::new(
-        rabbit::setup_integration_publish_channel(&rabbit_connection)
-            .await
-            .unwrap(),
-    ));
+    let execution_handler: Arc<ExecutionHandlerPool> =
+        Arc::new(setup_execution_handler_pool().await.unwrap());
+    let (rabbit_connection, voice_publish_channel, integration_publish_channel) =
+        rabbit::setup_rabbit_with_backoff(&rabbit_config, &server_state).await;
+    let voice_publish_channel: Arc<Mutex<PublishChannel>> =
+        Arc::new(Mutex::new(voice_publish_channel));
+    let integration_publish_channel: Arc<Mutex<PublishChannel>> =
+        Arc::new(Mutex::new(integration_publish_channel));
     setup_room_cleanup_task(
         server_state.clone(),
         voice_publish_channel.clone(),
         execution_handler.clone(),
     );
+    setup_idle_room_task(
+        server_state.clone(),
+        voice_publish_channel.clone(),
+        integration_publish_channel.clone(),
+        execution_handler.clone(),
+    );
     setup_room_queue_cleanup_task(server_state.clone());
-    rabbit::setup_integration_consume_task(&rabbit_connection, server_state.clone())
-        .await
-        .unwrap();
-    rabbit::setup_voice_consume_task(&rabbit_connection, server_state.clone())
-        .await
-        .unwrap();
+    setup_drain_deadline_task(server_state.clone());
+    setup_active_speaker_flush_task(server_state.clone());
+    #[cfg(unix)]
+    setup_drain_signal_task(server_state.clone());
+    setup_last_online_flush_task(execution_handler.clone());
+    setup_session_expiry_task(
+        server_state.clone(),
+        voice_publish_channel.clone(),
+        integration_publish_channel.clone(),
+        execution_handler.clone(),
+    );
+    rabbit::setup_rabbit_supervisor_task(
+        rabbit_config,
+        rabbit_connection,
+        voice_publish_channel.clone(),
+        integration_publish_channel.clone(),
+        server_state.clone(),
+    );
+    if let Some(health_addr) = health_addr {
+        setup_health_listener(
+            health_addr,
+            server_state.clone(),
+            execution_handler.clone(),
+            voice_publish_channel.clone(),
+            integration_publish_channel.clone(),
+        );
+    }
     setup_routes_and_serve(
         addr,
+        tls,
         server_state,
         execution_handler,
         voice_publish_channel,
@@ -69,50 +162,157 @@ pub async fn start_server<T: Into<SocketAddr>>(addr: T, tls_or_not: String) {
 async fn user_connected(
     ws: WebSocket,
     server_state: Arc<RwLock<ServerState>>,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
-    voice_publish_channel: Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: Arc<Mutex<lapin::Channel>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
+    voice_publish_channel: Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Arc<Mutex<PublishChannel>>,
 ) {
     // Split the socket into a sender and receive of messages.
     let (mut user_ws_tx, mut user_ws_rx) = ws.split();
+    if draining::is_draining(&*server_state.read().await) {
+        close_draining_connection(user_ws_tx).await;
+        return;
+    }
 
     //authenticate and ensure auth passed
     let auth_result = handle_authentication(&mut user_ws_rx, &execution_handler).await;
-    let user_id_option = match auth_result {
+    let (user_id_and_tokens, previous_resume_token) = match auth_result {
         Ok(auth_result) => auth_result,
-        Err(_e) => {
-            send_auth_response(&mut user_ws_tx, None, None, "auth-not-good".to_owned()).await;
+        Err(WsAuthFailure::Banned(banned_reason)) => {
+            close_banned_connection(user_ws_tx, banned_reason).await;
             return;
         }
-    };
-    let user_id_and_tokens = match user_id_option {
-        Some(user_id_option) => user_id_option,
-        None => {
-            send_auth_response(&mut user_ws_tx, None, None, "auth-not-good".to_owned()).await;
+        Err(failure) => {
+            reject_connection(user_ws_tx, failure.op_code()).await;
             return;
         }
     };
+    let current_user_id = user_id_and_tokens.user_id;
+    if user_id_and_tokens.guest {
+        guest_connected(
+            user_ws_tx,
+            user_ws_rx,
+            current_user_id,
+            server_state,
+            execution_handler,
+            voice_publish_channel,
+            integration_publish_channel,
+        )
+        .await;
+        return;
+    }
+    let (tx, rx, too_slow) = PeerSender::new();
+    let connection_tx = tx.clone();
+    let connection_id = ConnectionId::next();
+    let mut write_state = server_state.write().await;
+    // set when this socket joins a user who is already
+    // connected elsewhere, their presence is left as is.
+    let mut joined_room_id = None;
+    if write_state.peer_map.contains_user(&current_user_id) {
+        match session::DuplicateLoginPolicy::from_env() {
+            session::DuplicateLoginPolicy::RejectNew => {
+                drop(write_state);
+                reject_connection(user_ws_tx, "already_connected".to_owned()).await;
+                return;
+            }
+            session::DuplicateLoginPolicy::ReplaceOld => {
+                replace_old_connection(
+                    &mut write_state,
+                    &current_user_id,
+                    connection_id,
+                    &connection_tx,
+                    &voice_publish_channel,
+                    &integration_publish_channel,
+                    &execution_handler,
+                )
+                .await;
+            }
+            session::DuplicateLoginPolicy::AllowMultiple => {
+                write_state
+                    .peer_map
+                    .insert(connection_id, current_user_id, tx.clone());
+                joined_room_id = write_state
+                    .active_users
+                    .get(&current_user_id)
+                    .map(|user| user.current_room_id);
+            }
+        }
+    }
+    let resume_token = match joined_room_id {
+        Some(_) => session::join_session(&mut write_state, current_user_id),
+        None => session::start_session(&mut write_state, current_user_id),
+    };
+    drop(write_state);
     send_auth_response(
         &mut user_ws_tx,
         user_id_and_tokens.access,
         user_id_and_tokens.refresh,
+        Some(resume_token),
+        None,
         "auth-good".to_owned(),
     )
     .await;
 
-    logging::console::log_event(&format!(
-        "New user({}) connection!",
-        user_id_and_tokens.user_id
-    ));
+    logging::console::log_event(&format!("New user({}) connection!", current_user_id));
 
-    //Make use of a mpsc channel for each user.
-    let current_user_id = user_id_and_tokens.user_id;
-    let (tx, rx) = mpsc::unbounded_channel();
-    let rx = UnboundedReceiverStream::new(rx);
-    setup_outgoing_messages_task(user_ws_tx, rx);
-    insert_new_peer(server_state.clone(), tx, current_user_id.clone()).await;
+    //Make use of a bounded mpsc channel for each user.
+    let mut writer = setup_outgoing_messages_task(user_ws_tx, rx, too_slow, current_user_id);
+    let resumed_room_id = match (joined_room_id, previous_resume_token) {
+        // the new device catches up on the room the
+        // user's other connections are in
+        (Some(room_id), _) => Some(room_id).filter(|room_id| *room_id != -1),
+        (None, Some(previous_resume_token)) => session::resume_session(
+            &mut *server_state.write().await,
+            current_user_id,
+            &previous_resume_token,
+            connection_id,
+            tx.clone(),
+        ),
+        (None, None) => None,
+    };
+    match resumed_room_id {
+        Some(room_id) => {
+            send_session_resumed(&connection_tx, room_id);
+            let mut write_state = server_state.write().await;
+            // recording may have started while they were away
+            rooms::handler::send_recording_status_if_recording(
+                &mut write_state,
+                &room_id,
+                &current_user_id,
+            );
+            announce_connection_state(&mut write_state, &current_user_id).await;
+            drop(write_state);
+            logging::console::log_event(&format!(
+                "User({}) resumed their session in room({})",
+                current_user_id, room_id
+            ));
+        }
+        None => {
+            // A session we didn't resume is over,
+            // the user should leave that room before
+            // starting fresh.
+            end_disconnected_session(
+                &current_user_id,
+                &server_state,
+                &voice_publish_channel,
+                &integration_publish_channel,
+                &execution_handler,
+            )
+            .await;
+            if joined_room_id.is_none() {
+                insert_new_peer(
+                    server_state.clone(),
+                    connection_id,
+                    tx,
+                    current_user_id,
+                    false,
+                )
+                .await;
+            }
+        }
+    }
     block_and_handle_incoming_messages(
         &mut user_ws_rx,
+        &mut writer,
         &current_user_id,
         &server_state,
         &execution_handler,
@@ -122,6 +322,7 @@ async fn user_connected(
     .await;
     user_disconnected(
         &current_user_id,
+        connection_id,
         &server_state,
         &voice_publish_channel,
         &&integration_publish_channel,
@@ -130,13 +331,153 @@ async fn user_connected(
     .await;
 }
 
+/// Guests have no other connections or sessions to
+/// worry about, they get a peer and a user until the
+/// socket closes.
+async fn guest_connected(
+    mut user_ws_tx: SplitSink<WebSocket, Message>,
+    mut user_ws_rx: SplitStream<WebSocket>,
+    guest_id: i32,
+    server_state: Arc<RwLock<ServerState>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
+    voice_publish_channel: Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Arc<Mutex<PublishChannel>>,
+) {
+    let (tx, rx, too_slow) = PeerSender::new();
+    let connection_id = ConnectionId::next();
+    insert_new_peer(server_state.clone(), connection_id, tx, guest_id, true).await;
+    send_auth_response(
+        &mut user_ws_tx,
+        None,
+        None,
+        None,
+        Some(guest_id),
+        "auth-good".to_owned(),
+    )
+    .await;
+    logging::console::log_event(&format!("New guest({}) connection!", guest_id));
+    let mut writer = setup_outgoing_messages_task(user_ws_tx, rx, too_slow, guest_id);
+    block_and_handle_incoming_messages(
+        &mut user_ws_rx,
+        &mut writer,
+        &guest_id,
+        &server_state,
+        &execution_handler,
+        &voice_publish_channel,
+        &integration_publish_channel,
+    )
+    .await;
+    user_disconnected(
+        &guest_id,
+        connection_id,
+        &server_state,
+        &voice_publish_channel,
+        &integration_publish_channel,
+        &execution_handler,
+    )
+    .await;
+}
+
+/// The user logged in again while their old socket is still
+/// live. The old socket leaves its room and is told why it is
+/// being closed, the new connection takes its place in the
+/// peer map right away so the old disconnect cleanup skips it.
+async fn replace_old_connection(
+    write_state: &mut ServerState,
+    user_id: &i32,
+    connection_id: ConnectionId,
+    new_tx: &PeerSender,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let current_room_id = match write_state.active_users.get(user_id) {
+        Some(user) => user.current_room_id,
+        None => -1,
+    };
+    if current_room_id != -1 {
+        rooms::handler::leave_room(
+            write_state,
+            user_id,
+            &current_room_id,
+            voice_publish_channel,
+            integration_publish_channel,
+            execution_handler,
+        )
+        .await;
+    }
+    let response = BasicResponse {
+        response_op_code: "session_replaced".to_owned(),
+        response_containing_data: String::new(),
+    };
+    for old_tx in write_state.peer_map.remove_user(user_id) {
+        old_tx
+            .send(Message::text(serde_json::to_string(&response).unwrap()))
+            .and_then(|_| {
+                old_tx.send(Message::close_with(
+                    SESSION_REPLACED_CLOSE_CODE,
+                    "session_replaced",
+                ))
+            })
+            .unwrap_or_else(|e| eprintln!("issue closing replaced socket:{}", e));
+    }
+    write_state
+        .peer_map
+        .insert(connection_id, *user_id, new_tx.clone());
+    write_state.active_users.remove(user_id);
+    write_state.session_tokens.remove(user_id);
+    logging::console::log_event(&format!(
+        "User({}) logged in again, replacing their old connection",
+        user_id
+    ));
+}
+
+/// Nothing is routed for a connection until it authenticates,
+/// anything else gets the socket closed.
+async fn reject_connection(mut user_ws_tx: SplitSink<WebSocket, Message>, op: String) {
+    send_auth_response(&mut user_ws_tx, None, None, None, None, op).await;
+    user_ws_tx
+        .close()
+        .await
+        .unwrap_or_else(|e| eprintln!("issue closing unauthenticated socket:{}", e));
+}
+
+async fn close_banned_connection(
+    mut user_ws_tx: SplitSink<WebSocket, Message>,
+    banned_reason: String,
+) {
+    user_ws_tx
+        .send(Message::text(
+            communication::helpers::construct_banned_response(banned_reason),
+        ))
+        .await
+        .unwrap_or_else(|e| eprint!("{}", e));
+    user_ws_tx
+        .send(Message::close_with(
+            rooms::handler::BANNED_CLOSE_CODE,
+            "banned",
+        ))
+        .await
+        .unwrap_or_else(|e| eprintln!("issue closing banned socket:{}", e));
+}
+
+async fn close_draining_connection(mut user_ws_tx: SplitSink<WebSocket, Message>) {
+    user_ws_tx
+        .send(Message::close_with(
+            draining::DRAINING_CLOSE_CODE,
+            "draining",
+        ))
+        .await
+        .unwrap_or_else(|e| eprintln!("issue closing socket while draining:{}", e));
+}
+
 async fn user_message(
     current_user_id: &i32,
     msg: Message,
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    voice_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
 ) {
     // Skip any non-Text messages...
     let msg = if let Ok(s) = msg.to_str() {
@@ -144,6 +485,7 @@ async fn user_message(
     } else {
         return;
     };
+    activity::record_activity(current_user_id);
     router::route_msg(
         msg.to_string(),
         current_user_id.clone(),
@@ -158,28 +500,44 @@ async fn user_message(
 
 async fn user_disconnected(
     current_user_id: &i32,
+    connection_id: ConnectionId,
     server_state: &Arc<RwLock<ServerState>>,
-    voice_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
 ) {
     let mut write_state = server_state.write().await;
 
+    //a newer connection(or another device) for this user
+    //is still open, so there is nothing left for us to clean up.
+    write_state.peer_map.remove_connection(connection_id);
+    if write_state.peer_map.contains_user(current_user_id) {
+        return;
+    }
+    //guests are removed entirely, they can't come back
+    let guest = write_state.is_guest(*current_user_id);
+    if !guest {
+        write_final_last_online(current_user_id, execution_handler).await;
+    }
+
+    //users in a room get a chance to come back
+    if !guest && session::park_session(&mut write_state, current_user_id) {
+        announce_connection_state(&mut write_state, current_user_id).await;
+        logging::console::log_event(&format!(
+            "User({}) dropped, holding their session for {} seconds",
+            current_user_id,
+            session::session_resume_grace().as_secs()
+        ));
+        return;
+    }
+
     //if this user is in a room handle cleanup on the
     //voice server side.
-    if write_state
-        .active_users
-        .get(current_user_id)
-        .unwrap()
-        .current_room_id
-        != -1
-    {
-        let current_room_id = write_state
-            .active_users
-            .get(current_user_id)
-            .unwrap()
-            .current_room_id
-            .clone();
+    let current_room_id = match write_state.active_users.get(current_user_id) {
+        Some(user) => user.current_room_id,
+        None => -1,
+    };
+    if current_room_id != -1 {
         rooms::handler::leave_room(
             &mut write_state,
             current_user_id,
@@ -190,83 +548,195 @@ async fn user_disconnected(
         )
         .await;
     }
-    write_state.peer_map.remove(current_user_id);
     write_state.active_users.remove(current_user_id);
+    write_state.session_tokens.remove(current_user_id);
     logging::console::log_event(&format!(
         "User({}) disconnected from the server",
         current_user_id
     ));
 }
 
+/// The user is going offline, so their last online
+/// time is written now instead of waiting on the flush.
+async fn write_final_last_online(user_id: &i32, execution_handler: &Arc<ExecutionHandlerPool>) {
+    let last_online = activity::take_final_last_online(user_id);
+    let mut handler = execution_handler.lock().await;
+    let result =
+        data_capturer::capture_last_online_batch(vec![(*user_id, last_online)], &mut handler).await;
+    if result.encountered_error {
+        logging::console::log_failure(&result.desc);
+    }
+}
+
+/// Fully removes a user whose session
+/// can no longer be resumed.
+async fn end_disconnected_session(
+    current_user_id: &i32,
+    server_state: &Arc<RwLock<ServerState>>,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let mut write_state = server_state.write().await;
+    let session = match write_state.disconnected_sessions.remove(current_user_id) {
+        Some(session) => session,
+        None => return,
+    };
+    cleanup_expired_session(
+        &mut write_state,
+        current_user_id,
+        &session.room_id,
+        voice_publish_channel,
+        integration_publish_channel,
+        execution_handler,
+    )
+    .await;
+}
+
+async fn cleanup_expired_session(
+    write_state: &mut ServerState,
+    user_id: &i32,
+    room_id: &i32,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) {
+    let still_in_room = match write_state.rooms.get(room_id) {
+        Some(room) => room.user_ids.contains(user_id),
+        None => false,
+    };
+    if still_in_room {
+        rooms::handler::leave_room(
+            write_state,
+            user_id,
+            room_id,
+            voice_publish_channel,
+            integration_publish_channel,
+            execution_handler,
+        )
+        .await;
+    }
+    write_state.peer_map.remove_user(user_id);
+    write_state.active_users.remove(user_id);
+    write_state.session_tokens.remove(user_id);
+    logging::console::log_event(&format!(
+        "User({}) session in room({}) expired",
+        user_id, room_id
+    ));
+}
+
+/// Lets the rest of the user's room know whether
+/// they're connected or waiting to resume.
+async fn announce_connection_state(write_state: &mut ServerState, user_id: &i32) {
+    let room_id = write_state.current_room_id(*user_id);
+    if room_id == -1 {
+        return;
+    }
+    let response = BasicResponse {
+        response_op_code: "user_connection_state".to_owned(),
+        response_containing_data: serde_json::to_string(&UserConnectionState {
+            user_id: *user_id,
+            connection_state: session::connection_state(write_state, user_id),
+        })
+        .unwrap(),
+    };
+    ws_fan::fan::broadcast_message_to_room_excluding_user(
+        serde_json::to_string(&response).unwrap(),
+        write_state,
+        room_id,
+        *user_id,
+    )
+    .await;
+}
+
+fn send_session_resumed(tx: &PeerSender, room_id: i32) {
+    let response = BasicResponse {
+        response_op_code: "session_resumed".to_owned(),
+        response_containing_data: serde_json::to_string(&SessionResumed { room_id }).unwrap(),
+    };
+    tx.send(Message::text(serde_json::to_string(&response).unwrap()))
+        .unwrap_or_else(|e| eprintln!("issue sending msg:{}", e));
+}
+
+// Along with the user, gives back the resume
+// token the user presented, if any.
 async fn handle_authentication(
     user_ws_rx: &mut SplitStream<WebSocket>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-) -> Result<Option<UserIdAndNewAuthCredentials>, serde_json::Error> {
-    let msg = user_ws_rx.next().await;
-    let msg_result = match msg {
-        Some(msg) => msg,
-        None => return Ok(None),
-    };
-    let msg_value_result = match msg_result {
-        Ok(msg_result) => msg_result,
-        Err(_e) => return Ok(None),
+    execution_handler: &Arc<ExecutionHandlerPool>,
+) -> Result<(UserIdAndNewAuthCredentials, Option<String>), WsAuthFailure> {
+    //the very first frame has to be the credentials
+    let msg = match timeout(Duration::from_secs(AUTH_TIMEOUT_SECONDS), user_ws_rx.next()).await {
+        Ok(Some(Ok(msg))) => msg,
+        _ => return Err(WsAuthFailure::Invalid),
     };
-    let msg_value_to_str = msg_value_result.to_str();
-    let auth_credentials: AuthCredentials = match msg_value_to_str {
-        Ok(msg_value_to_str) => serde_json::from_str(msg_value_to_str)?,
-        Err(_e) => return Ok(None),
+    let auth_credentials: AuthCredentials = match msg.to_str() {
+        Ok(msg_value_to_str) => {
+            serde_json::from_str(msg_value_to_str).map_err(|_| WsAuthFailure::Invalid)?
+        }
+        Err(_e) => return Err(WsAuthFailure::Invalid),
     };
-    if auth_credentials.oauth_type == "discord" {
-        let res: Option<UserIdAndNewAuthCredentials> =
-            ws_auth_handler::gather_user_id_using_discord_id(
-                auth_credentials.refresh,
-                auth_credentials.access,
-                execution_handler,
-            )
-            .await;
-        return Ok(res);
-    } else {
-        let res: Option<UserIdAndNewAuthCredentials> =
-            ws_auth_handler::gather_user_id_using_github_id(
-                auth_credentials.access,
-                execution_handler,
-            )
-            .await;
-        return Ok(res);
+    let resume_token = auth_credentials.resume_token;
+    if auth_credentials.oauth_type == "guest" {
+        return ws_auth_handler::gather_guest_id().map(|res| (res, None));
     }
+    let res = if auth_credentials.oauth_type == "discord" {
+        ws_auth_handler::gather_user_id_using_discord_id(
+            auth_credentials.refresh,
+            auth_credentials.access,
+            execution_handler,
+        )
+        .await
+    } else {
+        ws_auth_handler::gather_user_id_using_github_id(
+            auth_credentials.refresh,
+            auth_credentials.access,
+            execution_handler,
+        )
+        .await
+    };
+    res.map(|res| (res, resume_token))
 }
 
 // Sets up a task for grabbing messages
 // from each user's channel and sending it
 // to the user via websocket. Each user
 // has a channel that we use to communicate
-// over tasks.
+// over tasks. The task ends once the socket
+// is dead or the peer is too slow, the read
+// half watches for that to disconnect them.
 fn setup_outgoing_messages_task(
-    mut user_ws_tx: SplitSink<WebSocket, Message>,
-    mut rx: UnboundedReceiverStream<Message>,
-) {
+    user_ws_tx: SplitSink<WebSocket, Message>,
+    rx: mpsc::Receiver<Message>,
+    too_slow: Arc<Notify>,
+    user_id: i32,
+) -> JoinHandle<()> {
     tokio::task::spawn(async move {
-        while let Some(message) = rx.next().await {
-            user_ws_tx
-                .send(message)
-                .unwrap_or_else(|e| {
-                    eprintln!("websocket send error: {}", e);
-                })
-                .await;
+        let exit = fan::drain_to_socket(user_ws_tx, rx, too_slow, fan::SOCKET_WRITE_TIMEOUT).await;
+        if exit == fan::WriterExit::TooSlow {
+            logging::console::log_failure(&format!("User({}) is too slow, disconnecting", user_id));
         }
-    });
+    })
 }
 
 //whenever we get a message from the user via websocket
 async fn block_and_handle_incoming_messages(
     user_ws_rx: &mut SplitStream<WebSocket>,
+    writer: &mut JoinHandle<()>,
     current_user_id: &i32,
     server_state: &Arc<RwLock<ServerState>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-    voice_publish_channel: &Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: &Arc<Mutex<lapin::Channel>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
 ) {
-    while let Some(result) = user_ws_rx.next().await {
+    loop {
+        let result = tokio::select! {
+            result = user_ws_rx.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            // nothing reaches them anymore, so they're done
+            _ = &mut *writer => break,
+        };
         let msg = match result {
             Ok(msg) => msg,
             Err(e) => {
@@ -292,26 +762,53 @@ async fn block_and_handle_incoming_messages(
 //this is how we send messages to our users.
 async fn insert_new_peer(
     server_state: Arc<RwLock<ServerState>>,
-    tx: UnboundedSender<Message>,
+    connection_id: ConnectionId,
+    tx: PeerSender,
     current_user_id: i32,
+    guest: bool,
 ) {
-    server_state
-        .write()
-        .await
+    // Use one guard so no broadcast can see the
+    // peer without the active user or vice versa.
+    let mut write_state = server_state.write().await;
+    write_state
         .peer_map
-        .insert(current_user_id, tx);
-    server_state.write().await.active_users.insert(
+        .insert(connection_id, current_user_id, tx);
+    write_state.active_users.insert(
         current_user_id,
         User {
             ip: "-1".to_owned(),
             current_room_id: -1,
             muted: false,
             deaf: false,
+            guest,
         },
     );
 }
 
+/// Opens PG_POOL_SIZE connections, the
+/// first one makes sure the tables exist.
+pub async fn setup_execution_handler_pool() -> Result<ExecutionHandlerPool, Error> {
+    let mut handlers = vec![setup_execution_handler().await?];
+    for _ in 1..pool::pool_size_from_env() {
+        handlers.push(connect_execution_handler().await?);
+    }
+    Ok(ExecutionHandlerPool::new(handlers))
+}
+
 pub async fn setup_execution_handler() -> Result<ExecutionHandler, Error> {
+    let mut handler = connect_execution_handler().await?;
+    //the server can't run against a schema it doesn't expect
+    match migrations::run_migrations(&mut handler).await {
+        Ok(applied) if !applied.is_empty() => {
+            logging::console::log_success(&format!("applied migrations {:?}", applied))
+        }
+        Ok(_) => {}
+        Err(e) => panic!("{}", e),
+    }
+    return Ok(handler);
+}
+
+async fn connect_execution_handler() -> Result<ExecutionHandler, Error> {
     //"host=localhost user=postgres port=5432 password=password"
     let host = env::var("PG_HOST").unwrap();
     let user = env::var("PG_USER").unwrap();
@@ -325,34 +822,29 @@ pub async fn setup_execution_handler() -> Result<ExecutionHandler, Error> {
     let (client, connection) = tokio_postgres::connect(&config, NoTls).await?;
     //TODO: handle connection error
     tokio::spawn(async move { if let Err(_e) = connection.await {} });
-    let mut handler = ExecutionHandler::new(client);
-    handler.create_all_tables_if_needed().await?;
-    return Ok(handler);
+    return Ok(ExecutionHandler::new(client));
 }
 
-/// Make sure the rooms are being cleaned up
-/// in the case that someone creates a room but
-/// don't join the room within 10 seconds
+/// Destroys rooms that stayed empty for the grace
+/// period(EMPTY_ROOM_GRACE_SECONDS), whether everyone
+/// left or the creator never joined.
 fn setup_room_cleanup_task(
     state: Arc<RwLock<ServerState>>,
-    publish_channel: Arc<Mutex<lapin::Channel>>,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
+    publish_channel: Arc<Mutex<PublishChannel>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
 ) {
+    let grace = rooms::handler::empty_room_grace();
+    let sweep_interval = grace
+        .min(Duration::from_secs(10))
+        .max(Duration::from_secs(1));
     tokio::spawn(async move {
         loop {
-            let mut to_delete: Vec<i32> = Vec::new();
-            sleep(Duration::from_millis(10000)).await;
+            sleep(sweep_interval).await;
             let mut write_state = state.write().await;
-            for id in write_state.rooms.keys() {
-                if let Some(room) = write_state.rooms.get(&id) {
-                    if room.amount_of_users == 0 {
-                        to_delete.push(id.clone());
-                    }
-                }
-            }
-            cleanup_rooms(
-                to_delete,
+            rooms::handler::destroy_expired_empty_rooms(
                 &mut write_state,
+                grace,
+                Instant::now(),
                 &publish_channel,
                 &execution_handler,
             )
@@ -361,6 +853,163 @@ fn setup_room_cleanup_task(
     });
 }
 
+/// Rooms nobody joined or spoke in for ROOM_IDLE_TIMEOUT_SECONDS
+/// are warned, then destroyed if they're still idle once the
+/// warning(ROOM_IDLE_WARNING_SECONDS) runs out. Off without a timeout.
+fn setup_idle_room_task(
+    state: Arc<RwLock<ServerState>>,
+    voice_publish_channel: Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Arc<Mutex<PublishChannel>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
+) {
+    let timeout = match idle_rooms::idle_timeout() {
+        Some(timeout) => timeout,
+        None => return,
+    };
+    let warning = idle_rooms::idle_warning();
+    let sweep_interval = timeout
+        .min(warning)
+        .min(Duration::from_secs(10))
+        .max(Duration::from_secs(1));
+    tokio::spawn(async move {
+        loop {
+            sleep(sweep_interval).await;
+            let mut write_state = state.write().await;
+            let sweep =
+                idle_rooms::sweep_idle_rooms(&mut write_state, timeout, warning, Instant::now());
+            for room_id in sweep.to_warn {
+                rooms::handler::warn_idle_room(&mut write_state, room_id, warning).await;
+            }
+            for room_id in sweep.to_destroy {
+                rooms::handler::destroy_idle_room(
+                    &mut write_state,
+                    room_id,
+                    &voice_publish_channel,
+                    &integration_publish_channel,
+                    &execution_handler,
+                )
+                .await;
+            }
+        }
+    });
+}
+
+/// Users that dropped while in a room and
+/// never came back are removed from their room.
+fn setup_session_expiry_task(
+    state: Arc<RwLock<ServerState>>,
+    voice_publish_channel: Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Arc<Mutex<PublishChannel>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
+) {
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_millis(10000)).await;
+            let mut write_state = state.write().await;
+            let expired =
+                session::gather_expired_sessions(&mut write_state, session::session_resume_grace());
+            for (user_id, room_id) in expired {
+                cleanup_expired_session(
+                    &mut write_state,
+                    &user_id,
+                    &room_id,
+                    &voice_publish_channel,
+                    &integration_publish_channel,
+                    &execution_handler,
+                )
+                .await;
+            }
+        }
+    });
+}
+
+/// Last online times are written in batches
+/// instead of on every request.
+fn setup_last_online_flush_task(execution_handler: Arc<ExecutionHandlerPool>) {
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_secs(activity::LAST_ONLINE_FLUSH_SECONDS)).await;
+            let updates = activity::take_unsaved_last_online();
+            if updates.is_empty() {
+                continue;
+            }
+            let mut handler = execution_handler.lock().await;
+            let result = data_capturer::capture_last_online_batch(updates, &mut handler).await;
+            if result.encountered_error {
+                logging::console::log_failure(&result.desc);
+            }
+        }
+    });
+}
+
+/// Connections still open once a drain's deadline passes
+/// are closed, their disconnect cleanup runs as usual.
+fn setup_drain_deadline_task(state: Arc<RwLock<ServerState>>) {
+    tokio::spawn(async move {
+        loop {
+            sleep(Duration::from_millis(5000)).await;
+            let read_state = state.read().await;
+            if !draining::drain_deadline_passed(&read_state) || read_state.peer_map.is_empty() {
+                continue;
+            }
+            logging::console::log_event(&format!(
+                "Drain deadline passed, closing {} connections",
+                read_state.peer_map.len()
+            ));
+            for tx in read_state.peer_map.values() {
+                tx.send(Message::close_with(
+                    draining::DRAINING_CLOSE_CODE,
+                    "draining",
+                ))
+                .unwrap_or_default();
+            }
+        }
+    });
+}
+
+/// SIGUSR1 starts a drain with the configured
+/// deadline, for deploy scripts without an admin
+/// account.
+#[cfg(unix)]
+fn setup_drain_signal_task(state: Arc<RwLock<ServerState>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut signals = signal(SignalKind::user_defined1()).unwrap();
+    tokio::spawn(async move {
+        while signals.recv().await.is_some() {
+            let mut write_state = state.write().await;
+            if draining::begin_drain(&mut write_state, draining::drain_deadline()) {
+                logging::console::log_event(&format!(
+                    "SIGUSR1 started draining, {} connections left",
+                    write_state.peer_map.len()
+                ));
+            }
+        }
+    });
+}
+
+/// Speaking toggles held back by the debounce go
+/// out once they settle.
+fn setup_active_speaker_flush_task(state: Arc<RwLock<ServerState>>) {
+    tokio::spawn(async move {
+        let debounce = Duration::from_millis(active_speakers::ACTIVE_SPEAKER_DEBOUNCE_MS);
+        loop {
+            sleep(debounce).await;
+            let mut write_state = state.write().await;
+            for (room_id, user_id, speaking) in
+                active_speakers::take_settled(&mut write_state, debounce)
+            {
+                vs_response::handler::broadcast_active_speaker(
+                    &mut write_state,
+                    room_id,
+                    user_id,
+                    speaking,
+                )
+                .await;
+            }
+        }
+    });
+}
+
 /// Make sure the queues are always cleared of
 /// users that are no longer in this room.'
 /// This helps reserve storage on the server.
@@ -370,30 +1019,19 @@ fn setup_room_queue_cleanup_task(state: Arc<RwLock<ServerState>>) {
             sleep(Duration::from_millis(10000)).await;
             let mut write_state = state.write().await;
             cleanup_owner_queues(&mut write_state);
+            idempotency::evict_expired_room_creation_keys(
+                &mut write_state,
+                Duration::from_secs(idempotency::ROOM_CREATION_KEY_SECONDS),
+            );
+            room_limits::evict_old_room_creations(&mut write_state);
+            speaker_requests::evict_expired_denials(
+                &mut write_state,
+                Duration::from_secs(speaker_requests::DENIED_HAND_COOLDOWN_SECONDS),
+            );
         }
     });
 }
 
-async fn cleanup_rooms(
-    mut to_delete: Vec<i32>,
-    write_state: &mut ServerState,
-    publish_channel: &Arc<Mutex<lapin::Channel>>,
-    execution_handler: &Arc<Mutex<ExecutionHandler>>,
-) {
-    while to_delete.len() > 0 {
-        let room_to_delete = to_delete.pop().unwrap();
-        write_state.rooms.remove(&room_to_delete);
-        rooms::handler::destroy_room(
-            write_state,
-            publish_channel,
-            execution_handler,
-            &room_to_delete,
-        )
-        .await;
-        logging::console::log_event(&format!("Removed idle room:{}", room_to_delete));
-    }
-}
-
 fn cleanup_owner_queues(write_state: &mut ServerState) {
     let mut to_delete = Vec::new();
     for queue in write_state.owner_queues.values_mut() {
@@ -412,6 +1050,8 @@ async fn send_auth_response(
     user_ws_tx: &mut SplitSink<WebSocket, Message>,
     access: Option<String>,
     refresh: Option<String>,
+    resume_token: Option<String>,
+    guest_id: Option<i32>,
     op: String,
 ) {
     user_ws_tx
@@ -421,6 +1061,8 @@ async fn send_auth_response(
                 response_containing_data: serde_json::to_string(&AuthResponse {
                     new_access: access,
                     new_refresh: refresh,
+                    resume_token,
+                    guest_id,
                 })
                 .unwrap(),
             })
@@ -430,31 +1072,108 @@ async fn send_auth_response(
         .unwrap_or_else(|e| eprint!("{}", e));
 }
 
+/// GET /livez only says the process is up.
+/// GET /readyz is a 200 once Postgres answers, both
+/// RabbitMQ channels are open and we aren't draining,
+/// otherwise a 503.
+/// GET /drainz reports how many connections a drain
+/// is waiting on.
+fn setup_health_listener(
+    addr: SocketAddr,
+    server_state: Arc<RwLock<ServerState>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
+    voice_publish_channel: Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Arc<Mutex<PublishChannel>>,
+) {
+    let livez_route = warp::path("livez").map(|| "live");
+    let drain_state = server_state.clone();
+    let readyz_route = warp::path("readyz").then(move || {
+        let server_state = drain_state.clone();
+        let execution_handler = execution_handler.clone();
+        let voice_publish_channel = voice_publish_channel.clone();
+        let integration_publish_channel = integration_publish_channel.clone();
+        async move {
+            if server_is_ready(
+                &server_state,
+                &execution_handler,
+                &voice_publish_channel,
+                &integration_publish_channel,
+            )
+            .await
+            {
+                warp::reply::with_status("ready", StatusCode::OK)
+            } else {
+                warp::reply::with_status("not ready", StatusCode::SERVICE_UNAVAILABLE)
+            }
+        }
+    });
+    let drainz_route = warp::path("drainz").then(move || {
+        let server_state = server_state.clone();
+        async move { warp::reply::json(&draining::drain_status(&*server_state.read().await)) }
+    });
+    let routes = warp::get().and(livez_route.or(readyz_route).or(drainz_route));
+    logging::console::log_event(&format!("Health checks listening on {}", addr));
+    tokio::task::spawn(warp::serve(routes).run(addr));
+}
+
+async fn server_is_ready(
+    server_state: &Arc<RwLock<ServerState>>,
+    execution_handler: &Arc<ExecutionHandlerPool>,
+    voice_publish_channel: &Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: &Arc<Mutex<PublishChannel>>,
+) -> bool {
+    let database_ready = execution_handler.lock().await.ping().await.is_ok();
+    let voice_ready = voice_publish_channel.lock().await.is_connected();
+    let integration_ready = integration_publish_channel.lock().await.is_connected();
+    let accepting = !draining::is_draining(&*server_state.read().await);
+    database_ready && voice_ready && integration_ready && accepting
+}
+
 async fn setup_routes_and_serve<T: Into<SocketAddr>>(
     addr: T,
+    tls: Option<TlsPaths>,
     server_state: Arc<RwLock<ServerState>>,
-    execution_handler: Arc<Mutex<ExecutionHandler>>,
-    voice_publish_channel: Arc<Mutex<lapin::Channel>>,
-    integration_publish_channel: Arc<Mutex<lapin::Channel>>,
+    execution_handler: Arc<ExecutionHandlerPool>,
+    voice_publish_channel: Arc<Mutex<PublishChannel>>,
+    integration_publish_channel: Arc<Mutex<PublishChannel>>,
 ) {
     let server_state = warp::any().map(move || server_state.clone());
     let execution_handler = warp::any().map(move || execution_handler.clone());
     let voice_publish_channel = warp::any().map(move || voice_publish_channel.clone());
     let integration_publish_channel = warp::any().map(move || integration_publish_channel.clone());
     //GET /user-api
+    let max_request_bytes = router::max_request_bytes();
+    let allowed_origins = origins::allowed_origins();
     let user_api_route = warp::path("user-api")
         // The `ws()` filter will prepare  Websocket handshake...
         .and(warp::ws())
+        .and(warp::header::optional::<String>("origin"))
         .and(server_state.clone())
         .and(execution_handler.clone())
         .and(voice_publish_channel.clone())
         .and(integration_publish_channel)
         .map(
-            |ws: warp::ws::Ws,
-             server_state: Arc<RwLock<ServerState>>,
-             execution_handler: Arc<Mutex<ExecutionHandler>>,
-             voice_publish_channel: Arc<Mutex<lapin::Channel>>,
-             integration_publish_channel: Arc<Mutex<lapin::Channel>>| {
+            move |ws: warp::ws::Ws,
+                  origin: Option<String>,
+                  server_state: Arc<RwLock<ServerState>>,
+                  execution_handler: Arc<ExecutionHandlerPool>,
+                  voice_publish_channel: Arc<Mutex<PublishChannel>>,
+                  integration_publish_channel: Arc<Mutex<PublishChannel>>| {
+                // refused before upgrading, so pages we don't
+                // trust can't ride along on a user's cookies
+                if !origins::origin_is_allowed(&allowed_origins, origin.as_deref()) {
+                    logging::console::log_failure(&format!(
+                        "refused websocket from origin {}",
+                        origin.unwrap_or_default()
+                    ));
+                    return warp::reply::with_status("origin not allowed", StatusCode::FORBIDDEN)
+                        .into_response();
+                }
+                // tungstenite errors out on anything bigger,
+                // which drops the connection before we buffer it
+                let ws = ws
+                    .max_message_size(max_request_bytes)
+                    .max_frame_size(max_request_bytes);
                 // This will call our function if the handshake succeeds.
                 ws.on_upgrade(move |socket| {
                     user_connected(
@@ -465,6 +1184,7 @@ async fn setup_routes_and_serve<T: Into<SocketAddr>>(
                         integration_publish_channel,
                     )
                 })
+                .into_response()
             },
         );
 
@@ -478,7 +1198,7 @@ async fn setup_routes_and_serve<T: Into<SocketAddr>>(
         .and(execution_handler.clone())
         .and(warp::query::<CodeParams>())
         .then(
-            |execution_handler: Arc<Mutex<ExecutionHandler>>, code: CodeParams| async {
+            |execution_handler: Arc<ExecutionHandlerPool>, code: CodeParams| async {
                 let token_url_result =
                     authentication_handler::gather_tokens_and_construct_save_url_discord(
                         code.code,
@@ -505,7 +1225,7 @@ async fn setup_routes_and_serve<T: Into<SocketAddr>>(
         .and(execution_handler.clone())
         .and(warp::query::<CodeParams>())
         .then(
-            |execution_handler: Arc<Mutex<ExecutionHandler>>, code: CodeParams| async {
+            |execution_handler: Arc<ExecutionHandlerPool>, code: CodeParams| async {
                 let token_url_result =
                     authentication_handler::gather_tokens_and_construct_save_url_github(
                         code.code,
@@ -528,6 +1248,31 @@ async fn setup_routes_and_serve<T: Into<SocketAddr>>(
             .or(github_auth_route)
             .or(github_auth_callback_route),
     );
-    logging::console::log_event("Server now listening for connections...");
-    warp::serve(routes).run(addr).await;
+
+    //GET /metrics
+    #[cfg(feature = "metrics")]
+    let routes = routes.or(warp::get()
+        .and(warp::path("metrics"))
+        .and(server_state.clone())
+        .then(|server_state: Arc<RwLock<ServerState>>| async move {
+            let stats = stats::gather_server_stats(&*server_state.read().await);
+            let queries = query_metrics::query_timings().lock().unwrap().snapshot();
+            stats::to_prometheus_text(&stats) + &query_metrics::to_prometheus_text(&queries)
+        }));
+    match tls {
+        #[cfg(feature = "tls")]
+        Some(tls) => {
+            logging::console::log_event("Server now listening for tls connections...");
+            warp::serve(routes)
+                .tls()
+                .cert_path(tls.cert_path)
+                .key_path(tls.key_path)
+                .run(addr)
+                .await;
+        }
+        _ => {
+            logging::console::log_event("Server now listening for connections...");
+            warp::serve(routes).run(addr).await;
+        }
+    }
 }
@@ -0,0 +1,78 @@
+use std::time::{Duration, Instant};
+
+use super::state::ServerState;
+
+/// A toggle this soon after the last one the room heard
+/// waits, only the latest state goes out once it settles.
+pub const ACTIVE_SPEAKER_DEBOUNCE_MS: u64 = 300;
+
+pub struct SpeakingState {
+    /// what the room was last told
+    pub broadcast: bool,
+    pub broadcast_at: Instant,
+    /// newest state the voice server reported inside the debounce
+    pub pending: Option<bool>,
+}
+
+/// Some(speaking) when the room should hear about it now.
+pub fn record_speaking(
+    state: &mut ServerState,
+    room_id: i32,
+    user_id: i32,
+    speaking: bool,
+    debounce: Duration,
+) -> Option<bool> {
+    let current = match state.active_speakers.get_mut(&(room_id, user_id)) {
+        Some(current) => current,
+        None if speaking => {
+            state.active_speakers.insert(
+                (room_id, user_id),
+                SpeakingState {
+                    broadcast: true,
+                    broadcast_at: Instant::now(),
+                    pending: None,
+                },
+            );
+            return Some(true);
+        }
+        None => return None,
+    };
+    if current.broadcast == speaking {
+        current.pending = None;
+        None
+    } else if current.broadcast_at.elapsed() >= debounce {
+        current.broadcast = speaking;
+        current.broadcast_at = Instant::now();
+        current.pending = None;
+        Some(speaking)
+    } else {
+        current.pending = Some(speaking);
+        None
+    }
+}
+
+/// Pending changes that waited out the debounce as (room id,
+/// user id, speaking). Users who left the room are forgotten.
+pub fn take_settled(state: &mut ServerState, debounce: Duration) -> Vec<(i32, i32, bool)> {
+    let mut settled = Vec::new();
+    let rooms = &state.rooms;
+    state.active_speakers.retain(|(room_id, user_id), current| {
+        let in_room = match rooms.get(room_id) {
+            Some(room) => room.user_ids.contains(user_id),
+            None => false,
+        };
+        if !in_room {
+            return false;
+        }
+        if let Some(speaking) = current.pending {
+            if current.broadcast_at.elapsed() >= debounce {
+                current.broadcast = speaking;
+                current.broadcast_at = Instant::now();
+                current.pending = None;
+                settled.push((*room_id, *user_id, speaking));
+            }
+        }
+        current.broadcast || current.pending.is_some()
+    });
+    settled
+}
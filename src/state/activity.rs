@@ -0,0 +1,43 @@
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use chrono::Utc;
+
+use crate::auth::guests;
+
+/// How often last online times are written to the database.
+pub const LAST_ONLINE_FLUSH_SECONDS: u64 = 30;
+
+/// Kept out of the server state, every message a user sends
+/// lands here and shouldn't have to wait on the state lock.
+/// User id -> last online not written to the database yet.
+fn unsaved_last_online() -> &'static Mutex<HashMap<i32, String>> {
+    static UNSAVED_LAST_ONLINE: OnceLock<Mutex<HashMap<i32, String>>> = OnceLock::new();
+    UNSAVED_LAST_ONLINE.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// Marks the user as seen now, the database only
+/// hears about it on the next flush.
+pub fn record_activity(user_id: &i32) {
+    // guests have no row to write it to
+    if guests::is_guest_id(*user_id) {
+        return;
+    }
+    unsaved_last_online()
+        .lock()
+        .unwrap()
+        .insert(*user_id, Utc::now().to_string());
+}
+
+/// Removes and returns every (user id, last online)
+/// that hasn't been written to the database yet.
+pub fn take_unsaved_last_online() -> Vec<(i32, String)> {
+    unsaved_last_online().lock().unwrap().drain().collect()
+}
+
+/// For a user going offline, their last online is written
+/// right away so the next flush doesn't need to.
+pub fn take_final_last_online(user_id: &i32) -> String {
+    unsaved_last_online().lock().unwrap().remove(user_id);
+    Utc::now().to_string()
+}
@@ -0,0 +1,21 @@
+use std::time::{Duration, Instant};
+
+use super::state::ServerState;
+
+/// How long an admin has to wait between announcements.
+pub const ANNOUNCEMENT_COOLDOWN_SECONDS: u64 = 30;
+
+/// Longest announcement an admin can send.
+pub const MAX_ANNOUNCEMENT_LENGTH: usize = 500;
+
+/// Records the announcement and returns true, unless the
+/// admin already sent one within the cooldown.
+pub fn try_start_announcement(state: &mut ServerState, admin_id: i32) -> bool {
+    if let Some(sent_at) = state.last_announcements.get(&admin_id) {
+        if sent_at.elapsed() < Duration::from_secs(ANNOUNCEMENT_COOLDOWN_SECONDS) {
+            return false;
+        }
+    }
+    state.last_announcements.insert(admin_id, Instant::now());
+    true
+}
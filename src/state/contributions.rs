@@ -0,0 +1,41 @@
+use chrono::{NaiveDate, Utc};
+
+use super::state::ServerState;
+
+/// Contributions are what a user gives back to the platform,
+/// each one is worth a single point:
+/// - Creating a room, someone has to host.
+/// - Being brought up to speak by someone else, mods adding
+///   themselves don't count.
+///
+/// Both are cheap to repeat, so a user can only earn
+/// this many points a day no matter what they do.
+pub const MAX_CONTRIBUTIONS_PER_DAY: u32 = 5;
+
+#[derive(Debug, PartialEq)]
+pub enum Contribution {
+    CreatedRoom,
+    BroughtUpToSpeak,
+}
+
+/// Counts the contribution against today's cap,
+/// false means it shouldn't be written.
+pub fn claim_contribution(state: &mut ServerState, user_id: &i32) -> bool {
+    claim_contribution_on(state, user_id, Utc::today().naive_utc())
+}
+
+pub fn claim_contribution_on(state: &mut ServerState, user_id: &i32, today: NaiveDate) -> bool {
+    let claimed = state
+        .contributions_today
+        .entry(*user_id)
+        .or_insert((today, 0));
+    // a new day starts the count over
+    if claimed.0 != today {
+        *claimed = (today, 0);
+    }
+    if claimed.1 >= MAX_CONTRIBUTIONS_PER_DAY {
+        return false;
+    }
+    claimed.1 += 1;
+    true
+}
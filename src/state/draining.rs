@@ -0,0 +1,68 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+use crate::communication::types::DrainStatus;
+
+use super::state::ServerState;
+
+/// How long existing connections get once a drain starts,
+/// unless DRAIN_DEADLINE_SECONDS says otherwise.
+pub const DEFAULT_DRAIN_DEADLINE_SECONDS: u64 = 600;
+
+/// Application close code sent with the "draining"
+/// reason while the server stops taking connections.
+pub const DRAINING_CLOSE_CODE: u16 = 4004;
+
+pub struct Drain {
+    pub started_at: Instant,
+    pub deadline: Duration,
+}
+
+pub fn drain_deadline() -> Duration {
+    let seconds = env::var("DRAIN_DEADLINE_SECONDS")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_DRAIN_DEADLINE_SECONDS);
+    Duration::from_secs(seconds)
+}
+
+/// Returns false when a drain already started,
+/// the first deadline is the one that holds.
+pub fn begin_drain(state: &mut ServerState, deadline: Duration) -> bool {
+    if state.draining.is_some() {
+        return false;
+    }
+    state.draining = Some(Drain {
+        started_at: Instant::now(),
+        deadline,
+    });
+    true
+}
+
+pub fn is_draining(state: &ServerState) -> bool {
+    state.draining.is_some()
+}
+
+/// True once the connections left over from a
+/// drain should be closed.
+pub fn drain_deadline_passed(state: &ServerState) -> bool {
+    match &state.draining {
+        Some(drain) => drain.started_at.elapsed() >= drain.deadline,
+        None => false,
+    }
+}
+
+/// What a deploy script polls to know when
+/// the process is safe to kill.
+pub fn drain_status(state: &ServerState) -> DrainStatus {
+    DrainStatus {
+        draining: state.draining.is_some(),
+        connections: state.peer_map.len(),
+        seconds_left: state.draining.as_ref().map(|drain| {
+            drain
+                .deadline
+                .saturating_sub(drain.started_at.elapsed())
+                .as_secs()
+        }),
+    }
+}
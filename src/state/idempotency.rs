@@ -0,0 +1,41 @@
+use std::time::{Duration, Instant};
+
+use super::state::ServerState;
+
+/// How long a room creation key is remembered, long enough
+/// to cover a client retrying after a dropped connection.
+pub const ROOM_CREATION_KEY_SECONDS: u64 = 300;
+
+/// Longest key a client can send.
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+
+pub fn record_room_creation(state: &mut ServerState, user_id: i32, key: String, room_id: i32) {
+    state
+        .room_creation_keys
+        .insert((user_id, key), (room_id, Instant::now()));
+}
+
+/// For a creation that was rolled back, a retry
+/// with the same key should try again.
+pub fn forget_room_creation(state: &mut ServerState, user_id: i32, key: &str) {
+    state.room_creation_keys.remove(&(user_id, key.to_owned()));
+}
+
+/// The room this user already created with the key, keys
+/// are per user so one user can't guess another's.
+pub fn find_created_room(state: &ServerState, user_id: i32, key: &str) -> Option<i32> {
+    match state.room_creation_keys.get(&(user_id, key.to_owned())) {
+        Some((room_id, created_at))
+            if created_at.elapsed() < Duration::from_secs(ROOM_CREATION_KEY_SECONDS) =>
+        {
+            Some(*room_id)
+        }
+        _ => None,
+    }
+}
+
+pub fn evict_expired_room_creation_keys(state: &mut ServerState, ttl: Duration) {
+    state
+        .room_creation_keys
+        .retain(|_, (_, created_at)| created_at.elapsed() < ttl);
+}
@@ -0,0 +1,79 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+use super::state::ServerState;
+
+/// How long before an idle room is destroyed its users are
+/// warned, unless ROOM_IDLE_WARNING_SECONDS says otherwise.
+pub const DEFAULT_IDLE_WARNING: Duration = Duration::from_secs(60);
+
+/// None unless ROOM_IDLE_TIMEOUT_SECONDS is set to a
+/// positive number of seconds, idle rooms are kept then.
+pub fn idle_timeout() -> Option<Duration> {
+    idle_timeout_from(env::var("ROOM_IDLE_TIMEOUT_SECONDS").ok().as_deref())
+}
+
+pub fn idle_timeout_from(value: Option<&str>) -> Option<Duration> {
+    match value?.trim().parse::<u64>() {
+        Ok(0) | Err(_) => None,
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+    }
+}
+
+pub fn idle_warning() -> Duration {
+    match env::var("ROOM_IDLE_WARNING_SECONDS") {
+        Ok(value) => value
+            .parse()
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_IDLE_WARNING),
+        Err(_) => DEFAULT_IDLE_WARNING,
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct IdleSweep {
+    /// rooms that just went idle, their users should be warned
+    pub to_warn: Vec<i32>,
+    /// rooms still idle once their warning ran out
+    pub to_destroy: Vec<i32>,
+}
+
+/// Rooms nobody joined or spoke in for the timeout are warned
+/// and marked, marked rooms are listed for destruction once the
+/// warning period passes without activity. Empty rooms are
+/// left to the empty room cleanup.
+pub fn sweep_idle_rooms(
+    state: &mut ServerState,
+    timeout: Duration,
+    warning: Duration,
+    now: Instant,
+) -> IdleSweep {
+    let mut sweep = IdleSweep::default();
+    for room in state.rooms.values_mut() {
+        if room.amount_of_users == 0 {
+            continue;
+        }
+        match room.idle_warned_at {
+            Some(warned_at) => {
+                if now.saturating_duration_since(warned_at) >= warning {
+                    sweep.to_destroy.push(room.room_id);
+                }
+            }
+            None => {
+                if now.saturating_duration_since(room.last_activity) >= timeout {
+                    room.idle_warned_at = Some(now);
+                    sweep.to_warn.push(room.room_id);
+                }
+            }
+        }
+    }
+    sweep
+}
+
+/// Joins and speaking keep a room alive,
+/// and take back any warning it was given.
+pub fn record_room_activity(state: &mut ServerState, room_id: &i32) {
+    if let Some(room) = state.rooms.get_mut(room_id) {
+        room.record_activity();
+    }
+}
@@ -0,0 +1,91 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+use super::state::ServerState;
+
+/// Live rooms one user can own at the same time.
+pub const MAX_OWNED_ROOMS: usize = 3;
+
+/// Rooms one user can create within an hour, whether
+/// or not those rooms are still around.
+pub const MAX_ROOM_CREATIONS_PER_HOUR: usize = 10;
+
+const ROOM_CREATION_WINDOW_SECONDS: u64 = 60 * 60;
+
+pub fn owned_room_count(state: &ServerState, user_id: i32) -> usize {
+    state
+        .rooms
+        .values()
+        .filter(|room| room.owner_id == user_id)
+        .count()
+}
+
+fn recent_creations(state: &ServerState, user_id: i32) -> usize {
+    let window = Duration::from_secs(ROOM_CREATION_WINDOW_SECONDS);
+    match state.room_creation_times.get(&user_id) {
+        Some(created_at) => created_at
+            .iter()
+            .filter(|created_at| created_at.elapsed() < window)
+            .count(),
+        None => 0,
+    }
+}
+
+/// Some(reason) when the user can't create another room yet.
+pub fn room_creation_problem(state: &ServerState, user_id: i32) -> Option<String> {
+    if owned_room_count(state, user_id) >= MAX_OWNED_ROOMS {
+        Some(format!(
+            "you can only own {} live rooms at once",
+            MAX_OWNED_ROOMS
+        ))
+    } else if recent_creations(state, user_id) >= MAX_ROOM_CREATIONS_PER_HOUR {
+        Some(format!(
+            "you can only create {} rooms an hour",
+            MAX_ROOM_CREATIONS_PER_HOUR
+        ))
+    } else {
+        None
+    }
+}
+
+/// Off unless UNIQUE_ROOM_NAMES is "true".
+pub fn unique_room_names() -> bool {
+    unique_room_names_from(env::var("UNIQUE_ROOM_NAMES").ok().as_deref())
+}
+
+pub fn unique_room_names_from(value: Option<&str>) -> bool {
+    value.is_some_and(|value| value.trim().eq_ignore_ascii_case("true"))
+}
+
+/// Whether one of the user's live rooms already goes by the name,
+/// ignoring case and surrounding whitespace.
+pub fn owns_room_named(state: &ServerState, user_id: i32, name: &str) -> bool {
+    let name = name.trim();
+    state
+        .rooms
+        .values()
+        .filter(|room| room.owner_id == user_id)
+        .any(|room| room.name.trim().eq_ignore_ascii_case(name))
+}
+
+pub fn record_created_room(state: &mut ServerState, user_id: i32) {
+    let window = Duration::from_secs(ROOM_CREATION_WINDOW_SECONDS);
+    let created_at = state.room_creation_times.entry(user_id).or_default();
+    while created_at
+        .front()
+        .is_some_and(|oldest| oldest.elapsed() >= window)
+    {
+        created_at.pop_front();
+    }
+    created_at.push_back(Instant::now());
+}
+
+/// Drops creations older than an hour, and
+/// users left with none.
+pub fn evict_old_room_creations(state: &mut ServerState) {
+    let window = Duration::from_secs(ROOM_CREATION_WINDOW_SECONDS);
+    state.room_creation_times.retain(|_, created_at| {
+        created_at.retain(|created_at| created_at.elapsed() < window);
+        !created_at.is_empty()
+    });
+}
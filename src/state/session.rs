@@ -0,0 +1,159 @@
+use std::env;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::state::ServerState;
+use super::types::{ConnectionId, DisconnectedSession, PeerSender};
+
+/// How long a user has to reconnect before they are fully
+/// removed from their room, unless SESSION_RESUME_SECONDS
+/// says otherwise.
+pub const DEFAULT_SESSION_RESUME_SECONDS: u64 = 60;
+
+pub fn session_resume_grace() -> Duration {
+    let seconds = match env::var("SESSION_RESUME_SECONDS") {
+        Ok(value) => value.parse().unwrap_or(DEFAULT_SESSION_RESUME_SECONDS),
+        Err(_) => DEFAULT_SESSION_RESUME_SECONDS,
+    };
+    Duration::from_secs(seconds)
+}
+
+/// Whether a user in a room has a live connection. Users that
+/// dropped stay in the room as reconnecting until they resume
+/// or their session expires.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectionState {
+    #[default]
+    Connected,
+    Reconnecting,
+}
+
+pub fn connection_state(state: &ServerState, user_id: &i32) -> ConnectionState {
+    if state.disconnected_sessions.contains_key(user_id) {
+        ConnectionState::Reconnecting
+    } else {
+        ConnectionState::Connected
+    }
+}
+
+/// What happens when a user who is still connected
+/// authenticates again on another socket.
+#[derive(Debug, PartialEq)]
+pub enum DuplicateLoginPolicy {
+    /// The new socket is turned away with "already_connected".
+    RejectNew,
+    /// The old socket gets "session_replaced" and is closed.
+    ReplaceOld,
+    /// Both sockets stay open and share the user's
+    /// presence, messages to the user reach each one.
+    AllowMultiple,
+}
+
+impl DuplicateLoginPolicy {
+    /// Read from DUPLICATE_LOGIN_POLICY, anything other
+    /// than "reject" or "multiple" keeps the newest socket.
+    pub fn from_env() -> Self {
+        Self::parse(env::var("DUPLICATE_LOGIN_POLICY").ok().as_deref())
+    }
+
+    pub fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("reject") => DuplicateLoginPolicy::RejectNew,
+            Some("multiple") => DuplicateLoginPolicy::AllowMultiple,
+            _ => DuplicateLoginPolicy::ReplaceOld,
+        }
+    }
+}
+
+/// Gives the user a new token they can use
+/// to resume their session if they drop.
+pub fn start_session(state: &mut ServerState, user_id: i32) -> String {
+    let resume_token = Uuid::new_v4().to_string();
+    state.session_tokens.insert(user_id, resume_token.clone());
+    resume_token
+}
+
+/// Another device joining a user who is already connected
+/// shares their session instead of starting a new one, so
+/// the first device's resume token keeps working.
+pub fn join_session(state: &mut ServerState, user_id: i32) -> String {
+    match state.session_tokens.get(&user_id) {
+        Some(resume_token) => resume_token.clone(),
+        None => start_session(state, user_id),
+    }
+}
+
+/// Instead of removing a user who was in a room right away,
+/// we hold on to their room membership so they can resume.
+///
+/// Returns false if there is nothing worth resuming,
+/// meaning the user should be cleaned up normally.
+pub fn park_session(state: &mut ServerState, user_id: &i32) -> bool {
+    let room_id = match state.active_users.get(user_id) {
+        Some(user) => user.current_room_id,
+        None => return false,
+    };
+    if room_id == -1 {
+        return false;
+    }
+    if let Some(resume_token) = state.session_tokens.remove(user_id) {
+        state.peer_map.remove_user(user_id);
+        state.disconnected_sessions.insert(
+            *user_id,
+            DisconnectedSession {
+                resume_token,
+                room_id,
+                disconnected_at: Instant::now(),
+            },
+        );
+        return true;
+    }
+    false
+}
+
+/// Re-associates a new connection with the user's
+/// previous room if the token matches and the session
+/// hasn't expired.
+///
+/// Returns the room the user was resumed into.
+pub fn resume_session(
+    state: &mut ServerState,
+    user_id: i32,
+    resume_token: &str,
+    connection_id: ConnectionId,
+    tx: PeerSender,
+) -> Option<i32> {
+    let session = state.disconnected_sessions.get(&user_id)?;
+    if session.resume_token != resume_token
+        || session.disconnected_at.elapsed() > session_resume_grace()
+    {
+        return None;
+    }
+    let room_id = session.room_id;
+    // The room could have been destroyed while
+    // this user was away.
+    if !state.rooms.contains_key(&room_id) || !state.active_users.contains_key(&user_id) {
+        return None;
+    }
+    state.disconnected_sessions.remove(&user_id);
+    state.peer_map.insert(connection_id, user_id, tx);
+    Some(room_id)
+}
+
+/// Removes and returns all (user id, room id) sessions
+/// that can no longer be resumed.
+pub fn gather_expired_sessions(state: &mut ServerState, max_age: Duration) -> Vec<(i32, i32)> {
+    let expired: Vec<(i32, i32)> = state
+        .disconnected_sessions
+        .iter()
+        .filter(|(_, session)| session.disconnected_at.elapsed() > max_age)
+        .map(|(user_id, session)| (*user_id, session.room_id))
+        .collect();
+    for (user_id, _) in expired.iter() {
+        state.disconnected_sessions.remove(user_id);
+    }
+    expired
+}
@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+use super::state::ServerState;
+
+/// Denials a user can get in one room before
+/// they have to wait to raise their hand again.
+pub const MAX_DENIALS_BEFORE_COOLDOWN: u32 = 3;
+
+/// How long the wait is, denials older than
+/// this no longer count.
+pub const DENIED_HAND_COOLDOWN_SECONDS: u64 = 120;
+
+/// Returns how many recent denials the user has in the room.
+pub fn record_denial(state: &mut ServerState, room_id: i32, user_id: i32) -> u32 {
+    let cooldown = Duration::from_secs(DENIED_HAND_COOLDOWN_SECONDS);
+    let denial = state
+        .speaker_request_denials
+        .entry((room_id, user_id))
+        .or_insert((0, Instant::now()));
+    if denial.1.elapsed() >= cooldown {
+        denial.0 = 0;
+    }
+    denial.0 += 1;
+    denial.1 = Instant::now();
+    denial.0
+}
+
+/// An approved request wipes the slate.
+pub fn clear_denials(state: &mut ServerState, room_id: i32, user_id: i32) {
+    state.speaker_request_denials.remove(&(room_id, user_id));
+}
+
+/// Some(time left) while the user has been denied too
+/// often to raise their hand in this room.
+pub fn raise_hand_cooldown(state: &ServerState, room_id: i32, user_id: i32) -> Option<Duration> {
+    let (denials, last_denied_at) = state.speaker_request_denials.get(&(room_id, user_id))?;
+    if *denials < MAX_DENIALS_BEFORE_COOLDOWN {
+        return None;
+    }
+    let left =
+        Duration::from_secs(DENIED_HAND_COOLDOWN_SECONDS).checked_sub(last_denied_at.elapsed())?;
+    Some(left)
+}
+
+pub fn evict_expired_denials(state: &mut ServerState, ttl: Duration) {
+    state
+        .speaker_request_denials
+        .retain(|_, (_, last_denied_at)| last_denied_at.elapsed() < ttl);
+}
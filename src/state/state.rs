@@ -1,8 +1,17 @@
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Instant;
 
-use crate::state::types::{ActiveRooms, ActiveUsers, PeerMap};
+use chrono::NaiveDate;
 
+use crate::state::types::{
+    ActiveRooms, ActiveUsers, DisconnectedSessions, PeerMap, Room, SessionTokens,
+};
+
+use super::active_speakers::SpeakingState;
+use super::draining::Drain;
 use super::owner_queue::OwnerQueue;
+use super::voice_servers::VoiceServers;
 
 pub struct ServerState {
     pub peer_map: PeerMap,
@@ -12,6 +21,28 @@ pub struct ServerState {
     /// maps external iot server ids
     /// to their local rooms
     pub external_servers: HashMap<String, i32>,
+    pub session_tokens: SessionTokens,
+    /// users who dropped while in a room and
+    /// can still resume where they left off
+    pub disconnected_sessions: DisconnectedSessions,
+    /// used to report uptime
+    pub started_at: Instant,
+    /// user id -> (day, contributions earned that day)
+    pub contributions_today: HashMap<i32, (NaiveDate, u32)>,
+    /// (user id, idempotency key) -> (room id, created at)
+    pub room_creation_keys: HashMap<(i32, String), (i32, Instant)>,
+    /// which voice server each new room goes to
+    pub voice_servers: VoiceServers,
+    /// admin id -> when they last sent an announcement
+    pub last_announcements: HashMap<i32, Instant>,
+    /// (room id, user id) -> (recent denials, last denied at)
+    pub speaker_request_denials: HashMap<(i32, i32), (u32, Instant)>,
+    /// (room id, user id) -> who the room thinks is talking
+    pub active_speakers: HashMap<(i32, i32), SpeakingState>,
+    /// user id -> when they created rooms in the last hour
+    pub room_creation_times: HashMap<i32, VecDeque<Instant>>,
+    /// set once the server stops taking new connections
+    pub draining: Option<Drain>,
 }
 
 //Holds all server memory state
@@ -23,6 +54,115 @@ impl ServerState {
             rooms: ActiveRooms::new(),
             owner_queues: HashMap::new(),
             external_servers: HashMap::new(),
+            session_tokens: SessionTokens::new(),
+            disconnected_sessions: DisconnectedSessions::new(),
+            started_at: Instant::now(),
+            contributions_today: HashMap::new(),
+            room_creation_keys: HashMap::new(),
+            voice_servers: VoiceServers::from_env(),
+            last_announcements: HashMap::new(),
+            speaker_request_denials: HashMap::new(),
+            active_speakers: HashMap::new(),
+            room_creation_times: HashMap::new(),
+            draining: None,
+        }
+    }
+
+    pub fn get_room(&self, room_id: i32) -> Option<&Room> {
+        self.rooms.get(&room_id)
+    }
+
+    /// False when the room doesn't exist.
+    pub fn user_in_room(&self, user_id: i32, room_id: i32) -> bool {
+        match self.rooms.get(&room_id) {
+            Some(room) => room.user_ids.contains(&user_id),
+            None => false,
+        }
+    }
+
+    /// False for signed in users and users that aren't active.
+    pub fn is_guest(&self, user_id: i32) -> bool {
+        self.active_users
+            .get(&user_id)
+            .is_some_and(|user| user.guest)
+    }
+
+    /// False when the room doesn't exist.
+    pub fn room_is_public(&self, room_id: i32) -> bool {
+        match self.rooms.get(&room_id) {
+            Some(room) => room.public,
+            None => false,
+        }
+    }
+
+    /// Every room listing the user, more than one
+    /// means the state went inconsistent.
+    pub fn rooms_containing(&self, user_id: i32) -> Vec<i32> {
+        self.rooms
+            .values()
+            .filter(|room| room.user_ids.contains(&user_id))
+            .map(|room| room.room_id)
+            .collect()
+    }
+
+    /// Users listed in more than one room, should always be empty.
+    pub fn users_in_multiple_rooms(&self) -> Vec<i32> {
+        let mut seen = HashSet::new();
+        let mut repeated: Vec<i32> = Vec::new();
+        for room in self.rooms.values() {
+            for user_id in room.user_ids.iter().copied() {
+                if !seen.insert(user_id) && !repeated.contains(&user_id) {
+                    repeated.push(user_id);
+                }
+            }
+        }
+        repeated
+    }
+
+    /// The pre-checks assume a user is in at most one room, so
+    /// any stale membership elsewhere is dropped before the user
+    /// goes in. Returns the rooms they were dropped from, a room
+    /// this empties is removed by the room cleanup task once
+    /// its grace period is up.
+    pub fn add_user_to_room(&mut self, user_id: i32, room_id: i32) -> Vec<i32> {
+        let mut stale_room_ids = Vec::new();
+        for room in self.rooms.values_mut() {
+            if room.room_id != room_id && room.remove_user(&user_id) {
+                stale_room_ids.push(room.room_id);
+            }
+        }
+        if let Some(room) = self.rooms.get_mut(&room_id) {
+            if room.user_ids.insert(user_id) {
+                room.amount_of_users += 1;
+            }
+            room.empty_since = None;
+            room.record_activity();
+        }
+        if let Some(user) = self.active_users.get_mut(&user_id) {
+            user.current_room_id = room_id;
+        }
+        debug_assert!(self.rooms_containing(user_id).len() <= 1);
+        stale_room_ids
+    }
+
+    /// Room ids come from the room table, so a taken id means the
+    /// state outlived the database. The existing room is never
+    /// replaced, false is returned instead.
+    pub fn insert_new_room(&mut self, room: Room) -> bool {
+        match self.rooms.entry(room.room_id) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(room);
+                true
+            }
+        }
+    }
+
+    /// -1 when the user isn't active or isn't in a room.
+    pub fn current_room_id(&self, user_id: i32) -> i32 {
+        match self.active_users.get(&user_id) {
+            Some(user) => user.current_room_id,
+            None => -1,
         }
     }
 }
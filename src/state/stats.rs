@@ -0,0 +1,61 @@
+use crate::communication::types::ServerStats;
+
+use super::state::ServerState;
+
+/// Counts everything straight out of memory, so this
+/// only needs a read lock and never touches the database.
+pub fn gather_server_stats(state: &ServerState) -> ServerStats {
+    ServerStats {
+        connections: state.peer_map.len(),
+        active_users: state.active_users.len(),
+        active_rooms: state.rooms.len(),
+        users_in_rooms: state.rooms.values().map(|room| room.user_ids.len()).sum(),
+        uptime_seconds: state.started_at.elapsed().as_secs(),
+    }
+}
+
+/// Prometheus text exposition format, served
+/// on GET /metrics for scraping.
+#[cfg(feature = "metrics")]
+pub fn to_prometheus_text(stats: &ServerStats) -> String {
+    let metrics: [(&str, &str, &str, u64); 5] = [
+        (
+            "merlin_connections",
+            "gauge",
+            "Open websocket connections.",
+            stats.connections as u64,
+        ),
+        (
+            "merlin_active_users",
+            "gauge",
+            "Users held in memory.",
+            stats.active_users as u64,
+        ),
+        (
+            "merlin_active_rooms",
+            "gauge",
+            "Live rooms.",
+            stats.active_rooms as u64,
+        ),
+        (
+            "merlin_users_in_rooms",
+            "gauge",
+            "Users across all live rooms.",
+            stats.users_in_rooms as u64,
+        ),
+        (
+            "merlin_uptime_seconds",
+            "counter",
+            "Seconds since the server started.",
+            stats.uptime_seconds,
+        ),
+    ];
+    let mut text = String::new();
+    for (name, kind, help, value) in metrics {
+        text.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n{} {}\n",
+            name, help, name, kind, name, value
+        ));
+    }
+    text
+}
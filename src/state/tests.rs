@@ -1,6 +1,31 @@
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
 
-use super::{owner_queue::OwnerQueue, types::User};
+use chrono::NaiveDate;
+
+use futures_util::{Sink, StreamExt};
+use tokio::sync::RwLock;
+use tokio::time::{timeout, Duration};
+use tokio_stream::wrappers::ReceiverStream;
+use warp::ws::Message;
+
+use super::types::{ConnectionId, PeerSendError, PeerSender, User, PEER_SEND_BUFFER};
+use super::{
+    active_speakers, activity, announcements, contributions, draining, idempotency, idle_rooms,
+    owner_queue::OwnerQueue,
+    room_limits, session, speaker_requests,
+    state::ServerState,
+    stats,
+    voice_servers::{self, NoVoiceServer, VoiceServerStrategy, VoiceServers},
+};
+use crate::auth::guests;
+use crate::common::response_logic;
+use crate::rooms::handler::construct_basic_room_for_state;
+use crate::ws_fan::fan;
 
 pub fn test_owners_queue() {
     let mut mock_queue = OwnerQueue::new(32);
@@ -51,3 +76,811 @@ fn adding_and_removing(queue: &mut OwnerQueue) {
     // while looking for the new valid user
     assert!(queue.user_queue.len() == 0);
 }
+
+/// Many users joining and broadcasting at the same time
+/// should never deadlock or leave the room state inconsistent.
+pub async fn test_concurrent_broadcast_and_join() {
+    let amount_of_users = 50;
+    let state: Arc<RwLock<ServerState>> = Arc::new(RwLock::new(ServerState::new()));
+    state.write().await.rooms.insert(
+        1,
+        construct_basic_room_for_state(1, true, "load".to_owned(), "load".to_owned()),
+    );
+    let mut tasks = Vec::new();
+    let mut receivers = Vec::new();
+    for user_id in 0..amount_of_users {
+        let (tx, rx, _) = PeerSender::new();
+        receivers.push(ReceiverStream::new(rx));
+        let task_state = state.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut write_state = task_state.write().await;
+            write_state
+                .peer_map
+                .insert(ConnectionId::next(), user_id, tx);
+            write_state.active_users.insert(
+                user_id,
+                User {
+                    current_room_id: 1,
+                    ..Default::default()
+                },
+            );
+            let room = write_state.rooms.get_mut(&1).unwrap();
+            room.user_ids.insert(user_id);
+            room.amount_of_users += 1;
+            drop(write_state);
+            // give other tasks a chance to grab the lock
+            tokio::time::sleep(Duration::from_millis(1)).await;
+            let mut write_state = task_state.write().await;
+            fan::broadcast_message_to_room(user_id.to_string(), &mut write_state, 1).await;
+        }));
+    }
+    for task in tasks {
+        timeout(Duration::from_secs(5), task)
+            .await
+            .expect("possible deadlock joining/broadcasting")
+            .unwrap();
+    }
+    let read_state = state.read().await;
+    let room = read_state.rooms.get(&1).unwrap();
+    assert_eq!(room.user_ids.len(), amount_of_users as usize);
+    assert_eq!(room.amount_of_users, amount_of_users);
+    drop(read_state);
+    // Every user was in the room when they broadcasted,
+    // so they should have at least gotten their own message.
+    for (user_id, rx) in receivers.iter_mut().enumerate() {
+        let mut found_own_message = false;
+        while let Ok(Some(message)) = timeout(Duration::from_millis(10), rx.next()).await {
+            if message.to_str().unwrap() == user_id.to_string() {
+                found_own_message = true;
+            }
+        }
+        assert!(found_own_message);
+    }
+}
+
+/// Closed senders are dropped after a room broadcast, the
+/// rest of the cleanup is left to the disconnect path.
+pub async fn test_dead_senders_are_dropped_on_broadcast() {
+    let mut state = ServerState::new();
+    let mut room = construct_basic_room_for_state(1, true, "dead".to_owned(), "dead".to_owned());
+    let (alive_tx, mut alive_rx, _) = PeerSender::new();
+    let (dead_tx, dead_rx, _) = PeerSender::new();
+    // closing the receiving end is what happens
+    // when the outgoing websocket task stops.
+    drop(dead_rx);
+    state.peer_map.insert(ConnectionId::next(), 1, alive_tx);
+    state.peer_map.insert(ConnectionId::next(), 2, dead_tx);
+    // user 3 has no connection at all
+    for user_id in [1, 2, 3] {
+        room.user_ids.insert(user_id);
+        room.amount_of_users += 1;
+        state.active_users.insert(
+            user_id,
+            User {
+                current_room_id: 1,
+                ..Default::default()
+            },
+        );
+    }
+    state.rooms.insert(1, room);
+
+    let delivered = response_logic::broadcast_to_room(&1, "hello".to_owned(), &mut state);
+    assert_eq!(delivered, 1);
+    assert_eq!(alive_rx.recv().await.unwrap().to_str().unwrap(), "hello");
+    assert!(state.peer_map.contains_user(&1));
+    assert!(!state.peer_map.contains_user(&2));
+    // leave_room(or parking the session) still has to run for them
+    let room = state.rooms.get(&1).unwrap();
+    assert_eq!(room.amount_of_users, 3);
+    assert!([1, 2, 3]
+        .iter()
+        .all(|user_id| room.user_ids.contains(user_id)));
+    assert!([1, 2, 3]
+        .iter()
+        .all(|user_id| state.active_users[user_id].current_room_id == 1));
+}
+
+/// A user that drops while in a room can come back
+/// with their token, and only with their token.
+pub fn test_session_resume() {
+    let mut state = ServerState::new();
+    let mut room =
+        construct_basic_room_for_state(1, true, "resume".to_owned(), "resume".to_owned());
+    room.user_ids.insert(5);
+    room.amount_of_users += 1;
+    state.rooms.insert(1, room);
+    let (tx, _rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 5, tx);
+    state.active_users.insert(
+        5,
+        User {
+            current_room_id: 1,
+            ..Default::default()
+        },
+    );
+    let resume_token = session::start_session(&mut state, 5);
+    assert_eq!(
+        session::connection_state(&state, &5),
+        session::ConnectionState::Connected
+    );
+    assert!(session::park_session(&mut state, &5));
+    assert!(!state.peer_map.contains_user(&5));
+    assert!(state.rooms[&1].user_ids.contains(&5));
+    assert_eq!(
+        session::connection_state(&state, &5),
+        session::ConnectionState::Reconnecting
+    );
+
+    // parked users are not pruned by broadcasts
+    assert_eq!(
+        response_logic::broadcast_to_room(&1, "hello".to_owned(), &mut state),
+        0
+    );
+    assert!(state.active_users.contains_key(&5));
+
+    let (new_tx, _new_rx, _) = PeerSender::new();
+    assert_eq!(
+        session::resume_session(&mut state, 5, "wrong", ConnectionId::next(), new_tx.clone()),
+        None
+    );
+    assert_eq!(
+        session::resume_session(&mut state, 5, &resume_token, ConnectionId::next(), new_tx),
+        Some(1)
+    );
+    assert!(state.peer_map.contains_user(&5));
+    assert!(state.disconnected_sessions.is_empty());
+    assert_eq!(
+        session::connection_state(&state, &5),
+        session::ConnectionState::Connected
+    );
+    assert_eq!(
+        session::session_resume_grace(),
+        Duration::from_secs(session::DEFAULT_SESSION_RESUME_SECONDS)
+    );
+
+    // users outside of a room have nothing to resume
+    state.active_users.get_mut(&5).unwrap().current_room_id = -1;
+    session::start_session(&mut state, 5);
+    assert!(!session::park_session(&mut state, &5));
+
+    // expired sessions are handed back for cleanup
+    state.active_users.get_mut(&5).unwrap().current_room_id = 1;
+    assert!(session::park_session(&mut state, &5));
+    let expired = session::gather_expired_sessions(&mut state, Duration::from_secs(0));
+    assert_eq!(expired, vec![(5, 1)]);
+    assert!(state.disconnected_sessions.is_empty());
+
+    // a second login replaces the first unless configured otherwise
+    assert_eq!(
+        session::DuplicateLoginPolicy::parse(Some("reject")),
+        session::DuplicateLoginPolicy::RejectNew
+    );
+    assert_eq!(
+        session::DuplicateLoginPolicy::parse(None),
+        session::DuplicateLoginPolicy::ReplaceOld
+    );
+    assert_eq!(
+        session::DuplicateLoginPolicy::parse(Some("multiple")),
+        session::DuplicateLoginPolicy::AllowMultiple
+    );
+}
+
+/// A user's connections are tracked separately, messages
+/// reach each of them and dropping one leaves the rest.
+pub async fn test_peer_map_connections() {
+    let mut state = ServerState::new();
+    let (phone_tx, mut phone_rx, _) = PeerSender::new();
+    let (laptop_tx, mut laptop_rx, _) = PeerSender::new();
+    let (other_tx, _other_rx, _) = PeerSender::new();
+    let phone = ConnectionId::next();
+    let laptop = ConnectionId::next();
+    state.peer_map.insert(phone, 1, phone_tx);
+    state.peer_map.insert(laptop, 1, laptop_tx);
+    state.peer_map.insert(ConnectionId::next(), 2, other_tx);
+    assert_ne!(phone, laptop);
+    assert_eq!(state.peer_map.len(), 3);
+    assert_eq!(state.peer_map.connection_count(&1), 2);
+
+    assert!(state
+        .peer_map
+        .send_to_user(&1, Message::text("both"))
+        .is_ok());
+    assert_eq!(phone_rx.recv().await.unwrap().to_str().unwrap(), "both");
+    assert_eq!(laptop_rx.recv().await.unwrap().to_str().unwrap(), "both");
+
+    // one closed device doesn't fail the send
+    drop(laptop_rx);
+    assert!(state
+        .peer_map
+        .send_to_user(&1, Message::text("phone"))
+        .is_ok());
+    assert!(state.peer_map.remove_connection(laptop).is_some());
+    assert!(state.peer_map.remove_connection(laptop).is_none());
+    assert!(state.peer_map.contains_user(&1));
+    assert!(state.peer_map.contains_connection(phone));
+
+    drop(phone_rx);
+    assert_eq!(
+        state.peer_map.send_to_user(&1, Message::text("gone")),
+        Err(PeerSendError::Closed)
+    );
+    assert_eq!(
+        state.peer_map.send_to_user(&3, Message::text("nobody")),
+        Err(PeerSendError::Closed)
+    );
+
+    assert_eq!(state.peer_map.remove_user(&1).len(), 1);
+    assert!(!state.peer_map.contains_user(&1));
+    assert!(state.peer_map.senders_for(&1).is_empty());
+    assert_eq!(state.peer_map.len(), 1);
+}
+
+/// Stats are counted straight from memory.
+pub fn test_server_stats() {
+    let mut state = ServerState::new();
+    let mut room = construct_basic_room_for_state(1, true, "stats".to_owned(), "".to_owned());
+    room.user_ids.insert(5);
+    room.user_ids.insert(6);
+    state.rooms.insert(1, room);
+    state.rooms.insert(
+        2,
+        construct_basic_room_for_state(2, false, "empty".to_owned(), "".to_owned()),
+    );
+    let (tx, _rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 5, tx);
+    state.active_users.insert(5, User::default());
+    state.active_users.insert(6, User::default());
+
+    let stats = stats::gather_server_stats(&state);
+    assert_eq!(stats.connections, 1);
+    assert_eq!(stats.active_users, 2);
+    assert_eq!(stats.active_rooms, 2);
+    assert_eq!(stats.users_in_rooms, 2);
+}
+
+/// A peer whose buffer fills up is told to disconnect
+/// instead of holding up the broadcast.
+pub async fn test_slow_consumer_is_flagged() {
+    let mut state = ServerState::new();
+    let mut room = construct_basic_room_for_state(1, true, "slow".to_owned(), "".to_owned());
+    room.user_ids.insert(1);
+    room.amount_of_users += 1;
+    state.rooms.insert(1, room);
+    let (tx, _rx, too_slow) = PeerSender::new();
+    for _ in 0..PEER_SEND_BUFFER {
+        tx.send(Message::text("filler")).unwrap();
+    }
+    state.peer_map.insert(ConnectionId::next(), 1, tx);
+    state.active_users.insert(
+        1,
+        User {
+            current_room_id: 1,
+            ..Default::default()
+        },
+    );
+
+    assert_eq!(
+        response_logic::broadcast_to_room(&1, "hello".to_owned(), &mut state),
+        0
+    );
+    timeout(Duration::from_secs(1), too_slow.notified())
+        .await
+        .expect("slow consumer was never flagged");
+    // the writer task handles the disconnect, the
+    // broadcast itself leaves the state alone
+    assert!(state.peer_map.contains_user(&1));
+    assert!(state.rooms[&1].user_ids.contains(&1));
+}
+
+/// A socket that never takes another write,
+/// like a client that stopped reading.
+struct StalledSocket;
+
+impl Sink<Message> for StalledSocket {
+    type Error = Infallible;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Pending
+    }
+
+    fn start_send(self: Pin<&mut Self>, _: Message) -> Result<(), Infallible> {
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Pending
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Pending
+    }
+}
+
+/// A writer stuck on a stalled socket still gives up, once the
+/// buffer fills or once the write times out, and lets go of the
+/// channel so broadcasts see the peer as closed.
+pub async fn test_stalled_socket_is_given_up_on() {
+    let (tx, rx, too_slow) = PeerSender::new();
+    let writer = tokio::spawn(fan::drain_to_socket(
+        StalledSocket,
+        rx,
+        too_slow,
+        Duration::from_secs(60),
+    ));
+    let mut sent = 0;
+    while tx.send(Message::text("filler")).is_ok() {
+        sent += 1;
+        assert!(sent <= PEER_SEND_BUFFER + 1);
+    }
+    let exit = timeout(Duration::from_secs(1), writer)
+        .await
+        .expect("writer stayed stuck on the stalled socket")
+        .unwrap();
+    assert_eq!(exit, fan::WriterExit::TooSlow);
+    assert_eq!(tx.send(Message::text("hello")), Err(PeerSendError::Closed));
+
+    // a single stuck write is enough once it times out
+    let (tx, rx, too_slow) = PeerSender::new();
+    tx.send(Message::text("hello")).unwrap();
+    let exit = timeout(
+        Duration::from_secs(1),
+        fan::drain_to_socket(StalledSocket, rx, too_slow, Duration::from_millis(50)),
+    )
+    .await
+    .expect("write timeout never fired");
+    assert_eq!(exit, fan::WriterExit::TooSlow);
+}
+
+/// Activity is only handed to the flush once.
+pub fn test_last_online_tracking() {
+    activity::take_unsaved_last_online();
+    activity::record_activity(&7);
+    activity::record_activity(&8);
+    // guests have nothing to write to
+    activity::record_activity(&guests::FIRST_GUEST_ID);
+    // 8 went offline and was written then
+    assert!(!activity::take_final_last_online(&8).is_empty());
+
+    let unsaved = activity::take_unsaved_last_online();
+    assert_eq!(
+        unsaved
+            .iter()
+            .map(|(user_id, _)| *user_id)
+            .collect::<Vec<i32>>(),
+        vec![7]
+    );
+    assert!(!unsaved[0].1.is_empty());
+    assert!(activity::take_unsaved_last_online().is_empty());
+}
+
+/// Contributions stop counting at the daily
+/// cap and start over the next day.
+pub fn test_contribution_daily_cap() {
+    let mut state = ServerState::new();
+    let today = NaiveDate::from_ymd(2022, 3, 1);
+    for _ in 0..contributions::MAX_CONTRIBUTIONS_PER_DAY {
+        assert!(contributions::claim_contribution_on(&mut state, &5, today));
+    }
+    assert!(!contributions::claim_contribution_on(&mut state, &5, today));
+    // other users have their own count
+    assert!(contributions::claim_contribution_on(&mut state, &6, today));
+    assert!(contributions::claim_contribution_on(
+        &mut state,
+        &5,
+        today.succ()
+    ));
+}
+
+/// Retried room creations find the first room,
+/// until the key expires.
+pub fn test_room_creation_keys() {
+    let mut state = ServerState::new();
+    idempotency::record_room_creation(&mut state, 5, "retry".to_owned(), 9);
+    assert_eq!(idempotency::find_created_room(&state, 5, "retry"), Some(9));
+    // keys belong to the user that sent them
+    assert_eq!(idempotency::find_created_room(&state, 6, "retry"), None);
+    assert_eq!(idempotency::find_created_room(&state, 5, "other"), None);
+    //a rolled back creation can be retried
+    idempotency::record_room_creation(&mut state, 5, "rolled_back".to_owned(), 10);
+    idempotency::forget_room_creation(&mut state, 5, "rolled_back");
+    assert_eq!(
+        idempotency::find_created_room(&state, 5, "rolled_back"),
+        None
+    );
+
+    idempotency::evict_expired_room_creation_keys(&mut state, Duration::from_secs(60));
+    assert_eq!(state.room_creation_keys.len(), 1);
+    idempotency::evict_expired_room_creation_keys(&mut state, Duration::from_secs(0));
+    assert!(state.room_creation_keys.is_empty());
+}
+
+/// Rooms are spread over the configured voice
+/// servers, skipping any that are down.
+pub fn test_voice_server_assignment() {
+    let ids = voice_servers::voice_server_ids_from(Some("a, b,,a,c"));
+    assert_eq!(ids, vec!["a", "b", "c"]);
+    assert_eq!(voice_servers::voice_server_ids_from(None), vec!["0"]);
+    assert_eq!(
+        VoiceServerStrategy::parse(Some("least_loaded")),
+        VoiceServerStrategy::LeastLoaded
+    );
+    assert_eq!(
+        VoiceServerStrategy::parse(Some("nonsense")),
+        VoiceServerStrategy::RoundRobin
+    );
+
+    let mut round_robin = VoiceServers::new(ids.clone(), VoiceServerStrategy::RoundRobin);
+    let picked: Vec<String> = (0..4).map(|_| round_robin.assign().unwrap()).collect();
+    assert_eq!(picked, vec!["a", "b", "c", "a"]);
+    round_robin.set_reachable("b", false);
+    assert_eq!(round_robin.assign().unwrap(), "c");
+    assert_eq!(round_robin.assign().unwrap(), "a");
+    // unknown servers can't be marked up and picked
+    round_robin.set_reachable("z", true);
+    assert!(!round_robin.ids.contains(&"z".to_owned()));
+
+    let mut least_loaded = VoiceServers::new(ids, VoiceServerStrategy::LeastLoaded);
+    assert_eq!(least_loaded.assign().unwrap(), "a");
+    assert_eq!(least_loaded.assign().unwrap(), "b");
+    least_loaded.release("a");
+    least_loaded.release("unknown");
+    assert_eq!(least_loaded.assign().unwrap(), "a");
+    assert_eq!(least_loaded.assign().unwrap(), "c");
+    assert_eq!(least_loaded.room_counts.get("a"), Some(&1));
+
+    for id in ["a", "b", "c"] {
+        least_loaded.set_reachable(id, false);
+    }
+    assert_eq!(least_loaded.assign(), Err(NoVoiceServer::Unreachable));
+    least_loaded.set_reachable("b", true);
+    assert_eq!(least_loaded.assign().unwrap(), "b");
+}
+
+/// Full servers are rolled over, and once every
+/// server is full nothing is assigned.
+pub fn test_voice_server_capacity() {
+    assert_eq!(
+        voice_servers::max_rooms_per_server_from(Some(" 2 ")),
+        Some(2)
+    );
+    assert_eq!(voice_servers::max_rooms_per_server_from(Some("0")), None);
+    assert_eq!(voice_servers::max_rooms_per_server_from(Some("lots")), None);
+    assert_eq!(voice_servers::max_rooms_per_server_from(None), None);
+
+    let ids = vec!["a".to_owned(), "b".to_owned()];
+    let mut servers = VoiceServers::new(ids, VoiceServerStrategy::RoundRobin);
+    servers.max_rooms = Some(2);
+    servers.room_counts.insert("a".to_owned(), 2);
+    // a is full so its turn rolls over to b
+    assert_eq!(servers.assign().unwrap(), "b");
+    assert_eq!(servers.assign().unwrap(), "b");
+    assert_eq!(servers.assign(), Err(NoVoiceServer::AtCapacity));
+    servers.release("a");
+    assert_eq!(servers.assign().unwrap(), "a");
+    // down servers are reported before full ones
+    servers.set_reachable("a", false);
+    servers.set_reachable("b", false);
+    assert_eq!(servers.assign(), Err(NoVoiceServer::Unreachable));
+}
+
+pub fn test_state_accessors() {
+    let mut state = ServerState::new();
+    let mut room = construct_basic_room_for_state(1, true, "open".to_owned(), "".to_owned());
+    room.user_ids.insert(33);
+    state.rooms.insert(1, room);
+    state.rooms.insert(
+        2,
+        construct_basic_room_for_state(2, false, "hidden".to_owned(), "".to_owned()),
+    );
+    state.active_users.insert(
+        33,
+        User {
+            current_room_id: 1,
+            ..Default::default()
+        },
+    );
+    state.active_users.insert(34, User::default());
+
+    assert_eq!(state.get_room(1).map(|room| room.room_id), Some(1));
+    assert!(state.get_room(3).is_none());
+
+    assert!(state.user_in_room(33, 1));
+    assert!(!state.user_in_room(34, 1));
+    assert!(!state.user_in_room(33, 3));
+
+    assert!(state.room_is_public(1));
+    assert!(!state.room_is_public(2));
+    assert!(!state.room_is_public(3));
+
+    assert_eq!(state.current_room_id(33), 1);
+    assert_eq!(state.current_room_id(35), -1);
+
+    //an active room is never replaced
+    let clash = construct_basic_room_for_state(1, false, "clash".to_owned(), "".to_owned());
+    assert!(!state.insert_new_room(clash));
+    assert_eq!(state.get_room(1).unwrap().name, "open");
+    let new_room = construct_basic_room_for_state(3, true, "new".to_owned(), "".to_owned());
+    assert!(state.insert_new_room(new_room));
+    assert!(state.room_is_public(3));
+}
+
+/// Announcements reach every connected peer, and each
+/// admin has to wait out the cooldown between them.
+pub async fn test_announcements() {
+    let mut state = ServerState::new();
+    let (first_tx, mut first_rx, _) = PeerSender::new();
+    let (second_tx, mut second_rx, _) = PeerSender::new();
+    let (closed_tx, closed_rx, _) = PeerSender::new();
+    drop(closed_rx);
+    state.peer_map.insert(ConnectionId::next(), 1, first_tx);
+    state.peer_map.insert(ConnectionId::next(), 2, second_tx);
+    state.peer_map.insert(ConnectionId::next(), 3, closed_tx);
+
+    let delivered = response_logic::broadcast_to_all_peers("notice".to_owned(), &state);
+    assert_eq!(delivered, 2);
+    assert_eq!(first_rx.recv().await.unwrap().to_str().unwrap(), "notice");
+    assert_eq!(second_rx.recv().await.unwrap().to_str().unwrap(), "notice");
+
+    assert!(announcements::try_start_announcement(&mut state, 7));
+    assert!(!announcements::try_start_announcement(&mut state, 7));
+    // the cooldown is per admin
+    assert!(announcements::try_start_announcement(&mut state, 8));
+    let cooldown = Duration::from_secs(announcements::ANNOUNCEMENT_COOLDOWN_SECONDS);
+    if let Some(sent_at) = Instant::now().checked_sub(cooldown) {
+        state.last_announcements.insert(7, sent_at);
+        assert!(announcements::try_start_announcement(&mut state, 7));
+    }
+}
+
+/// Only the first drain sets the deadline, and the status
+/// counts down from it.
+pub fn test_draining() {
+    let mut state = ServerState::new();
+    let (tx, _rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 1, tx);
+    assert!(!draining::is_draining(&state));
+    assert!(!draining::drain_deadline_passed(&state));
+    let status = draining::drain_status(&state);
+    assert!(!status.draining);
+    assert_eq!(status.seconds_left, None);
+
+    assert!(draining::begin_drain(&mut state, Duration::from_secs(60)));
+    assert!(!draining::begin_drain(&mut state, Duration::ZERO));
+    assert!(draining::is_draining(&state));
+    assert!(!draining::drain_deadline_passed(&state));
+    let status = draining::drain_status(&state);
+    assert!(status.draining);
+    assert_eq!(status.connections, 1);
+    assert!(status.seconds_left.unwrap() <= 60);
+
+    state.draining.as_mut().unwrap().deadline = Duration::ZERO;
+    assert!(draining::drain_deadline_passed(&state));
+    assert_eq!(draining::drain_status(&state).seconds_left, Some(0));
+}
+
+/// A few denials in a row put raising a hand on cooldown,
+/// an approval or the cooldown running out clears them.
+pub fn test_speaker_request_denials() {
+    let mut state = ServerState::new();
+    for denials in 1..speaker_requests::MAX_DENIALS_BEFORE_COOLDOWN {
+        assert_eq!(speaker_requests::record_denial(&mut state, 3, 7), denials);
+        assert!(speaker_requests::raise_hand_cooldown(&state, 3, 7).is_none());
+    }
+    speaker_requests::record_denial(&mut state, 3, 7);
+    let left = speaker_requests::raise_hand_cooldown(&state, 3, 7).unwrap();
+    assert!(left <= Duration::from_secs(speaker_requests::DENIED_HAND_COOLDOWN_SECONDS));
+    // denials are per room
+    assert!(speaker_requests::raise_hand_cooldown(&state, 4, 7).is_none());
+
+    speaker_requests::clear_denials(&mut state, 3, 7);
+    assert!(speaker_requests::raise_hand_cooldown(&state, 3, 7).is_none());
+
+    let cooldown = Duration::from_secs(speaker_requests::DENIED_HAND_COOLDOWN_SECONDS);
+    if let Some(denied_at) = Instant::now().checked_sub(cooldown) {
+        state.speaker_request_denials.insert(
+            (3, 7),
+            (speaker_requests::MAX_DENIALS_BEFORE_COOLDOWN, denied_at),
+        );
+        assert!(speaker_requests::raise_hand_cooldown(&state, 3, 7).is_none());
+        // an old streak starts over
+        assert_eq!(speaker_requests::record_denial(&mut state, 3, 7), 1);
+        speaker_requests::evict_expired_denials(&mut state, Duration::ZERO);
+        assert!(state.speaker_request_denials.is_empty());
+    }
+}
+
+/// A user left behind in one room's set is moved,
+/// never duplicated, when they join another.
+pub fn test_single_room_membership() {
+    let mut state = ServerState::new();
+    for room_id in [1, 2] {
+        state.rooms.insert(
+            room_id,
+            construct_basic_room_for_state(room_id, true, "room".to_owned(), "room".to_owned()),
+        );
+    }
+    state.active_users.insert(5, User::default());
+    assert!(state.add_user_to_room(5, 1).is_empty());
+    // joining the same room twice doesn't count the user twice
+    assert!(state.add_user_to_room(5, 1).is_empty());
+    assert_eq!(state.rooms[&1].amount_of_users, 1);
+
+    // the user's room id was reset without the room's set
+    // being cleaned up, so the stale membership is dropped
+    state.active_users.get_mut(&5).unwrap().current_room_id = -1;
+    assert_eq!(state.add_user_to_room(5, 2), vec![1]);
+    assert_eq!(state.rooms_containing(5), vec![2]);
+    assert_eq!(state.rooms[&1].amount_of_users, 0);
+    assert_eq!(state.rooms[&2].amount_of_users, 1);
+    assert_eq!(state.current_room_id(5), 2);
+    assert!(state.users_in_multiple_rooms().is_empty());
+
+    state.rooms.get_mut(&1).unwrap().user_ids.insert(5);
+    assert_eq!(state.users_in_multiple_rooms(), vec![5]);
+}
+
+/// The first change goes out right away, rapid toggles
+/// after it wait and only the latest one is sent.
+pub fn test_active_speaker_debounce() {
+    let mut state = ServerState::new();
+    let mut room = construct_basic_room_for_state(1, true, "room".to_owned(), "room".to_owned());
+    room.user_ids.insert(5);
+    state.rooms.insert(1, room);
+    let long = Duration::from_secs(60);
+
+    // nobody was speaking, so stopping says nothing
+    assert_eq!(
+        active_speakers::record_speaking(&mut state, 1, 5, false, long),
+        None
+    );
+    assert_eq!(
+        active_speakers::record_speaking(&mut state, 1, 5, true, long),
+        Some(true)
+    );
+    assert_eq!(
+        active_speakers::record_speaking(&mut state, 1, 5, true, long),
+        None
+    );
+    // toggles inside the debounce are held back
+    assert_eq!(
+        active_speakers::record_speaking(&mut state, 1, 5, false, long),
+        None
+    );
+    assert!(active_speakers::take_settled(&mut state, long).is_empty());
+    assert_eq!(
+        active_speakers::take_settled(&mut state, Duration::ZERO),
+        vec![(1, 5, false)]
+    );
+    // quiet users are dropped once nothing is pending
+    assert!(state.active_speakers.is_empty());
+
+    // outside the debounce a toggle goes straight out
+    active_speakers::record_speaking(&mut state, 1, 5, true, Duration::ZERO);
+    assert_eq!(
+        active_speakers::record_speaking(&mut state, 1, 5, false, Duration::ZERO),
+        Some(false)
+    );
+
+    // users who left the room are forgotten
+    active_speakers::record_speaking(&mut state, 1, 5, true, long);
+    state.rooms.get_mut(&1).unwrap().user_ids.remove(&5);
+    assert!(active_speakers::take_settled(&mut state, Duration::ZERO).is_empty());
+    assert!(state.active_speakers.is_empty());
+}
+
+/// Owned live rooms and creations within the hour are
+/// limited separately.
+pub fn test_room_creation_limits() {
+    let mut state = ServerState::new();
+    for room_id in 0..room_limits::MAX_OWNED_ROOMS as i32 {
+        assert!(room_limits::room_creation_problem(&state, 5).is_none());
+        let mut room =
+            construct_basic_room_for_state(room_id, true, "room".to_owned(), "room".to_owned());
+        room.owner_id = 5;
+        state.rooms.insert(room_id, room);
+        room_limits::record_created_room(&mut state, 5);
+    }
+    assert!(room_limits::room_creation_problem(&state, 5)
+        .unwrap()
+        .contains("live rooms"));
+    // other users aren't affected
+    assert!(room_limits::room_creation_problem(&state, 6).is_none());
+
+    // handing a room to someone else frees a slot
+    state.rooms.get_mut(&0).unwrap().owner_id = 6;
+    assert_eq!(room_limits::owned_room_count(&state, 5), 2);
+    assert!(room_limits::room_creation_problem(&state, 5).is_none());
+
+    state.rooms.clear();
+    for _ in room_limits::MAX_OWNED_ROOMS..room_limits::MAX_ROOM_CREATIONS_PER_HOUR {
+        room_limits::record_created_room(&mut state, 5);
+    }
+    assert!(room_limits::room_creation_problem(&state, 5)
+        .unwrap()
+        .contains("an hour"));
+
+    // only creations inside the hour count
+    let hour = Duration::from_secs(60 * 60);
+    if let Some(long_ago) = Instant::now().checked_sub(hour) {
+        for created_at in state.room_creation_times.get_mut(&5).unwrap().iter_mut() {
+            *created_at = long_ago;
+        }
+        assert!(room_limits::room_creation_problem(&state, 5).is_none());
+        room_limits::evict_old_room_creations(&mut state);
+        assert!(state.room_creation_times.is_empty());
+    }
+}
+
+/// Names only clash between live rooms of the same owner.
+pub fn test_duplicate_room_names() {
+    assert!(room_limits::unique_room_names_from(Some("true")));
+    assert!(!room_limits::unique_room_names_from(Some("no")));
+    assert!(!room_limits::unique_room_names_from(None));
+
+    let mut state = ServerState::new();
+    let mut room =
+        construct_basic_room_for_state(0, true, "Study Hall".to_owned(), "room".to_owned());
+    room.owner_id = 5;
+    state.rooms.insert(0, room);
+    assert!(room_limits::owns_room_named(&state, 5, "Study Hall"));
+    assert!(room_limits::owns_room_named(&state, 5, " study hall "));
+    assert!(!room_limits::owns_room_named(&state, 5, "Study Hall 2"));
+    // other owners can reuse it
+    assert!(!room_limits::owns_room_named(&state, 6, "Study Hall"));
+
+    // a room that's gone frees its name
+    state.rooms.clear();
+    assert!(!room_limits::owns_room_named(&state, 5, "Study Hall"));
+}
+
+/// Idle rooms are warned first and destroyed only if nothing
+/// happens before the warning runs out.
+pub fn test_idle_rooms() {
+    assert_eq!(
+        idle_rooms::idle_timeout_from(Some("600")),
+        Some(Duration::from_secs(600))
+    );
+    assert_eq!(idle_rooms::idle_timeout_from(Some("0")), None);
+    assert_eq!(idle_rooms::idle_timeout_from(Some("soon")), None);
+    assert_eq!(idle_rooms::idle_timeout_from(None), None);
+
+    let timeout = Duration::from_secs(600);
+    let warning = Duration::from_secs(60);
+    let mut state = ServerState::new();
+    for room_id in 0..3 {
+        state.rooms.insert(
+            room_id,
+            construct_basic_room_for_state(room_id, true, "room".to_owned(), "room".to_owned()),
+        );
+    }
+    state.active_users.insert(5, User::default());
+    state.active_users.insert(6, User::default());
+    state.add_user_to_room(5, 0);
+    state.add_user_to_room(6, 1);
+    let start = Instant::now();
+    let sweep = idle_rooms::sweep_idle_rooms(&mut state, timeout, warning, start + warning);
+    assert_eq!(sweep, idle_rooms::IdleSweep::default());
+
+    // empty rooms are left to the empty room cleanup
+    let idle_at = start + timeout;
+    let mut sweep = idle_rooms::sweep_idle_rooms(&mut state, timeout, warning, idle_at);
+    sweep.to_warn.sort();
+    assert_eq!(sweep.to_warn, vec![0, 1]);
+    assert!(sweep.to_destroy.is_empty());
+    // a warning goes out once
+    let sweep = idle_rooms::sweep_idle_rooms(&mut state, timeout, warning, idle_at);
+    assert!(sweep.to_warn.is_empty() && sweep.to_destroy.is_empty());
+
+    // activity takes the warning back
+    idle_rooms::record_room_activity(&mut state, &1);
+    assert!(state.rooms[&1].idle_warned_at.is_none());
+    state.rooms.get_mut(&1).unwrap().last_activity = idle_at;
+    let sweep = idle_rooms::sweep_idle_rooms(&mut state, timeout, warning, idle_at + warning);
+    assert_eq!(sweep.to_destroy, vec![0]);
+    assert!(sweep.to_warn.is_empty());
+
+    // so does someone joining
+    state.active_users.insert(7, User::default());
+    state.add_user_to_room(7, 0);
+    assert!(state.rooms[&0].idle_warned_at.is_none());
+}
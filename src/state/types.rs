@@ -1,7 +1,14 @@
+use crate::rooms::chat_modes::ChatMode;
 #[allow(unused_imports)]
 use futures_util::{stream::SplitSink, SinkExt, StreamExt};
-use std::collections::{HashMap, HashSet};
-use tokio::sync::mpsc;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::Notify;
 use warp::ws::Message;
 //.keys().cloned().collect::<Vec<_>>();
 
@@ -16,42 +23,353 @@ pub struct Board {
     /// of passive data.
     pub passive_data_snapshot: Option<String>,
     pub outside_name: String,
+    /// The most recent commands sent to this board,
+    /// newest at the back.
+    pub recent_commands: VecDeque<BoardCommandAudit>,
 }
+
+/// Lets collaborators of a board see who did what.
+#[derive(Clone, Serialize)]
+pub struct BoardCommandAudit {
+    pub user_id: i32,
+    pub command: String,
+    pub sent_at: String,
+}
+
+/// Something that happened in a room which users
+/// joining later should know about.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RoomEvent {
+    UserJoined { user_id: i32 },
+    UserLeft { user_id: i32 },
+    UserRemoved { user_id: i32 },
+    HandRaised { user_id: i32 },
+    HandLowered { user_id: i32 },
+    SpeakerAdded { user_id: i32 },
+    SpeakerRemoved { user_id: i32 },
+}
+
+#[derive(Clone, Serialize)]
+pub struct RoomEventRecord {
+    #[serde(flatten)]
+    pub event: RoomEvent,
+    pub happened_at: String,
+}
+
+/// A user that lost their connection while in a room,
+/// they can resume with their token until it expires.
+pub struct DisconnectedSession {
+    pub resume_token: String,
+    pub room_id: i32,
+    pub disconnected_at: Instant,
+}
+
+/// started_at is rfc3339, recording_id is the room_recording row.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RoomRecording {
+    pub recording_id: i32,
+    pub started_by: i32,
+    pub started_at: String,
+}
+
 #[derive(Default)]
 pub struct User {
     pub muted: bool,
     pub deaf: bool,
     pub ip: String,
     pub current_room_id: i32,
+    /// guests only exist here, never in the database
+    pub guest: bool,
 }
 
 pub struct Room {
     pub room_id: i32,
+    /// mirrors room.ownerId, -1 until the creator is set
+    pub owner_id: i32,
     pub muted: HashSet<i32>,
     pub name: String,
     pub desc: String,
     pub chat_throttle: i32,
+    /// who besides mods and the owner can chat
+    pub chat_mode: ChatMode,
     pub voice_server_id: String,
     pub deaf: HashSet<i32>,
     pub user_ids: HashSet<i32>,
+    /// mirrors room_permission.isMod for users
+    /// that joined since the room was created.
+    pub mod_ids: HashSet<i32>,
     pub amount_of_users: i32,
+    /// most users the room admits, starts at the
+    /// server default and can only be lowered.
+    pub max_occupancy: usize,
     pub public: bool,
     pub auto_speaker: bool,
     pub created_at: String, //datetime
     pub iot_server_connections: HashMap<String, Board>,
+    /// recent events, oldest at the front.
+    pub event_history: VecDeque<RoomEventRecord>,
+    /// when the room last became empty, None while
+    /// anyone is in it. Empty rooms are torn down
+    /// once they've stayed empty for the grace period.
+    pub empty_since: Option<Instant>,
+    /// mirrors room_tags, normalized and capped by rooms/tags.rs
+    pub tags: Vec<String>,
+    /// when an admin pinned the room, pinned public
+    /// rooms lead the top rooms in the order they were pinned.
+    pub pinned_at: Option<Instant>,
+    /// set while the voice server records the room
+    pub recording: Option<RoomRecording>,
+    /// last join or speaking report, rooms idle for
+    /// too long are destroyed by state/idle_rooms.rs
+    pub last_activity: Instant,
+    /// when the room was warned it's idle, None
+    /// again once anything happens in it.
+    pub idle_warned_at: Option<Instant>,
+}
+
+impl Room {
+    /// Returns false if the user wasn't in the room.
+    pub fn remove_user(&mut self, user_id: &i32) -> bool {
+        if !self.user_ids.remove(user_id) {
+            return false;
+        }
+        self.amount_of_users -= 1;
+        if self.amount_of_users == 0 {
+            self.empty_since = Some(Instant::now());
+        }
+        true
+    }
+
+    pub fn record_activity(&mut self) {
+        self.last_activity = Instant::now();
+        self.idle_warned_at = None;
+    }
 }
 
 /// IoTServerConnectionId -> Permissions for the connection(represented as the board)
 /// Read the docs about the Board concept
 pub type IoTServerConnections = HashMap<String, Board>;
 
-//user id -> write connection.
-//broadcasting requires you to acquire the lock of the mutex
-//to access peer connections.
-pub type PeerMap = HashMap<i32, mpsc::UnboundedSender<Message>>;
+/// Unique to a single websocket. A user can have more
+/// than one at a time(overlapping reconnects, several
+/// devices), so a user id alone can't name a connection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ConnectionId(u64);
+
+impl ConnectionId {
+    pub fn next() -> Self {
+        static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+        ConnectionId(NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+//connection id -> write connection, along with an index
+//of every connection a user has open.
+//none of these collections have their own lock, they
+//live inside of the ServerState which is behind a
+//tokio RwLock. Broadcasting requires you to acquire
+//that lock to access peer connections, so never
+//hold a std lock across an await.
+#[derive(Default)]
+pub struct PeerMap {
+    connections: HashMap<ConnectionId, (i32, PeerSender)>,
+    by_user: HashMap<i32, HashSet<ConnectionId>>,
+}
+
+impl PeerMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a connection for the user, any
+    /// connections they already have are kept.
+    pub fn insert(&mut self, connection_id: ConnectionId, user_id: i32, tx: PeerSender) {
+        if let Some((previous_user_id, _)) = self.connections.insert(connection_id, (user_id, tx)) {
+            self.unindex(connection_id, previous_user_id);
+        }
+        self.by_user
+            .entry(user_id)
+            .or_default()
+            .insert(connection_id);
+    }
+
+    /// Only drops this one connection, the
+    /// user's other connections are left alone.
+    pub fn remove_connection(&mut self, connection_id: ConnectionId) -> Option<PeerSender> {
+        let (user_id, tx) = self.connections.remove(&connection_id)?;
+        self.unindex(connection_id, user_id);
+        Some(tx)
+    }
+
+    /// Drops every connection the user has,
+    /// giving back their senders.
+    pub fn remove_user(&mut self, user_id: &i32) -> Vec<PeerSender> {
+        let connection_ids = self.by_user.remove(user_id).unwrap_or_default();
+        connection_ids
+            .into_iter()
+            .filter_map(|connection_id| self.connections.remove(&connection_id))
+            .map(|(_, tx)| tx)
+            .collect()
+    }
+
+    fn unindex(&mut self, connection_id: ConnectionId, user_id: i32) {
+        if let Some(connection_ids) = self.by_user.get_mut(&user_id) {
+            connection_ids.remove(&connection_id);
+            if connection_ids.is_empty() {
+                self.by_user.remove(&user_id);
+            }
+        }
+    }
+
+    /// Drops the user's connections whose writer task is gone,
+    /// returns how many were dropped. The user's own disconnect
+    /// handling still runs once their socket task notices.
+    pub fn remove_closed_connections(&mut self, user_id: &i32) -> usize {
+        let closed: Vec<ConnectionId> = self
+            .by_user
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter(|connection_id| {
+                self.connections
+                    .get(connection_id)
+                    .is_some_and(|(_, tx)| tx.is_closed())
+            })
+            .copied()
+            .collect();
+        for connection_id in closed.iter() {
+            self.remove_connection(*connection_id);
+        }
+        closed.len()
+    }
+
+    pub fn contains_user(&self, user_id: &i32) -> bool {
+        self.by_user.contains_key(user_id)
+    }
+
+    pub fn contains_connection(&self, connection_id: ConnectionId) -> bool {
+        self.connections.contains_key(&connection_id)
+    }
+
+    pub fn connection_count(&self, user_id: &i32) -> usize {
+        self.by_user.get(user_id).map_or(0, HashSet::len)
+    }
+
+    /// Every open connection of the user.
+    pub fn senders_for(&self, user_id: &i32) -> Vec<&PeerSender> {
+        self.by_user
+            .get(user_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|connection_id| self.connections.get(connection_id))
+            .map(|(_, tx)| tx)
+            .collect()
+    }
+
+    /// Sends to each of the user's connections. Ok as long as
+    /// one of them got it, otherwise the error says whether the
+    /// user is slow(being disconnected) or has nothing open.
+    pub fn send_to_user(&self, user_id: &i32, message: Message) -> Result<(), PeerSendError> {
+        let mut result = Err(PeerSendError::Closed);
+        for tx in self.senders_for(user_id) {
+            match tx.send(message.clone()) {
+                Ok(()) => result = Ok(()),
+                Err(PeerSendError::SlowConsumer) if result.is_err() => {
+                    result = Err(PeerSendError::SlowConsumer)
+                }
+                Err(_) => {}
+            }
+        }
+        result
+    }
+
+    /// Every open connection, across all users.
+    pub fn values(&self) -> impl Iterator<Item = &PeerSender> {
+        self.connections.values().map(|(_, tx)| tx)
+    }
+
+    /// Counts connections, not users.
+    pub fn len(&self) -> usize {
+        self.connections.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+}
+
+/// How many messages can wait on a peer's
+/// socket before they count as too slow.
+pub const PEER_SEND_BUFFER: usize = 256;
+
+/// The write half of a peer's connection, a dedicated
+/// task drains the other end into the websocket.
+///
+/// Sending never waits, if the buffer is full the peer
+/// can't keep up and their writer task is told to
+/// disconnect them, so one laggy client can't back
+/// up broadcasts for everyone else.
+#[derive(Clone)]
+pub struct PeerSender {
+    tx: mpsc::Sender<Message>,
+    too_slow: Arc<Notify>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PeerSendError {
+    /// the writer task is gone
+    Closed,
+    /// the buffer filled up, the peer is being disconnected
+    SlowConsumer,
+}
+
+impl fmt::Display for PeerSendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PeerSendError::Closed => write!(f, "peer channel closed"),
+            PeerSendError::SlowConsumer => write!(f, "slow_consumer"),
+        }
+    }
+}
+
+impl PeerSender {
+    /// Gives back the sender along with what the writer task
+    /// needs, the receiver and the slow consumer signal.
+    pub fn new() -> (Self, mpsc::Receiver<Message>, Arc<Notify>) {
+        let (tx, rx) = mpsc::channel(PEER_SEND_BUFFER);
+        let too_slow = Arc::new(Notify::new());
+        let sender = PeerSender {
+            tx,
+            too_slow: too_slow.clone(),
+        };
+        (sender, rx, too_slow)
+    }
+
+    pub fn send(&self, message: Message) -> Result<(), PeerSendError> {
+        match self.tx.try_send(message) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => {
+                self.too_slow.notify_one();
+                Err(PeerSendError::SlowConsumer)
+            }
+            Err(TrySendError::Closed(_)) => Err(PeerSendError::Closed),
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+}
 
 //current connected and authed users
 pub type ActiveUsers = HashMap<i32, User>;
 
 //room collection
 pub type ActiveRooms = HashMap<i32, Room>;
+
+//user id -> resume token given during auth
+pub type SessionTokens = HashMap<i32, String>;
+
+//user id -> session waiting to be resumed
+pub type DisconnectedSessions = HashMap<i32, DisconnectedSession>;
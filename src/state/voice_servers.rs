@@ -0,0 +1,165 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+/// How a new room picks its voice server.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VoiceServerStrategy {
+    /// Take turns through the configured servers.
+    RoundRobin,
+    /// Pick whichever server hosts the fewest rooms.
+    LeastLoaded,
+}
+
+impl VoiceServerStrategy {
+    /// Reads VOICE_SERVER_STRATEGY, anything
+    /// unrecognized falls back to round robin.
+    pub fn from_env() -> Self {
+        Self::parse(env::var("VOICE_SERVER_STRATEGY").ok().as_deref())
+    }
+
+    pub fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some("least_loaded") => VoiceServerStrategy::LeastLoaded,
+            _ => VoiceServerStrategy::RoundRobin,
+        }
+    }
+}
+
+/// Reads VOICE_SERVER_IDS, a comma separated list of
+/// voice server ids. A single server "0" is assumed
+/// when nothing is configured.
+pub fn voice_server_ids_from_env() -> Vec<String> {
+    voice_server_ids_from(env::var("VOICE_SERVER_IDS").ok().as_deref())
+}
+
+pub fn voice_server_ids_from(value: Option<&str>) -> Vec<String> {
+    let mut ids: Vec<String> = Vec::new();
+    for id in value.unwrap_or("").split(',').map(str::trim) {
+        if !id.is_empty() && !ids.iter().any(|existing| existing == id) {
+            ids.push(id.to_owned());
+        }
+    }
+    if ids.is_empty() {
+        ids.push("0".to_owned());
+    }
+    ids
+}
+
+/// Reads VOICE_SERVER_MAX_ROOMS, the most rooms a single
+/// voice server may host. Unset or 0 means no cap.
+pub fn max_rooms_per_server_from_env() -> Option<usize> {
+    max_rooms_per_server_from(env::var("VOICE_SERVER_MAX_ROOMS").ok().as_deref())
+}
+
+pub fn max_rooms_per_server_from(value: Option<&str>) -> Option<usize> {
+    value
+        .and_then(|value| value.trim().parse::<usize>().ok())
+        .filter(|max| *max > 0)
+}
+
+/// Why no voice server could be picked for a new room.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoVoiceServer {
+    /// every server is reported down
+    Unreachable,
+    /// every reachable server already hosts max_rooms
+    AtCapacity,
+}
+
+/// Every voice server rooms can be placed on, along
+/// with how many rooms each one currently hosts.
+pub struct VoiceServers {
+    pub strategy: VoiceServerStrategy,
+    pub ids: Vec<String>,
+    pub room_counts: HashMap<String, usize>,
+    /// servers reported as down, skipped
+    /// until they are reported up again.
+    pub unreachable: HashSet<String>,
+    /// None means servers can host any number of rooms.
+    pub max_rooms: Option<usize>,
+    next: usize,
+}
+
+impl VoiceServers {
+    pub fn new(ids: Vec<String>, strategy: VoiceServerStrategy) -> Self {
+        let room_counts = ids.iter().map(|id| (id.clone(), 0)).collect();
+        Self {
+            strategy,
+            ids,
+            room_counts,
+            unreachable: HashSet::new(),
+            max_rooms: None,
+            next: 0,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let mut servers = Self::new(voice_server_ids_from_env(), VoiceServerStrategy::from_env());
+        servers.max_rooms = max_rooms_per_server_from_env();
+        servers
+    }
+
+    fn at_capacity(&self, id: &str) -> bool {
+        self.max_rooms
+            .is_some_and(|max| *self.room_counts.get(id).unwrap_or(&0) >= max)
+    }
+
+    /// Picks a voice server for a new room and counts the
+    /// room against it, servers at capacity are passed over
+    /// for the next one.
+    pub fn assign(&mut self) -> Result<String, NoVoiceServer> {
+        let reachable: Vec<usize> = (0..self.ids.len())
+            .filter(|i| !self.unreachable.contains(&self.ids[*i]))
+            .collect();
+        if reachable.is_empty() {
+            return Err(NoVoiceServer::Unreachable);
+        }
+        let reachable: Vec<usize> = reachable
+            .into_iter()
+            .filter(|i| !self.at_capacity(&self.ids[*i]))
+            .collect();
+        if reachable.is_empty() {
+            return Err(NoVoiceServer::AtCapacity);
+        }
+        let chosen = match self.strategy {
+            VoiceServerStrategy::RoundRobin => {
+                // the first reachable server at or after our turn
+                let turn = self.next % self.ids.len();
+                *reachable
+                    .iter()
+                    .find(|i| **i >= turn)
+                    .unwrap_or(&reachable[0])
+            }
+            // ties go to the server configured first
+            VoiceServerStrategy::LeastLoaded => *reachable
+                .iter()
+                .min_by_key(|i| self.room_counts.get(&self.ids[**i]).unwrap_or(&0))
+                .unwrap(),
+        };
+        self.next = chosen + 1;
+        let id = self.ids[chosen].clone();
+        *self.room_counts.entry(id.clone()).or_insert(0) += 1;
+        Ok(id)
+    }
+
+    /// Called once a room on this server is destroyed,
+    /// unknown ids are ignored.
+    pub fn release(&mut self, id: &str) {
+        if let Some(count) = self.room_counts.get_mut(id) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    /// Unknown ids are ignored, only configured
+    /// servers can ever be picked.
+    pub fn set_reachable(&mut self, id: &str, reachable: bool) {
+        if !self.room_counts.contains_key(id) {
+            return;
+        }
+        if reachable {
+            self.unreachable.remove(id);
+        } else {
+            self.unreachable.insert(id.to_owned());
+        }
+    }
+}
@@ -3,5 +3,72 @@ async fn run_all_tests() {
     crate::rabbitmq::test::test().await;
     crate::data_store::test::test().await;
     crate::communication::test::test().await;
+    crate::rabbitmq::test::test_pending_messages();
+    crate::rabbitmq::test::test_rabbit_config();
+    crate::rabbitmq::test::test_voice_codec_config();
+    crate::rabbitmq::test::test_voice_codec_round_trips();
+    crate::data_store::tests::pool::test_pool_hands_out_free_members().await;
+    crate::data_store::tests::migrations::test_migration_versions_are_ordered();
+    #[cfg(feature = "metrics")]
+    crate::data_store::tests::query_metrics::test_query_histograms();
     crate::state::tests::test_owners_queue();
+    crate::state::tests::test_concurrent_broadcast_and_join().await;
+    crate::state::tests::test_dead_senders_are_dropped_on_broadcast().await;
+    crate::state::tests::test_slow_consumer_is_flagged().await;
+    crate::state::tests::test_stalled_socket_is_given_up_on().await;
+    crate::state::tests::test_session_resume();
+    crate::state::tests::test_peer_map_connections().await;
+    crate::state::tests::test_server_stats();
+    crate::state::tests::test_last_online_tracking();
+    crate::state::tests::test_contribution_daily_cap();
+    crate::state::tests::test_room_creation_keys();
+    crate::state::tests::test_voice_server_assignment();
+    crate::state::tests::test_voice_server_capacity();
+    crate::state::tests::test_state_accessors();
+    crate::state::tests::test_announcements().await;
+    crate::state::tests::test_draining();
+    crate::state::tests::test_speaker_request_denials();
+    crate::state::tests::test_single_room_membership();
+    crate::state::tests::test_active_speaker_debounce();
+    crate::state::tests::test_room_creation_limits();
+    crate::state::tests::test_duplicate_room_names();
+    crate::state::tests::test_idle_rooms();
+    crate::communication::tests::op_code_tests::test_op_codes_are_unique();
+    crate::communication::tests::op_code_tests::test_voice_server_ops_serialize();
+    crate::communication::tests::op_code_tests::test_voice_server_types_are_camel_case();
+    crate::communication::tests::op_code_tests::test_request_timeouts();
+    crate::communication::tests::op_code_tests::test_timed_out_requests_are_awaited().await;
+    crate::communication::tests::profile_tests::test_profile_edit_validation();
+    crate::communication::tests::profile_tests::test_image_url_allowlist();
+    crate::communication::tests::profile_tests::test_user_preferences_parsing();
+    crate::communication::tests::search_tests::test_user_search_patterns();
+    crate::communication::tests::join_tests::test_already_in_room().await;
+    crate::communication::tests::chat_tests::test_chat_refusal().await;
+    crate::communication::tests::chat_tests::test_content_filter();
+    crate::board::tests::test_board_permissions();
+    crate::common::tests::test_response_envelope().await;
+    crate::auth::tests::test_origin_allowlist();
+    crate::auth::tests::test_require_admin().await;
+    crate::auth::tests::test_guests();
+    crate::logging::tests::test_log_settings();
+    crate::logging::tests::test_json_log_lines();
+    crate::vs_response::tests::test_route_msg_rejects_malformed_messages().await;
+    crate::rooms::tests::test_search_public_rooms();
+    crate::rooms::tests::test_top_rooms_are_public();
+    crate::rooms::tests::test_pinned_rooms_lead_top_rooms();
+    crate::rooms::tests::test_resolve_type_of_join();
+    crate::rooms::tests::test_room_is_full();
+    crate::rooms::tests::test_expired_empty_rooms();
+    crate::rooms::tests::test_room_event_history();
+    crate::rooms::tests::test_online_followers_outside_room();
+    crate::rooms::tests::test_check_raise_hand();
+    crate::rooms::tests::test_check_speaker_approval();
+    crate::rooms::tests::test_role_permissions().await;
+    crate::rooms::tests::test_speaker_change_problem();
+    crate::rooms::tests::test_chat_modes();
+    crate::rooms::tests::test_room_tags();
+    crate::rooms::tests::test_my_room_permissions().await;
+    crate::rooms::tests::test_force_mute_user().await;
+    crate::rooms::tests::test_join_room_as_guest().await;
+    crate::rooms::tests::test_room_confirmations().await;
 }
@@ -1,8 +1,14 @@
-use crate::communication::types::BasicResponse;
+use crate::communication::types::{ActiveSpeaker, BasicResponse};
 use crate::logging;
+use crate::rooms::confirmations;
 use crate::state::state::ServerState;
-use crate::vs_response::types::VoiceServerResponse;
+use crate::state::{active_speakers, idle_rooms};
+use crate::vs_response::types::{
+    ActiveSpeakerUpdate, RoomCreatedConfirmation, VoiceServerResponse, VoiceServerStatus,
+};
 use crate::ws_fan::fan;
+use anyhow::{Context, Result};
+use std::time::Duration;
 
 //used for basic events where
 //the user_id is the only containing data
@@ -13,13 +19,14 @@ pub async fn notify_user_and_room(
     response: VoiceServerResponse,
     state: &mut ServerState,
     op_code_for_other_users: String,
-) {
+) -> Result<()> {
     logging::console::log_event(&format!(
         "VoiceServer triggered op code:{}",
         op_code_for_other_users
     ));
-    let room_id: i32 = grab_room_id(response.d["roomId"].to_string());
-    let user_id: i32 = response.uid.parse().unwrap();
+    let room_id: i32 =
+        try_grab_id(response.d["roomId"].to_string()).context("missing or bad roomId")?;
+    let user_id: i32 = response.uid.parse()?;
     let basic_response_for_user = BasicResponse {
         response_op_code: response.op,
         response_containing_data: response.d.to_string(),
@@ -41,28 +48,36 @@ pub async fn notify_user_and_room(
         user_id,
     )
     .await;
+    Ok(())
 }
 
-pub async fn notify_user_only(response: VoiceServerResponse, state: &mut ServerState) {
+pub async fn notify_user_only(
+    response: VoiceServerResponse,
+    state: &mut ServerState,
+) -> Result<()> {
     let basic_response_for_user = BasicResponse {
         response_op_code: response.op,
         response_containing_data: response.d.to_string(),
     };
-    let user_id: i32 = response.uid.parse().unwrap();
+    let user_id: i32 = response.uid.parse()?;
     fan::broadcast_message_to_single_user(
         serde_json::to_string(&basic_response_for_user).unwrap(),
         state,
         &user_id,
     )
     .await;
+    Ok(())
 }
 
-pub async fn notify_entire_room(response: serde_json::Value, state: &mut ServerState) {
+pub async fn notify_entire_room(
+    response: serde_json::Value,
+    state: &mut ServerState,
+) -> Result<()> {
     let basic_response_for_user = BasicResponse {
         response_op_code: response["op"].to_string(),
         response_containing_data: response["d"].to_string(),
     };
-    let room_id: i32 = response["rid"].to_string().parse().unwrap();
+    let room_id: i32 = try_grab_id(response["rid"].to_string()).context("missing or bad rid")?;
 
     fan::broadcast_message_to_room(
         serde_json::to_string(&basic_response_for_user).unwrap(),
@@ -70,13 +85,81 @@ pub async fn notify_entire_room(response: serde_json::Value, state: &mut ServerS
         room_id,
     )
     .await;
+    Ok(())
+}
+
+/// Voice servers report when they go down or come back,
+/// new rooms are only placed on servers that are up.
+pub fn update_voice_server_status(status: VoiceServerStatus, state: &mut ServerState) {
+    logging::console::log_event(&format!(
+        "VoiceServer({}) reachable:{}",
+        status.voice_server_id, status.reachable
+    ));
+    state
+        .voice_servers
+        .set_reachable(&status.voice_server_id, status.reachable);
+}
+
+/// Only goes out when the user is still in the room, toggles
+/// inside the debounce wait for the active speaker flush.
+pub async fn relay_active_speaker(update: ActiveSpeakerUpdate, state: &mut ServerState) {
+    let (room_id, user_id) = match (
+        try_grab_id(update.room_id.to_string()),
+        try_grab_id(update.peer_id.to_string()),
+    ) {
+        (Some(room_id), Some(user_id)) => (room_id, user_id),
+        _ => return,
+    };
+    if !state.user_in_room(user_id, room_id) {
+        return;
+    }
+    if update.speaking {
+        idle_rooms::record_room_activity(state, &room_id);
+    }
+    let debounce = Duration::from_millis(active_speakers::ACTIVE_SPEAKER_DEBOUNCE_MS);
+    if let Some(speaking) =
+        active_speakers::record_speaking(state, room_id, user_id, update.speaking, debounce)
+    {
+        broadcast_active_speaker(state, room_id, user_id, speaking).await;
+    }
+}
+
+pub async fn broadcast_active_speaker(
+    state: &mut ServerState,
+    room_id: i32,
+    user_id: i32,
+    speaking: bool,
+) {
+    let response = BasicResponse {
+        response_op_code: "active_speaker".to_owned(),
+        response_containing_data: serde_json::to_string(&ActiveSpeaker {
+            room_id,
+            user_id,
+            speaking,
+        })
+        .unwrap(),
+    };
+    fan::broadcast_message_to_room(serde_json::to_string(&response).unwrap(), state, room_id).await;
+}
+
+/// Wakes the create_room request waiting on this room,
+/// an answer nobody waits for anymore is only logged.
+pub fn confirm_room_creation(confirmation: RoomCreatedConfirmation) -> Result<()> {
+    let room_id = try_grab_id(confirmation.room_id.to_string()).context("missing or bad roomId")?;
+    if !confirmations::resolve_confirmation(room_id, confirmation.created) {
+        logging::console::log_failure(&format!(
+            "voice server answered for room({}) after it stopped waiting",
+            room_id
+        ));
+    }
+    Ok(())
 }
 
 /// Simply remove the wrapping double quotes
-/// from the roomId if it exists
-fn grab_room_id(mut data: String) -> i32 {
+/// from the id if it exists
+fn try_grab_id(mut data: String) -> Option<i32> {
     if data.contains(r#"""#) {
         data = data[1..data.len() - 1].to_string();
     }
-    data.parse().unwrap()
+    data.parse().ok()
 }
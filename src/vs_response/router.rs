@@ -1,14 +1,25 @@
 use crate::state::state::ServerState;
 use crate::vs_response::handler;
-use crate::vs_response::types::VoiceServerResponse;
-pub async fn route_msg(msg: String, state: &mut ServerState) {
-    let temp_value: serde_json::Value = serde_json::from_str(&msg).unwrap();
-    if temp_value["uid"] != serde_json::Value::Null {
-        let op = temp_value["op"].as_str().unwrap();
-        match op {
+use crate::vs_response::types::{
+    ActiveSpeakerUpdate, RoomCreatedConfirmation, VoiceServerResponse, VoiceServerStatus,
+};
+use anyhow::Result;
+
+/// An Err means the message couldn't be understood,
+/// the consumer rejects it instead of acking.
+pub async fn route_msg(msg: String, state: &mut ServerState) -> Result<()> {
+    let temp_value: serde_json::Value = serde_json::from_str(&msg)?;
+    // checked before the uid, the voice server may echo
+    // back the creator's uid from create-room
+    if temp_value["op"] == "room-created" {
+        let confirmation: RoomCreatedConfirmation =
+            serde_json::from_value(temp_value["d"].clone())?;
+        handler::confirm_room_creation(confirmation)
+    } else if temp_value["uid"] != serde_json::Value::Null {
+        let response: VoiceServerResponse = serde_json::from_value(temp_value)?;
+        match response.op.as_str() {
             "you_left_room" => {
-                let response: VoiceServerResponse = serde_json::from_str(&msg).unwrap();
-                handler::notify_user_and_room(response, state, "user_left_room".to_owned()).await;
+                handler::notify_user_and_room(response, state, "user_left_room".to_owned()).await
             }
             //we don't need to let them know which one
             //because they will get the permissions and know
@@ -17,29 +28,32 @@ pub async fn route_msg(msg: String, state: &mut ServerState) {
             //Everytime a user joins, the frontend
             //requests permissions.
             "you-joined-as-speaker" | "you-joined-as-peer" => {
-                let response: VoiceServerResponse = serde_json::from_str(&msg).unwrap();
-                handler::notify_user_and_room(response, state, "new_user_joined".to_owned()).await;
+                handler::notify_user_and_room(response, state, "new_user_joined".to_owned()).await
             }
 
             "you-are-now-a-speaker" => {
-                let response: VoiceServerResponse = serde_json::from_str(&msg).unwrap();
-                handler::notify_user_and_room(response, state, "new_speaker".to_owned()).await;
+                handler::notify_user_and_room(response, state, "new_speaker".to_owned()).await
             }
 
             //private updates for users only, like
             //getting recv tracks and connecting send
             //transports etc.
-            _ => {
-                let response: VoiceServerResponse = serde_json::from_str(&msg).unwrap();
-                handler::notify_user_only(response, state).await;
-            }
-        };
+            _ => handler::notify_user_only(response, state).await,
+        }
     }
     //when the response is suppose to go to the entire room
     //with no filters as to who see what. Some responses
     //have filters meaning only one user sees like
     //credentials etc.
-    else {
-        handler::notify_entire_room(temp_value, state).await;
+    else if temp_value["op"] == "voice_server_status" {
+        let status: VoiceServerStatus = serde_json::from_value(temp_value["d"].clone())?;
+        handler::update_voice_server_status(status, state);
+        Ok(())
+    } else if temp_value["op"] == "active_speaker" {
+        let update: ActiveSpeakerUpdate = serde_json::from_value(temp_value["d"].clone())?;
+        handler::relay_active_speaker(update, state).await;
+        Ok(())
+    } else {
+        handler::notify_entire_room(temp_value, state).await
     }
 }
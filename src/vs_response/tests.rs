@@ -0,0 +1,53 @@
+use super::router;
+use crate::rooms::confirmations;
+use crate::state::state::ServerState;
+use crate::state::types::{ConnectionId, PeerSender};
+
+/// Anything the consumer would otherwise have panicked on comes
+/// back as an Err, so the message is rejected instead of acked.
+pub async fn test_route_msg_rejects_malformed_messages() {
+    let mut state = ServerState::new();
+    let (tx, mut rx, _) = PeerSender::new();
+    state.peer_map.insert(ConnectionId::next(), 5, tx);
+
+    let malformed = [
+        "not json",
+        r#"{"op":"you-are-now-a-speaker","d":{"roomId":"3"},"uid":"five"}"#,
+        r#"{"op":"you_left_room","d":{},"uid":"5"}"#,
+        r#"{"op":"voice_server_status","d":{"reachable":true}}"#,
+        r#"{"op":"active_speaker","d":{"roomId":"3"}}"#,
+        r#"{"op":"room_closing","d":{}}"#,
+    ];
+    for message in malformed {
+        assert!(
+            router::route_msg(message.to_owned(), &mut state)
+                .await
+                .is_err(),
+            "{} should be rejected",
+            message
+        );
+    }
+
+    let for_user = r#"{"op":"@get-recv-tracks-done","d":{"consumers":[]},"uid":"5"}"#;
+    router::route_msg(for_user.to_owned(), &mut state)
+        .await
+        .unwrap();
+    let sent = rx.recv().await.unwrap();
+    assert!(sent.to_str().unwrap().contains("@get-recv-tracks-done"));
+    // answers to create-room are routed even with a uid
+    let confirmation = confirmations::expect_confirmation(9101);
+    let room_created = r#"{"op":"room-created","d":{"roomId":"9101","created":true},"uid":"5"}"#;
+    router::route_msg(room_created.to_owned(), &mut state)
+        .await
+        .unwrap();
+    assert_eq!(confirmation.await, Ok(true));
+    let no_created = r#"{"op":"room-created","d":{"roomId":"9101"}}"#;
+    assert!(router::route_msg(no_created.to_owned(), &mut state)
+        .await
+        .is_err());
+    // rooms are matched whether the id is a string or a number
+    for rid in [r#""3""#, "3"] {
+        let for_room = format!(r#"{{"op":"room_closing","d":{{}},"rid":{}}}"#, rid);
+        router::route_msg(for_room, &mut state).await.unwrap();
+    }
+}
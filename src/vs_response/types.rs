@@ -6,3 +6,30 @@ pub struct VoiceServerResponse {
     pub d: serde_json::Value,
     pub uid: String,
 }
+
+/// Sent without a uid, {"op":"voice_server_status","d":<this>}.
+#[derive(Deserialize, Serialize)]
+pub struct VoiceServerStatus {
+    pub voice_server_id: String,
+    pub reachable: bool,
+}
+
+/// Sent without a uid, {"op":"active_speaker","d":<this>}.
+/// The voice server uses our room and user ids, sent
+/// as either strings or numbers.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveSpeakerUpdate {
+    pub room_id: serde_json::Value,
+    pub peer_id: serde_json::Value,
+    pub speaking: bool,
+}
+
+/// {"op":"room-created","d":<this>}, the answer to create-room.
+/// created is false when the voice server couldn't make it.
+#[derive(Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RoomCreatedConfirmation {
+    pub room_id: serde_json::Value,
+    pub created: bool,
+}
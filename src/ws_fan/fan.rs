@@ -1,13 +1,83 @@
+use crate::common::response_logic;
 use crate::state::state::ServerState;
-use futures::lock::Mutex;
+use futures::FutureExt;
+use futures_util::{Sink, SinkExt};
+use std::fmt;
 use std::sync::Arc;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::{timeout, Duration};
 use warp::ws::Message;
 
+/// Policy violation, sent with the "slow_consumer"
+/// reason to peers that can't keep up.
+pub const SLOW_CONSUMER_CLOSE_CODE: u16 = 1008;
+
+/// How long a single websocket write may take
+/// before the peer counts as too slow.
+pub const SOCKET_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Why a peer's writer stopped.
+#[derive(Debug, PartialEq)]
+pub enum WriterExit {
+    /// every sender is gone or a close frame went out
+    Finished,
+    /// the socket returned an error
+    SocketError,
+    /// the buffer filled up or a write timed out
+    TooSlow,
+}
+
+/// Drains a peer's channel into their socket until either side
+/// is done. A write never blocks the slow consumer signal, so a
+/// client that stops reading is given up on instead of stalling
+/// this forever. On the way out the socket is only offered the
+/// close frame, it's dropped either way.
+pub async fn drain_to_socket<S>(
+    mut socket: S,
+    mut rx: mpsc::Receiver<Message>,
+    too_slow: Arc<Notify>,
+    write_timeout: Duration,
+) -> WriterExit
+where
+    S: Sink<Message> + Unpin,
+    S::Error: fmt::Display,
+{
+    let exit = loop {
+        let message = tokio::select! {
+            message = rx.recv() => match message {
+                Some(message) => message,
+                None => break WriterExit::Finished,
+            },
+            _ = too_slow.notified() => break WriterExit::TooSlow,
+        };
+        let closing = message.is_close();
+        tokio::select! {
+            result = timeout(write_timeout, socket.send(message)) => match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => {
+                    eprintln!("websocket send error: {}", e);
+                    break WriterExit::SocketError;
+                }
+                Err(_) => break WriterExit::TooSlow,
+            },
+            _ = too_slow.notified() => break WriterExit::TooSlow,
+        }
+        if closing {
+            break WriterExit::Finished;
+        }
+    };
+    if exit == WriterExit::TooSlow {
+        let close = Message::close_with(SLOW_CONSUMER_CLOSE_CODE, "slow_consumer");
+        socket.send(close).now_or_never();
+    }
+    exit
+}
+
 pub async fn broadcast_message_to_all_active_users(
     new_msg: String,
-    server_state: &Arc<Mutex<ServerState>>,
+    server_state: &mut ServerState,
 ) {
-    for (&_uid, tx) in server_state.lock().await.peer_map.iter() {
+    for tx in server_state.peer_map.values() {
         if let Err(_disconnected) = tx.send(Message::text(new_msg.clone())) {
             //user disconnection is handled in another task
         }
@@ -19,14 +89,8 @@ pub async fn broadcast_message_to_room(
     server_state: &mut ServerState,
     room_id: i32,
 ) {
-    if let Some(room) = server_state.rooms.get(&room_id) {
-        let room_users: Vec<&i32> = room.user_ids.iter().collect();
-        for id in room_users {
-            let user_websocket_channel = server_state.peer_map.get(id).unwrap();
-            user_websocket_channel
-                .send(Message::text(new_msg.clone()))
-                .unwrap_or_else(|e| eprintln!("issue sending msg:{}", e));
-        }
+    if server_state.rooms.contains_key(&room_id) {
+        response_logic::broadcast_to_room(&room_id, new_msg, server_state);
     } else {
         println!("Room removed before message was send: {}", new_msg);
     }
@@ -42,10 +106,13 @@ pub async fn broadcast_message_to_room_excluding_user(
         let room_users: Vec<&i32> = room.user_ids.iter().filter(|x| x != &&user_id).collect();
 
         for id in room_users {
-            let user_websocket_channel = server_state.peer_map.get(id).unwrap();
-            user_websocket_channel
-                .send(Message::text(new_msg.clone()))
-                .unwrap_or_else(|e| eprintln!("issue sending msg:{}", e));
+            // users waiting to resume their session have no channel
+            if server_state.peer_map.contains_user(id) {
+                server_state
+                    .peer_map
+                    .send_to_user(id, Message::text(new_msg.clone()))
+                    .unwrap_or_else(|e| eprintln!("issue sending msg:{}", e));
+            }
         }
     } else {
         println!("Room removed before message was send: {}", new_msg);
@@ -57,9 +124,10 @@ pub async fn broadcast_message_to_single_user(
     server_state: &mut ServerState,
     user_id: &i32,
 ) {
-    if let Some(user_websocket_channel) = server_state.peer_map.get(user_id) {
-        user_websocket_channel
-            .send(Message::text(new_msg.clone()))
+    if server_state.peer_map.contains_user(user_id) {
+        server_state
+            .peer_map
+            .send_to_user(user_id, Message::text(new_msg.clone()))
             .unwrap_or_else(|e| eprintln!("issue sending msg:{}", e));
     }
 }